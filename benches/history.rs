@@ -0,0 +1,66 @@
+//! Searches in a large history: incremental search, with and without a scan
+//! limit, and anchored search.
+//!
+//! `cargo bench --bench history`
+extern crate rustyline;
+
+use std::time::{Duration, Instant};
+
+use rustyline::history::{Direction, History, HistoryBackend};
+
+/// Number of entries of the synthetic history
+const ENTRIES: usize = 100_000;
+/// Number of searches timed
+const RUNS: u32 = 20;
+
+fn bench<F: Fn() -> Option<usize>>(name: &str, f: F) {
+    let start = Instant::now();
+    let mut found = None;
+    for _ in 0..RUNS {
+        found = f();
+    }
+    let elapsed = start.elapsed() / RUNS;
+    println!(
+        "{:<24} {:>10.3} ms/search (found: {:?})",
+        name,
+        millis(elapsed),
+        found
+    );
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs() as f64 * 1e3 + f64::from(d.subsec_nanos()) / 1e6
+}
+
+fn main() {
+    let mut history = History::new();
+    history.set_max_len(ENTRIES);
+    for i in 0..ENTRIES {
+        history.add(format!("cargo build --release --bin tool{} && ./run {}", i % 97, i));
+    }
+    let last = history.len() - 1;
+    // the worst case: nothing matches, the whole history is scanned
+    bench("unlimited (no match)", || {
+        history.search("missing", last, Direction::Reverse)
+    });
+    for &limit in &[1_000, 10_000] {
+        bench(&format!("limit {} (no match)", limit), || {
+            history.search_limited("missing", last, Direction::Reverse, limit)
+        });
+    }
+    bench("unlimited (recent match)", || {
+        history.search("tool42", last, Direction::Reverse)
+    });
+    // the first one builds the index
+    bench("prefix (no match)", || {
+        history.starts_with("missing", last, Direction::Reverse)
+    });
+    // a short prefix of all the entries
+    bench("prefix (common)", || {
+        history.starts_with("car", last, Direction::Reverse)
+    });
+    let old = "cargo build --release --bin tool42 && ./run 42";
+    bench("prefix (old match)", || {
+        history.starts_with(old, last, Direction::Reverse)
+    });
+}
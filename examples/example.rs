@@ -4,9 +4,9 @@ extern crate rustyline;
 use log::{Level, LevelFilter, Metadata, Record, SetLoggerError};
 use std::borrow::Cow::{self, Borrowed, Owned};
 
-use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::completion::{Completer, FilenameCompleter, LsColors, Pair};
 use rustyline::error::ReadlineError;
-use rustyline::highlight::Highlighter;
+use rustyline::highlight::{Highlighter, MatchingBracketHighlighter};
 use rustyline::hint::Hinter;
 use rustyline::{Cmd, CompletionType, Config, EditMode, Editor, Helper, KeyPress};
 
@@ -14,7 +14,7 @@ static COLORED_PROMPT: &'static str = "\x1b[1;32m>>\x1b[0m ";
 
 static PROMPT: &'static str = ">> ";
 
-struct MyHelper(FilenameCompleter);
+struct MyHelper(FilenameCompleter, MatchingBracketHighlighter);
 
 impl Completer for MyHelper {
     type Candidate = Pair;
@@ -22,6 +22,10 @@ impl Completer for MyHelper {
     fn complete(&self, line: &str, pos: usize) -> Result<(usize, Vec<Pair>), ReadlineError> {
         self.0.complete(line, pos)
     }
+
+    fn is_partial(&self, candidate: &Pair) -> bool {
+        self.0.is_partial(candidate)
+    }
 }
 
 impl Hinter for MyHelper {
@@ -46,6 +50,22 @@ impl Highlighter for MyHelper {
     fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
         Owned("\x1b[1m".to_owned() + hint + "\x1b[m")
     }
+
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        self.1.highlight(line, pos)
+    }
+
+    fn highlight_char_at(&self, line: &str, pos: usize) -> bool {
+        self.1.highlight_char_at(line, pos)
+    }
+
+    fn highlight_candidate<'c>(
+        &self,
+        candidate: &'c str,
+        completion: CompletionType,
+    ) -> Cow<'c, str> {
+        self.0.highlight_candidate(candidate, completion)
+    }
 }
 
 impl Helper for MyHelper {}
@@ -57,7 +77,9 @@ fn main() {
         .completion_type(CompletionType::List)
         .edit_mode(EditMode::Emacs)
         .build();
-    let h = MyHelper(FilenameCompleter::new());
+    let mut completer = FilenameCompleter::new();
+    completer.set_ls_colors(LsColors::from_env());
+    let h = MyHelper(completer, MatchingBracketHighlighter::new());
     let mut rl = Editor::with_config(config);
     rl.set_helper(Some(h));
     rl.bind_sequence(KeyPress::Meta('N'), Cmd::HistorySearchForward);
@@ -0,0 +1,308 @@
+//! Line editor for tokio runtimes (see `AsyncEditor`).
+use std::cell::OnceCell;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::panic;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use tokio::task::{self, JoinHandle};
+
+use super::{Editor, Helper, Result};
+use config::Config;
+
+/// Line editor reading the lines asynchronously: `readline` returns a future
+/// which yields to the runtime while the line is edited, instead of blocking
+/// a thread of the runtime.
+///
+/// It dereferences to the `Editor`, so the helper, the history and the
+/// configuration are handled the same way.
+///
+/// ```no_run
+/// # extern crate rustyline;
+/// # extern crate tokio;
+/// # fn main() {
+/// let mut rl = rustyline::AsyncEditor::<()>::new();
+/// let runtime = tokio::runtime::Builder::new_current_thread()
+///     .enable_all()
+///     .build()
+///     .unwrap();
+/// let readline = runtime.block_on(rl.readline(">> "));
+/// match readline {
+///     Ok(line) => println!("Line: {:?}", line),
+///     Err(_) => println!("No input"),
+/// }
+/// # }
+/// ```
+pub struct AsyncEditor<H: Helper> {
+    // empty while a line is read, until the editor is given back
+    editor: OnceCell<Editor<H>>,
+    // the read whose future has been dropped, which gives the editor back
+    // once it has stopped
+    cancelled: Mutex<Option<Task<H>>>,
+}
+
+impl<H: Helper + Send + 'static> AsyncEditor<H> {
+    /// Create an editor with the default configuration
+    pub fn new() -> AsyncEditor<H> {
+        Self::from(Editor::new())
+    }
+
+    /// Create an editor with a specific configuration.
+    pub fn with_config(config: Config) -> AsyncEditor<H> {
+        Self::from(Editor::with_config(config))
+    }
+
+    /// Returns a future of the line read from STDIN after displaying the
+    /// `prompt`. The line is edited by `Editor::readline` on a blocking task
+    /// of the runtime (see `tokio::task::spawn_blocking`), with the editor:
+    /// the whole edition, with its nested reads like the incremental search
+    /// or the completion list, never blocks the thread polling the future.
+    ///
+    /// Dropping the future before its completion abandons the line being
+    /// edited, without waiting: the read stops at the next check (every
+    /// 50 ms while no key is typed), or at the end of the command being
+    /// executed (like an external editor or a completion), or of the line
+    /// when `stdin` is not a terminal (this line is lost). Then the terminal
+    /// mode is restored, the cursor moved to the next line and the editor
+    /// given back: the next `readline` waits for it asynchronously, but an
+    /// access to the editor meanwhile (through `Deref`) blocks.
+    ///
+    /// It must be polled from a tokio runtime.
+    pub fn readline<'e>(&'e mut self, prompt: &'e str) -> Readline<'e, H> {
+        Readline {
+            editor: self,
+            prompt,
+            task: None,
+        }
+    }
+
+    /// Returns the wrapped editor.
+    pub fn into_inner(mut self) -> Editor<H> {
+        self.take_back();
+        self.editor.take().expect(LOST)
+    }
+}
+
+impl<H: Helper> AsyncEditor<H> {
+    /// Waits for the editor of the cancelled read (if any), blocking.
+    fn take_back(&self) {
+        if self.editor.get().is_some() {
+            return;
+        }
+        let task = self.cancelled.lock().unwrap().take().expect(LOST);
+        let (editor, _) = task.result.recv().expect(LOST);
+        let _ = self.editor.set(editor);
+    }
+}
+
+/// Message of the panic when the editor has been lost by a panic while a
+/// line was read
+const LOST: &str = "editor lost by a panic while a line was read";
+
+impl<H: Helper> From<Editor<H>> for AsyncEditor<H> {
+    fn from(editor: Editor<H>) -> AsyncEditor<H> {
+        AsyncEditor {
+            editor: OnceCell::from(editor),
+            cancelled: Mutex::new(None),
+        }
+    }
+}
+
+impl<H: Helper> Deref for AsyncEditor<H> {
+    type Target = Editor<H>;
+
+    fn deref(&self) -> &Editor<H> {
+        self.take_back();
+        self.editor.get().unwrap()
+    }
+}
+
+impl<H: Helper> DerefMut for AsyncEditor<H> {
+    fn deref_mut(&mut self) -> &mut Editor<H> {
+        self.take_back();
+        self.editor.get_mut().unwrap()
+    }
+}
+
+/// Blocking task reading a line with the editor
+struct Task<H: Helper> {
+    handle: JoinHandle<()>,
+    // stops the read (see `CancellableReader`)
+    cancel: Arc<AtomicBool>,
+    // the editor given back with the line, once it is read
+    result: Receiver<(Editor<H>, Result<String>)>,
+}
+
+impl<H: Helper> Task<H> {
+    /// Polls the end of the task: the editor and the line read.
+    fn poll_result(&mut self, cx: &mut Context) -> Poll<(Editor<H>, Result<String>)> {
+        match Pin::new(&mut self.handle).poll(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Ok(())) => Poll::Ready(self.result.recv().unwrap()),
+            Poll::Ready(Err(err)) => {
+                if err.is_panic() {
+                    panic::resume_unwind(err.into_panic());
+                }
+                // the runtime is shutting down
+                panic!("{}: {}", LOST, err)
+            }
+        }
+    }
+}
+
+/// Future of the line read by `AsyncEditor::readline`
+#[must_use = "futures do nothing unless polled"]
+pub struct Readline<'e, H: Helper + 'e> {
+    editor: &'e mut AsyncEditor<H>,
+    prompt: &'e str,
+    // started by the first poll
+    task: Option<Task<H>>,
+}
+
+impl<'e, H: Helper + Send + 'static> Readline<'e, H> {
+    fn spawn(&mut self, mut editor: Editor<H>) -> Task<H> {
+        let cancel = Arc::new(AtomicBool::new(false));
+        editor.cancel = Some(Arc::clone(&cancel));
+        let prompt = self.prompt.to_owned();
+        let (sender, result) = mpsc::channel();
+        let handle = task::spawn_blocking(move || {
+            let line = editor.readline(&prompt);
+            editor.cancel = None;
+            let _ = sender.send((editor, line));
+        });
+        Task {
+            handle,
+            cancel,
+            result,
+        }
+    }
+}
+
+impl<'e, H: Helper + Send + 'static> Future for Readline<'e, H> {
+    type Output = Result<String>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<String>> {
+        let this = self.get_mut();
+        if this.task.is_none() {
+            let editor = match this.editor.editor.take() {
+                Some(editor) => editor,
+                None => {
+                    // the previous read has been cancelled
+                    let cancelled = this.editor.cancelled.get_mut().unwrap();
+                    let editor = match cancelled.as_mut().expect(LOST).poll_result(cx) {
+                        Poll::Pending => return Poll::Pending,
+                        Poll::Ready((editor, _)) => editor,
+                    };
+                    *cancelled = None;
+                    editor
+                }
+            };
+            this.task = Some(this.spawn(editor));
+        }
+        match this.task.as_mut().unwrap().poll_result(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready((editor, line)) => {
+                this.task = None;
+                let _ = this.editor.editor.set(editor);
+                Poll::Ready(line)
+            }
+        }
+    }
+}
+
+impl<'e, H: Helper + 'e> Drop for Readline<'e, H> {
+    fn drop(&mut self) {
+        // cancelled (no-op once the line is returned): the editor is given
+        // back later
+        if let Some(task) = self.task.take() {
+            task.cancel.store(true, Ordering::SeqCst);
+            *self.editor.cancelled.get_mut().unwrap() = Some(task);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::{Context, Wake, Waker};
+    use std::time::Duration;
+
+    use tokio::runtime::Builder;
+    use tokio::time;
+
+    use super::AsyncEditor;
+    use keys::KeyPress;
+
+    struct Noop;
+
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    fn init_editor(keys: &[KeyPress]) -> AsyncEditor<()> {
+        let mut editor = AsyncEditor::<()>::new();
+        editor.term.keys.extend(keys.iter().cloned());
+        editor
+    }
+
+    #[test]
+    fn readline() {
+        let mut editor = init_editor(&[KeyPress::Char('a'), KeyPress::Char('b'), KeyPress::Enter]);
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+        let line = runtime.block_on(editor.readline(">>")).unwrap();
+        assert_eq!("ab", line);
+    }
+
+    #[test]
+    fn history() {
+        let mut editor = init_editor(&[KeyPress::Up, KeyPress::Up, KeyPress::Enter]);
+        editor.add_history_entry("ls");
+        editor.add_history_entry("cd");
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+        assert_eq!("ls", runtime.block_on(editor.readline(">>")).unwrap());
+    }
+
+    #[test]
+    fn cancel() {
+        // the incremental search waits for the next key
+        let mut editor = init_editor(&[KeyPress::Ctrl('R'), KeyPress::Char('l')]);
+        editor.add_history_entry("ls");
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+        let _guard = runtime.enter();
+        {
+            let mut readline = editor.readline(">>");
+            let waker = Waker::from(Arc::new(Noop));
+            let mut cx = Context::from_waker(&waker);
+            assert!(Pin::new(&mut readline).poll(&mut cx).is_pending());
+            assert!(readline.editor.editor.get().is_none());
+        }
+        // given back once stopped
+        assert!(editor.cancel.is_none());
+        assert_eq!(1, editor.history().len());
+        editor.term.keys = vec![KeyPress::Char('a'), KeyPress::Enter];
+        assert_eq!("a", runtime.block_on(editor.readline(">>")).unwrap());
+    }
+
+    #[test]
+    fn timeout() {
+        let mut editor = init_editor(&[KeyPress::Ctrl('R'), KeyPress::Char('l')]);
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+        // the timers are created in the runtime
+        let _guard = runtime.enter();
+        let delay = Duration::from_millis(20);
+        // dropped by the timeout without blocking the runtime
+        for _ in 0..2 {
+            let timeout = runtime.block_on(time::timeout(delay, editor.readline(">>")));
+            assert!(timeout.is_err());
+        }
+        editor.term.keys = vec![KeyPress::Char('a'), KeyPress::Enter];
+        let timeout = runtime.block_on(time::timeout(delay * 50, editor.readline(">>")));
+        assert_eq!("a", timeout.unwrap().unwrap());
+    }
+}
@@ -1,12 +1,29 @@
 //! Completion API
 use std::borrow::Cow::{self, Borrowed, Owned};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::ffi::OsString;
 use std::fs;
-use std::path::{self, Path};
+use std::future::Future;
+use std::io;
+use std::ops::Range;
+use std::path::{self, Path, PathBuf};
+use std::pin::Pin;
 
 use super::Result;
+use anstyle;
+use config::CompletionType;
+use dirs;
+use highlight::Highlighter;
 use line_buffer::LineBuffer;
 use memchr::memchr;
 
+mod fuzzy;
+mod ls_colors;
+pub use self::fuzzy::{fuzzy_match, FuzzyCandidate, FuzzyCompleter};
+pub use self::ls_colors::LsColors;
+
 // TODO: let the implementers choose/find word boudaries ???
 // (line, pos) is like (rl_line_buffer, rl_point) to make contextual completion
 // ("select t.na| from tbl as t")
@@ -18,6 +35,31 @@ pub trait Candidate {
     fn display(&self) -> &str;
     /// Text to insert in line.
     fn replacement(&self) -> &str;
+    /// Longer description (like a signature or its docs) displayed below the
+    /// line while the candidate is selected (see
+    /// `Configurer::set_completion_docs_height`).
+    fn documentation(&self) -> Option<&str> {
+        None
+    }
+    /// Byte indices of the chars of `display` matched by the typed text
+    /// (like with fuzzy completion): `Highlighter::highlight_candidate_item`
+    /// displays them in bold by default.
+    fn match_indices(&self) -> &[usize] {
+        &[]
+    }
+    /// Short annotation of the candidate (like its kind: `fn`, `var` or
+    /// `keyword`), displayed (dim by default, see
+    /// `Highlighter::highlight_candidate_kind`) in a column after the
+    /// `display` texts when the candidates are listed.
+    fn kind(&self) -> Option<&str> {
+        None
+    }
+    /// One-line description of the candidate, displayed after its `display`
+    /// and `kind` with `CompletionType::ListWithDescription` (truncated to
+    /// the terminal width).
+    fn description(&self) -> Option<&str> {
+        None
+    }
 }
 
 impl Candidate for String {
@@ -30,6 +72,16 @@ impl Candidate for String {
     }
 }
 
+impl<'s> Candidate for &'s str {
+    fn display(&self) -> &str {
+        self
+    }
+
+    fn replacement(&self) -> &str {
+        self
+    }
+}
+
 pub struct Pair {
     pub display: String,
     pub replacement: String,
@@ -45,6 +97,24 @@ impl Candidate for Pair {
     }
 }
 
+/// Result of `Completer::complete_advanced`.
+pub struct Completion<C> {
+    /// Start of the text replaced by the elected candidate
+    pub start: usize,
+    /// End of the text replaced by the elected candidate (the cursor
+    /// position by default, see `Completer::complete_range`)
+    pub end: usize,
+    pub candidates: Vec<C>,
+    /// Line replacing the whole edited line when a candidate is elected (like
+    /// the line reformatted): `start` and `end` are positions in this line,
+    /// the cursor is moved to `end`, then the candidate replaces
+    /// `start..end`.
+    ///
+    /// When there is no candidate (or with `CompletionType::List`), the line
+    /// is replaced right away.
+    pub replace_whole_line: Option<String>,
+}
+
 /// To be called for tab-completion.
 pub trait Completer {
     type Candidate: Candidate;
@@ -55,11 +125,188 @@ pub trait Completer {
     ///
     /// ("ls /usr/loc", 11) => Ok((3, vec!["/usr/local/"]))
     fn complete(&self, line: &str, pos: usize) -> Result<(usize, Vec<Self::Candidate>)>;
+    /// Same as `complete` but returns the range of the `line` replaced by the
+    /// elected candidate: it may extend past the cursor, like to rewrite the
+    /// whole word when the cursor is in its middle.
+    ///
+    /// ("ls /usr/loc|al/b", 11) => Ok((3..13, vec!["/usr/local/"]))
+    ///
+    /// By default, the range ends at the cursor `pos`ition.
+    fn complete_range(
+        &self,
+        line: &str,
+        pos: usize,
+    ) -> Result<(Range<usize>, Vec<Self::Candidate>)> {
+        let (start, candidates) = try!(self.complete(line, pos));
+        Ok((start..pos, candidates))
+    }
+    /// Same as `complete_range` but the completion may reshape the whole
+    /// line (see `Completion::replace_whole_line`), like a SQL completer
+    /// which also fixes the case of the keywords already typed.
+    ///
+    /// ```
+    /// use rustyline::completion::{Completer, Completion};
+    /// use rustyline::Result;
+    ///
+    /// struct SqlCompleter;
+    ///
+    /// impl Completer for SqlCompleter {
+    ///     type Candidate = String;
+    ///
+    ///     fn complete(&self, _line: &str, _pos: usize) -> Result<(usize, Vec<String>)> {
+    ///         Ok((0, Vec::new()))
+    ///     }
+    ///
+    ///     fn complete_advanced(&self, line: &str, pos: usize) -> Result<Completion<String>> {
+    ///         let start = line[..pos].rfind(' ').map_or(0, |i| i + 1);
+    ///         let candidates = vec!["FROM".to_owned()];
+    ///         Ok(Completion {
+    ///             start,
+    ///             end: pos,
+    ///             candidates,
+    ///             replace_whole_line: Some(line[..pos].to_uppercase()),
+    ///         })
+    ///     }
+    /// }
+    ///
+    /// let completion = SqlCompleter.complete_advanced("select * f", 10).unwrap();
+    /// assert_eq!(Some("SELECT * F".to_owned()), completion.replace_whole_line);
+    /// ```
+    ///
+    /// By default, the result of `complete_range`.
+    fn complete_advanced(&self, line: &str, pos: usize) -> Result<Completion<Self::Candidate>> {
+        let (range, candidates) = try!(self.complete_range(line, pos));
+        Ok(Completion {
+            start: range.start,
+            end: range.end,
+            candidates,
+            replace_whole_line: None,
+        })
+    }
     /// Updates the edited `line` with the `elected` candidate.
     fn update(&self, line: &mut LineBuffer, start: usize, elected: &str) {
         let end = line.pos();
         line.replace(start..end, elected)
     }
+    /// Returns `true` if the `candidate` can be completed further (like a
+    /// directory): when it is the only candidate, the next `Cmd::Complete`
+    /// completes the updated line instead of cycling back to the original one.
+    fn is_partial(&self, _candidate: &Self::Candidate) -> bool {
+        false
+    }
+    /// Returns the candidates nested under the `selected` one, for a
+    /// drill-down completion (like `std::` refined into `std::io` and
+    /// `std::fs`).
+    ///
+    /// When the user presses Enter on a candidate with a refinement (while
+    /// cycling with `CompletionType::Circular` or in the
+    /// `CompletionType::Menu`), the candidates are replaced by this
+    /// refinement, and they replace the same range of the line.
+    /// Otherwise, Enter behaves as usual.
+    ///
+    /// By default, no candidate is refined.
+    fn refine(&self, selected: &Self::Candidate) -> Vec<Self::Candidate> {
+        let _ = selected;
+        Vec::new()
+    }
+    /// Reorders the `candidates` before they are displayed, after the order
+    /// set by `Configurer::set_completion_sort`: sort by kind to group the
+    /// candidates alphabetically sorted by the editor, or with your own
+    /// comparator.
+    ///
+    /// Use a stable sort (like `slice::sort_by`) to keep ties in their
+    /// previous order.
+    /// By default, the order is kept.
+    fn sort(&self, candidates: &mut [Self::Candidate]) {
+        let _ = candidates;
+    }
+    /// Called when a completion session starts (before `complete`).
+    ///
+    /// Useful to set up expensive resources lazily (like a database
+    /// connection) and to reuse them while the user cycles through candidates.
+    /// As `complete`, it takes `&self`: use interior mutability to keep state.
+    ///
+    /// ```
+    /// use std::cell::RefCell;
+    /// use rustyline::completion::Completer;
+    /// use rustyline::Result;
+    ///
+    /// struct Connection; // database connection
+    ///
+    /// impl Connection {
+    ///     fn open() -> Connection {
+    ///         Connection
+    ///     }
+    ///     fn tables(&self, prefix: &str) -> Vec<String> {
+    ///         vec![format!("{}_table", prefix)]
+    ///     }
+    /// }
+    ///
+    /// struct DatabaseCompleter {
+    ///     conn: RefCell<Option<Connection>>,
+    /// }
+    ///
+    /// impl Completer for DatabaseCompleter {
+    ///     type Candidate = String;
+    ///
+    ///     fn complete(&self, line: &str, pos: usize) -> Result<(usize, Vec<String>)> {
+    ///         let conn = self.conn.borrow();
+    ///         let tables = conn.as_ref().map(|c| c.tables(&line[..pos]));
+    ///         Ok((0, tables.unwrap_or_default()))
+    ///     }
+    ///
+    ///     fn begin_completion(&self) {
+    ///         let mut conn = self.conn.borrow_mut();
+    ///         if conn.is_none() {
+    ///             *conn = Some(Connection::open());
+    ///         }
+    ///     }
+    ///
+    ///     fn end_completion(&self) {
+    ///         self.conn.borrow_mut().take();
+    ///     }
+    /// }
+    /// ```
+    fn begin_completion(&self) {}
+    /// Called when a completion session ends: a candidate is accepted, the
+    /// list is dismissed or the completion is aborted (Escape / Ctrl-G).
+    fn end_completion(&self) {}
+    /// Returns the completer used in place of `complete` when the candidates
+    /// are computed asynchronously (see `AsyncCompleter`).
+    ///
+    /// By default, `None`.
+    fn async_completer(&self) -> Option<&AsyncCompleter<Candidate = Self::Candidate>> {
+        None
+    }
+}
+
+/// Candidates (and their start position) computed asynchronously.
+pub type CompletionFuture<'a, C> = Pin<Box<Future<Output = Result<(usize, Vec<C>)>> + 'a>>;
+
+/// Completer whose candidates are computed asynchronously (like by a
+/// network-backed language server).
+///
+/// While the candidates are loading, the prompt stays responsive and a
+/// spinner hint ("| computing…") is displayed.
+///
+/// # Cancellation
+///
+/// If a key is pressed before the candidates are ready, the completion is
+/// cancelled: the future is dropped (without being polled again) and the key
+/// is processed as usual. So the user can keep typing: the pending request
+/// must be aborted (or its result ignored) when the future is dropped, and a
+/// new completion (like with Tab) starts a new request for the updated line.
+///
+/// The future is polled by the editor itself, not by an executor: it must
+/// wake its `Waker` when it can make progress (like a future fed by a channel
+/// from another thread or runtime).
+pub trait AsyncCompleter {
+    type Candidate: Candidate;
+
+    /// Takes the currently edited `line` with the cursor `pos`ition and
+    /// returns the future of the start position and the completion
+    /// candidates (see `Completer::complete`).
+    fn complete<'a>(&'a self, line: &str, pos: usize) -> CompletionFuture<'a, Self::Candidate>;
 }
 
 impl Completer for () {
@@ -81,9 +328,45 @@ impl<'c, C: ?Sized + Completer> Completer for &'c C {
         (**self).complete(line, pos)
     }
 
+    fn complete_range(
+        &self,
+        line: &str,
+        pos: usize,
+    ) -> Result<(Range<usize>, Vec<Self::Candidate>)> {
+        (**self).complete_range(line, pos)
+    }
+
+    fn complete_advanced(&self, line: &str, pos: usize) -> Result<Completion<Self::Candidate>> {
+        (**self).complete_advanced(line, pos)
+    }
+
     fn update(&self, line: &mut LineBuffer, start: usize, elected: &str) {
         (**self).update(line, start, elected)
     }
+
+    fn is_partial(&self, candidate: &Self::Candidate) -> bool {
+        (**self).is_partial(candidate)
+    }
+
+    fn refine(&self, selected: &Self::Candidate) -> Vec<Self::Candidate> {
+        (**self).refine(selected)
+    }
+
+    fn sort(&self, candidates: &mut [Self::Candidate]) {
+        (**self).sort(candidates)
+    }
+
+    fn begin_completion(&self) {
+        (**self).begin_completion()
+    }
+
+    fn end_completion(&self) {
+        (**self).end_completion()
+    }
+
+    fn async_completer(&self) -> Option<&AsyncCompleter<Candidate = Self::Candidate>> {
+        (**self).async_completer()
+    }
 }
 macro_rules! box_completer {
     ($($id: ident)*) => {
@@ -94,9 +377,41 @@ macro_rules! box_completer {
                 fn complete(&self, line: &str, pos: usize) -> Result<(usize, Vec<Self::Candidate>)> {
                     (**self).complete(line, pos)
                 }
+                fn complete_range(
+                    &self,
+                    line: &str,
+                    pos: usize,
+                ) -> Result<(Range<usize>, Vec<Self::Candidate>)> {
+                    (**self).complete_range(line, pos)
+                }
+                fn complete_advanced(
+                    &self,
+                    line: &str,
+                    pos: usize,
+                ) -> Result<Completion<Self::Candidate>> {
+                    (**self).complete_advanced(line, pos)
+                }
                 fn update(&self, line: &mut LineBuffer, start: usize, elected: &str) {
                     (**self).update(line, start, elected)
                 }
+                fn is_partial(&self, candidate: &Self::Candidate) -> bool {
+                    (**self).is_partial(candidate)
+                }
+                fn refine(&self, selected: &Self::Candidate) -> Vec<Self::Candidate> {
+                    (**self).refine(selected)
+                }
+                fn sort(&self, candidates: &mut [Self::Candidate]) {
+                    (**self).sort(candidates)
+                }
+                fn begin_completion(&self) {
+                    (**self).begin_completion()
+                }
+                fn end_completion(&self) {
+                    (**self).end_completion()
+                }
+                fn async_completer(&self) -> Option<&AsyncCompleter<Candidate = Self::Candidate>> {
+                    (**self).async_completer()
+                }
             }
         )*
     }
@@ -110,6 +425,16 @@ box_completer! { Box Rc Arc }
 pub struct FilenameCompleter {
     break_chars: &'static [u8],
     double_quotes_special_chars: &'static [u8],
+    case_insensitive: bool,
+    ls_colors: Option<LsColors>,
+    // `true` if the paths ignored by `.gitignore` / `.ignore` files are skipped
+    ignore: bool,
+    // `true` if `~` and the variables are kept as typed in the replacements
+    keep_unexpanded: bool,
+    // directory of `~` (`None` for the one of the user)
+    home_dir: Option<PathBuf>,
+    // styles of the candidates last found (by file name)
+    styles: RefCell<HashMap<String, anstyle::Style>>,
 }
 
 static DOUBLE_QUOTES_ESCAPE_CHAR: Option<char> = Some('\\');
@@ -150,8 +475,63 @@ impl FilenameCompleter {
         FilenameCompleter {
             break_chars: &DEFAULT_BREAK_CHARS,
             double_quotes_special_chars: &DOUBLE_QUOTES_SPECIAL_CHARS,
+            case_insensitive: false,
+            ls_colors: None,
+            ignore: false,
+            keep_unexpanded: true,
+            home_dir: None,
+            styles: RefCell::new(HashMap::new()),
         }
     }
+
+    /// Matches the file names regardless of their case (like on
+    /// case-insensitive filesystems): `READ` completes `README.md`.
+    ///
+    /// The file names are inserted with their actual case.
+    pub fn set_case_insensitive(&mut self, yes: bool) {
+        self.case_insensitive = yes;
+    }
+
+    /// Colorizes the candidates by file type like `ls` (see
+    /// `LsColors::from_env`) when they are listed (`CompletionType::List` or
+    /// `CompletionType::Menu`).
+    ///
+    /// The colors are applied by `highlight_candidate`: the helper must
+    /// forward it to this completer.
+    /// By default, `None`: the candidates are not colorized.
+    pub fn set_ls_colors(&mut self, ls_colors: Option<LsColors>) {
+        self.ls_colors = ls_colors;
+    }
+
+    /// Keeps the leading `~` / `~user` and the `$VAR` / `${VAR}` (see `expand`)
+    /// as typed in the replacements: `~/pro` completes `~/projects/`.
+    ///
+    /// When `false`, the directory is replaced by its expansion:
+    /// `~/pro` completes `/home/user/projects/`.
+    /// By default, `true`.
+    pub fn set_keep_unexpanded(&mut self, yes: bool) {
+        self.keep_unexpanded = yes;
+    }
+
+    /// Directory to which a leading `~` expands.
+    ///
+    /// By default, `None`: the home directory of the user.
+    pub fn set_home_dir(&mut self, home_dir: Option<PathBuf>) {
+        self.home_dir = home_dir;
+    }
+
+    /// Skips the paths ignored by the `.gitignore` / `.ignore` files (of the
+    /// completed directory and its parents, negated patterns included) and
+    /// by the git exclude / global ignore files.
+    ///
+    /// Like git, the `.gitignore` files only apply inside a repository: no
+    /// path is skipped when completing a directory outside of any.
+    /// By default, `false`: all the entries are listed.
+    #[cfg(feature = "ignore")]
+    pub fn with_ignore(mut self, yes: bool) -> FilenameCompleter {
+        self.ignore = yes;
+        self
+    }
 }
 
 impl Default for FilenameCompleter {
@@ -160,6 +540,25 @@ impl Default for FilenameCompleter {
     }
 }
 
+impl Highlighter for FilenameCompleter {
+    fn highlight_candidate<'c>(
+        &self,
+        candidate: &'c str,
+        completion: CompletionType,
+    ) -> Cow<'c, str> {
+        let _ = completion;
+        match self.styles.borrow().get(candidate) {
+            Some(style) => Owned(format!(
+                "{}{}{}",
+                style.render(),
+                candidate,
+                style.render_reset()
+            )),
+            None => Borrowed(candidate),
+        }
+    }
+}
+
 impl Completer for FilenameCompleter {
     type Candidate = Pair;
 
@@ -185,13 +584,53 @@ impl Completer for FilenameCompleter {
                     )
                 }
             } else {
-                let (start, path) = extract_word(line, pos, ESCAPE_CHAR, &self.break_chars);
-                let path = unescape(path, ESCAPE_CHAR);
+                let (mut start, _) = extract_word(line, pos, ESCAPE_CHAR, &self.break_chars);
+                // `$` and `{` are break chars but a variable reference
+                // (`$VAR/...` or `${VAR}/...`) is part of the path
+                loop {
+                    let var_start = if line[..start].ends_with("${") {
+                        start - 2
+                    } else if line[..start].ends_with('$') {
+                        start - 1
+                    } else {
+                        break;
+                    };
+                    start = extract_word(line, var_start, ESCAPE_CHAR, &self.break_chars).0;
+                }
+                let path = unescape(&line[start..pos], ESCAPE_CHAR);
                 (start, path, ESCAPE_CHAR, &self.break_chars, Quote::None)
             };
-        let matches = try!(filename_complete(&path, esc_char, break_chars, quote));
+        let home_dir = self.home_dir.clone().or_else(dirs::home_dir);
+        let matches = try!(filename_complete(
+            &path,
+            &line[start..pos],
+            esc_char,
+            break_chars,
+            quote,
+            self.case_insensitive,
+            self.ignore,
+            self.keep_unexpanded,
+            home_dir.as_ref().map(PathBuf::as_path),
+            self.ls_colors.as_ref()
+        ));
+        let mut styles = self.styles.borrow_mut();
+        styles.clear();
+        let matches = matches
+            .into_iter()
+            .map(|(pair, style)| {
+                if let Some(style) = style {
+                    styles.insert(pair.display.clone(), style);
+                }
+                pair
+            })
+            .collect();
         Ok((start, matches))
     }
+
+    /// Directories (and symbolic links to directories) are partial.
+    fn is_partial(&self, candidate: &Pair) -> bool {
+        candidate.replacement.ends_with(path::MAIN_SEPARATOR)
+    }
 }
 
 /// Remove escape char
@@ -263,11 +702,16 @@ pub fn escape(
 
 fn filename_complete(
     path: &str,
+    typed: &str,
     esc_char: Option<char>,
     break_chars: &[u8],
     quote: Quote,
-) -> Result<Vec<Pair>> {
-    use dirs::home_dir;
+    case_insensitive: bool,
+    ignore: bool,
+    keep_unexpanded: bool,
+    home_dir: Option<&Path>,
+    ls_colors: Option<&LsColors>,
+) -> Result<Vec<(Pair, Option<anstyle::Style>)>> {
     use std::env::current_dir;
 
     let sep = path::MAIN_SEPARATOR;
@@ -276,19 +720,19 @@ fn filename_complete(
         None => ("", path),
     };
 
-    let dir_path = Path::new(dir_name);
-    let dir = if dir_path.starts_with("~") {
-        // ~[/...]
-        if let Some(home) = home_dir() {
-            match dir_path.strip_prefix("~") {
-                Ok(rel_path) => home.join(rel_path),
-                _ => home,
-            }
-        } else {
-            dir_path.to_path_buf()
-        }
-    } else if dir_path.is_relative() {
-        // TODO ~user[/...] (https://crates.io/crates/users)
+    // nothing is expanded in single quotes
+    let expanded = if quote == Quote::Single {
+        Borrowed(dir_name)
+    } else {
+        expand_with_home(dir_name, home_dir)
+    };
+    // the directory is inserted as typed (unexpanded) or expanded
+    let typed_dir = match expanded {
+        Owned(_) if keep_unexpanded => typed.rfind(sep).map(|idx| &typed[..idx + sep.len_utf8()]),
+        _ => None,
+    };
+    let dir_path = Path::new(expanded.as_ref());
+    let dir = if dir_path.is_relative() {
         if let Ok(cwd) = current_dir() {
             cwd.join(dir_path)
         } else {
@@ -298,20 +742,64 @@ fn filename_complete(
         dir_path.to_path_buf()
     };
 
-    let mut entries: Vec<Pair> = Vec::new();
-    for entry in try!(dir.read_dir()) {
+    // only the file name is matched regardless of its case: the directory is
+    // resolved by the filesystem
+    let lowercase_name = if case_insensitive {
+        Some(file_name.to_lowercase())
+    } else {
+        None
+    };
+    #[cfg(feature = "ignore")]
+    let not_ignored = if ignore {
+        Some(not_ignored_entries(&dir))
+    } else {
+        None
+    };
+    #[cfg(not(feature = "ignore"))]
+    let not_ignored: Option<HashSet<OsString>> = {
+        let _ = ignore;
+        None
+    };
+    let mut entries = Vec::new();
+    let read_dir = match dir.read_dir() {
+        Ok(read_dir) => read_dir,
+        Err(ref err) if err.kind() == io::ErrorKind::PermissionDenied => return Ok(entries),
+        Err(err) => return Err(err.into()),
+    };
+    for entry in read_dir {
         let entry = try!(entry);
+        if let Some(ref not_ignored) = not_ignored {
+            if !not_ignored.contains(&entry.file_name()) {
+                continue;
+            }
+        }
         if let Some(s) = entry.file_name().to_str() {
-            if s.starts_with(file_name) {
+            let matched = match lowercase_name {
+                Some(ref name) => s.to_lowercase().starts_with(name.as_str()),
+                None => s.starts_with(file_name),
+            };
+            if matched {
                 if let Ok(metadata) = fs::metadata(entry.path()) {
-                    let mut path = String::from(dir_name) + s;
+                    let mut path = match typed_dir {
+                        Some(_) => String::from(s),
+                        None => String::from(expanded.as_ref()) + s,
+                    };
                     if metadata.is_dir() {
                         path.push(sep);
                     }
-                    entries.push(Pair {
-                        display: String::from(s),
-                        replacement: escape(path, esc_char, break_chars, quote),
+                    let mut replacement = escape(path, esc_char, break_chars, quote);
+                    if let Some(typed_dir) = typed_dir {
+                        replacement.insert_str(0, typed_dir);
+                    }
+                    let style = ls_colors.and_then(|ls_colors| match entry.metadata() {
+                        Ok(ref link) => ls_colors.style(s, link, Some(&metadata)),
+                        Err(_) => None,
                     });
+                    let pair = Pair {
+                        display: String::from(s),
+                        replacement,
+                    };
+                    entries.push((pair, style));
                 } // else ignore PermissionDenied
             }
         }
@@ -319,6 +807,138 @@ fn filename_complete(
     Ok(entries)
 }
 
+/// Returns the names of the entries of `dir` which are not ignored (see
+/// `FilenameCompleter::with_ignore`).
+#[cfg(feature = "ignore")]
+fn not_ignored_entries(dir: &Path) -> HashSet<OsString> {
+    use ignore::WalkBuilder;
+
+    WalkBuilder::new(dir)
+        .max_depth(Some(1))
+        // hidden files are completed (when not ignored)
+        .hidden(false)
+        .build()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.depth() == 1)
+        .map(|entry| entry.file_name().to_owned())
+        .collect()
+}
+
+/// Expands a leading `~` (or `~user`) and the environment variables (`$VAR`
+/// and `${VAR}`, or `%VAR%` on Windows) in `path`.
+///
+/// Unknown users and unset variables are kept as typed.
+pub fn expand<'p>(path: &'p str) -> Cow<'p, str> {
+    let home_dir = dirs::home_dir();
+    expand_with_home(path, home_dir.as_ref().map(PathBuf::as_path))
+}
+
+/// Like `expand` but `~` is expanded to `home_dir`.
+fn expand_with_home<'p>(path: &'p str, home_dir: Option<&Path>) -> Cow<'p, str> {
+    let mut result = String::with_capacity(path.len());
+    let mut expanded = false;
+    let mut rest = path;
+    if rest.starts_with('~') {
+        let end = rest
+            .find(path::MAIN_SEPARATOR)
+            .unwrap_or_else(|| rest.len());
+        let home = if end == 1 {
+            home_dir.map(Path::to_path_buf)
+        } else {
+            user_home_dir(&rest[1..end])
+        };
+        if let Some(home) = home.as_ref().and_then(|home| home.to_str()) {
+            result.push_str(home);
+            rest = &rest[end..];
+            expanded = true;
+        }
+    }
+    while let Some((i, name, len)) = find_var(rest) {
+        result.push_str(&rest[..i]);
+        match env::var(name) {
+            Ok(ref value) if !name.is_empty() => {
+                result.push_str(value);
+                expanded = true;
+            }
+            _ => result.push_str(&rest[i..i + len]),
+        }
+        rest = &rest[i + len..];
+    }
+    if !expanded {
+        return Borrowed(path);
+    }
+    result.push_str(rest);
+    Owned(result)
+}
+
+/// Finds the first variable reference in `s`: its start, its name and its
+/// length.
+#[cfg(unix)]
+fn find_var(s: &str) -> Option<(usize, &str, usize)> {
+    let i = match s.find('$') {
+        Some(i) => i,
+        None => return None,
+    };
+    let after = &s[i + 1..];
+    if after.starts_with('{') {
+        if let Some(end) = after.find('}') {
+            return Some((i, &after[1..end], end + 2));
+        }
+    }
+    let end = after
+        .find(|c: char| !c.is_alphanumeric() && c != '_')
+        .unwrap_or_else(|| after.len());
+    Some((i, &after[..end], end + 1))
+}
+
+/// Finds the first variable reference in `s`: its start, its name and its
+/// length.
+#[cfg(windows)]
+fn find_var(s: &str) -> Option<(usize, &str, usize)> {
+    let i = match s.find('%') {
+        Some(i) => i,
+        None => return None,
+    };
+    s[i + 1..]
+        .find('%')
+        .map(|end| (i, &s[i + 1..i + 1 + end], end + 2))
+}
+
+#[cfg(unix)]
+fn user_home_dir(user: &str) -> Option<PathBuf> {
+    use libc;
+    use std::ffi::{CStr, CString};
+    use std::mem;
+    use std::ptr;
+
+    let name = match CString::new(user) {
+        Ok(name) => name,
+        Err(_) => return None,
+    };
+    let mut buf = vec![0 as libc::c_char; 4096];
+    let mut pwd: libc::passwd = unsafe { mem::zeroed() };
+    let mut found = ptr::null_mut();
+    let rc = unsafe {
+        libc::getpwnam_r(
+            name.as_ptr(),
+            &mut pwd,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut found,
+        )
+    };
+    if rc != 0 || found.is_null() || pwd.pw_dir.is_null() {
+        return None;
+    }
+    let dir = unsafe { CStr::from_ptr(pwd.pw_dir) };
+    dir.to_str().ok().map(PathBuf::from)
+}
+
+#[cfg(windows)]
+fn user_home_dir(_user: &str) -> Option<PathBuf> {
+    None
+}
+
 /// Given a `line` and a cursor `pos`ition,
 /// try to find backward the start of a word.
 /// Return (0, `line[..pos]`) if no break char has been found.
@@ -388,6 +1008,45 @@ pub fn longest_common_prefix<C: Candidate>(candidates: &[C]) -> Option<&str> {
     Some(&candidate[0..longest_common_prefix])
 }
 
+/// Like `longest_common_prefix` but the candidates are compared regardless
+/// of their case: the prefix is taken from the first candidate.
+pub fn longest_common_prefix_ignore_case<C: Candidate>(candidates: &[C]) -> Option<&str> {
+    if candidates.is_empty() {
+        return None;
+    }
+    let candidate = candidates[0].replacement();
+    let mut others: Vec<_> = candidates[1..]
+        .iter()
+        .map(|c| c.replacement().chars())
+        .collect();
+    let mut longest_common_prefix = candidate.len();
+    'o: for (i, c1) in candidate.char_indices() {
+        for chars in &mut others {
+            match chars.next() {
+                Some(c2) if c1 == c2 || c1.to_lowercase().eq(c2.to_lowercase()) => {}
+                _ => {
+                    longest_common_prefix = i;
+                    break 'o;
+                }
+            }
+        }
+    }
+    if longest_common_prefix == 0 && candidates.len() > 1 {
+        return None;
+    }
+    Some(&candidate[0..longest_common_prefix])
+}
+
+/// Tell if `candidate` starts with `prefix` regardless of the case: the
+/// chars are compared one by one (like `longest_common_prefix_ignore_case`).
+pub fn starts_with_ignore_case(candidate: &str, prefix: &str) -> bool {
+    let mut chars = candidate.chars();
+    prefix.chars().all(|c1| match chars.next() {
+        Some(c2) => c1 == c2 || c1.to_lowercase().eq(c2.to_lowercase()),
+        None => false,
+    })
+}
+
 #[derive(PartialEq)]
 enum ScanMode {
     DoubleQuote,
@@ -530,6 +1189,40 @@ mod tests {
         assert_eq!(Some("f"), lcp);
     }
 
+    #[test]
+    pub fn longest_common_prefix_ignore_case() {
+        let candidates: Vec<String> = vec![];
+        assert!(super::longest_common_prefix_ignore_case(&candidates).is_none());
+
+        let candidates = vec![String::from("README"), String::from("readme.txt")];
+        let lcp = super::longest_common_prefix_ignore_case(&candidates);
+        assert_eq!(Some("README"), lcp);
+        assert_eq!(
+            Some("R"),
+            super::longest_common_prefix(&[String::from("README"), String::from("Readme"),])
+        );
+
+        let candidates = vec![
+            String::from("ÉTÉ"),
+            String::from("étés"),
+            String::from("Étang"),
+        ];
+        let lcp = super::longest_common_prefix_ignore_case(&candidates);
+        assert_eq!(Some("ÉT"), lcp);
+
+        let candidates = vec![String::from("abc"), String::from("xyz")];
+        assert!(super::longest_common_prefix_ignore_case(&candidates).is_none());
+    }
+
+    #[test]
+    pub fn starts_with_ignore_case() {
+        assert!(super::starts_with_ignore_case("README", "read"));
+        assert!(super::starts_with_ignore_case("Étang", "ét"));
+        assert!(super::starts_with_ignore_case("abc", ""));
+        assert!(!super::starts_with_ignore_case("re", "read"));
+        assert!(!super::starts_with_ignore_case("xyz", "x y"));
+    }
+
     #[test]
     pub fn find_unclosed_quote() {
         assert_eq!(None, super::find_unclosed_quote("ls /etc"));
@@ -546,4 +1239,204 @@ mod tests {
             super::find_unclosed_quote("\"c:\\users\\All Users\\")
         )
     }
+
+    #[test]
+    pub fn case_insensitive() {
+        extern crate tempdir;
+        use super::{Candidate, Completer, FilenameCompleter};
+        use std::fs;
+        use std::path::MAIN_SEPARATOR;
+
+        let td = tempdir::TempDir::new("rustyline").unwrap();
+        fs::File::create(td.path().join("README.md")).unwrap();
+        fs::File::create(td.path().join("Été.txt")).unwrap();
+        let root = format!("{}{}", td.path().to_str().unwrap(), MAIN_SEPARATOR);
+        let mut completer = FilenameCompleter::new();
+        let line = format!("ls {}READ", root);
+        let (start, candidates) = completer.complete(&line, line.len()).unwrap();
+        assert_eq!(3, start);
+        assert_eq!(1, candidates.len());
+        let line = format!("ls {}read", root);
+        let (_, candidates) = completer.complete(&line, line.len()).unwrap();
+        assert!(candidates.is_empty());
+
+        completer.set_case_insensitive(true);
+        for &(typed, expected) in &[
+            ("read", "README.md"),
+            ("rEaDmE.", "README.md"),
+            ("été", "Été.txt"),
+        ] {
+            let line = format!("ls {}{}", root, typed);
+            let (start, candidates) = completer.complete(&line, line.len()).unwrap();
+            assert_eq!(3, start);
+            assert_eq!(1, candidates.len());
+            assert_eq!(expected, candidates[0].display());
+            assert_eq!(format!("{}{}", root, expected), candidates[0].replacement());
+        }
+    }
+
+    #[test]
+    pub fn expand_tilde() {
+        use std::path::MAIN_SEPARATOR;
+
+        let home = super::dirs::home_dir().unwrap();
+        let home = home.to_str().unwrap();
+        assert_eq!(home, super::expand("~"));
+        let dir = format!("~{}", MAIN_SEPARATOR);
+        assert_eq!(format!("{}{}", home, MAIN_SEPARATOR), super::expand(&dir));
+        // not a home directory
+        assert_eq!("a~", super::expand("a~"));
+        let unknown = format!("~rustyline_unknown_user{}", MAIN_SEPARATOR);
+        assert_eq!(unknown, super::expand(&unknown));
+
+        // completion in a fake home directory
+        #[cfg(unix)]
+        {
+            extern crate tempdir;
+            use super::{Candidate, Completer, FilenameCompleter};
+            use std::fs;
+
+            let td = tempdir::TempDir::new("rustyline").unwrap();
+            fs::create_dir(td.path().join("projects")).unwrap();
+            let home = td.path().to_str().unwrap();
+
+            let mut completer = FilenameCompleter::new();
+            completer.set_home_dir(Some(td.path().to_path_buf()));
+            let (start, candidates) = completer.complete("cd ~/pro", 8).unwrap();
+            assert_eq!(3, start);
+            assert_eq!(1, candidates.len());
+            assert_eq!("~/projects/", candidates[0].replacement());
+
+            completer.set_keep_unexpanded(false);
+            let (_, candidates) = completer.complete("cd ~/pro", 8).unwrap();
+            assert_eq!(format!("{}/projects/", home), candidates[0].replacement());
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    pub fn expand_var() {
+        extern crate tempdir;
+        use super::{Candidate, Completer, FilenameCompleter};
+        use std::borrow::Cow;
+        use std::env;
+        use std::fs;
+
+        match super::expand("$RUSTYLINE_UNDEFINED/x") {
+            Cow::Borrowed(path) => assert_eq!("$RUSTYLINE_UNDEFINED/x", path),
+            Cow::Owned(_) => panic!("unset variable expanded"),
+        }
+
+        let td = tempdir::TempDir::new("rustyline").unwrap();
+        fs::File::create(td.path().join("file")).unwrap();
+        let dir = td.path().to_str().unwrap();
+        env::set_var("RUSTYLINE_TEST_DIR", dir);
+        assert_eq!(format!("{}/x", dir), super::expand("$RUSTYLINE_TEST_DIR/x"));
+        assert_eq!(
+            format!("a{}x", dir),
+            super::expand("a${RUSTYLINE_TEST_DIR}x")
+        );
+
+        let completer = FilenameCompleter::new();
+        for &(typed, start) in &[
+            ("$RUSTYLINE_TEST_DIR/", 3),
+            ("${RUSTYLINE_TEST_DIR}/f", 3),
+            ("\"$RUSTYLINE_TEST_DIR/", 4),
+        ] {
+            let line = format!("ls {}", typed);
+            let (actual, candidates) = completer.complete(&line, line.len()).unwrap();
+            assert_eq!(start, actual);
+            assert_eq!(1, candidates.len());
+            // the variable is kept
+            let dir = line[start..].rsplitn(2, '/').last().unwrap();
+            assert_eq!(format!("{}/file", dir), candidates[0].replacement());
+        }
+    }
+
+    #[test]
+    pub fn ls_colors() {
+        extern crate tempdir;
+        use super::{Completer, FilenameCompleter, LsColors};
+        use config::CompletionType;
+        use highlight::Highlighter;
+        use std::fs;
+        use std::path::MAIN_SEPARATOR;
+
+        let td = tempdir::TempDir::new("rustyline").unwrap();
+        fs::create_dir(td.path().join("dir")).unwrap();
+        fs::File::create(td.path().join("d.tar")).unwrap();
+        fs::File::create(td.path().join("doc")).unwrap();
+        let line = format!("{}{}d", td.path().to_str().unwrap(), MAIN_SEPARATOR);
+        let mut completer = FilenameCompleter::new();
+        let (_, candidates) = completer.complete(&line, line.len()).unwrap();
+        assert_eq!(3, candidates.len());
+        for candidate in &candidates {
+            // not colorized by default
+            let highlighted = completer.highlight_candidate_item(candidate, CompletionType::List);
+            assert_eq!(candidate.display, highlighted);
+        }
+
+        completer.set_ls_colors(Some(LsColors::parse("di=01;34:*.tar=31")));
+        let (_, candidates) = completer.complete(&line, line.len()).unwrap();
+        for candidate in &candidates {
+            let expected = match candidate.display.as_str() {
+                "dir" => "\x1b[1m\x1b[34mdir\x1b[0m",
+                "d.tar" => "\x1b[31md.tar\x1b[0m",
+                _ => "doc",
+            };
+            let highlighted = completer.highlight_candidate_item(candidate, CompletionType::Menu);
+            assert_eq!(expected, highlighted);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "ignore")]
+    pub fn ignore() {
+        extern crate tempdir;
+        use super::{Candidate, Completer, FilenameCompleter};
+        use std::fs;
+        use std::path::{Path, MAIN_SEPARATOR};
+
+        fn complete(completer: &FilenameCompleter, dir: &Path) -> Vec<String> {
+            let line = format!("ls {}{}", dir.to_str().unwrap(), MAIN_SEPARATOR);
+            let (_, candidates) = completer.complete(&line, line.len()).unwrap();
+            let mut names: Vec<String> =
+                candidates.iter().map(|c| c.display().to_owned()).collect();
+            names.sort();
+            names
+        }
+
+        let td = tempdir::TempDir::new("rustyline").unwrap();
+        let sub = td.path().join("sub");
+        fs::create_dir(&sub).unwrap();
+        fs::write(td.path().join(".gitignore"), "*.log\n!keep.log\n").unwrap();
+        fs::write(sub.join(".ignore"), "tmp*\n").unwrap();
+        for name in &["a.log", "keep.log", "main.rs"] {
+            fs::File::create(td.path().join(name)).unwrap();
+        }
+        for name in &["b.log", "tmp.rs", "lib.rs"] {
+            fs::File::create(sub.join(name)).unwrap();
+        }
+
+        let completer = FilenameCompleter::new();
+        assert_eq!(5, complete(&completer, td.path()).len());
+        let completer = FilenameCompleter::new().with_ignore(true);
+        // outside of a repository, only the `.ignore` files apply
+        assert_eq!(
+            vec![".gitignore", "a.log", "keep.log", "main.rs", "sub"],
+            complete(&completer, td.path())
+        );
+        assert_eq!(
+            vec![".ignore", "b.log", "lib.rs"],
+            complete(&completer, &sub)
+        );
+
+        fs::create_dir(td.path().join(".git")).unwrap();
+        assert_eq!(
+            vec![".git", ".gitignore", "keep.log", "main.rs", "sub"],
+            complete(&completer, td.path())
+        );
+        // nested ignore file
+        assert_eq!(vec![".ignore", "lib.rs"], complete(&completer, &sub));
+    }
 }
@@ -0,0 +1,354 @@
+//! Fuzzy (subsequence) matching of the candidates
+use std::ops::Range;
+
+use super::{Candidate, Completer, Completion};
+use line_buffer::LineBuffer;
+use Result;
+
+/// Score of each matched char
+const MATCH_SCORE: i64 = 1;
+/// Bonus of a char matched at the start of a word
+const BOUNDARY_BONUS: i64 = 8;
+/// Bonus of a char matched right after the previous matched one
+const CONSECUTIVE_BONUS: i64 = 8;
+
+/// Matches `pattern` as a subsequence of `text`, regardless of the case.
+///
+/// Returns the score of the best match and the byte indices of the matched
+/// chars of `text`, or `None` if `text` does not contain all the chars of
+/// `pattern` in order.
+///
+/// The score is the sum, for each matched char, of:
+/// - 1,
+/// - 8 if it starts a word: it is the first char of `text`, or it follows a
+///   char which is not alphanumeric (like `_`, `-` or `/`), or it is an
+///   uppercase letter following a lowercase one (camelCase),
+/// - 8 if it immediately follows the previous matched char.
+///
+/// When several matches have the same best score, the leftmost one is
+/// returned.
+///
+/// ```
+/// use rustyline::completion::fuzzy_match;
+///
+/// assert_eq!(Some((27, vec![0, 4, 11])), fuzzy_match("gsm", "get_struct_member"));
+/// assert_eq!(None, fuzzy_match("gsm", "get_member"));
+/// ```
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<(i64, Vec<usize>)> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    if pattern.is_empty() {
+        return Some((0, Vec::new()));
+    }
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    if chars.len() < pattern.len() {
+        return None;
+    }
+    let bonus: Vec<i64> = (0..chars.len())
+        .map(|j| {
+            let c = chars[j].1;
+            let boundary = j == 0 || {
+                let prev = chars[j - 1].1;
+                !prev.is_alphanumeric() || (prev.is_lowercase() && c.is_uppercase())
+            };
+            if boundary {
+                MATCH_SCORE + BOUNDARY_BONUS
+            } else {
+                MATCH_SCORE
+            }
+        })
+        .collect();
+    // scores[i][j]: best score of `pattern[..=i]` with `pattern[i]` matched at
+    // `chars[j]` (`None` when impossible); from[i][j]: where `pattern[i - 1]`
+    // is matched then
+    let mut scores: Vec<Vec<Option<i64>>> = Vec::with_capacity(pattern.len());
+    let mut from: Vec<Vec<usize>> = Vec::with_capacity(pattern.len());
+    for (i, &p) in pattern.iter().enumerate() {
+        let mut row = vec![None; chars.len()];
+        let mut row_from = vec![0; chars.len()];
+        // best score (and its position) of the previous row before `j - 1`
+        let mut best: Option<(i64, usize)> = None;
+        for j in 0..chars.len() {
+            if i > 0 && j >= 2 {
+                if let Some(score) = scores[i - 1][j - 2] {
+                    if best.map_or(true, |(b, _)| score > b) {
+                        best = Some((score, j - 2));
+                    }
+                }
+            }
+            if !eq_ignore_case(p, chars[j].1) {
+                continue;
+            }
+            if i == 0 {
+                row[j] = Some(bonus[j]);
+                continue;
+            }
+            let consecutive = if j >= 1 {
+                scores[i - 1][j - 1].map(|score| (score + CONSECUTIVE_BONUS, j - 1))
+            } else {
+                None
+            };
+            let candidates = [best, consecutive];
+            let prev =
+                candidates
+                    .iter()
+                    .filter_map(|&c| c)
+                    .fold(None, |acc: Option<(i64, usize)>, c| match acc {
+                        Some(a) if a.0 > c.0 || (a.0 == c.0 && a.1 < c.1) => Some(a),
+                        _ => Some(c),
+                    });
+            if let Some((score, k)) = prev {
+                row[j] = Some(score + bonus[j]);
+                row_from[j] = k;
+            }
+        }
+        scores.push(row);
+        from.push(row_from);
+    }
+    // best end, leftmost on ties
+    let last = pattern.len() - 1;
+    let mut end: Option<(i64, usize)> = None;
+    for (j, score) in scores[last].iter().enumerate() {
+        if let Some(score) = *score {
+            if end.map_or(true, |(b, _)| score > b) {
+                end = Some((score, j));
+            }
+        }
+    }
+    end.map(|(score, mut j)| {
+        let mut indices = vec![0; pattern.len()];
+        for i in (0..pattern.len()).rev() {
+            indices[i] = chars[j].0;
+            j = from[i][j];
+        }
+        (score, indices)
+    })
+}
+
+fn eq_ignore_case(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+/// A candidate matched by `FuzzyCompleter`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FuzzyCandidate<C> {
+    /// Candidate of the wrapped completer
+    pub candidate: C,
+    /// Score of the match (see `fuzzy_match`)
+    pub score: i64,
+    /// Byte indices of the matched chars in the `display` text
+    pub indices: Vec<usize>,
+}
+
+impl<C: Candidate> Candidate for FuzzyCandidate<C> {
+    fn display(&self) -> &str {
+        self.candidate.display()
+    }
+
+    fn replacement(&self) -> &str {
+        self.candidate.replacement()
+    }
+
+    fn documentation(&self) -> Option<&str> {
+        self.candidate.documentation()
+    }
+
+    fn match_indices(&self) -> &[usize] {
+        &self.indices
+    }
+
+    fn kind(&self) -> Option<&str> {
+        self.candidate.kind()
+    }
+
+    fn description(&self) -> Option<&str> {
+        self.candidate.description()
+    }
+}
+
+/// Filters the candidates of the wrapped completer with fuzzy matching (see
+/// `fuzzy_match`): typing `gsm` matches `get_struct_member`.
+///
+/// The word typed (from the start position returned by the wrapped completer
+/// up to the cursor) is matched against the `display` of each candidate, so
+/// the wrapped completer should not filter its candidates by prefix.
+/// The candidates are sorted best-first: by descending score, then by
+/// shorter display, then in their original order (the `sort` of the wrapped
+/// completer is not applied).
+///
+/// The matched chars are displayed in bold by `highlight_candidate_item` (see
+/// `Candidate::match_indices`).
+///
+/// `Configurer::set_fuzzy_completion` wraps the completer of the helper.
+pub struct FuzzyCompleter<C> {
+    completer: C,
+    max_results: Option<usize>,
+}
+
+impl<C: Completer> FuzzyCompleter<C> {
+    pub fn new(completer: C) -> FuzzyCompleter<C> {
+        FuzzyCompleter {
+            completer,
+            max_results: None,
+        }
+    }
+
+    /// Keeps only the `max` best candidates (to keep huge lists fast).
+    ///
+    /// By default, `None`: all the matching candidates are kept.
+    pub fn set_max_results(&mut self, max: Option<usize>) {
+        self.max_results = max;
+    }
+}
+
+impl<C: Completer> Completer for FuzzyCompleter<C> {
+    type Candidate = FuzzyCandidate<C::Candidate>;
+
+    fn complete(&self, line: &str, pos: usize) -> Result<(usize, Vec<Self::Candidate>)> {
+        let (range, candidates) = try!(self.complete_range(line, pos));
+        Ok((range.start, candidates))
+    }
+
+    fn complete_range(
+        &self,
+        line: &str,
+        pos: usize,
+    ) -> Result<(Range<usize>, Vec<Self::Candidate>)> {
+        let completion = try!(self.complete_advanced(line, pos));
+        Ok((completion.start..completion.end, completion.candidates))
+    }
+
+    fn complete_advanced(&self, line: &str, pos: usize) -> Result<Completion<Self::Candidate>> {
+        let Completion {
+            start,
+            end,
+            candidates,
+            replace_whole_line,
+        } = try!(self.completer.complete_advanced(line, pos));
+        // the word typed is before the cursor (at `end` in a new line)
+        let (line, pos) = match replace_whole_line {
+            Some(ref new_line) => (new_line.as_str(), end),
+            None => (line, pos),
+        };
+        let pattern = line.get(start..pos).unwrap_or("");
+        let mut matched: Vec<Self::Candidate> = candidates
+            .into_iter()
+            .filter_map(|candidate| {
+                fuzzy_match(pattern, candidate.display()).map(|(score, indices)| FuzzyCandidate {
+                    candidate,
+                    score,
+                    indices,
+                })
+            })
+            .collect();
+        // stable sort
+        matched.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then(a.display().len().cmp(&b.display().len()))
+        });
+        if let Some(max) = self.max_results {
+            matched.truncate(max);
+        }
+        Ok(Completion {
+            start,
+            end,
+            candidates: matched,
+            replace_whole_line,
+        })
+    }
+
+    fn update(&self, line: &mut LineBuffer, start: usize, elected: &str) {
+        self.completer.update(line, start, elected)
+    }
+
+    fn is_partial(&self, candidate: &Self::Candidate) -> bool {
+        self.completer.is_partial(&candidate.candidate)
+    }
+
+    // the refinement is not filtered (nor highlighted)
+    fn refine(&self, selected: &Self::Candidate) -> Vec<Self::Candidate> {
+        self.completer
+            .refine(&selected.candidate)
+            .into_iter()
+            .map(|candidate| FuzzyCandidate {
+                candidate,
+                score: 0,
+                indices: Vec::new(),
+            })
+            .collect()
+    }
+
+    fn begin_completion(&self) {
+        self.completer.begin_completion()
+    }
+
+    fn end_completion(&self) {
+        self.completer.end_completion()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fuzzy_match, FuzzyCompleter};
+    use completion::{Candidate, Completer};
+    use config::CompletionType;
+    use highlight::Highlighter;
+    use Result;
+
+    #[test]
+    fn score() {
+        assert_eq!(Some((0, vec![])), fuzzy_match("", "abc"));
+        assert_eq!(None, fuzzy_match("abcd", "abc"));
+        assert_eq!(None, fuzzy_match("ba", "abc"));
+        // word boundaries are preferred to the leftmost chars
+        assert_eq!(Some((27, vec![0, 4, 8])), fuzzy_match("gsm", "gas_sum_max"));
+        // prefix
+        assert_eq!(Some((18, vec![0, 1])), fuzzy_match("GE", "get"));
+        // camelCase
+        assert_eq!(Some((18, vec![0, 3])), fuzzy_match("gm", "getMember"));
+        // multi-byte chars
+        assert_eq!(Some((27, vec![0, 2, 3])), fuzzy_match("été", "Été"));
+    }
+
+    struct Words;
+
+    impl Completer for Words {
+        type Candidate = String;
+
+        fn complete(&self, line: &str, _pos: usize) -> Result<(usize, Vec<String>)> {
+            let words = [
+                "get_member",
+                "get_struct_member",
+                "gsm",
+                "set_struct_member",
+            ];
+            let start = line.rfind(' ').map_or(0, |i| i + 1);
+            Ok((start, words.iter().map(|&w| w.to_owned()).collect()))
+        }
+    }
+
+    #[test]
+    fn sorted() {
+        let mut completer = FuzzyCompleter::new(Words);
+        let (start, candidates) = completer.complete("x gsm", 5).unwrap();
+        assert_eq!(2, start);
+        let displays: Vec<&str> = candidates.iter().map(|c| c.display()).collect();
+        assert_eq!(vec!["gsm", "get_struct_member"], displays);
+        assert_eq!(
+            "\x1b[1mg\x1b[0met_\x1b[1ms\x1b[0mtruct_\x1b[1mm\x1b[0member",
+            ().highlight_candidate_item(&candidates[1], CompletionType::List)
+        );
+        assert_eq!(
+            "\x1b[1mgsm\x1b[0m",
+            ().highlight_candidate_item(&candidates[0], CompletionType::List)
+        );
+
+        completer.set_max_results(Some(1));
+        let (_, candidates) = completer.complete("x gsm", 5).unwrap();
+        assert_eq!(1, candidates.len());
+        // all the candidates match an empty word
+        completer.set_max_results(None);
+        let (_, candidates) = completer.complete("x ", 2).unwrap();
+        assert_eq!(4, candidates.len());
+    }
+}
@@ -0,0 +1,218 @@
+//! File names colorized like `ls` (with `LS_COLORS`)
+use std::collections::HashMap;
+use std::env;
+use std::fs::Metadata;
+
+use anstyle::{Ansi256Color, AnsiColor, Color, Effects, RgbColor, Style};
+
+/// Styles of the file names by type or by extension, parsed from the
+/// `LS_COLORS` syntax (like `di=01;34:ex=01;32:*.tar=01;31`).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LsColors {
+    // by file type (`di`, `ln`, `ex`, ...)
+    types: HashMap<String, Style>,
+    // by file name suffix (`*.tar`)
+    suffixes: Vec<(String, Style)>,
+}
+
+impl LsColors {
+    /// Parses the `LS_COLORS` environment variable.
+    /// Returns `None` if it is unset or empty.
+    pub fn from_env() -> Option<LsColors> {
+        match env::var("LS_COLORS") {
+            Ok(ref spec) if !spec.is_empty() => Some(LsColors::parse(spec)),
+            _ => None,
+        }
+    }
+
+    /// Parses `spec` like `LS_COLORS`: malformed entries are ignored.
+    pub fn parse(spec: &str) -> LsColors {
+        let mut ls_colors = LsColors::default();
+        for entry in spec.split(':') {
+            let mut parts = entry.splitn(2, '=');
+            let (key, codes) = match (parts.next(), parts.next()) {
+                (Some(key), Some(codes)) if !key.is_empty() => (key, codes),
+                _ => continue,
+            };
+            let style = parse_sgr(codes);
+            if key.starts_with('*') {
+                ls_colors.suffixes.push((key[1..].to_owned(), style));
+            } else {
+                ls_colors.types.insert(key.to_owned(), style);
+            }
+        }
+        ls_colors
+    }
+
+    /// Returns the style of the file `name`, from its `metadata` (not
+    /// following symbolic links) and from the metadata of its `target` (`None`
+    /// when the symbolic link is broken).
+    pub fn style(
+        &self,
+        name: &str,
+        metadata: &Metadata,
+        target: Option<&Metadata>,
+    ) -> Option<Style> {
+        let file_type = metadata.file_type();
+        let key = if file_type.is_symlink() {
+            if target.is_some() {
+                "ln"
+            } else {
+                "or"
+            }
+        } else if file_type.is_dir() {
+            "di"
+        } else if let Some(key) = special_type(metadata) {
+            key
+        } else if is_executable(metadata) && self.types.contains_key("ex") {
+            "ex"
+        } else {
+            // regular file
+            let suffix = self
+                .suffixes
+                .iter()
+                .find(|&&(ref suffix, _)| name.ends_with(suffix.as_str()));
+            if let Some(&(_, style)) = suffix {
+                return Some(style);
+            }
+            "fi"
+        };
+        self.types.get(key).cloned()
+    }
+}
+
+#[cfg(unix)]
+fn special_type(metadata: &Metadata) -> Option<&'static str> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let file_type = metadata.file_type();
+    if file_type.is_fifo() {
+        Some("pi")
+    } else if file_type.is_socket() {
+        Some("so")
+    } else if file_type.is_block_device() {
+        Some("bd")
+    } else if file_type.is_char_device() {
+        Some("cd")
+    } else {
+        None
+    }
+}
+
+#[cfg(windows)]
+fn special_type(_metadata: &Metadata) -> Option<&'static str> {
+    None
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(windows)]
+fn is_executable(_metadata: &Metadata) -> bool {
+    false
+}
+
+/// Parses the SGR parameters `codes` (like `01;34` or `38;5;208`).
+/// Unsupported parameters are ignored.
+fn parse_sgr(codes: &str) -> Style {
+    let codes: Vec<u8> = codes.split(';').filter_map(|c| c.parse().ok()).collect();
+    let mut style = Style::new();
+    let mut i = 0;
+    while i < codes.len() {
+        let code = codes[i];
+        i += 1;
+        style = match code {
+            0 => Style::new(),
+            1 => style.bold(),
+            2 => style.dimmed(),
+            3 => style.italic(),
+            4 => style.underline(),
+            5 => style.blink(),
+            7 => style.invert(),
+            8 => style.effects(style.get_effects() | Effects::HIDDEN),
+            9 => style.strikethrough(),
+            30..=37 => style.fg_color(ansi_color(code - 30, false)),
+            90..=97 => style.fg_color(ansi_color(code - 90, true)),
+            40..=47 => style.bg_color(ansi_color(code - 40, false)),
+            100..=107 => style.bg_color(ansi_color(code - 100, true)),
+            38 | 48 => {
+                let color = match (codes.get(i), codes.get(i + 1)) {
+                    (Some(&5), Some(&n)) => {
+                        i += 2;
+                        Some(Color::Ansi256(Ansi256Color(n)))
+                    }
+                    (Some(&2), _) if i + 3 < codes.len() => {
+                        let (r, g, b) = (codes[i + 1], codes[i + 2], codes[i + 3]);
+                        i += 4;
+                        Some(Color::Rgb(RgbColor(r, g, b)))
+                    }
+                    _ => None,
+                };
+                if code == 38 {
+                    style.fg_color(color)
+                } else {
+                    style.bg_color(color)
+                }
+            }
+            _ => style,
+        };
+    }
+    style
+}
+
+fn ansi_color(n: u8, bright: bool) -> Option<Color> {
+    let color = match n {
+        0 => AnsiColor::Black,
+        1 => AnsiColor::Red,
+        2 => AnsiColor::Green,
+        3 => AnsiColor::Yellow,
+        4 => AnsiColor::Blue,
+        5 => AnsiColor::Magenta,
+        6 => AnsiColor::Cyan,
+        _ => AnsiColor::White,
+    };
+    Some(Color::Ansi(if bright { color.bright(true) } else { color }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_sgr, LsColors};
+    use anstyle::{Ansi256Color, AnsiColor, Color, RgbColor, Style};
+
+    #[test]
+    fn sgr() {
+        let blue = Style::new().bold().fg_color(Some(AnsiColor::Blue.into()));
+        assert_eq!(blue, parse_sgr("01;34"));
+        assert_eq!(
+            Style::new().fg_color(Some(Color::Ansi256(Ansi256Color(208)))),
+            parse_sgr("38;5;208")
+        );
+        assert_eq!(
+            Style::new().bg_color(Some(Color::Rgb(RgbColor(1, 2, 3)))),
+            parse_sgr("48;2;1;2;3")
+        );
+        assert_eq!(
+            Style::new().fg_color(Some(AnsiColor::BrightRed.into())),
+            parse_sgr("91")
+        );
+        assert_eq!(Style::new(), parse_sgr("01;0;x"));
+    }
+
+    #[test]
+    fn parse() {
+        let ls_colors = LsColors::parse("di=01;34:bogus:=1:*.tar=31");
+        assert_eq!(1, ls_colors.types.len());
+        assert_eq!(
+            Some(&Style::new().bold().fg_color(Some(AnsiColor::Blue.into()))),
+            ls_colors.types.get("di")
+        );
+        assert_eq!(
+            vec![(".tar".to_owned(), parse_sgr("31"))],
+            ls_colors.suffixes
+        );
+    }
+}
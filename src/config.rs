@@ -1,5 +1,6 @@
 //! Customize line editor
 use std::default::Default;
+use std::time::Duration;
 
 /// User preferences
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -8,10 +9,31 @@ pub struct Config {
     max_history_size: usize, // history_max_entries
     history_duplicates: HistoryDuplicates,
     history_ignore_space: bool,
+    /// Maximum number of history entries examined by the incremental search
+    /// per keystroke (0 for no limit).
+    search_scan_limit: usize,
     completion_type: CompletionType,
     /// When listing completion alternatives, only display
     /// one screen of possibilities at a time.
     completion_prompt_limit: usize,
+    /// Number of rows of the completion list displayed before `--More--`
+    /// (0 for one screen).
+    completion_page_size: usize,
+    /// Set of the (ASCII) characters which trigger completion when typed.
+    completion_triggers: u128,
+    /// Duration after which the candidates of a slow completer are
+    /// discarded (once it has returned).
+    completion_timeout: Option<Duration>,
+    /// Maximum number of rows of the documentation of the selected
+    /// candidate (0 to disable).
+    completion_docs_height: usize,
+    /// Whether the candidates are filtered and their common prefix computed
+    /// regardless of the case.
+    completion_ignore_case: bool,
+    /// Whether the candidates are filtered and ranked by fuzzy matching.
+    fuzzy_completion: bool,
+    /// Order of the completion candidates.
+    completion_sort: CompletionSort,
     /// Duration (milliseconds) Rustyline will wait for a character when
     /// reading an ambiguous key sequence.
     keyseq_timeout: i32,
@@ -22,6 +44,22 @@ pub struct Config {
     auto_add_history: bool,
     /// if colors should be enabled.
     color_mode: ColorMode,
+    /// Number of colors supported by the terminal (`None` to detect it).
+    color_depth: Option<ColorDepth>,
+    /// How control characters in the line are displayed.
+    control_char_rendering: ControlCharRender,
+    /// Display a line number gutter before each line of the input.
+    line_numbers: bool,
+    /// Move the cursor faster when a motion key is held down.
+    cursor_acceleration: bool,
+    /// Title of the terminal set when a line is accepted.
+    update_title: Option<TitleTemplate>,
+    /// Ignore Ctrl-D on a non-empty line.
+    ignore_eof_nonempty: bool,
+    /// Keep the final newline of an accepted line whose last line is empty.
+    keep_trailing_newline: bool,
+    /// Enable the bracketed paste mode of the terminal.
+    bracketed_paste: bool,
 }
 
 impl Config {
@@ -38,14 +76,18 @@ impl Config {
         self.max_history_size = max_size;
     }
 
-    /// Tell if lines which match the previous history entry are saved or not
-    /// in the history list.
+    /// Tell if lines which match the previous history entry (or any entry)
+    /// are saved or not in the history list.
     ///
-    /// By default, they are ignored.
+    /// By default, the lines matching the previous entry are ignored.
     pub fn history_duplicates(&self) -> HistoryDuplicates {
         self.history_duplicates
     }
 
+    pub(crate) fn set_history_duplicates(&mut self, duplicates: HistoryDuplicates) {
+        self.history_duplicates = duplicates;
+    }
+
     pub(crate) fn set_history_ignore_dups(&mut self, yes: bool) {
         self.history_duplicates = if yes {
             HistoryDuplicates::IgnoreConsecutive
@@ -66,6 +108,14 @@ impl Config {
         self.history_ignore_space = yes;
     }
 
+    /// Tell how many history entries (at most) the incremental search
+    /// examines per keystroke, most recent first.
+    ///
+    /// By default, 0: there is no limit.
+    pub fn search_scan_limit(&self) -> usize {
+        self.search_scan_limit
+    }
+
     pub fn completion_type(&self) -> CompletionType {
         self.completion_type
     }
@@ -74,10 +124,78 @@ impl Config {
         self.completion_prompt_limit
     }
 
+    /// Tell how many rows of the completion list (`CompletionType::List`)
+    /// are displayed before pausing with `--More--`.
+    ///
+    /// By default, 0: one screen at a time.
+    pub fn completion_page_size(&self) -> usize {
+        self.completion_page_size
+    }
+
+    /// Tell if typing `c` triggers completion (as if Tab were pressed).
+    ///
+    /// By default, no character does.
+    pub fn is_completion_trigger(&self, c: char) -> bool {
+        c.is_ascii() && self.completion_triggers & (1u128 << c as u32) != 0
+    }
+
+    pub(crate) fn set_completion_triggers(&mut self, chars: &str) {
+        self.completion_triggers = chars
+            .chars()
+            .filter(char::is_ascii)
+            .fold(0, |set, c| set | 1u128 << c as u32);
+    }
+
+    /// Tell how long the completer may run before its candidates are
+    /// discarded.
+    ///
+    /// The completer is not interrupted: the timeout only discards the
+    /// candidates it returns late (see `Configurer::set_completion_timeout`).
+    /// By default, there is no timeout.
+    pub fn completion_timeout(&self) -> Option<Duration> {
+        self.completion_timeout
+    }
+
+    /// Tell how many rows (at most) of the documentation of the selected
+    /// candidate are displayed below the line.
+    ///
+    /// By default, 0: the documentation is not displayed.
+    pub fn completion_docs_height(&self) -> usize {
+        self.completion_docs_height
+    }
+
+    /// Tell if the candidates are filtered and their common prefix computed
+    /// regardless of the case (like readline's `completion-ignore-case`).
+    ///
+    /// By default, `false`.
+    pub fn completion_ignore_case(&self) -> bool {
+        self.completion_ignore_case
+    }
+
+    /// Tell if the candidates are filtered and ranked by fuzzy matching (see
+    /// `completion::FuzzyCompleter`).
+    ///
+    /// By default, `false`.
+    pub fn fuzzy_completion(&self) -> bool {
+        self.fuzzy_completion
+    }
+
+    /// Tell how the completion candidates are sorted (before
+    /// `Completer::sort`).
+    ///
+    /// By default, `CompletionSort::Unsorted`.
+    pub fn completion_sort(&self) -> CompletionSort {
+        self.completion_sort
+    }
+
     pub fn keyseq_timeout(&self) -> i32 {
         self.keyseq_timeout
     }
 
+    pub(crate) fn set_keyseq_timeout(&mut self, keyseq_timeout_ms: i32) {
+        self.keyseq_timeout = keyseq_timeout_ms;
+    }
+
     pub fn edit_mode(&self) -> EditMode {
         self.edit_mode
     }
@@ -99,6 +217,68 @@ impl Config {
     pub(crate) fn set_color_mode(&mut self, color_mode: ColorMode) {
         self.color_mode = color_mode;
     }
+
+    /// Tell the number of colors the styles are downsampled to.
+    ///
+    /// By default, `None`: it is detected from `$COLORTERM` and `$TERM`.
+    pub fn color_depth(&self) -> Option<ColorDepth> {
+        self.color_depth
+    }
+
+    /// Tell how control characters in the line are displayed.
+    ///
+    /// By default, with the caret notation (`^A`).
+    pub fn control_char_rendering(&self) -> ControlCharRender {
+        self.control_char_rendering
+    }
+
+    /// Tell if a line number gutter is displayed before each line of the
+    /// input.
+    ///
+    /// By default, it is not.
+    pub fn line_numbers(&self) -> bool {
+        self.line_numbers
+    }
+
+    /// Tell if the cursor moves faster when a motion key is held down.
+    ///
+    /// By default, it does not.
+    pub fn cursor_acceleration(&self) -> bool {
+        self.cursor_acceleration
+    }
+
+    /// Tell how the title of the terminal is set when a line is accepted.
+    ///
+    /// By default, it is not set.
+    pub fn update_title(&self) -> Option<TitleTemplate> {
+        self.update_title
+    }
+
+    /// Tell if Ctrl-D is ignored on a non-empty line.
+    ///
+    /// By default, it is not.
+    pub fn ignore_eof_nonempty(&self) -> bool {
+        self.ignore_eof_nonempty
+    }
+
+    /// Tell if the final newline of an accepted line is kept when its last
+    /// line is empty.
+    ///
+    /// By default, it is kept.
+    pub fn keep_trailing_newline(&self) -> bool {
+        self.keep_trailing_newline
+    }
+
+    /// Tell if the bracketed paste mode of the terminal is enabled.
+    ///
+    /// By default, it is.
+    pub fn bracketed_paste(&self) -> bool {
+        self.bracketed_paste
+    }
+
+    pub(crate) fn set_bracketed_paste(&mut self, yes: bool) {
+        self.bracketed_paste = yes;
+    }
 }
 
 impl Default for Config {
@@ -107,12 +287,28 @@ impl Default for Config {
             max_history_size: 100,
             history_duplicates: HistoryDuplicates::IgnoreConsecutive,
             history_ignore_space: false,
+            search_scan_limit: 0,
             completion_type: CompletionType::Circular, // TODO Validate
             completion_prompt_limit: 100,
+            completion_page_size: 0,
+            completion_triggers: 0,
+            completion_timeout: None,
+            completion_docs_height: 0,
+            completion_ignore_case: false,
+            fuzzy_completion: false,
+            completion_sort: CompletionSort::Unsorted,
             keyseq_timeout: -1,
             edit_mode: EditMode::Emacs,
             auto_add_history: false,
             color_mode: ColorMode::Enabled,
+            color_depth: None,
+            control_char_rendering: ControlCharRender::Caret,
+            line_numbers: false,
+            cursor_acceleration: false,
+            update_title: None,
+            ignore_eof_nonempty: false,
+            keep_trailing_newline: true,
+            bracketed_paste: true,
         }
     }
 }
@@ -122,6 +318,15 @@ pub enum HistoryDuplicates {
     AlwaysAdd,
     /// a line will not be added to the history if it matches the previous entry
     IgnoreConsecutive,
+    /// the entries matching a new line are removed from the history: a line
+    /// entered again moves to the most recent position
+    IgnoreAll,
+}
+
+impl Default for HistoryDuplicates {
+    fn default() -> HistoryDuplicates {
+        HistoryDuplicates::IgnoreConsecutive
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -132,6 +337,25 @@ pub enum CompletionType {
     /// When more than one match, list all matches
     /// (like in Bash/Readline).
     List,
+    /// List all matches and select one with the arrow keys (or Tab):
+    /// Enter inserts the selected candidate and Esc cancels.
+    Menu,
+    /// Same as `List` but the matches are listed one per row, with their
+    /// description (see `Candidate::description`) aligned in a second
+    /// column.
+    ListWithDescription,
+}
+
+/// Order of the completion candidates
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompletionSort {
+    /// As returned by the completer (best-first with the fuzzy completion)
+    Unsorted,
+    /// By `display` text
+    Alphabetical,
+    /// Grouped by kind (alphabetically sorted, the candidates without kind
+    /// last), in the order of the completer within a group
+    Kind,
 }
 
 /// Style of editing / Standard keymaps
@@ -149,6 +373,37 @@ pub enum ColorMode {
     Disabled,
 }
 
+/// Number of colors supported by the terminal
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorDepth {
+    /// 16 colors (like `TERM=linux`)
+    Ansi16,
+    /// 256 colors (like `TERM=xterm-256color`)
+    Ansi256,
+    /// 24-bit RGB colors (like `COLORTERM=truecolor`)
+    TrueColor,
+}
+
+/// Visible representation of control characters
+/// (the line buffer keeps the real characters).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ControlCharRender {
+    /// `^A`
+    Caret,
+    /// `<0x01>`
+    Hex,
+}
+
+/// Title of the terminal set when a line is accepted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TitleTemplate {
+    /// A fixed title, like the name of the application
+    Static(&'static str),
+    /// The first word of the line, like the command run by a shell (the
+    /// title is kept when the line is blank)
+    FirstWord,
+}
+
 /// Configuration builder
 #[derive(Debug, Default)]
 pub struct Builder {
@@ -177,6 +432,14 @@ impl Builder {
         self
     }
 
+    /// Set the policy for the lines which match history entries.
+    ///
+    /// By default, `HistoryDuplicates::IgnoreConsecutive`.
+    pub fn history_duplicates(mut self, duplicates: HistoryDuplicates) -> Builder {
+        self.set_history_duplicates(duplicates);
+        self
+    }
+
     /// Tell if lines which begin with a space character are saved or not in
     /// the history list.
     ///
@@ -186,6 +449,17 @@ impl Builder {
         self
     }
 
+    /// Limit the number of history entries examined by the incremental
+    /// search per keystroke (to stay responsive with huge histories): the
+    /// prompt tells when the search has been cut off and the next
+    /// `Ctrl-R` (`Ctrl-S`) resumes it.
+    ///
+    /// By default, 0: there is no limit.
+    pub fn search_scan_limit(mut self, limit: usize) -> Builder {
+        self.set_search_scan_limit(limit);
+        self
+    }
+
     /// Set `completion_type`.
     pub fn completion_type(mut self, completion_type: CompletionType) -> Builder {
         self.set_completion_type(completion_type);
@@ -199,6 +473,68 @@ impl Builder {
         self
     }
 
+    /// The number of rows of the completion list displayed at a time: Space
+    /// displays the next page and `q` cancels (see
+    /// `Configurer::set_completion_page_size`).
+    pub fn completion_page_size(mut self, page_size: usize) -> Builder {
+        self.set_completion_page_size(page_size);
+        self
+    }
+
+    /// Characters which trigger completion when typed (like `.` after an
+    /// object name).
+    ///
+    /// By default, only Tab triggers completion.
+    pub fn completion_triggers(mut self, chars: &str) -> Builder {
+        self.set_completion_triggers(chars);
+        self
+    }
+
+    /// Give up on the candidates of a completer running longer than
+    /// `timeout`.
+    ///
+    /// The completer is not interrupted: the timeout only discards the
+    /// candidates it returns late (see `Configurer::set_completion_timeout`).
+    /// By default, there is no timeout.
+    pub fn completion_timeout(mut self, timeout: Duration) -> Builder {
+        self.set_completion_timeout(timeout);
+        self
+    }
+
+    /// Display (at most `rows` of) the documentation of the selected
+    /// candidate below the line.
+    ///
+    /// By default, the documentation is not displayed.
+    pub fn completion_docs_height(mut self, rows: usize) -> Builder {
+        self.set_completion_docs_height(rows);
+        self
+    }
+
+    /// Whether the candidates are filtered and their common prefix computed
+    /// regardless of the case.
+    ///
+    /// By default, the case matters.
+    pub fn completion_ignore_case(mut self, yes: bool) -> Builder {
+        self.set_completion_ignore_case(yes);
+        self
+    }
+
+    /// Whether the candidates are filtered and ranked by fuzzy matching.
+    ///
+    /// By default, they are not.
+    pub fn fuzzy_completion(mut self, yes: bool) -> Builder {
+        self.set_fuzzy_completion(yes);
+        self
+    }
+
+    /// How the completion candidates are sorted.
+    ///
+    /// By default, they are kept in the order of the completer.
+    pub fn completion_sort(mut self, sort: CompletionSort) -> Builder {
+        self.set_completion_sort(sort);
+        self
+    }
+
     /// Timeout for ambiguous key sequences in milliseconds.
     /// Currently, it is used only to distinguish a single ESC from an ESC
     /// sequence.
@@ -231,6 +567,73 @@ impl Builder {
         self
     }
 
+    /// Overrides the number of colors supported by the terminal: the colors
+    /// of the styles are downsampled to it (`None` to detect it).
+    ///
+    /// By default, it is detected from `$COLORTERM` and `$TERM`.
+    pub fn color_depth(mut self, depth: Option<ColorDepth>) -> Builder {
+        self.set_color_depth(depth);
+        self
+    }
+
+    /// Choose how control characters in the line are displayed.
+    ///
+    /// By default, with the caret notation (`^A`).
+    pub fn control_char_rendering(mut self, rendering: ControlCharRender) -> Builder {
+        self.set_control_char_rendering(rendering);
+        self
+    }
+
+    /// Display a line number gutter (like ` 9 | `) before each line of a
+    /// multi-line input.
+    ///
+    /// By default, it is not displayed.
+    pub fn line_numbers(mut self, yes: bool) -> Builder {
+        self.set_line_numbers(yes);
+        self
+    }
+
+    /// Move the cursor faster when a motion key (like Left or Right) is held
+    /// down.
+    ///
+    /// By default, it does not.
+    pub fn cursor_acceleration(mut self, yes: bool) -> Builder {
+        self.set_cursor_acceleration(yes);
+        self
+    }
+
+    /// Set the title of the terminal when a line is accepted.
+    ///
+    /// By default, it is not set.
+    pub fn update_title(mut self, template: Option<TitleTemplate>) -> Builder {
+        self.set_update_title(template);
+        self
+    }
+
+    /// Ignore Ctrl-D on a non-empty line.
+    ///
+    /// By default, it is not ignored.
+    pub fn ignore_eof_nonempty(mut self, yes: bool) -> Builder {
+        self.set_ignore_eof_nonempty(yes);
+        self
+    }
+
+    /// Keep the final newline of an accepted line whose last line is empty.
+    ///
+    /// By default, it is kept.
+    pub fn keep_trailing_newline(mut self, yes: bool) -> Builder {
+        self.set_keep_trailing_newline(yes);
+        self
+    }
+
+    /// Enable the bracketed paste mode of the terminal.
+    ///
+    /// By default, it is enabled.
+    pub fn bracketed_paste(mut self, yes: bool) -> Builder {
+        self.set_bracketed_paste(yes);
+        self
+    }
+
     pub fn build(self) -> Config {
         self.p
     }
@@ -258,6 +661,13 @@ pub trait Configurer {
         self.config_mut().set_history_ignore_dups(yes);
     }
 
+    /// Set the policy for the lines which match history entries.
+    ///
+    /// By default, `HistoryDuplicates::IgnoreConsecutive`.
+    fn set_history_duplicates(&mut self, duplicates: HistoryDuplicates) {
+        self.config_mut().set_history_duplicates(duplicates);
+    }
+
     /// Tell if lines which begin with a space character are saved or not in
     /// the history list.
     ///
@@ -265,6 +675,16 @@ pub trait Configurer {
     fn set_history_ignore_space(&mut self, yes: bool) {
         self.config_mut().set_history_ignore_space(yes);
     }
+
+    /// Limit the number of history entries examined by the incremental
+    /// search per keystroke (to stay responsive with huge histories): the
+    /// prompt tells when the search has been cut off and the next
+    /// `Ctrl-R` (`Ctrl-S`) resumes it.
+    ///
+    /// By default, 0: there is no limit.
+    fn set_search_scan_limit(&mut self, limit: usize) {
+        self.config_mut().search_scan_limit = limit;
+    }
     /// Set `completion_type`.
     fn set_completion_type(&mut self, completion_type: CompletionType) {
         self.config_mut().completion_type = completion_type;
@@ -276,6 +696,86 @@ pub trait Configurer {
         self.config_mut().completion_prompt_limit = completion_prompt_limit;
     }
 
+    /// The number of rows of the completion list (`CompletionType::List`)
+    /// displayed at a time, at most one screen: `--More--` is displayed,
+    /// Space (or `y`) displays the next page, Enter the next row and `q` (or
+    /// `n`) cancels.
+    ///
+    /// By default, 0: one screen at a time.
+    fn set_completion_page_size(&mut self, page_size: usize) {
+        self.config_mut().completion_page_size = page_size;
+    }
+
+    /// Characters which trigger completion when typed (like `.` after an
+    /// object name): with a unique candidate, it is inserted directly.
+    ///
+    /// By default, only Tab triggers completion.
+    fn set_completion_triggers(&mut self, chars: &str) {
+        self.config_mut().set_completion_triggers(chars);
+    }
+
+    /// Give up on the candidates of a completer running longer than
+    /// `timeout`: the bell rings and editing continues.
+    ///
+    /// A spinner is displayed at the cursor while the completer is running
+    /// (after 200ms).
+    ///
+    /// The completer is still called on the editing thread (a `Completer` is
+    /// neither `Send` nor `'static`) so it is not interrupted: its late
+    /// candidates are only discarded: a hanging completer still hangs
+    /// `readline`. A completer that may block should be an `AsyncCompleter`
+    /// (cancelled by a key press) or delegate the work to another thread (or
+    /// a cache) and return no candidates on its own timeout.
+    fn set_completion_timeout(&mut self, timeout: Duration) {
+        self.config_mut().completion_timeout = Some(timeout);
+    }
+
+    /// Display (at most `rows` of) the documentation of the selected
+    /// candidate (see `Candidate::documentation`) below the line (and the
+    /// menu with `CompletionType::Menu`), wrapped to the terminal width.
+    ///
+    /// By default, the documentation is not displayed (0 row).
+    fn set_completion_docs_height(&mut self, rows: usize) {
+        self.config_mut().completion_docs_height = rows;
+    }
+
+    /// Whether the common prefix of the candidates is computed regardless of
+    /// the case (like readline's `completion-ignore-case`): `READ` and
+    /// `readme.txt` are completed to `README` (with the case of the first
+    /// candidate).
+    ///
+    /// The candidates returned by the completer which do not start with the
+    /// typed word (regardless of the case) are dropped, except with
+    /// `fuzzy_completion` or when the whole line is replaced. The completer
+    /// still decides which candidates are returned: a case sensitive one
+    /// (like `FilenameCompleter` without `set_case_insensitive`) does not
+    /// return `README` for `read`, so both should be set.
+    /// The common prefix is used only with `CompletionType::List`.
+    /// By default, the case matters.
+    fn set_completion_ignore_case(&mut self, yes: bool) {
+        self.config_mut().completion_ignore_case = yes;
+    }
+
+    /// Whether the candidates are filtered and ranked by fuzzy matching: the
+    /// completer of the helper is wrapped in a `completion::FuzzyCompleter`,
+    /// so typing `slt` matches `SELECT`, and the matched chars are displayed
+    /// in bold.
+    ///
+    /// The completer should then return all the candidates, not only the
+    /// ones starting with the typed word. An `AsyncCompleter` is not wrapped.
+    /// By default, they are not.
+    fn set_fuzzy_completion(&mut self, yes: bool) {
+        self.config_mut().fuzzy_completion = yes;
+    }
+
+    /// How the completion candidates are sorted (with a stable sort) before
+    /// being displayed, then reordered by `Completer::sort`.
+    ///
+    /// By default, they are kept in the order of the completer.
+    fn set_completion_sort(&mut self, sort: CompletionSort) {
+        self.config_mut().completion_sort = sort;
+    }
+
     /// Timeout for ambiguous key sequences in milliseconds.
     fn set_keyseq_timeout(&mut self, keyseq_timeout_ms: i32) {
         self.config_mut().keyseq_timeout = keyseq_timeout_ms;
@@ -303,4 +803,100 @@ pub trait Configurer {
     fn set_color_mode(&mut self, color_mode: ColorMode) {
         self.config_mut().set_color_mode(color_mode);
     }
+
+    /// Overrides the number of colors supported by the terminal: the colors
+    /// of the styles are downsampled to it (`None` to detect it).
+    ///
+    /// By default, it is detected from `$COLORTERM` and `$TERM`.
+    fn set_color_depth(&mut self, depth: Option<ColorDepth>) {
+        self.config_mut().color_depth = depth;
+    }
+
+    /// Choose how control characters in the line are displayed.
+    ///
+    /// By default, with the caret notation (`^A`).
+    fn set_control_char_rendering(&mut self, rendering: ControlCharRender) {
+        self.config_mut().control_char_rendering = rendering;
+    }
+
+    /// Display a line number gutter (like ` 9 | `) before each line of a
+    /// multi-line input.
+    ///
+    /// The gutter is not part of the line buffer: it is right-aligned to the
+    /// number of lines and styled with `Highlighter::highlight_line_number`.
+    ///
+    /// By default, it is not displayed.
+    fn set_line_numbers(&mut self, yes: bool) {
+        self.config_mut().line_numbers = yes;
+    }
+
+    /// Move the cursor faster when a motion key is held down: after 8, 16
+    /// then 24 auto-repeats of the key, the character motions (like Left or
+    /// Right) move the cursor by 2, 4 then 8 characters at once.
+    ///
+    /// The auto-repeat is inferred from the timing of the keys (see
+    /// `EventContext::repeat_count`).
+    ///
+    /// By default, it does not.
+    fn set_cursor_acceleration(&mut self, yes: bool) {
+        self.config_mut().cursor_acceleration = yes;
+    }
+
+    /// Set the title of the terminal (with the `OSC 2` escape sequence, or
+    /// the console API on Windows) from the `template` when a line is
+    /// accepted.
+    ///
+    /// The title is only set when the output is a terminal, and never for a
+    /// line read by `Editor::readline_private`. The control chars of the
+    /// line are removed.
+    /// The previous title is neither restored nor cleared on exit (the
+    /// terminals cannot portably report it): set it back if needed.
+    ///
+    /// By default, it is not set.
+    fn set_update_title(&mut self, template: Option<TitleTemplate>) {
+        self.config_mut().update_title = template;
+    }
+
+    /// Ignore Ctrl-D (`Cmd::EndOfFile`) on a non-empty line: it beeps
+    /// instead of deleting the char under the cursor (in Emacs mode) or
+    /// accepting the line (in Vi mode), so that the line typed is not lost
+    /// by mistake. On an empty line, it still ends the input.
+    ///
+    /// When the input itself is closed while the line is not empty, the line
+    /// can be recovered with `Editor::take_eof_line`.
+    ///
+    /// By default, it is not ignored.
+    fn set_ignore_eof_nonempty(&mut self, yes: bool) {
+        self.config_mut().ignore_eof_nonempty = yes;
+    }
+
+    /// Keep (`true`) or remove (`false`) the final newline of an accepted
+    /// line whose last line is empty (like `"a\n"` when Enter is pressed
+    /// after pasting `a` and a newline). Only this newline is removed: the
+    /// other lines are returned as typed, so `"a\n\n"` is returned as
+    /// `"a\n"`.
+    ///
+    /// A single-line input has no newline: it is returned unchanged either
+    /// way (Enter accepts the line without adding one). When `stdin` is not a
+    /// terminal, the line is returned as read, with its line terminator.
+    ///
+    /// By default, it is kept.
+    fn set_keep_trailing_newline(&mut self, yes: bool) {
+        self.config_mut().keep_trailing_newline = yes;
+    }
+
+    /// Enable the bracketed paste mode of the terminal while a line is read
+    /// (unix only): a pasted text is inserted as a whole, newlines included,
+    /// instead of being typed (so a newline does not accept the line and
+    /// the validator is not called before Enter is pressed). The mode is
+    /// disabled when the terminal mode is restored (when the line is
+    /// returned, or when the editor is dropped after `Editor::try_readline`).
+    ///
+    /// When it is disabled, the terminal is left as it is: a pasted newline
+    /// accepts the line.
+    ///
+    /// By default, it is enabled.
+    fn set_bracketed_paste(&mut self, yes: bool) {
+        self.config_mut().set_bracketed_paste(yes);
+    }
 }
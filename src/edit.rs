@@ -1,38 +1,79 @@
 //! Command processor
 
+use std::borrow::Cow;
 use std::cell::RefCell;
+use std::cmp;
+use std::collections::HashMap;
 use std::fmt;
+use std::mem;
+use std::ops::Range;
 use std::rc::Rc;
+use std::time::Instant;
 use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthChar;
 
 use super::Result;
-use highlight::Highlighter;
-use hint::Hinter;
-use history::{Direction, History};
+use completion::Candidate;
+use config::{CompletionType, ControlCharRender};
+use highlight::{Highlighter, Span};
+use hint::{truncate_hint, Context, Hinter};
+use history::{Direction, HistoryBackend, VecHistory};
 use keymap::{Anchor, At, CharSearch, Cmd, Movement, RepeatCount, Word};
 use keymap::{InputState, Refresher};
-use line_buffer::{LineBuffer, WordAction, MAX_LINE};
-use tty::{Position, RawReader, Renderer};
+use line_buffer::{Block, LineBuffer, WordAction, MAX_LINE};
+use tty::{remaining_ms, Position, RawReader, Renderer};
 use undo::Changeset;
 
 /// Represent the state during line editing.
 /// Implement rendering.
 pub struct State<'out, 'prompt> {
     pub out: &'out mut Renderer,
-    prompt: &'prompt str,  // Prompt to display (rl_prompt)
-    prompt_size: Position, // Prompt Unicode/visible width and height
-    pub line: LineBuffer,  // Edited line buffer
-    pub cursor: Position,  /* Cursor position (relative to the start of the prompt
-                            * for `row`) */
+    prompt: &'prompt str,              // Prompt to display (rl_prompt)
+    prompt_size: Position,             // Prompt Unicode/visible width and height
+    pub rprompt: Option<&'prompt str>, // Prompt displayed flush right on the first input row
+    pub line: LineBuffer,              // Edited line buffer
+    pub cursor: Position,              /* Cursor position (relative to the start of the prompt
+                                        * for `row`) */
     pub old_rows: usize, // Number of rows used so far (from start of prompt to end of input)
     history_index: usize, // The history index we are currently editing
     saved_line_for_history: LineBuffer, // Current edited line before history browsing
+    history_edits: HashMap<usize, String>, // Pending edits of the recalled history entries
     byte_buffer: [u8; 4],
     pub changes: Rc<RefCell<Changeset>>, // changes to line, for undo/redo
     pub hinter: Option<&'out Hinter>,
+    pub history: Option<&'out HistoryBackend>, // History given to the hinter
+    pub last_cmd: Cmd,                         // Last command typed (given to the hinter)
     pub highlighter: Option<&'out Highlighter>,
     no_hint: bool, // `false` if an hint has been displayed
+    // Index of the hint selected with `Cmd::CycleHint` and the line (with
+    // the cursor position) it has been selected for
+    hint_cycle: Option<(usize, String, usize)>,
+    highlight_char: bool, // `true` if a char has been highlighted
+    highlight_cache: RefCell<Vec<(String, String)>>, // (line, highlighted line) most recent first
+    mark: Option<usize>,  // Mark position (byte position) when the region is active
+    replace_region: bool, // `true` if the region is replaced by the next insertion or deletion
+    search_match: Option<Range<usize>>, // Match of the incremental search (byte range)
+    control_char_rendering: ControlCharRender,
+    line_numbers: bool, // `true` if a line number gutter is displayed
+    accepted: bool,     // `true` once the line has been accepted (see `refresh_accepted`)
+    pub private: bool,  // `true` if the line is wiped when dropped
+}
+
+/// Edition state kept between two calls of `Editor::try_readline` (the line
+/// is still displayed).
+pub struct Snapshot {
+    line: String,
+    pos: usize,
+    cursor: Position,
+    old_rows: usize,
+    history_index: usize,
+    saved_line_for_history: (String, usize),
+    history_edits: HashMap<usize, String>,
+    changes: Changeset,
+    last_cmd: Cmd,
+    hint_cycle: Option<(usize, String, usize)>,
+    mark: Option<usize>,
+    replace_region: bool,
 }
 
 impl<'out, 'prompt> State<'out, 'prompt> {
@@ -42,26 +83,86 @@ impl<'out, 'prompt> State<'out, 'prompt> {
         history_index: usize,
         hinter: Option<&'out Hinter>,
         highlighter: Option<&'out Highlighter>,
+        control_char_rendering: ControlCharRender,
+        line_numbers: bool,
     ) -> State<'out, 'prompt> {
         let capacity = MAX_LINE;
         let prompt_size = out.calculate_position(prompt, Position::default());
+        // skip the highlighting entirely
+        let highlighter = highlighter.filter(|h| h.is_enabled());
         State {
             out,
             prompt,
             prompt_size,
+            rprompt: None,
             line: LineBuffer::with_capacity(capacity),
             cursor: prompt_size,
             old_rows: 0,
             history_index,
             saved_line_for_history: LineBuffer::with_capacity(capacity),
+            history_edits: HashMap::new(),
             byte_buffer: [0; 4],
             changes: Rc::new(RefCell::new(Changeset::new())),
             hinter,
+            history: None,
+            last_cmd: Cmd::Noop,
             highlighter,
             no_hint: true,
+            hint_cycle: None,
+            highlight_char: false,
+            highlight_cache: RefCell::new(Vec::with_capacity(HIGHLIGHT_CACHE_SIZE)),
+            mark: None,
+            replace_region: false,
+            search_match: None,
+            control_char_rendering,
+            line_numbers,
+            accepted: false,
+            private: false,
         }
     }
 
+    /// Saves the edition state when the edition is suspended.
+    pub fn snapshot(&mut self) -> Snapshot {
+        Snapshot {
+            line: self.line.as_str().to_owned(),
+            pos: self.line.pos(),
+            cursor: self.cursor,
+            old_rows: self.old_rows,
+            history_index: self.history_index,
+            saved_line_for_history: (
+                self.saved_line_for_history.as_str().to_owned(),
+                self.saved_line_for_history.pos(),
+            ),
+            history_edits: mem::replace(&mut self.history_edits, HashMap::new()),
+            changes: mem::replace(&mut *self.changes.borrow_mut(), Changeset::new()),
+            last_cmd: self.last_cmd.clone(),
+            hint_cycle: self.hint_cycle.take(),
+            mark: self.mark,
+            replace_region: self.replace_region,
+        }
+    }
+
+    /// Resumes the edition saved by `snapshot`.
+    pub fn resume(&mut self, snapshot: Snapshot) {
+        // not a change to undo
+        self.line.remove_change_listener();
+        self.line.update(&snapshot.line, snapshot.pos);
+        *self.changes.borrow_mut() = snapshot.changes;
+        if !self.private {
+            self.line.set_change_listener(self.changes.clone());
+        }
+        self.cursor = snapshot.cursor;
+        self.old_rows = snapshot.old_rows;
+        self.history_index = snapshot.history_index;
+        let (saved_line, saved_pos) = snapshot.saved_line_for_history;
+        self.saved_line_for_history.update(&saved_line, saved_pos);
+        self.history_edits = snapshot.history_edits;
+        self.last_cmd = snapshot.last_cmd;
+        self.hint_cycle = snapshot.hint_cycle;
+        self.mark = snapshot.mark;
+        self.replace_region = snapshot.replace_region;
+    }
+
     pub fn next_cmd<R: RawReader>(
         &mut self,
         input_state: &mut InputState,
@@ -78,6 +179,27 @@ impl<'out, 'prompt> State<'out, 'prompt> {
             if let Ok(Cmd::Replace(_, _)) = rc {
                 self.changes.borrow_mut().begin();
             }
+            if let Ok(ref cmd) = rc {
+                self.last_cmd = cmd.clone();
+            }
+            return rc;
+        }
+    }
+
+    /// Waits at most `timeout_ms` milliseconds for some input (see
+    /// `RawReader::poll`), redrawing the line when the terminal is resized
+    /// meanwhile.
+    pub fn poll_input<R: RawReader>(&mut self, rdr: &mut R, timeout_ms: i32) -> Result<bool> {
+        let started = Instant::now();
+        let mut remaining = timeout_ms;
+        loop {
+            let rc = rdr.poll(remaining);
+            if rc.is_err() && self.out.sigwinch() {
+                self.out.update_size();
+                try!(self.refresh_line());
+                remaining = remaining_ms(timeout_ms, started);
+                continue;
+            }
             return rc;
         }
     }
@@ -95,6 +217,11 @@ impl<'out, 'prompt> State<'out, 'prompt> {
     }
 
     pub fn move_cursor(&mut self) -> Result<()> {
+        if self.highlight_changes_width() {
+            // the cursor position depends on the highlighted line
+            let prompt_size = self.prompt_size;
+            return self.refresh(self.prompt, prompt_size, None);
+        }
         // calculate the desired position of the cursor
         let cursor = self
             .out
@@ -102,11 +229,11 @@ impl<'out, 'prompt> State<'out, 'prompt> {
         if self.cursor == cursor {
             return Ok(());
         }
-        if self.highlighter.map_or(false, |h| {
-            self.line
-                .grapheme_at_cursor()
-                .map_or(false, |s| h.highlight_char(s))
-        }) {
+        if self.mark.is_some() && self.highlighter.is_some() {
+            // the region has changed
+            let prompt_size = self.prompt_size;
+            try!(self.refresh(self.prompt, prompt_size, None));
+        } else if self.highlight_char() {
             let prompt_size = self.prompt_size;
             try!(self.refresh(self.prompt, prompt_size, None));
         } else {
@@ -117,14 +244,102 @@ impl<'out, 'prompt> State<'out, 'prompt> {
     }
 
     fn refresh(&mut self, prompt: &str, prompt_size: Position, hint: Option<String>) -> Result<()> {
+        let control_chars = has_control_chars(&self.line);
+        let accepted = match self.highlighter {
+            Some(highlighter) if self.accepted => Some(Accepted { highlighter }),
+            _ => None,
+        };
+        let highlighter = match accepted {
+            Some(ref accepted) => Some(accepted as &Highlighter),
+            None => self.highlighter,
+        };
+        let cached = match highlighter {
+            // the accepted line is highlighted only once
+            _ if self.accepted => None,
+            // the match changes without the line
+            _ if self.search_match.is_some() => None,
+            Some(highlighter) if !highlighter.highlight_is_pos_dependent() => Some(Cached {
+                highlighter,
+                cache: &self.highlight_cache,
+            }),
+            _ => None,
+        };
+        let highlighter = match cached {
+            Some(ref cached) => Some(cached as &Highlighter),
+            None => highlighter,
+        };
+        let search_match = match (highlighter, &self.search_match) {
+            (Some(highlighter), &Some(ref range)) => {
+                let offset = |i: usize| {
+                    if control_chars {
+                        display_control_chars(&self.line[..i], self.control_char_rendering).len()
+                    } else {
+                        i
+                    }
+                };
+                Some(SearchMatch {
+                    highlighter,
+                    start: offset(range.start),
+                    end: offset(range.end),
+                })
+            }
+            _ => None,
+        };
+        let highlighter = match search_match {
+            Some(ref search_match) => Some(search_match as &Highlighter),
+            None => highlighter,
+        };
+        let selection = match (highlighter, self.mark) {
+            // the match is displayed instead of the region
+            _ if self.search_match.is_some() => None,
+            (Some(highlighter), Some(mark)) => Some(Selection {
+                highlighter,
+                mark: if control_chars {
+                    display_control_chars(&self.line[..mark], self.control_char_rendering).len()
+                } else {
+                    mark
+                },
+            }),
+            _ => None,
+        };
+        let highlighter = match selection {
+            Some(ref selection) => Some(selection as &Highlighter),
+            None => highlighter,
+        };
+        let control_chars = if control_chars {
+            Some(ControlChars {
+                highlighter,
+                rendering: self.control_char_rendering,
+            })
+        } else {
+            None
+        };
+        let highlighter = match control_chars {
+            Some(ref control_chars) => Some(control_chars as &Highlighter),
+            None => highlighter,
+        };
+        let line_numbers = if self.line_numbers {
+            let lines = self.line.matches('\n').count() + 1;
+            Some(LineNumbers {
+                highlighter,
+                width: lines.to_string().len(),
+            })
+        } else {
+            None
+        };
+        let highlighter = match line_numbers {
+            Some(ref line_numbers) => Some(line_numbers as &Highlighter),
+            None => highlighter,
+        };
         let (cursor, end_pos) = try!(self.out.refresh_line(
             prompt,
             prompt_size,
+            self.rprompt,
             &self.line,
             hint,
             self.cursor.row,
             self.old_rows,
-            self.highlighter,
+            highlighter,
         ));
 
         self.cursor = cursor;
@@ -132,15 +347,492 @@ impl<'out, 'prompt> State<'out, 'prompt> {
         Ok(())
     }
 
+    /// Tell if the line is displayed with a different width than its content.
+    fn highlight_changes_width(&self) -> bool {
+        self.line_numbers
+            || has_control_chars(&self.line)
+            || self
+                .highlighter
+                .map_or(false, |h| h.highlight_changes_width())
+    }
+
+    fn highlight_char(&mut self) -> bool {
+        if let Some(highlighter) = self.highlighter {
+            if highlighter.highlight_char_at(&self.line, self.line.pos()) {
+                self.highlight_char = true;
+                true
+            } else if self.highlight_char {
+                // previously highlighted => force a full refresh
+                self.highlight_char = false;
+                true
+            } else {
+                false
+            }
+        } else {
+            false
+        }
+    }
+
+    /// Refreshes the accepted line for the last time: without hint nor
+    /// region and highlighted with `Highlighter::highlight_accepted`.
+    ///
+    /// Does nothing when there is neither a hinter nor a highlighter (the
+    /// line is already displayed as typed).
+    pub fn refresh_accepted(&mut self) -> Result<()> {
+        if self.hinter.is_none() && self.highlighter.is_none() {
+            return Ok(());
+        }
+        self.hinter = None;
+        self.mark = None;
+        self.accepted = true;
+        self.refresh_line()
+    }
+
+    /// Refreshes the line with `hint` displayed in place of the hinter's one,
+    /// until the next refresh.
+    pub fn show_hint(&mut self, hint: &str) -> Result<()> {
+        let prompt_size = self.prompt_size;
+        self.refresh(self.prompt, prompt_size, Some(hint.to_owned()))
+    }
+
+    /// Displays (at most `max_rows` of) `text` wrapped below the line,
+    /// until the next refresh.
+    pub fn show_below(&mut self, text: &str, max_rows: usize) -> Result<()> {
+        let cols = self.out.get_columns();
+        let rows = wrap(text, cmp::max(cols, 2) - 1);
+        self.show_rows(&rows[..cmp::min(rows.len(), max_rows)])
+    }
+
+    /// Displays the (possibly highlighted) `rows` below the line, until the
+    /// next refresh.
+    ///
+    /// Each row must be narrower than the terminal.
+    pub fn show_rows<S: AsRef<str>>(&mut self, rows: &[S]) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let end = Position {
+            col: 0,
+            row: self.old_rows,
+        };
+        try!(self.out.move_cursor(self.cursor, end));
+        let mut below = String::new();
+        for row in rows {
+            below.push('\n');
+            below.push_str(row.as_ref());
+        }
+        try!(self.out.write_and_flush(below.as_bytes()));
+        let last = Position {
+            col: self
+                .out
+                .calculate_position(rows[rows.len() - 1].as_ref(), Position::default())
+                .col,
+            row: self.old_rows + rows.len(),
+        };
+        try!(self.out.move_cursor(last, self.cursor));
+        // erased by the next refresh
+        self.old_rows += rows.len();
+        Ok(())
+    }
+
+    /// Hint displayed (truncated to `Hinter::max_hint_width`)
     fn hint(&mut self) -> Option<String> {
         if let Some(hinter) = self.hinter {
             self.no_hint = false;
-            hinter.hint(self.line.as_str(), self.line.pos())
+            let hint = self.selected_hint(hinter);
+            match hinter.max_hint_width() {
+                Some(max_width) => hint.and_then(|hint| truncate_hint(&hint, max_width)),
+                None => hint,
+            }
         } else {
             self.no_hint = true;
             None
         }
     }
+
+    /// Index of the hint selected with `Cmd::CycleHint` (0 once the line has
+    /// changed).
+    fn hint_index(&self) -> usize {
+        match self.hint_cycle {
+            Some((index, ref line, pos)) if pos == self.line.pos() && line == &self.line[..] => {
+                index
+            }
+            _ => 0,
+        }
+    }
+
+    fn selected_hint(&self, hinter: &Hinter) -> Option<String> {
+        let (line, pos) = (self.line.as_str(), self.line.pos());
+        let empty = VecHistory::new();
+        let history = self.history.unwrap_or(&empty);
+        let ctx = Context::new(history, &self.last_cmd);
+        let index = self.hint_index();
+        if index == 0 {
+            return hinter.hint_with_context(line, pos, &ctx);
+        }
+        let mut hints = hinter.hints_with_context(line, pos, &ctx);
+        if hints.is_empty() {
+            None
+        } else {
+            let index = index % hints.len();
+            Some(hints.swap_remove(index))
+        }
+    }
+}
+
+/// Number of highlighted lines cached: the whole line and the prefix before
+/// the cursor (when the highlighter changes the width).
+const HIGHLIGHT_CACHE_SIZE: usize = 2;
+
+/// Reuses the highlighted line when only the cursor has moved (see
+/// `Highlighter::highlight_is_pos_dependent`).
+struct Cached<'a> {
+    highlighter: &'a Highlighter,
+    cache: &'a RefCell<Vec<(String, String)>>,
+}
+
+impl<'a> Highlighter for Cached<'a> {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        let mut cache = self.cache.borrow_mut();
+        if let Some(i) = cache.iter().position(|&(ref l, _)| l == line) {
+            let entry = cache.remove(i);
+            let highlighted = entry.1.clone();
+            cache.insert(0, entry);
+            return Cow::Owned(highlighted);
+        }
+        let highlighted = self.highlighter.highlight(line, pos);
+        cache.truncate(HIGHLIGHT_CACHE_SIZE - 1);
+        cache.insert(0, (line.to_owned(), highlighted.clone().into_owned()));
+        highlighted
+    }
+
+    fn highlight_selection<'l>(
+        &self,
+        line: &'l str,
+        pos: usize,
+        start: usize,
+        end: usize,
+    ) -> Cow<'l, str> {
+        self.highlighter.highlight_selection(line, pos, start, end)
+    }
+
+    fn highlight_prompt<'p>(&self, prompt: &'p str) -> Cow<'p, str> {
+        self.highlighter.highlight_prompt(prompt)
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        self.highlighter.highlight_hint(hint)
+    }
+
+    fn highlight_line_number<'g>(&self, gutter: &'g str) -> Cow<'g, str> {
+        self.highlighter.highlight_line_number(gutter)
+    }
+
+    fn highlight_candidate_item<'c>(
+        &self,
+        candidate: &'c Candidate,
+        completion: CompletionType,
+    ) -> Cow<'c, str> {
+        self.highlighter.highlight_candidate_item(candidate, completion)
+    }
+
+    fn highlight_selected_candidate<'c>(
+        &self,
+        candidate: &'c Candidate,
+        completion: CompletionType,
+    ) -> Cow<'c, str> {
+        self.highlighter
+            .highlight_selected_candidate(candidate, completion)
+    }
+
+    fn highlight_candidate_kind<'k>(
+        &self,
+        kind: &'k str,
+        completion: CompletionType,
+    ) -> Cow<'k, str> {
+        self.highlighter.highlight_candidate_kind(kind, completion)
+    }
+
+    fn highlight_spans(&self, line: &str, pos: usize) -> Vec<Span> {
+        self.highlighter.highlight_spans(line, pos)
+    }
+
+    fn highlight_changes_width(&self) -> bool {
+        self.highlighter.highlight_changes_width()
+    }
+
+    fn highlight_char_at(&self, line: &str, pos: usize) -> bool {
+        self.highlighter.highlight_char_at(line, pos)
+    }
+
+    fn highlight_is_pos_dependent(&self) -> bool {
+        false
+    }
+}
+
+/// Highlights the accepted line with `Highlighter::highlight_accepted`.
+struct Accepted<'a> {
+    highlighter: &'a Highlighter,
+}
+
+impl<'a> Highlighter for Accepted<'a> {
+    fn highlight<'l>(&self, line: &'l str, _: usize) -> Cow<'l, str> {
+        self.highlighter.highlight_accepted(line)
+    }
+
+    fn highlight_prompt<'p>(&self, prompt: &'p str) -> Cow<'p, str> {
+        self.highlighter.highlight_prompt(prompt)
+    }
+
+    fn highlight_line_number<'g>(&self, gutter: &'g str) -> Cow<'g, str> {
+        self.highlighter.highlight_line_number(gutter)
+    }
+
+    fn highlight_changes_width(&self) -> bool {
+        self.highlighter.highlight_changes_width()
+    }
+}
+
+/// Highlights the match of the incremental search.
+struct SearchMatch<'a> {
+    highlighter: &'a Highlighter,
+    start: usize,
+    end: usize,
+}
+
+impl<'a> Highlighter for SearchMatch<'a> {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        // the prefix before the cursor may end within the match
+        let end = cmp::min(self.end, line.len());
+        let start = cmp::min(self.start, end);
+        self.highlighter
+            .highlight_search_match(line, pos, start, end)
+    }
+
+    fn highlight_prompt<'p>(&self, prompt: &'p str) -> Cow<'p, str> {
+        self.highlighter.highlight_prompt(prompt)
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        self.highlighter.highlight_hint(hint)
+    }
+
+    fn highlight_line_number<'g>(&self, gutter: &'g str) -> Cow<'g, str> {
+        self.highlighter.highlight_line_number(gutter)
+    }
+
+    fn highlight_changes_width(&self) -> bool {
+        self.highlighter.highlight_changes_width()
+    }
+
+    fn highlight_char_at(&self, line: &str, pos: usize) -> bool {
+        self.highlighter.highlight_char_at(line, pos)
+    }
+}
+
+/// Highlights the active region (between the mark and the cursor).
+struct Selection<'a> {
+    highlighter: &'a Highlighter,
+    mark: usize,
+}
+
+impl<'a> Highlighter for Selection<'a> {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        let start = cmp::min(self.mark, pos);
+        let end = cmp::max(self.mark, pos);
+        self.highlighter.highlight_selection(line, pos, start, end)
+    }
+
+    fn highlight_prompt<'p>(&self, prompt: &'p str) -> Cow<'p, str> {
+        self.highlighter.highlight_prompt(prompt)
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        self.highlighter.highlight_hint(hint)
+    }
+
+    fn highlight_line_number<'g>(&self, gutter: &'g str) -> Cow<'g, str> {
+        self.highlighter.highlight_line_number(gutter)
+    }
+
+    fn highlight_candidate_item<'c>(
+        &self,
+        candidate: &'c Candidate,
+        completion: CompletionType,
+    ) -> Cow<'c, str> {
+        self.highlighter.highlight_candidate_item(candidate, completion)
+    }
+
+    fn highlight_selected_candidate<'c>(
+        &self,
+        candidate: &'c Candidate,
+        completion: CompletionType,
+    ) -> Cow<'c, str> {
+        self.highlighter
+            .highlight_selected_candidate(candidate, completion)
+    }
+
+    fn highlight_candidate_kind<'k>(
+        &self,
+        kind: &'k str,
+        completion: CompletionType,
+    ) -> Cow<'k, str> {
+        self.highlighter.highlight_candidate_kind(kind, completion)
+    }
+
+    fn highlight_spans(&self, line: &str, pos: usize) -> Vec<Span> {
+        self.highlighter.highlight_spans(line, pos)
+    }
+
+    fn highlight_changes_width(&self) -> bool {
+        self.highlighter.highlight_changes_width()
+    }
+
+    fn highlight_char_at(&self, line: &str, pos: usize) -> bool {
+        self.highlighter.highlight_char_at(line, pos)
+    }
+}
+
+/// Displays the control characters of the line with a visible notation.
+struct ControlChars<'a> {
+    highlighter: Option<&'a Highlighter>,
+    rendering: ControlCharRender,
+}
+
+impl<'a> Highlighter for ControlChars<'a> {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        let visible = display_control_chars(line, self.rendering);
+        match self.highlighter {
+            Some(highlighter) => {
+                let pos = display_control_chars(&line[..pos], self.rendering).len();
+                Cow::Owned(highlighter.highlight(&visible, pos).into_owned())
+            }
+            None => Cow::Owned(visible),
+        }
+    }
+
+    fn highlight_prompt<'p>(&self, prompt: &'p str) -> Cow<'p, str> {
+        match self.highlighter {
+            Some(highlighter) => highlighter.highlight_prompt(prompt),
+            None => Cow::Borrowed(prompt),
+        }
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        match self.highlighter {
+            Some(highlighter) => highlighter.highlight_hint(hint),
+            None => Cow::Borrowed(hint),
+        }
+    }
+
+    fn highlight_line_number<'g>(&self, gutter: &'g str) -> Cow<'g, str> {
+        match self.highlighter {
+            Some(highlighter) => highlighter.highlight_line_number(gutter),
+            None => Cow::Borrowed(gutter),
+        }
+    }
+
+    fn highlight_changes_width(&self) -> bool {
+        true
+    }
+}
+
+/// Displays a line number gutter before each line of the input.
+struct LineNumbers<'a> {
+    highlighter: Option<&'a Highlighter>,
+    // width of the greatest line number
+    width: usize,
+}
+
+impl<'a> Highlighter for LineNumbers<'a> {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        use std::fmt::Write;
+        let highlighted = match self.highlighter {
+            Some(highlighter) => highlighter.highlight(line, pos),
+            None => Cow::Borrowed(line),
+        };
+        let mut numbered = String::with_capacity(highlighted.len() + self.width + 3);
+        let mut gutter = String::with_capacity(self.width + 3);
+        for (i, l) in highlighted.split('\n').enumerate() {
+            if i > 0 {
+                numbered.push('\n');
+            }
+            gutter.clear();
+            write!(gutter, "{:>width$} | ", i + 1, width = self.width).unwrap();
+            match self.highlighter {
+                Some(highlighter) => numbered.push_str(&highlighter.highlight_line_number(&gutter)),
+                None => numbered.push_str(&gutter),
+            }
+            numbered.push_str(l);
+        }
+        Cow::Owned(numbered)
+    }
+
+    fn highlight_prompt<'p>(&self, prompt: &'p str) -> Cow<'p, str> {
+        match self.highlighter {
+            Some(highlighter) => highlighter.highlight_prompt(prompt),
+            None => Cow::Borrowed(prompt),
+        }
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        match self.highlighter {
+            Some(highlighter) => highlighter.highlight_hint(hint),
+            None => Cow::Borrowed(hint),
+        }
+    }
+
+    fn highlight_changes_width(&self) -> bool {
+        true
+    }
+}
+
+/// Splits `text` in rows of at most `cols` columns.
+fn wrap(text: &str, cols: usize) -> Vec<&str> {
+    let mut rows = Vec::new();
+    for line in text.lines() {
+        let mut start = 0;
+        let mut width = 0;
+        for (i, c) in line.char_indices() {
+            let cw = c.width().unwrap_or(0);
+            if width + cw > cols {
+                rows.push(&line[start..i]);
+                start = i;
+                width = 0;
+            }
+            width += cw;
+        }
+        rows.push(&line[start..]);
+    }
+    rows
+}
+
+fn is_control_char(c: char) -> bool {
+    // new lines and tabs are displayed as is
+    c.is_control() && c != '\n' && c != '\t'
+}
+
+fn has_control_chars(s: &str) -> bool {
+    s.chars().any(is_control_char)
+}
+
+/// Replaces the control characters of `s` by their visible representation.
+fn display_control_chars(s: &str, rendering: ControlCharRender) -> String {
+    use std::fmt::Write;
+    let mut visible = String::with_capacity(s.len());
+    for c in s.chars() {
+        if !is_control_char(c) {
+            visible.push(c);
+        } else if rendering == ControlCharRender::Caret && (c as u32) < 0x20 {
+            visible.push('^');
+            visible.push((c as u8 + 0x40) as char);
+        } else if rendering == ControlCharRender::Caret && c == '\x7f' {
+            visible.push_str("^?");
+        } else {
+            write!(visible, "<0x{:02x}>", c as u32).unwrap();
+        }
+    }
+    visible
 }
 
 impl<'out, 'prompt> Refresher for State<'out, 'prompt> {
@@ -180,10 +872,20 @@ impl<'out, 'prompt> fmt::Debug for State<'out, 'prompt> {
             .field("old_rows", &self.old_rows)
             .field("history_index", &self.history_index)
             .field("saved_line_for_history", &self.saved_line_for_history)
+            .field("history_edits", &self.history_edits)
             .finish()
     }
 }
 
+impl<'out, 'prompt> Drop for State<'out, 'prompt> {
+    fn drop(&mut self) {
+        if self.private {
+            self.line.wipe();
+            self.saved_line_for_history.wipe();
+        }
+    }
+}
+
 impl<'out, 'prompt> State<'out, 'prompt> {
     /// Insert the character `ch` at cursor current position.
     pub fn edit_insert(&mut self, ch: char, n: RepeatCount) -> Result<()> {
@@ -195,7 +897,8 @@ impl<'out, 'prompt> State<'out, 'prompt> {
                 if n == 1
                     && self.cursor.col + ch.width().unwrap_or(0) < self.out.get_columns()
                     && (hint.is_none() && no_previous_hint) // TODO refresh only current line
-                    && !self.highlighter.map_or(true, |h| h.highlight_char(ch.encode_utf8(&mut self.byte_buffer)))
+                    && !self.highlight_changes_width()
+                    && !self.highlight_char()
                 {
                     // Avoid a full update of the line in the trivial case.
                     let cursor = self
@@ -256,30 +959,31 @@ impl<'out, 'prompt> State<'out, 'prompt> {
         text: &str,
         anchor: Anchor,
         n: RepeatCount,
-    ) -> Result<()> {
+    ) -> Result<bool> {
         if let Anchor::After = anchor {
             self.line.move_forward(1);
         }
         if self.line.yank(text, n).is_some() {
-            if !input_state.is_emacs_mode() {
+            // on the last char inserted (like the text put in vi command mode)
+            if input_state.is_vi_command_mode() {
                 self.line.move_backward(1);
             }
-            self.refresh_line()
+            try!(self.refresh_line());
+            Ok(true)
         } else {
-            Ok(())
+            Ok(false)
         }
     }
 
     // Delete previously yanked text and yank/paste `text` at current position.
-    pub fn edit_yank_pop(&mut self, yank_size: usize, text: &str) -> Result<()> {
+    // Return `false` if `text` has not been inserted (line full).
+    pub fn edit_yank_pop(&mut self, yank_size: usize, text: &str) -> Result<bool> {
         self.changes.borrow_mut().begin();
-        let result = if self.line.yank_pop(yank_size, text).is_some() {
-            self.refresh_line()
-        } else {
-            Ok(())
-        };
+        let yanked = self.line.yank_pop(yank_size, text).is_some();
         self.changes.borrow_mut().end();
-        result
+        // the previously yanked text is deleted anyway
+        try!(self.refresh_line());
+        Ok(yanked)
     }
 
     /// Move cursor on the left.
@@ -335,6 +1039,56 @@ impl<'out, 'prompt> State<'out, 'prompt> {
         self.refresh_line()
     }
 
+    /// Insert the next word of the hint when the cursor is at the end of the
+    /// line. Returns `false` if there is no hint to accept.
+    pub fn edit_accept_hint_word(&mut self) -> Result<bool> {
+        if self.line.pos() != self.line.len() {
+            return Ok(false);
+        }
+        let hinter = match self.hinter {
+            Some(hinter) => hinter,
+            None => return Ok(false),
+        };
+        let hint = match self.selected_hint(hinter) {
+            Some(ref hint) if !hint.is_empty() => hint.clone(),
+            _ => return Ok(false),
+        };
+        // the separators before the next word and the word itself (the
+        // whole hint when there is no word left)
+        let mut words = LineBuffer::with_capacity(hint.len());
+        words.update(&hint, 0);
+        words.move_to_next_word(At::AfterEnd, Word::Emacs, 1);
+        let accepted = words.pos();
+        if self.line.yank(&hint[..accepted], 1).is_none() {
+            return Ok(false); // line full
+        }
+        hinter.hint_accepted(&hint, accepted);
+        try!(self.refresh_line());
+        Ok(true)
+    }
+
+    /// Display the next hint (see `Hinter::hints_with_context`), or the
+    /// first one after the last one.
+    pub fn edit_cycle_hint(&mut self) -> Result<()> {
+        let count = match self.hinter {
+            Some(hinter) => {
+                let empty = VecHistory::new();
+                let history = self.history.unwrap_or(&empty);
+                let ctx = Context::new(history, &self.last_cmd);
+                hinter
+                    .hints_with_context(self.line.as_str(), self.line.pos(), &ctx)
+                    .len()
+            }
+            None => 0,
+        };
+        if count < 2 {
+            return self.out.beep();
+        }
+        let index = (self.hint_index() + 1) % count;
+        self.hint_cycle = Some((index, self.line.as_str().to_owned(), self.line.pos()));
+        self.refresh_line()
+    }
+
     pub fn edit_delete(&mut self, n: RepeatCount) -> Result<()> {
         if self.line.delete(n).is_some() {
             self.refresh_line()
@@ -396,14 +1150,172 @@ impl<'out, 'prompt> State<'out, 'prompt> {
         self.changes.borrow_mut().end();
         if succeed {
             self.refresh_line()
+        } else {
+            self.out.beep()
+        }
+    }
+
+    /// Set the mark at the cursor position (activate the region).
+    pub fn edit_set_mark(&mut self) -> Result<()> {
+        self.mark = Some(self.line.pos());
+        if self.highlighter.is_some() {
+            self.refresh_line()
         } else {
             Ok(())
         }
     }
 
+    /// Swap the cursor and the mark.
+    pub fn edit_exchange_point_and_mark(&mut self) -> Result<()> {
+        if let Some(mark) = self.mark {
+            self.mark = Some(self.line.pos());
+            self.line.set_pos(mark);
+            self.move_cursor()
+        } else {
+            self.out.beep()
+        }
+    }
+
+    /// Start inserting the typed text on each line of the region, at the
+    /// column of the mark (where the cursor is moved).
+    /// Return `None` (and beep) if the region is not active.
+    pub fn edit_block_start(&mut self) -> Result<Option<Block>> {
+        let mark = match self.mark.take() {
+            Some(mark) => mark,
+            None => {
+                try!(self.out.beep());
+                return Ok(None);
+            }
+        };
+        let block = self.line.block(mark);
+        self.line.set_pos(mark);
+        try!(self.refresh_line());
+        Ok(Some(block))
+    }
+
+    /// Insert `c` (`n` times) on each line of the `block`.
+    pub fn edit_block_insert(&mut self, block: &mut Block, c: char, n: RepeatCount) -> Result<()> {
+        self.changes.borrow_mut().begin();
+        let succeed = (0..n).all(|_| self.line.block_insert(block, c));
+        self.changes.borrow_mut().end();
+        if succeed {
+            self.refresh_line()
+        } else {
+            self.out.beep()
+        }
+    }
+
+    /// Delete the last `n` chars inserted on each line of the `block`.
+    pub fn edit_block_backspace(&mut self, block: &mut Block, n: RepeatCount) -> Result<()> {
+        self.changes.borrow_mut().begin();
+        let succeed = (0..n).all(|_| self.line.block_backspace(block));
+        self.changes.borrow_mut().end();
+        if succeed {
+            self.refresh_line()
+        } else {
+            self.out.beep()
+        }
+    }
+
+    /// Select the whole line (the mark at the start and the cursor at the
+    /// end): the selection is replaced by the next insertion or deletion
+    /// (see `replace_selection`).
+    pub fn select_line(&mut self) {
+        self.line.move_end();
+        self.mark = Some(0);
+        self.replace_region = true;
+    }
+
+    /// Delete the text selected by `select_line` if `cmd` is an insertion or
+    /// a deletion, and return `true` if `cmd` is done (a deletion of a char).
+    /// Any other command deselects the text.
+    pub fn replace_selection(&mut self, cmd: &Cmd) -> Result<bool> {
+        if !mem::replace(&mut self.replace_region, false) {
+            return Ok(false);
+        }
+        let mark = match self.mark {
+            Some(mark) => mark,
+            None => return Ok(false),
+        };
+        match *cmd {
+            Cmd::SelfInsert(_, _)
+            | Cmd::Insert(_, _)
+            | Cmd::Yank(_, _)
+            | Cmd::Kill(Movement::BackwardChar(_))
+            | Cmd::Kill(Movement::ForwardChar(_)) => {
+                self.mark = None;
+                let pos = self.line.pos();
+                self.changes.borrow_mut().begin();
+                self.line
+                    .delete_range(cmp::min(mark, pos)..cmp::max(mark, pos));
+                self.changes.borrow_mut().end();
+                if let Cmd::Kill(_) = *cmd {
+                    try!(self.refresh_line());
+                    return Ok(true);
+                }
+                Ok(false)
+            }
+            _ => {
+                try!(self.deactivate_mark());
+                Ok(false)
+            }
+        }
+    }
+
+    /// Deactivate the region (if any).
+    pub fn deactivate_mark(&mut self) -> Result<()> {
+        self.replace_region = false;
+        if self.mark.take().is_some() && self.highlighter.is_some() {
+            self.refresh_line()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Set (or clear) the byte range of the incremental search match,
+    /// highlighted with `Highlighter::highlight_search_match` from the next
+    /// refresh.
+    pub fn set_search_match(&mut self, range: Option<Range<usize>>) {
+        self.search_match = range;
+    }
+
+    /// Keep the edit of the recalled history entry (if any) until the line is
+    /// accepted.
+    fn save_history_edit(&mut self, history: &HistoryBackend) {
+        if let Some(entry) = history.get(self.history_index) {
+            if entry == self.line.as_str() {
+                self.history_edits.remove(&self.history_index);
+            } else {
+                self.history_edits
+                    .insert(self.history_index, self.line.as_str().to_owned());
+            }
+        }
+    }
+
+    /// Substitute the currently edited line with the current history entry
+    /// (or with its pending edit).
+    fn recall_history_entry(&mut self, history: &HistoryBackend) {
+        let buf = match self.history_edits.get(&self.history_index) {
+            Some(edit) => Cow::Borrowed(edit.as_str()),
+            None => history.get(self.history_index).unwrap(),
+        };
+        self.changes.borrow_mut().begin();
+        self.line.update(&buf, buf.len());
+        self.changes.borrow_mut().end();
+    }
+
+    /// Replace the whole line with `text` (as a single change), the cursor at
+    /// its end.
+    pub fn edit_replace_line(&mut self, text: &str) -> Result<()> {
+        self.changes.borrow_mut().begin();
+        self.line.update(text, text.len());
+        self.changes.borrow_mut().end();
+        self.refresh_line()
+    }
+
     /// Substitute the currently edited line with the next or previous history
     /// entry.
-    pub fn edit_history_next(&mut self, history: &History, prev: bool) -> Result<()> {
+    pub fn edit_history_next(&mut self, history: &HistoryBackend, prev: bool) -> Result<()> {
         if history.is_empty() {
             return Ok(());
         }
@@ -417,16 +1329,14 @@ impl<'out, 'prompt> State<'out, 'prompt> {
         } else if self.history_index == 0 && prev {
             return Ok(());
         }
+        self.save_history_edit(history);
         if prev {
             self.history_index -= 1;
         } else {
             self.history_index += 1;
         }
         if self.history_index < history.len() {
-            let buf = history.get(self.history_index).unwrap();
-            self.changes.borrow_mut().begin();
-            self.line.update(buf, buf.len());
-            self.changes.borrow_mut().end();
+            self.recall_history_entry(history);
         } else {
             // Restore current edited line
             self.restore();
@@ -435,7 +1345,7 @@ impl<'out, 'prompt> State<'out, 'prompt> {
     }
 
     // Non-incremental, anchored search
-    pub fn edit_history_search(&mut self, history: &History, dir: Direction) -> Result<()> {
+    pub fn edit_history_search(&mut self, history: &HistoryBackend, dir: Direction) -> Result<()> {
         if history.is_empty() {
             return self.out.beep();
         }
@@ -444,6 +1354,7 @@ impl<'out, 'prompt> State<'out, 'prompt> {
         {
             return self.out.beep();
         }
+        self.save_history_edit(history);
         if dir == Direction::Reverse {
             self.history_index -= 1;
         } else {
@@ -455,10 +1366,7 @@ impl<'out, 'prompt> State<'out, 'prompt> {
             dir,
         ) {
             self.history_index = history_index;
-            let buf = history.get(history_index).unwrap();
-            self.changes.borrow_mut().begin();
-            self.line.update(buf, buf.len());
-            self.changes.borrow_mut().end();
+            self.recall_history_entry(history);
             self.refresh_line()
         } else {
             self.out.beep()
@@ -466,7 +1374,7 @@ impl<'out, 'prompt> State<'out, 'prompt> {
     }
 
     /// Substitute the currently edited line with the first/last history entry.
-    pub fn edit_history(&mut self, history: &History, first: bool) -> Result<()> {
+    pub fn edit_history(&mut self, history: &HistoryBackend, first: bool) -> Result<()> {
         if history.is_empty() {
             return Ok(());
         }
@@ -480,12 +1388,10 @@ impl<'out, 'prompt> State<'out, 'prompt> {
         } else if self.history_index == 0 && first {
             return Ok(());
         }
+        self.save_history_edit(history);
         if first {
             self.history_index = 0;
-            let buf = history.get(self.history_index).unwrap();
-            self.changes.borrow_mut().begin();
-            self.line.update(buf, buf.len());
-            self.changes.borrow_mut().end();
+            self.recall_history_entry(history);
         } else {
             self.history_index = history.len();
             // Restore current edited line
@@ -501,24 +1407,43 @@ pub fn init_state<'out>(out: &'out mut Renderer, line: &str, pos: usize) -> Stat
         out,
         prompt: "",
         prompt_size: Position::default(),
+        rprompt: None,
         line: LineBuffer::init(line, pos, None),
         cursor: Position::default(),
         old_rows: 0,
         history_index: 0,
         saved_line_for_history: LineBuffer::with_capacity(100),
+        history_edits: HashMap::new(),
         byte_buffer: [0; 4],
         changes: Rc::new(RefCell::new(Changeset::new())),
         hinter: None,
+        history: None,
+        last_cmd: Cmd::Noop,
         highlighter: None,
         no_hint: true,
+        hint_cycle: None,
+        highlight_char: false,
+        highlight_cache: RefCell::new(Vec::with_capacity(HIGHLIGHT_CACHE_SIZE)),
+        mark: None,
+        replace_region: false,
+        search_match: None,
+        control_char_rendering: ControlCharRender::Caret,
+        line_numbers: false,
+        accepted: false,
+        private: false,
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::init_state;
+    use super::{display_control_chars, init_state, wrap, State};
+    use config::ControlCharRender;
+    use highlight::Highlighter;
     use history::History;
-    use tty::Sink;
+    use keymap::Refresher;
+    use std::borrow::Cow;
+    use std::cell::{Cell, RefCell};
+    use tty::{Position, Renderer, Sink};
 
     #[test]
     fn edit_history_next() {
@@ -557,4 +1482,296 @@ mod test {
         assert_eq!(2, s.history_index);
         assert_eq!(line, s.line.as_str());
     }
+
+    struct ReplaceHighlighter(&'static str, &'static str);
+
+    impl Highlighter for ReplaceHighlighter {
+        fn highlight<'l>(&self, line: &'l str, _: usize) -> Cow<'l, str> {
+            Cow::Owned(line.replace(self.0, self.1))
+        }
+
+        fn highlight_changes_width(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn highlight_changes_width() {
+        let mut out = Sink::new();
+        let h = ReplaceHighlighter("\t", "--->");
+        let mut s = init_state(&mut out, "a\tb", 1);
+        s.highlighter = Some(&h);
+
+        s.edit_insert('c', 1).unwrap();
+        assert_eq!(2, s.cursor.col);
+        // cursor after the tab
+        s.edit_move_forward(1).unwrap();
+        assert_eq!(6, s.cursor.col);
+        s.edit_move_end().unwrap();
+        assert_eq!(7, s.cursor.col);
+    }
+
+    struct SelectionHighlighter(RefCell<Option<(usize, usize)>>);
+
+    impl Highlighter for SelectionHighlighter {
+        fn highlight_selection<'l>(
+            &self,
+            line: &'l str,
+            _: usize,
+            start: usize,
+            end: usize,
+        ) -> Cow<'l, str> {
+            *self.0.borrow_mut() = Some((start, end));
+            Cow::Borrowed(line)
+        }
+
+        fn highlight_changes_width(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn highlight_selection() {
+        let mut out = Sink::new();
+        let h = SelectionHighlighter(RefCell::new(None));
+        let mut s = init_state(&mut out, "abcd", 3);
+        s.highlighter = Some(&h);
+
+        s.refresh_line().unwrap();
+        assert_eq!(None, *h.0.borrow());
+        s.edit_set_mark().unwrap();
+        s.edit_move_home().unwrap();
+        assert_eq!(Some((0, 3)), *h.0.borrow());
+        s.edit_move_forward(1).unwrap();
+        assert_eq!(Some((1, 3)), *h.0.borrow());
+    }
+
+    #[test]
+    fn highlight_changes_width_wide_chars() {
+        let mut out = Sink::new();
+        let h = ReplaceHighlighter("\t", "\u{3000}>");
+        let mut s = init_state(&mut out, "\u{4e2d}\tb", 0);
+        s.highlighter = Some(&h);
+
+        s.edit_move_forward(1).unwrap();
+        assert_eq!(2, s.cursor.col);
+        s.edit_move_end().unwrap();
+        assert_eq!(6, s.cursor.col);
+    }
+
+    #[test]
+    fn highlight_changes_width_mid_token() {
+        let mut out = Sink::new();
+        let h = ReplaceHighlighter("lambda", "\u{3bb}");
+        let mut s = init_state(&mut out, "lambda x", 3);
+        s.highlighter = Some(&h);
+
+        s.refresh_line().unwrap();
+        assert_eq!(3, s.cursor.col);
+        // the highlighted prefix is wider than the highlighted line
+        s.line.set_pos(5);
+        s.refresh_line().unwrap();
+        assert_eq!(3, s.cursor.col);
+        s.edit_move_end().unwrap();
+        assert_eq!(3, s.cursor.col);
+    }
+
+    struct CountingHighlighter(Cell<usize>);
+
+    impl Highlighter for CountingHighlighter {
+        fn highlight<'l>(&self, line: &'l str, _: usize) -> Cow<'l, str> {
+            self.0.set(self.0.get() + 1);
+            Cow::Owned(line.replace("\t", "--->"))
+        }
+
+        fn highlight_changes_width(&self) -> bool {
+            true
+        }
+
+        fn highlight_is_pos_dependent(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn highlight_cache() {
+        let mut out = Sink::new();
+        let h = CountingHighlighter(Cell::new(0));
+        let mut s = init_state(&mut out, "a\tb", 1);
+        s.highlighter = Some(&h);
+
+        // whole line and prefix
+        s.refresh_line().unwrap();
+        assert_eq!(2, h.0.get());
+        s.refresh_line().unwrap();
+        assert_eq!(2, h.0.get());
+        // the prefix is the whole line
+        s.edit_move_end().unwrap();
+        assert_eq!(2, h.0.get());
+        assert_eq!(6, s.cursor.col);
+        s.edit_move_home().unwrap();
+        assert_eq!(3, h.0.get());
+        assert_eq!(0, s.cursor.col);
+        // the line has changed
+        s.edit_insert('c', 1).unwrap();
+        assert_eq!(5, h.0.get());
+        assert_eq!(1, s.cursor.col);
+    }
+
+    struct AcceptedHighlighter(RefCell<Vec<String>>);
+
+    impl Highlighter for AcceptedHighlighter {
+        fn highlight<'l>(&self, line: &'l str, _: usize) -> Cow<'l, str> {
+            Cow::Borrowed(line)
+        }
+
+        fn highlight_accepted<'l>(&self, line: &'l str) -> Cow<'l, str> {
+            self.0.borrow_mut().push(line.to_owned());
+            Cow::Owned(format!("\x1b[32m{}\x1b[0m", line))
+        }
+
+        fn highlight_changes_width(&self) -> bool {
+            true
+        }
+
+        fn highlight_is_pos_dependent(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn refresh_accepted() {
+        let mut out = Sink::new();
+        let h = AcceptedHighlighter(RefCell::new(Vec::new()));
+        let mut s = init_state(&mut out, "line", 1);
+        s.highlighter = Some(&h);
+        s.mark = Some(0);
+
+        s.refresh_line().unwrap();
+        assert!(h.0.borrow().is_empty());
+        s.edit_move_end().unwrap();
+        s.refresh_accepted().unwrap();
+        // whole line and prefix
+        assert_eq!(vec!["line"; 2], *h.0.borrow());
+        assert_eq!(None, s.mark);
+        assert_eq!(4, s.cursor.col);
+        // not cached
+        s.refresh_line().unwrap();
+        assert_eq!(4, h.0.borrow().len());
+
+        // no highlighter
+        let mut out = Sink::new();
+        let mut s = init_state(&mut out, "line", 1);
+        s.refresh_accepted().unwrap();
+        assert!(!s.accepted);
+    }
+
+    struct DisabledHighlighter(Cell<usize>);
+
+    impl Highlighter for DisabledHighlighter {
+        fn highlight<'l>(&self, line: &'l str, _: usize) -> Cow<'l, str> {
+            self.0.set(self.0.get() + 1);
+            Cow::Borrowed(line)
+        }
+
+        fn highlight_changes_width(&self) -> bool {
+            true
+        }
+
+        fn is_enabled(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn highlight_disabled() {
+        let mut out = Sink::new();
+        let h = DisabledHighlighter(Cell::new(0));
+        let mut s = State::new(
+            &mut out,
+            "> ",
+            0,
+            None,
+            Some(&h),
+            ControlCharRender::Caret,
+            false,
+        );
+        assert!(s.highlighter.is_none());
+        s.edit_insert('a', 1).unwrap();
+        s.edit_insert('b', 1).unwrap();
+        s.edit_move_home().unwrap();
+        s.refresh_line().unwrap();
+        assert_eq!(0, h.0.get());
+        assert_eq!("ab", s.line.as_str());
+        assert_eq!(2, s.cursor.col);
+    }
+
+    #[test]
+    fn hyperlink_width() {
+        let out = Sink::new();
+        // ST and BEL terminators
+        for link in &[
+            "\x1b]8;;https://example.com\x1b\\ab\x1b]8;;\x1b\\c",
+            "\x1b]8;;https://example.com\x07ab\x1b]8;;\x07c",
+        ] {
+            let pos = out.calculate_position(link, Position::default());
+            assert_eq!(3, pos.col);
+        }
+    }
+
+    #[test]
+    fn line_numbers() {
+        let mut out = Sink::new();
+        let mut s = init_state(&mut out, "ab", 1);
+        s.line_numbers = true;
+        s.refresh_line().unwrap();
+        // after "1 | a"
+        assert_eq!(5, s.cursor.col);
+        s.edit_move_end().unwrap();
+        assert_eq!(6, s.cursor.col);
+
+        // the gutter is sized to the number of lines
+        let line = "a\n".repeat(9) + "b";
+        s.line.update(&line, 0);
+        s.refresh_line().unwrap();
+        assert_eq!(5, s.cursor.col);
+        assert_eq!(line, s.line.as_str());
+    }
+
+    #[test]
+    fn show_below() {
+        assert_eq!(vec!["abc", "de", "", "f"], wrap("abcde\n\nf", 3));
+        assert_eq!(vec!["\u{4e2d}", "\u{6587}"], wrap("\u{4e2d}\u{6587}", 3));
+
+        let mut out = Sink::new();
+        let mut s = init_state(&mut out, "ab", 1);
+        s.refresh_line().unwrap();
+        s.show_below("fn foo()\nDoes foo.\nAnd bar.", 2).unwrap();
+        assert_eq!(2, s.old_rows);
+        assert_eq!(1, s.cursor.col);
+        // erased
+        s.refresh_line().unwrap();
+        assert_eq!(0, s.old_rows);
+    }
+
+    #[test]
+    fn control_chars() {
+        let mut out = Sink::new();
+        let mut s = init_state(&mut out, "a\x01b", 2);
+        s.refresh_line().unwrap();
+        assert_eq!(3, s.cursor.col);
+        s.edit_move_end().unwrap();
+        assert_eq!(4, s.cursor.col);
+        s.edit_insert('\x1b', 1).unwrap();
+        assert_eq!(6, s.cursor.col);
+        assert_eq!("a\x01b\x1b", s.line.as_str());
+
+        s.control_char_rendering = ControlCharRender::Hex;
+        s.refresh_line().unwrap();
+        assert_eq!(14, s.cursor.col);
+        assert_eq!(
+            "^A<0x85>\t\n^?",
+            display_control_chars("\x01\u{85}\t\n\x7f", ControlCharRender::Caret)
+        );
+    }
 }
@@ -0,0 +1,100 @@
+//! Edition of the line in an external editor (see `Cmd::Edit`).
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::Result;
+
+#[cfg(unix)]
+const DEFAULT_EDITOR: &str = "vi";
+#[cfg(windows)]
+const DEFAULT_EDITOR: &str = "notepad";
+
+// distinguishes the temporary files of a process
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Temporary file, removed when dropped.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn create() -> io::Result<(TempFile, File)> {
+        let dir = env::temp_dir();
+        loop {
+            let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+            let path = dir.join(format!("rustyline-{}-{}.txt", process::id(), n));
+            match create_new(&path) {
+                Ok(file) => return Ok((TempFile(path), file)),
+                Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+// readable by the user only (the line may be sensitive)
+#[cfg(unix)]
+fn create_new(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+}
+#[cfg(windows)]
+fn create_new(path: &Path) -> io::Result<File> {
+    OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+/// Command line of the editor when none is set: `$VISUAL`, `$EDITOR` or the
+/// platform default (`vi` / `notepad`).
+fn default_editor() -> String {
+    env::var("VISUAL")
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .or_else(|| env::var("EDITOR").ok())
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_owned())
+}
+
+/// Lets the user edit `text` in an external editor (the `editor` command
+/// line, or else the default one) and returns the edited text (without the
+/// final newline added by most editors).
+///
+/// Returns `None` if the editor has exited with a non-zero status.
+pub fn edit(text: &str, editor: Option<&str>) -> Result<Option<String>> {
+    let (temp_file, mut file) = try!(TempFile::create());
+    try!(file.write_all(text.as_bytes()));
+    drop(file);
+
+    let editor = match editor {
+        Some(editor) if !editor.trim().is_empty() => editor.to_owned(),
+        _ => default_editor(),
+    };
+    let mut args = editor.split_whitespace();
+    let program = args.next().unwrap();
+    let status = try!(Command::new(program).args(args).arg(&temp_file.0).status());
+    if !status.success() {
+        return Ok(None);
+    }
+
+    let mut edited = String::new();
+    try!(try!(File::open(&temp_file.0)).read_to_string(&mut edited));
+    if edited.ends_with('\n') {
+        edited.pop();
+        if edited.ends_with('\r') {
+            edited.pop();
+        }
+    }
+    Ok(Some(edited))
+}
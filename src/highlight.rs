@@ -1,23 +1,110 @@
 //! Syntax highlighting
 
-use config::CompletionType;
-use std::borrow::Cow::{self, Borrowed};
+use anstyle::{self, AnsiColor};
+use completion::Candidate;
+use config::{ColorDepth, CompletionType};
+use memchr::memchr;
+use std::borrow::Cow::{self, Borrowed, Owned};
+use std::fmt::Write;
+use std::io;
+use std::iter::{self, FromIterator};
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+#[cfg(feature = "regex")]
+mod pattern;
+#[cfg(feature = "regex")]
+pub use self::pattern::PatternHighlighter;
+#[cfg(feature = "tree-sitter")]
+mod tree_sitter;
+#[cfg(feature = "tree-sitter")]
+pub use self::tree_sitter::TreeSitterHighlighter;
+
+/// A byte range of the line with its style.
+pub type Span = (Range<usize>, anstyle::Style);
 
 /// Syntax highlighter with [ansi color](https://en.wikipedia.org/wiki/ANSI_escape_code#SGR_(Select_Graphic_Rendition)_parameters).
 /// Rustyline will try to handle escape sequence for ansi color on windows
 /// when not supported natively (windows <10).
 ///
-/// Currently, the highlighted version *must* have the same display width as
-/// the original input.
+/// By default, the highlighted version *must* have the same display width as
+/// the original input (see `highlight_changes_width`).
 pub trait Highlighter {
     /// Takes the currently edited `line` with the cursor `pos`ition and
     /// returns the highlighted version (with ANSI color).
     ///
     /// For example, you can implement
     /// [blink-matching-paren](https://www.gnu.org/software/bash/manual/html_node/Readline-Init-File-Syntax.html).
+    ///
+    /// By default, the styles returned by `highlight_spans` are applied
+    /// (and the mixed indentation, see `highlight_mixed_indent`).
+    /// Override this method for full control over the output.
     fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
-        let _ = pos;
-        Borrowed(line)
+        let spans = self.highlight_spans(line, pos);
+        if self.highlight_mixed_indent() {
+            let indents = mixed_indent_spans(line, self.mixed_indent_style());
+            if !indents.is_empty() {
+                let spans = merge_spans(line.len(), spans.into_iter().chain(indents));
+                return render_spans(line, &spans);
+            }
+        }
+        render_spans(line, &spans)
+    }
+    /// Takes the currently edited `line` with the cursor `pos`ition and the
+    /// byte range [`start`, `end`) of the active region (between the mark
+    /// and the cursor) and returns the highlighted version (with ANSI color).
+    ///
+    /// Only called while a mark is set (see `Cmd::SetMark`).
+    /// By default, the selection is not distinguished and `highlight` is used.
+    fn highlight_selection<'l>(
+        &self,
+        line: &'l str,
+        pos: usize,
+        start: usize,
+        end: usize,
+    ) -> Cow<'l, str> {
+        let _ = (start, end);
+        self.highlight(line, pos)
+    }
+    /// Takes the `line` recalled by the incremental search with the cursor
+    /// `pos`ition and the byte range [`start`, `end`) matched by the query
+    /// and returns the highlighted version (with ANSI color).
+    ///
+    /// By default, the match is displayed in reverse video over the
+    /// `highlight_spans`.
+    fn highlight_search_match<'l>(
+        &self,
+        line: &'l str,
+        pos: usize,
+        start: usize,
+        end: usize,
+    ) -> Cow<'l, str> {
+        let matched = (start..end, anstyle::Style::new().invert());
+        let spans = self
+            .highlight_spans(line, pos)
+            .into_iter()
+            .chain(Some(matched));
+        render_spans(line, &merge_spans(line.len(), spans))
+    }
+    /// Takes the accepted `line` and returns the highlighted version (with
+    /// ANSI color) displayed once the user has pressed Enter, like to show
+    /// it as submitted.
+    ///
+    /// Only used for the final refresh (without hint) of the line.
+    /// By default, `highlight` is used with the cursor at the end of the
+    /// line.
+    fn highlight_accepted<'l>(&self, line: &'l str) -> Cow<'l, str> {
+        self.highlight(line, line.len())
+    }
+    /// Takes the currently edited `line` with the cursor `pos`ition and
+    /// returns the styled byte ranges (sorted and non-overlapping).
+    ///
+    /// Spans are easier to test and to compose than the output of
+    /// `highlight`: a highlighter which only overrides `highlight`
+    /// contributes nothing to a `CombinedHighlighter`.
+    fn highlight_spans(&self, line: &str, pos: usize) -> Vec<Span> {
+        let _ = (line, pos);
+        Vec::new()
     }
     /// Takes the `prompt` and
     /// returns the highlighted version (with ANSI color).
@@ -26,19 +113,57 @@ pub trait Highlighter {
     }
     /// Takes the dynamic `prompt` and
     /// returns the highlighted version (with ANSI color).
-    #[deprecated(
-        since = "2.0.1",
-        note = "please use `highlight_prompt` instead"
-    )]
+    #[deprecated(since = "2.0.1", note = "please use `highlight_prompt` instead")]
     fn highlight_dynamic_prompt<'p>(&self, prompt: &'p str) -> Cow<'p, str> {
         Borrowed(prompt)
     }
     /// Takes the `hint` and
     /// returns the highlighted version (with ANSI color).
+    ///
+    /// The hint can be split in segments styled differently with
+    /// `StyledBlocks`:
+    ///
+    /// ```
+    /// extern crate anstyle;
+    /// extern crate rustyline;
+    ///
+    /// use anstyle::Style;
+    /// use rustyline::highlight::{Highlighter, StyledBlocks};
+    /// use std::borrow::Cow;
+    ///
+    /// struct HintHighlighter {
+    ///     // styles of the first word (accepted on Tab) and of the remainder
+    ///     styles: [Style; 2],
+    /// }
+    ///
+    /// impl Highlighter for HintHighlighter {
+    ///     fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+    ///         let word = hint.find(' ').unwrap_or(hint.len());
+    ///         let segments = [&hint[..word], &hint[word..]];
+    ///         StyledBlocks(self.styles.iter().cloned().zip(segments.iter().cloned())).into()
+    ///     }
+    /// }
+    /// # fn main() {
+    /// let h = HintHighlighter {
+    ///     styles: [Style::new().bold(), Style::new().dimmed()],
+    /// };
+    /// assert_eq!(
+    ///     "\x1b[1mfoo\x1b[0m\x1b[2m bar\x1b[0m",
+    ///     h.highlight_hint("foo bar")
+    /// );
+    /// assert_eq!("", h.highlight_hint(""));
+    /// # }
+    /// ```
     fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
         Borrowed(hint)
     }
-    /// Takes the completion `canditate` and
+    /// Takes the line number `gutter` (like ` 9 | `, see
+    /// `Configurer::set_line_numbers`) and
+    /// returns the highlighted version (with ANSI color).
+    fn highlight_line_number<'g>(&self, gutter: &'g str) -> Cow<'g, str> {
+        Borrowed(gutter)
+    }
+    /// Takes the completion `candidate` and
     /// returns the highlighted version (with ANSI color).
     ///
     /// Currently, used only with `CompletionType::List`.
@@ -50,6 +175,70 @@ pub trait Highlighter {
         let _ = completion;
         Borrowed(candidate)
     }
+    /// Takes the completion `candidate` and
+    /// returns the highlighted version (with ANSI color) of its `display`.
+    ///
+    /// The `replacement` is also available, for example to style the part of
+    /// the `display` text which is a description differently from the match.
+    ///
+    /// By default, `highlight_candidate` is applied to the `display` and, if
+    /// it is left as is, the chars matched by the typed text (see
+    /// `Candidate::match_indices`) are displayed in bold.
+    ///
+    /// Currently, used only with `CompletionType::List`,
+    /// `CompletionType::ListWithDescription` and `CompletionType::Menu`.
+    fn highlight_candidate_item<'c>(
+        &self,
+        candidate: &'c Candidate,
+        completion: CompletionType,
+    ) -> Cow<'c, str> {
+        match self.highlight_candidate(candidate.display(), completion) {
+            Borrowed(display) => highlight_match_indices(display, candidate.match_indices()),
+            highlighted => highlighted,
+        }
+    }
+    /// Takes the completion `candidate` selected in the menu and
+    /// returns the highlighted version (with ANSI color) of its `display`.
+    ///
+    /// By default, the output of `highlight_candidate_item` is displayed in
+    /// reverse video.
+    /// Currently, used only with `CompletionType::Menu`.
+    fn highlight_selected_candidate<'c>(
+        &self,
+        candidate: &'c Candidate,
+        completion: CompletionType,
+    ) -> Cow<'c, str> {
+        Owned(format!(
+            "\x1b[7m{}\x1b[27m",
+            self.highlight_candidate_item(candidate, completion)
+        ))
+    }
+    /// Takes the `kind` of a completion candidate (see `Candidate::kind`)
+    /// and returns the highlighted version (with ANSI color).
+    ///
+    /// By default, the kind is dim.
+    /// Currently, used only with `CompletionType::List`,
+    /// `CompletionType::ListWithDescription` and `CompletionType::Menu`.
+    fn highlight_candidate_kind<'k>(
+        &self,
+        kind: &'k str,
+        completion: CompletionType,
+    ) -> Cow<'k, str> {
+        let _ = completion;
+        Owned(format!("\x1b[2m{}\x1b[22m", kind))
+    }
+    /// Tells if the highlighted version of the line may have a different
+    /// display width than the original input (e.g. tabs rendered as arrows
+    /// or long tokens collapsed).
+    ///
+    /// When `true`, the display width is measured from the highlighted output
+    /// and the cursor is placed at the end of the highlighted `line[..pos]`
+    /// (but never after the end of the highlighted line, if the cursor lands
+    /// in the middle of a collapsed token).
+    /// Each cursor movement triggers a full refresh.
+    fn highlight_changes_width(&self) -> bool {
+        false
+    }
     /// Tells if the `ch`ar needs to be highlighted when typed or when cursor
     /// is moved under.
     ///
@@ -59,6 +248,2006 @@ pub trait Highlighter {
         let _ = grapheme;
         false
     }
+    /// Tells if `line` needs to be highlighted when a specific char is typed
+    /// or when cursor is moved under a specific char.
+    /// `pos` is the cursor position (after the char typed).
+    ///
+    /// Used to optimize refresh when a character is inserted or the cursor is
+    /// moved. By default, `highlight_char` is asked for the grapheme before
+    /// and the one under the cursor.
+    fn highlight_char_at(&self, line: &str, pos: usize) -> bool {
+        // the char typed may change the indentation
+        if self.highlight_mixed_indent() && is_indent(line, pos) {
+            return true;
+        }
+        line[..pos]
+            .graphemes(true)
+            .next_back()
+            .map_or(false, |g| self.highlight_char(g))
+            || line[pos..]
+                .graphemes(true)
+                .next()
+                .map_or(false, |g| self.highlight_char(g))
+    }
+    /// Tells if the highlighted version of the line depends on the cursor
+    /// `pos`ition (like a bracket matching the one under the cursor).
+    ///
+    /// When `false`, the output of `highlight` is reused when only the cursor
+    /// has moved (for example after `highlight_char_at` returned `true`): an
+    /// expensive highlighter (a full-line tokenizer) should opt in.
+    fn highlight_is_pos_dependent(&self) -> bool {
+        true
+    }
+    /// Tells if the highlighter does anything at all.
+    ///
+    /// When `false`, the line is never highlighted (nor remeasured), which
+    /// saves work on each keystroke for long lines.
+    fn is_enabled(&self) -> bool {
+        true
+    }
+    /// Tells if the leading whitespace of the lines indented with both tabs
+    /// and spaces (an error in Python for example) is highlighted with
+    /// `mixed_indent_style`.
+    ///
+    /// Applied by the default `highlight` and `highlight_char_at`.
+    fn highlight_mixed_indent(&self) -> bool {
+        false
+    }
+    /// Style of the mixed indentation (yellow background by default).
+    fn mixed_indent_style(&self) -> anstyle::Style {
+        anstyle::Style::new().bg_color(Some(AnsiColor::Yellow.into()))
+    }
+}
+
+impl Highlighter for () {
+    fn is_enabled(&self) -> bool {
+        false
+    }
+}
+
+/// Tells if the leading whitespace of `line` contains both tabs and spaces.
+pub fn has_mixed_indent(line: &str) -> bool {
+    let indent = line.bytes().take_while(|&b| b == b' ' || b == b'\t');
+    let (mut space, mut tab) = (false, false);
+    for b in indent {
+        if b == b' ' {
+            space = true;
+        } else {
+            tab = true;
+        }
+    }
+    space && tab
+}
+
+// leading whitespace of each line (of a multi-line input) with mixed indent
+fn mixed_indent_spans(line: &str, style: anstyle::Style) -> Vec<Span> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+    for l in line.split('\n') {
+        if has_mixed_indent(l) {
+            // spaces and tabs are single bytes
+            let blanks = l.chars().take_while(|c| is_blank(*c)).count();
+            spans.push((start..start + blanks, style));
+        }
+        start += l.len() + 1;
+    }
+    spans
+}
+
+// `line[..pos]` ends in the indentation of its (logical) line
+fn is_indent(line: &str, pos: usize) -> bool {
+    let start = line[..pos].rfind('\n').map_or(0, |i| i + 1);
+    pos > start && line[start..pos].chars().all(is_blank)
+}
+
+/// Merges the `spans` (in order: on overlapping ranges, the later style
+/// wins) of a `len` bytes long line.
+fn merge_spans<I: IntoIterator<Item = Span>>(len: usize, spans: I) -> Vec<Span> {
+    let mut styles: Vec<Option<anstyle::Style>> = vec![None; len];
+    for (range, style) in spans {
+        for s in &mut styles[range] {
+            *s = Some(style);
+        }
+    }
+    // coalesce adjacent bytes with the same style
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < styles.len() {
+        if let Some(style) = styles[i] {
+            let start = i;
+            while i < styles.len() && styles[i] == Some(style) {
+                i += 1;
+            }
+            spans.push((start..i, style));
+        } else {
+            i += 1;
+        }
+    }
+    spans
+}
+
+/// Renders `line` with the styled `spans` (sorted and non-overlapping).
+pub fn render_spans<'l>(line: &'l str, spans: &[Span]) -> Cow<'l, str> {
+    if spans.iter().all(|&(_, style)| style.is_plain()) {
+        return Borrowed(line);
+    }
+    let mut copy = String::with_capacity(line.len() + 8 * spans.len());
+    let mut end = 0;
+    for &(ref range, style) in spans {
+        copy.push_str(&line[end..range.start]);
+        write!(
+            copy,
+            "{}{}{}",
+            style.render(),
+            &line[range.clone()],
+            style.render_reset()
+        )
+        .unwrap();
+        end = range.end;
+    }
+    copy.push_str(&line[end..]);
+    Owned(copy)
+}
+
+/// Renders `display` with the chars at the byte `indices` (sorted) in bold
+/// (see `Candidate::match_indices`). The indices out of order, out of bounds
+/// or inside a char are ignored.
+fn highlight_match_indices<'d>(display: &'d str, indices: &[usize]) -> Cow<'d, str> {
+    let bold = anstyle::Style::new().bold();
+    let mut spans: Vec<Span> = Vec::new();
+    for &i in indices {
+        let valid = i < display.len()
+            && display.is_char_boundary(i)
+            && spans.last().map_or(true, |&(ref range, _)| range.end <= i);
+        if !valid {
+            continue;
+        }
+        let end = i + display[i..].chars().next().map_or(0, char::len_utf8);
+        match spans.last_mut() {
+            Some(&mut (ref mut range, _)) if range.end == i => range.end = end,
+            _ => spans.push((i..end, bold)),
+        }
+    }
+    render_spans(display, &spans)
+}
+
+/// Escape sequences around a styled text.
+pub trait Style {
+    /// Sequence which applies the style (nothing for a plain style).
+    fn start(&self) -> String;
+    /// Sequence which resets the style (nothing for a plain style).
+    fn end(&self) -> String;
+}
+
+impl Style for anstyle::Style {
+    fn start(&self) -> String {
+        self.render().to_string()
+    }
+
+    fn end(&self) -> String {
+        self.render_reset().to_string()
+    }
+}
+
+#[cfg(feature = "crossterm-style")]
+impl Style for ::crossterm::style::ContentStyle {
+    fn start(&self) -> String {
+        use crossterm::style::SetStyle;
+        use crossterm::Command;
+        let mut s = String::new();
+        SetStyle(*self).write_ansi(&mut s).unwrap();
+        s
+    }
+
+    fn end(&self) -> String {
+        if *self == Self::default() {
+            String::new()
+        } else {
+            "\x1b[0m".to_owned()
+        }
+    }
+}
+
+/// A segment of text with its style.
+pub trait StyledBlock {
+    /// Type of the style (like `anstyle::Style`)
+    type Style: Style;
+    /// Style of the `text`
+    fn style(&self) -> &Self::Style;
+    /// Text to display
+    fn text(&self) -> &str;
+    /// Target of the hyperlink on the `text` (if any)
+    fn url(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl<'t, S: Style> StyledBlock for (S, &'t str) {
+    type Style = S;
+
+    fn style(&self) -> &S {
+        &self.0
+    }
+
+    fn text(&self) -> &str {
+        self.1
+    }
+}
+
+impl<S: Style> StyledBlock for (S, String) {
+    type Style = S;
+
+    fn style(&self) -> &S {
+        &self.0
+    }
+
+    fn text(&self) -> &str {
+        &self.1
+    }
+}
+
+impl<'b, B: StyledBlock> StyledBlock for &'b B {
+    type Style = B::Style;
+
+    fn style(&self) -> &B::Style {
+        (**self).style()
+    }
+
+    fn text(&self) -> &str {
+        (**self).text()
+    }
+
+    fn url(&self) -> Option<&str> {
+        (**self).url()
+    }
+}
+
+/// A segment of text rendered as a hyperlink (with the OSC 8 escape
+/// sequence) on terminals which support it.
+///
+/// The escape sequence has no display width.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinkedBlock<'t> {
+    pub style: anstyle::Style,
+    pub text: &'t str,
+    pub url: &'t str,
+}
+
+impl<'t> StyledBlock for LinkedBlock<'t> {
+    type Style = anstyle::Style;
+
+    fn style(&self) -> &anstyle::Style {
+        &self.style
+    }
+
+    fn text(&self) -> &str {
+        self.text
+    }
+
+    fn url(&self) -> Option<&str> {
+        Some(self.url)
+    }
+}
+
+/// Styled segments rendered one after the other.
+///
+/// The blocks are iterated exactly once, when rendered.
+/// Empty blocks and plain styles produce no escape sequence.
+///
+/// Precomputed blocks can be rendered from a borrowed slice (or `Vec`):
+///
+/// ```
+/// extern crate anstyle;
+/// extern crate rustyline;
+///
+/// use anstyle::Style;
+/// use rustyline::highlight::StyledBlocks;
+///
+/// # fn main() {
+/// let tokens = vec![
+///     (Style::new().bold(), "let".to_owned()),
+///     (Style::new(), " x".to_owned()),
+/// ];
+/// assert_eq!("\x1b[1mlet\x1b[0m x", StyledBlocks(&tokens[..]).render());
+/// // still available
+/// assert_eq!(2, tokens.len());
+/// # }
+/// ```
+pub struct StyledBlocks<I>(pub I);
+
+impl<I> StyledBlocks<I>
+where
+    I: IntoIterator,
+    I::Item: StyledBlock,
+{
+    /// Renders the blocks (with ANSI color).
+    pub fn render(self) -> String {
+        let mut s = String::new();
+        for block in self.0 {
+            render_block(&block, &mut s);
+        }
+        s
+    }
+
+    /// Writes the blocks (with ANSI color) to `w`, one after the other.
+    ///
+    /// Unlike formatting the rendered blocks, the actual I/O error is
+    /// returned (like `BrokenPipe` when the reader of a pipe exits early).
+    pub fn print_to<W: io::Write>(self, w: &mut W) -> io::Result<()> {
+        let mut s = String::new();
+        for block in self.0 {
+            s.clear();
+            render_block(&block, &mut s);
+            try!(w.write_all(s.as_bytes()));
+        }
+        Ok(())
+    }
+
+    /// Writes the blocks (with ANSI color) to the standard output.
+    pub fn print(self) -> io::Result<()> {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        try!(self.print_to(&mut stdout));
+        io::Write::flush(&mut stdout)
+    }
+
+    /// Post-processes each block with `f` (lazily, when rendered).
+    ///
+    /// ```
+    /// extern crate anstyle;
+    /// extern crate rustyline;
+    ///
+    /// use anstyle::Style;
+    /// use rustyline::highlight::StyledBlocks;
+    ///
+    /// # fn main() {
+    /// let line = "let x";
+    /// let blocks: StyledBlocks<_> = line.split(' ').map(|w| (Style::new(), w)).collect();
+    /// let keyword = Style::new().bold();
+    /// let blocks = blocks.map(|(style, w)| {
+    ///     let style = if w == "let" { keyword } else { style };
+    ///     (style, format!("{} ", w))
+    /// });
+    /// assert_eq!("\x1b[1mlet \x1b[0mx ", blocks.render());
+    /// # }
+    /// ```
+    pub fn map<F, B>(self, f: F) -> StyledBlocks<iter::Map<I::IntoIter, F>>
+    where
+        F: FnMut(I::Item) -> B,
+        B: StyledBlock,
+    {
+        StyledBlocks(self.0.into_iter().map(f))
+    }
+}
+
+/// Collects the blocks (like `(Style, &str)` tuples) to be rendered.
+impl<B: StyledBlock> FromIterator<B> for StyledBlocks<Vec<B>> {
+    fn from_iter<T: IntoIterator<Item = B>>(iter: T) -> StyledBlocks<Vec<B>> {
+        StyledBlocks(iter.into_iter().collect())
+    }
+}
+
+fn render_block<B: StyledBlock>(block: &B, s: &mut String) {
+    let (style, text) = (block.style(), block.text());
+    if text.is_empty() {
+        return;
+    }
+    let url = block.url();
+    if let Some(url) = url {
+        write!(s, "\x1b]8;;{}\x1b\\", url).unwrap();
+    }
+    let start = style.start();
+    if start.is_empty() {
+        s.push_str(text);
+    } else {
+        s.push_str(&start);
+        s.push_str(text);
+        s.push_str(&style.end());
+    }
+    if url.is_some() {
+        s.push_str("\x1b]8;;\x1b\\");
+    }
+}
+
+impl<'t, I> From<StyledBlocks<I>> for Cow<'t, str>
+where
+    I: IntoIterator,
+    I::Item: StyledBlock,
+{
+    fn from(blocks: StyledBlocks<I>) -> Cow<'t, str> {
+        Owned(blocks.render())
+    }
+}
+
+/// Rewrites the colors of the SGR escape sequences in `s` (24-bit and 256
+/// colors) to the nearest colors supported with `depth`.
+pub fn downsample<'s>(s: &'s str, depth: ColorDepth) -> Cow<'s, str> {
+    if depth == ColorDepth::TrueColor || !s.contains("\x1b[") {
+        return Borrowed(s);
+    }
+    let mut copy = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find("\x1b[") {
+        copy.push_str(&rest[..i]);
+        let seq = &rest[i + 2..];
+        let end = seq.find(|c: char| !(c.is_ascii_digit() || c == ';'));
+        match end {
+            Some(j) if seq.as_bytes()[j] == b'm' => {
+                let params = downsample_params(&seq[..j], depth);
+                if !params.is_empty() || j == 0 {
+                    write!(copy, "\x1b[{}m", params).unwrap();
+                }
+                rest = &seq[j + 1..];
+            }
+            _ => {
+                // not an SGR sequence
+                copy.push_str("\x1b[");
+                rest = seq;
+            }
+        }
+    }
+    copy.push_str(rest);
+    Owned(copy)
+}
+
+fn downsample_params(params: &str, depth: ColorDepth) -> String {
+    let params: Vec<&str> = params.split(';').collect();
+    let mut codes: Vec<String> = Vec::with_capacity(params.len());
+    let mut i = 0;
+    while i < params.len() {
+        // foreground, background or underline color
+        let target = params[i];
+        if target != "38" && target != "48" && target != "58" {
+            codes.push(params[i].to_owned());
+            i += 1;
+            continue;
+        }
+        let (index, len) = match params.get(i + 1) {
+            Some(&"2") if i + 4 < params.len() => {
+                let rgb: Vec<u8> = params[i + 2..i + 5]
+                    .iter()
+                    .filter_map(|c| c.parse().ok())
+                    .collect();
+                if rgb.len() == 3 {
+                    (Some(rgb_to_ansi256(rgb[0], rgb[1], rgb[2])), 5)
+                } else {
+                    (None, 5)
+                }
+            }
+            Some(&"5") if i + 2 < params.len() => (params[i + 2].parse().ok(), 3),
+            _ => (None, 1),
+        };
+        match index {
+            Some(index) if depth == ColorDepth::Ansi256 => {
+                codes.push(format!("{};5;{}", target, index));
+            }
+            Some(index) => {
+                let (r, g, b) = ansi256_to_rgb(index);
+                let index = rgb_to_ansi16(r, g, b);
+                let (normal, bright) = match target {
+                    "38" => (30, 90),
+                    "48" => (40, 100),
+                    _ => {
+                        // no underline color with 16 colors
+                        i += len;
+                        continue;
+                    }
+                };
+                if index < 8 {
+                    codes.push((normal + index).to_string());
+                } else {
+                    codes.push((bright + index - 8).to_string());
+                }
+            }
+            None => codes.extend(params[i..i + len].iter().map(|c| (*c).to_owned())),
+        }
+        i += len;
+    }
+    codes.join(";")
+}
+
+// xterm default colors
+static ANSI16: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+fn distance(c1: (u8, u8, u8), c2: (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2) as u32;
+    d(c1.0, c2.0) + d(c1.1, c2.1) + d(c1.2, c2.2)
+}
+
+fn rgb_to_ansi16(r: u8, g: u8, b: u8) -> u8 {
+    (0..16)
+        .min_by_key(|&i| distance(ANSI16[i], (r, g, b)))
+        .unwrap() as u8
+}
+
+fn rgb_to_ansi256(r: u8, g: u8, b: u8) -> u8 {
+    // 6x6x6 color cube
+    let cube = |v: u8| {
+        if v < 48 {
+            0
+        } else if v < 115 {
+            1
+        } else {
+            (v - 35) / 40
+        }
+    };
+    let cube_index = 16 + 36 * cube(r) + 6 * cube(g) + cube(b);
+    // grayscale ramp
+    let average = (u32::from(r) + u32::from(g) + u32::from(b)) / 3;
+    let gray_index = if average > 238 {
+        255
+    } else {
+        232 + (average.saturating_sub(3) / 10) as u8
+    };
+    if distance(ansi256_to_rgb(gray_index), (r, g, b))
+        < distance(ansi256_to_rgb(cube_index), (r, g, b))
+    {
+        gray_index
+    } else {
+        cube_index
+    }
+}
+
+fn ansi256_to_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI16[index as usize],
+        16..=231 => {
+            let index = index - 16;
+            let level = |v: u8| if v == 0 { 0 } else { 55 + 40 * v };
+            (level(index / 36), level(index / 6 % 6), level(index % 6))
+        }
+        _ => {
+            let gray = 8 + 10 * (index - 232);
+            (gray, gray, gray)
+        }
+    }
+}
+
+fn downsample_cow<'s>(s: Cow<'s, str>, depth: ColorDepth) -> Cow<'s, str> {
+    match s {
+        Borrowed(s) => downsample(s, depth),
+        Owned(s) => {
+            let downsampled = match downsample(&s, depth) {
+                Owned(downsampled) => Some(downsampled),
+                Borrowed(_) => None,
+            };
+            Owned(downsampled.unwrap_or(s))
+        }
+    }
+}
+
+/// Downsamples the colors of a highlighter to the color depth of the
+/// terminal.
+pub(crate) struct Downsampler<'h> {
+    pub highlighter: &'h Highlighter,
+    pub depth: ColorDepth,
+}
+
+impl<'h> Highlighter for Downsampler<'h> {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        downsample_cow(self.highlighter.highlight(line, pos), self.depth)
+    }
+
+    fn highlight_selection<'l>(
+        &self,
+        line: &'l str,
+        pos: usize,
+        start: usize,
+        end: usize,
+    ) -> Cow<'l, str> {
+        downsample_cow(
+            self.highlighter.highlight_selection(line, pos, start, end),
+            self.depth,
+        )
+    }
+
+    fn highlight_search_match<'l>(
+        &self,
+        line: &'l str,
+        pos: usize,
+        start: usize,
+        end: usize,
+    ) -> Cow<'l, str> {
+        downsample_cow(
+            self.highlighter
+                .highlight_search_match(line, pos, start, end),
+            self.depth,
+        )
+    }
+
+    fn highlight_accepted<'l>(&self, line: &'l str) -> Cow<'l, str> {
+        downsample_cow(self.highlighter.highlight_accepted(line), self.depth)
+    }
+
+    fn highlight_spans(&self, line: &str, pos: usize) -> Vec<Span> {
+        self.highlighter.highlight_spans(line, pos)
+    }
+
+    fn highlight_prompt<'p>(&self, prompt: &'p str) -> Cow<'p, str> {
+        downsample_cow(self.highlighter.highlight_prompt(prompt), self.depth)
+    }
+
+    fn highlight_hint<'t>(&self, hint: &'t str) -> Cow<'t, str> {
+        downsample_cow(self.highlighter.highlight_hint(hint), self.depth)
+    }
+
+    fn highlight_line_number<'g>(&self, gutter: &'g str) -> Cow<'g, str> {
+        downsample_cow(self.highlighter.highlight_line_number(gutter), self.depth)
+    }
+
+    fn highlight_candidate<'c>(
+        &self,
+        candidate: &'c str,
+        completion: CompletionType,
+    ) -> Cow<'c, str> {
+        downsample_cow(
+            self.highlighter.highlight_candidate(candidate, completion),
+            self.depth,
+        )
+    }
+
+    fn highlight_candidate_item<'c>(
+        &self,
+        candidate: &'c Candidate,
+        completion: CompletionType,
+    ) -> Cow<'c, str> {
+        downsample_cow(
+            self.highlighter
+                .highlight_candidate_item(candidate, completion),
+            self.depth,
+        )
+    }
+
+    fn highlight_selected_candidate<'c>(
+        &self,
+        candidate: &'c Candidate,
+        completion: CompletionType,
+    ) -> Cow<'c, str> {
+        downsample_cow(
+            self.highlighter
+                .highlight_selected_candidate(candidate, completion),
+            self.depth,
+        )
+    }
+
+    fn highlight_candidate_kind<'k>(
+        &self,
+        kind: &'k str,
+        completion: CompletionType,
+    ) -> Cow<'k, str> {
+        downsample_cow(
+            self.highlighter.highlight_candidate_kind(kind, completion),
+            self.depth,
+        )
+    }
+
+    fn highlight_changes_width(&self) -> bool {
+        self.highlighter.highlight_changes_width()
+    }
+
+    fn highlight_char(&self, grapheme: &str) -> bool {
+        self.highlighter.highlight_char(grapheme)
+    }
+
+    fn highlight_char_at(&self, line: &str, pos: usize) -> bool {
+        self.highlighter.highlight_char_at(line, pos)
+    }
+
+    fn highlight_is_pos_dependent(&self) -> bool {
+        self.highlighter.highlight_is_pos_dependent()
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.highlighter.is_enabled()
+    }
+}
+
+/// Layers multiple highlighters.
+///
+/// The spans of all highlighters are merged: on overlapping ranges, the style
+/// of the later highlighter wins.
+/// The prompt, hint, line numbers and candidates are highlighted by the first
+/// highlighter that changes them.
+pub struct CombinedHighlighter {
+    highlighters: Vec<Box<Highlighter>>,
+}
+
+impl CombinedHighlighter {
+    pub fn new(highlighters: Vec<Box<Highlighter>>) -> CombinedHighlighter {
+        CombinedHighlighter { highlighters }
+    }
 }
 
-impl Highlighter for () {}
+impl Highlighter for CombinedHighlighter {
+    fn highlight_spans(&self, line: &str, pos: usize) -> Vec<Span> {
+        merge_spans(
+            line.len(),
+            self.highlighters
+                .iter()
+                .flat_map(|h| h.highlight_spans(line, pos)),
+        )
+    }
+
+    fn highlight_prompt<'p>(&self, prompt: &'p str) -> Cow<'p, str> {
+        for highlighter in &self.highlighters {
+            if let Owned(s) = highlighter.highlight_prompt(prompt) {
+                return Owned(s);
+            }
+        }
+        Borrowed(prompt)
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        for highlighter in &self.highlighters {
+            if let Owned(s) = highlighter.highlight_hint(hint) {
+                return Owned(s);
+            }
+        }
+        Borrowed(hint)
+    }
+
+    fn highlight_line_number<'g>(&self, gutter: &'g str) -> Cow<'g, str> {
+        for highlighter in &self.highlighters {
+            if let Owned(s) = highlighter.highlight_line_number(gutter) {
+                return Owned(s);
+            }
+        }
+        Borrowed(gutter)
+    }
+
+    fn highlight_candidate<'c>(
+        &self,
+        candidate: &'c str,
+        completion: CompletionType,
+    ) -> Cow<'c, str> {
+        for highlighter in &self.highlighters {
+            if let Owned(s) = highlighter.highlight_candidate(candidate, completion) {
+                return Owned(s);
+            }
+        }
+        Borrowed(candidate)
+    }
+
+    fn highlight_candidate_item<'c>(
+        &self,
+        candidate: &'c Candidate,
+        completion: CompletionType,
+    ) -> Cow<'c, str> {
+        for highlighter in &self.highlighters {
+            if let Owned(s) = highlighter.highlight_candidate_item(candidate, completion) {
+                return Owned(s);
+            }
+        }
+        Borrowed(candidate.display())
+    }
+
+    fn highlight_candidate_kind<'k>(
+        &self,
+        kind: &'k str,
+        completion: CompletionType,
+    ) -> Cow<'k, str> {
+        for highlighter in &self.highlighters {
+            if let Owned(s) = highlighter.highlight_candidate_kind(kind, completion) {
+                return Owned(s);
+            }
+        }
+        Borrowed(kind)
+    }
+
+    fn highlight_changes_width(&self) -> bool {
+        self.highlighters
+            .iter()
+            .any(|h| h.highlight_changes_width())
+    }
+
+    fn highlight_char(&self, grapheme: &str) -> bool {
+        self.highlighters.iter().any(|h| h.highlight_char(grapheme))
+    }
+
+    fn highlight_char_at(&self, line: &str, pos: usize) -> bool {
+        self.highlighters
+            .iter()
+            .any(|h| h.highlight_char_at(line, pos))
+    }
+
+    fn highlight_is_pos_dependent(&self) -> bool {
+        self.highlighters
+            .iter()
+            .any(|h| h.highlight_is_pos_dependent())
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.highlighters.iter().any(|h| h.is_enabled())
+    }
+
+    fn highlight_mixed_indent(&self) -> bool {
+        self.highlighters.iter().any(|h| h.highlight_mixed_indent())
+    }
+
+    fn mixed_indent_style(&self) -> anstyle::Style {
+        match self
+            .highlighters
+            .iter()
+            .find(|h| h.highlight_mixed_indent())
+        {
+            Some(h) => h.mixed_indent_style(),
+            None => anstyle::Style::new().bg_color(Some(AnsiColor::Yellow.into())),
+        }
+    }
+}
+
+/// Applies multiple highlighters in sequence: each highlighter highlights the
+/// output of the previous one.
+///
+/// The cursor position is not adjusted, so the earlier highlighters should
+/// only transform the text (keeping the byte positions, like uppercasing)
+/// and the ones emitting escape sequences should come last.
+/// To layer the styles of multiple highlighters, use `CombinedHighlighter`.
+///
+/// The line (including the selection and the accepted line), the prompt,
+/// the hint and the line numbers are chained. The search match is rendered
+/// by the last highlighter (over the line highlighted by the others). The
+/// other methods (the spans, the candidates and the flags like
+/// `highlight_char_at`) behave like with a `CombinedHighlighter`.
+pub struct CompositeHighlighter {
+    combined: CombinedHighlighter,
+}
+
+impl CompositeHighlighter {
+    pub fn new(highlighters: Vec<Box<Highlighter>>) -> CompositeHighlighter {
+        CompositeHighlighter {
+            combined: CombinedHighlighter::new(highlighters),
+        }
+    }
+
+    // feeds `s` to each highlighter through `f`
+    fn chain<'s, F>(&self, s: &'s str, f: F) -> Cow<'s, str>
+    where
+        F: for<'a> Fn(&Highlighter, &'a str) -> Cow<'a, str>,
+    {
+        let mut highlighted = Borrowed(s);
+        for highlighter in &self.combined.highlighters {
+            highlighted = match highlighted {
+                Borrowed(s) => f(&**highlighter, s),
+                Owned(s) => Owned(f(&**highlighter, &s).into_owned()),
+            };
+        }
+        highlighted
+    }
+}
+
+impl Highlighter for CompositeHighlighter {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        self.chain(line, |h, l| h.highlight(l, pos))
+    }
+
+    fn highlight_selection<'l>(
+        &self,
+        line: &'l str,
+        pos: usize,
+        start: usize,
+        end: usize,
+    ) -> Cow<'l, str> {
+        self.chain(line, |h, l| h.highlight_selection(l, pos, start, end))
+    }
+
+    fn highlight_search_match<'l>(
+        &self,
+        line: &'l str,
+        pos: usize,
+        start: usize,
+        end: usize,
+    ) -> Cow<'l, str> {
+        // the match is rendered once, by the last highlighter
+        let last = self.combined.highlighters.len().saturating_sub(1);
+        let mut highlighted = Borrowed(line);
+        for (i, highlighter) in self.combined.highlighters.iter().enumerate() {
+            let next = if i == last {
+                highlighter.highlight_search_match(&highlighted, pos, start, end)
+            } else {
+                highlighter.highlight(&highlighted, pos)
+            };
+            highlighted = Owned(next.into_owned());
+        }
+        highlighted
+    }
+
+    fn highlight_accepted<'l>(&self, line: &'l str) -> Cow<'l, str> {
+        self.chain(line, |h, l| h.highlight_accepted(l))
+    }
+
+    fn highlight_spans(&self, line: &str, pos: usize) -> Vec<Span> {
+        self.combined.highlight_spans(line, pos)
+    }
+
+    fn highlight_prompt<'p>(&self, prompt: &'p str) -> Cow<'p, str> {
+        self.chain(prompt, |h, p| h.highlight_prompt(p))
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        self.chain(hint, |h, s| h.highlight_hint(s))
+    }
+
+    fn highlight_line_number<'g>(&self, gutter: &'g str) -> Cow<'g, str> {
+        self.chain(gutter, |h, g| h.highlight_line_number(g))
+    }
+
+    fn highlight_candidate<'c>(
+        &self,
+        candidate: &'c str,
+        completion: CompletionType,
+    ) -> Cow<'c, str> {
+        self.combined.highlight_candidate(candidate, completion)
+    }
+
+    fn highlight_candidate_item<'c>(
+        &self,
+        candidate: &'c Candidate,
+        completion: CompletionType,
+    ) -> Cow<'c, str> {
+        self.combined
+            .highlight_candidate_item(candidate, completion)
+    }
+
+    fn highlight_selected_candidate<'c>(
+        &self,
+        candidate: &'c Candidate,
+        completion: CompletionType,
+    ) -> Cow<'c, str> {
+        self.combined
+            .highlight_selected_candidate(candidate, completion)
+    }
+
+    fn highlight_candidate_kind<'k>(
+        &self,
+        kind: &'k str,
+        completion: CompletionType,
+    ) -> Cow<'k, str> {
+        self.combined.highlight_candidate_kind(kind, completion)
+    }
+
+    fn highlight_changes_width(&self) -> bool {
+        self.combined.highlight_changes_width()
+    }
+
+    fn highlight_char(&self, grapheme: &str) -> bool {
+        self.combined.highlight_char(grapheme)
+    }
+
+    fn highlight_char_at(&self, line: &str, pos: usize) -> bool {
+        self.combined.highlight_char_at(line, pos)
+    }
+
+    fn highlight_is_pos_dependent(&self) -> bool {
+        self.combined.highlight_is_pos_dependent()
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.combined.is_enabled()
+    }
+
+    fn highlight_mixed_indent(&self) -> bool {
+        self.combined.highlight_mixed_indent()
+    }
+
+    fn mixed_indent_style(&self) -> anstyle::Style {
+        self.combined.mixed_indent_style()
+    }
+}
+
+/// Highlights a bracket (under or before the cursor) and its matching
+/// bracket.
+///
+/// By default, both brackets are rendered in bold blue and a bracket without
+/// match in bold red.
+pub struct MatchingBracketHighlighter {
+    style: anstyle::Style,
+    error_style: anstyle::Style,
+    brackets: BracketSet,
+}
+
+impl MatchingBracketHighlighter {
+    pub fn new() -> MatchingBracketHighlighter {
+        Self::with_style(
+            anstyle::Style::new()
+                .bold()
+                .fg_color(Some(AnsiColor::Blue.into())),
+        )
+    }
+
+    /// Use `style` instead of the default bold blue.
+    pub fn with_style(style: anstyle::Style) -> MatchingBracketHighlighter {
+        MatchingBracketHighlighter {
+            style,
+            error_style: anstyle::Style::new()
+                .bold()
+                .fg_color(Some(AnsiColor::Red.into())),
+            brackets: BracketSet::default(),
+        }
+    }
+
+    /// Change the style of the brackets (e.g. to match the terminal theme).
+    pub fn set_style(&mut self, style: anstyle::Style) {
+        self.style = style;
+    }
+
+    /// Change the style of an unmatched bracket (bold red by default).
+    pub fn set_error_style(&mut self, error_style: anstyle::Style) {
+        self.error_style = error_style;
+    }
+
+    /// Change the pairs of brackets matched (e.g. to add `<>`).
+    pub fn set_brackets(&mut self, brackets: BracketSet) {
+        self.brackets = brackets;
+    }
+}
+
+impl Default for MatchingBracketHighlighter {
+    fn default() -> MatchingBracketHighlighter {
+        MatchingBracketHighlighter::new()
+    }
+}
+
+impl Highlighter for MatchingBracketHighlighter {
+    fn highlight_spans(&self, line: &str, pos: usize) -> Vec<Span> {
+        // highlight matching brace/bracket/parenthesis if it exists
+        if let Some((bracket, pos)) = check_bracket(&self.brackets, line, pos) {
+            if let Some((_, idx)) = find_matching_bracket(&self.brackets, line, pos, bracket) {
+                // brackets are ASCII (single bytes on char boundaries)
+                let (first, second) = if pos < idx { (pos, idx) } else { (idx, pos) };
+                return vec![
+                    (first..first + 1, self.style),
+                    (second..second + 1, self.style),
+                ];
+            }
+            // unbalanced
+            return vec![(pos..pos + 1, self.error_style)];
+        }
+        Vec::new()
+    }
+
+    fn highlight_char_at(&self, line: &str, pos: usize) -> bool {
+        // will highlight matching brace/bracket/parenthesis or the unmatched
+        // one
+        check_bracket(&self.brackets, line, pos).is_some()
+    }
+}
+
+/// Colors brackets by nesting depth (rainbow brackets), wherever the cursor
+/// is.
+///
+/// The palette is cycled through for deeper levels.
+/// Stray closing brackets are not colored.
+pub struct RainbowBracketHighlighter {
+    palette: Vec<anstyle::Style>,
+    brackets: BracketSet,
+}
+
+impl RainbowBracketHighlighter {
+    pub fn new() -> RainbowBracketHighlighter {
+        Self::with_palette(
+            [AnsiColor::Yellow, AnsiColor::Magenta, AnsiColor::Blue]
+                .iter()
+                .map(|&c| anstyle::Style::new().fg_color(Some(c.into())))
+                .collect(),
+        )
+    }
+
+    /// Use `palette` (one style per depth level) instead of the default
+    /// yellow, magenta and blue.
+    pub fn with_palette(palette: Vec<anstyle::Style>) -> RainbowBracketHighlighter {
+        RainbowBracketHighlighter {
+            palette,
+            brackets: BracketSet::default(),
+        }
+    }
+
+    /// Change the pairs of brackets colored (e.g. to add `<>`).
+    pub fn set_brackets(&mut self, brackets: BracketSet) {
+        self.brackets = brackets;
+    }
+}
+
+impl Default for RainbowBracketHighlighter {
+    fn default() -> RainbowBracketHighlighter {
+        RainbowBracketHighlighter::new()
+    }
+}
+
+impl Highlighter for RainbowBracketHighlighter {
+    fn highlight_spans(&self, line: &str, _pos: usize) -> Vec<Span> {
+        let mut spans = Vec::new();
+        if self.palette.is_empty() {
+            return spans;
+        }
+        // open brackets not yet closed
+        let mut opens = Vec::new();
+        // brackets are single bytes
+        for (i, &b) in line.as_bytes().iter().enumerate() {
+            if self.brackets.is_open_bracket(b) {
+                let style = self.palette[opens.len() % self.palette.len()];
+                spans.push((i..i + 1, style));
+                opens.push(b);
+            } else if self.brackets.is_close_bracket(b) {
+                if opens.last().cloned() == self.brackets.matching_bracket(b) {
+                    opens.pop();
+                    let style = self.palette[opens.len() % self.palette.len()];
+                    spans.push((i..i + 1, style));
+                }
+            }
+        }
+        spans
+    }
+
+    fn highlight_char_at(&self, line: &str, pos: usize) -> bool {
+        // a bracket typed may change the depth of the following ones
+        line.as_bytes()[..pos].last().map_or(false, |&b| {
+            self.brackets.is_open_bracket(b) || self.brackets.is_close_bracket(b)
+        })
+    }
+
+    fn highlight_is_pos_dependent(&self) -> bool {
+        false
+    }
+}
+
+/// Finds the bracket matching `bracket` (found at byte index `pos` of
+/// `line`), skipping the nested pairs of the same kind: forward for an open
+/// bracket, backward for a close bracket.
+///
+/// Returns the matching bracket and its byte index, or `None` if `bracket`
+/// is unbalanced (or not in `brackets`).
+///
+/// `pos` is a byte index: it must fall on a char boundary (as brackets are
+/// ASCII, any index returned by `check_bracket` does).
+///
+/// ```
+/// use rustyline::highlight::{check_bracket, find_matching_bracket, BracketSet};
+///
+/// let brackets = BracketSet::default();
+/// let line = "f(a[0])";
+/// assert_eq!(Some((b')', 6)), check_bracket(&brackets, line, 7));
+/// assert_eq!(Some((b'(', 1)), find_matching_bracket(&brackets, line, 6, b')'));
+/// assert_eq!(Some((b']', 5)), find_matching_bracket(&brackets, line, 3, b'['));
+/// assert_eq!(None, find_matching_bracket(&brackets, "(a", 0, b'('));
+/// ```
+pub fn find_matching_bracket(
+    brackets: &BracketSet,
+    line: &str,
+    pos: usize,
+    bracket: u8,
+) -> Option<(u8, usize)> {
+    let matching = match brackets.matching_bracket(bracket) {
+        Some(matching) => matching,
+        None => return None,
+    };
+    let mut idx;
+    let mut unmatched = 1;
+    if brackets.is_open_bracket(bracket) {
+        // forward search
+        idx = pos + 1;
+        let bytes = &line.as_bytes()[idx..];
+        for b in bytes {
+            if *b == matching {
+                unmatched -= 1;
+                if unmatched == 0 {
+                    debug_assert_eq!(matching, line.as_bytes()[idx]);
+                    return Some((matching, idx));
+                }
+            } else if *b == bracket {
+                unmatched += 1;
+            }
+            idx += 1;
+        }
+        debug_assert_eq!(idx, line.len());
+    } else {
+        // backward search
+        idx = pos;
+        let bytes = &line.as_bytes()[..idx];
+        for b in bytes.iter().rev() {
+            if *b == matching {
+                unmatched -= 1;
+                if unmatched == 0 {
+                    debug_assert_eq!(matching, line.as_bytes()[idx - 1]);
+                    return Some((matching, idx - 1));
+                }
+            } else if *b == bracket {
+                unmatched += 1;
+            }
+            idx -= 1;
+        }
+        debug_assert_eq!(idx, 0);
+    }
+    None
+}
+
+/// Finds the bracket under the cursor `pos` (or else just before it) in
+/// `line`.
+///
+/// Returns the bracket and its byte index, or `None` if there is no bracket
+/// or if it cannot have a match (a close bracket at the start of the line or
+/// an open bracket at the end). At the end of the line (`pos >= line.len()`),
+/// only a close bracket before the cursor is considered.
+///
+/// `pos` is a byte index: it must fall on a char boundary.
+pub fn check_bracket(brackets: &BracketSet, line: &str, pos: usize) -> Option<(u8, usize)> {
+    if line.is_empty() {
+        return None;
+    }
+    let mut pos = pos;
+    if pos >= line.len() {
+        pos = line.len() - 1; // before cursor
+        let b = line.as_bytes()[pos]; // previous byte
+        if brackets.is_close_bracket(b) {
+            Some((b, pos))
+        } else {
+            None
+        }
+    } else {
+        let mut under_cursor = true;
+        loop {
+            let b = line.as_bytes()[pos];
+            if brackets.is_close_bracket(b) {
+                if pos == 0 {
+                    return None;
+                } else {
+                    return Some((b, pos));
+                }
+            } else if brackets.is_open_bracket(b) {
+                if pos + 1 == line.len() {
+                    return None;
+                } else {
+                    return Some((b, pos));
+                }
+            } else if under_cursor && pos > 0 {
+                under_cursor = false;
+                pos -= 1; // or before cursor
+            } else {
+                return None;
+            }
+        }
+    }
+}
+
+/// Pairs of (ASCII) brackets: `()`, `[]` and `{}` by default.
+///
+/// ```
+/// use rustyline::highlight::BracketSet;
+///
+/// // with angle brackets for generics
+/// let brackets = BracketSet::new(&[(b'(', b')'), (b'[', b']'), (b'{', b'}'), (b'<', b'>')]);
+/// assert_eq!(Some(b'<'), brackets.matching_bracket(b'>'));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BracketSet {
+    opens: Vec<u8>,
+    closes: Vec<u8>,
+}
+
+impl BracketSet {
+    /// `pairs` of (open, close) brackets.
+    ///
+    /// # Panics
+    ///
+    /// If a bracket is not ASCII: a non-ASCII byte may be part of a
+    /// multi-byte char (and its position may not be a char boundary).
+    pub fn new(pairs: &[(u8, u8)]) -> BracketSet {
+        assert!(pairs.iter().all(|&(o, c)| o.is_ascii() && c.is_ascii()));
+        BracketSet {
+            opens: pairs.iter().map(|&(open, _)| open).collect(),
+            closes: pairs.iter().map(|&(_, close)| close).collect(),
+        }
+    }
+
+    /// Return the close bracket of an open `bracket` and vice versa.
+    pub fn matching_bracket(&self, bracket: u8) -> Option<u8> {
+        if let Some(i) = memchr(bracket, &self.opens) {
+            Some(self.closes[i])
+        } else if let Some(i) = memchr(bracket, &self.closes) {
+            Some(self.opens[i])
+        } else {
+            None
+        }
+    }
+
+    pub fn is_open_bracket(&self, bracket: u8) -> bool {
+        memchr(bracket, &self.opens).is_some()
+    }
+
+    pub fn is_close_bracket(&self, bracket: u8) -> bool {
+        memchr(bracket, &self.closes).is_some()
+    }
+}
+
+impl Default for BracketSet {
+    fn default() -> BracketSet {
+        BracketSet::new(&[(b'{', b'}'), (b'[', b']'), (b'(', b')')])
+    }
+}
+
+/// Highlights runs of spaces and tabs at the end of each line
+/// so that trailing whitespace is visible.
+///
+/// By default, trailing whitespace is rendered with a red background.
+pub struct TrailingWhitespaceHighlighter {
+    style: anstyle::Style,
+}
+
+impl TrailingWhitespaceHighlighter {
+    pub fn new() -> TrailingWhitespaceHighlighter {
+        Self::with_style(anstyle::Style::new().bg_color(Some(AnsiColor::Red.into())))
+    }
+
+    /// Use `style` instead of the default red background.
+    pub fn with_style(style: anstyle::Style) -> TrailingWhitespaceHighlighter {
+        TrailingWhitespaceHighlighter { style }
+    }
+}
+
+impl Default for TrailingWhitespaceHighlighter {
+    fn default() -> TrailingWhitespaceHighlighter {
+        TrailingWhitespaceHighlighter::new()
+    }
+}
+
+impl Highlighter for TrailingWhitespaceHighlighter {
+    fn highlight_spans(&self, line: &str, _pos: usize) -> Vec<Span> {
+        let mut spans = Vec::new();
+        let mut start = 0;
+        for l in line.split('\n') {
+            let end = start + l.len();
+            // spaces and tabs are single bytes
+            let blanks = l.chars().rev().take_while(|c| is_blank(*c)).count();
+            if blanks > 0 {
+                spans.push((end - blanks..end, self.style));
+            }
+            start = end + 1;
+        }
+        spans
+    }
+
+    fn highlight_char_at(&self, line: &str, pos: usize) -> bool {
+        // only blanks after the cursor (until the end of the logical line)
+        let end = line[pos..].find('\n').map_or(line.len(), |i| pos + i);
+        if !line[pos..end].chars().all(is_blank) {
+            return false;
+        }
+        // the char typed may be (or may follow) trailing whitespace
+        line[..pos].chars().rev().take(2).any(is_blank)
+    }
+
+    fn highlight_is_pos_dependent(&self) -> bool {
+        false
+    }
+}
+
+fn is_blank(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+/// Highlights keywords (as whole words) with their style.
+///
+/// When keywords overlap, the first rule wins.
+///
+/// ```
+/// extern crate anstyle;
+/// extern crate rustyline;
+///
+/// use anstyle::{AnsiColor, Style};
+/// use rustyline::highlight::{Highlighter, KeywordHighlighter};
+///
+/// # fn main() {
+/// let blue = Style::new().fg_color(Some(AnsiColor::Blue.into()));
+/// let h = KeywordHighlighter::new(vec![("SELECT", blue), ("FROM", blue)]);
+/// assert_eq!(
+///     "\x1b[34mSELECT\x1b[0m * \x1b[34mFROM\x1b[0m t",
+///     h.highlight("SELECT * FROM t", 0)
+/// );
+/// # }
+/// ```
+pub struct KeywordHighlighter {
+    rules: Vec<(String, anstyle::Style)>,
+}
+
+impl KeywordHighlighter {
+    pub fn new<S: Into<String>>(rules: Vec<(S, anstyle::Style)>) -> KeywordHighlighter {
+        KeywordHighlighter {
+            rules: rules
+                .into_iter()
+                .map(|(keyword, style)| (keyword.into(), style))
+                .filter(|&(ref keyword, _)| !keyword.is_empty())
+                .collect(),
+        }
+    }
+}
+
+impl Highlighter for KeywordHighlighter {
+    fn highlight_spans(&self, line: &str, _pos: usize) -> Vec<Span> {
+        let mut spans: Vec<Span> = Vec::new();
+        for &(ref keyword, style) in &self.rules {
+            for (start, _) in line.match_indices(keyword.as_str()) {
+                let end = start + keyword.len();
+                if !is_whole_word(line, start, end) {
+                    continue;
+                }
+                if spans
+                    .iter()
+                    .any(|&(ref range, _)| range.start < end && start < range.end)
+                {
+                    continue; // first match wins
+                }
+                spans.push((start..end, style));
+            }
+        }
+        spans.sort_by_key(|&(ref range, _)| range.start);
+        spans
+    }
+
+    fn highlight_char_at(&self, line: &str, pos: usize) -> bool {
+        // the word typed may be (or no longer be) a keyword
+        let before = line[..pos].chars().next_back();
+        let under = line[pos..].chars().next();
+        if before.map_or(false, is_word_char) || under.map_or(false, is_word_char) {
+            return true;
+        }
+        // a keyword with other chars (like `->`) touching the cursor, after
+        // or before the char typed
+        let touches = |line: &str, pos: usize| {
+            self.highlight_spans(line, pos)
+                .iter()
+                .any(|&(ref range, _)| range.start <= pos && pos <= range.end)
+        };
+        if touches(line, pos) {
+            return true;
+        }
+        match before {
+            Some(c) => {
+                let start = pos - c.len_utf8();
+                touches(&format!("{}{}", &line[..start], &line[pos..]), start)
+            }
+            None => false,
+        }
+    }
+
+    fn highlight_is_pos_dependent(&self) -> bool {
+        false
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// `line[start..end]` is not part of a bigger word
+fn is_whole_word(line: &str, start: usize, end: usize) -> bool {
+    let word = &line[start..end];
+    let before = line[..start].chars().next_back();
+    let after = line[end..].chars().next();
+    let first = word.chars().next().unwrap();
+    let last = word.chars().next_back().unwrap();
+    !(is_word_char(first) && before.map_or(false, is_word_char))
+        && !(is_word_char(last) && after.map_or(false, is_word_char))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        downsample, find_matching_bracket, has_mixed_indent, BracketSet, CombinedHighlighter,
+        CompositeHighlighter, Highlighter, KeywordHighlighter, LinkedBlock,
+        MatchingBracketHighlighter, RainbowBracketHighlighter, Span, StyledBlocks,
+        TrailingWhitespaceHighlighter,
+    };
+    use anstyle::{AnsiColor, Style};
+    use completion::{Candidate, Pair};
+    use config::{ColorDepth, CompletionType};
+    use std::borrow::Cow;
+    use std::cell::Cell;
+    use std::cmp;
+    use std::io;
+
+    #[test]
+    pub fn matching_bracket() {
+        let h = MatchingBracketHighlighter::new();
+        assert_eq!("(", h.highlight("(", 0));
+        // unmatched
+        assert_eq!("\x1b[1m\x1b[31m(\x1b[0ma", h.highlight("(a", 0));
+        assert_eq!("a\x1b[1m\x1b[31m)\x1b[0m", h.highlight("a)", 2));
+        assert_eq!("\x1b[1m\x1b[31m)\x1b[0m", h.highlight(")", 1));
+        assert!(h.highlight_char_at("a)", 2));
+        // cursor on the outer open paren
+        assert_eq!(
+            "\x1b[1m\x1b[34m(\x1b[0m()\x1b[1m\x1b[34m)\x1b[0m",
+            h.highlight("(())", 0)
+        );
+        // adjacent brackets
+        assert_eq!(
+            "(\x1b[1m\x1b[34m(\x1b[0m\x1b[1m\x1b[34m)\x1b[0m)",
+            h.highlight("(())", 1)
+        );
+        // cursor after the closing bracket
+        assert_eq!(
+            "\x1b[1m\x1b[34m[\x1b[0ma\x1b[1m\x1b[34m]\x1b[0m",
+            h.highlight("[a]", 3)
+        );
+        assert!(h.highlight_char_at("(())", 0));
+        assert!(!h.highlight_char_at("a b", 1));
+
+        let mut h = MatchingBracketHighlighter::with_style(Style::new().underline());
+        assert_eq!("{\x1b[4m{\x1b[0m\x1b[4m}\x1b[0m", h.highlight("{{}", 3));
+        h.set_style(Style::new().italic());
+        assert_eq!("\x1b[3m(\x1b[0m\x1b[3m)\x1b[0m", h.highlight("()", 0));
+        h.set_error_style(Style::new().underline());
+        assert_eq!("}\x1b[4m}\x1b[0m", h.highlight("}}", 1));
+    }
+
+    #[test]
+    pub fn multibyte_brackets() {
+        let brackets = BracketSet::default();
+        assert_eq!(
+            Some((b'(', 0)),
+            find_matching_bracket(&brackets, "(café)", 6, b')')
+        );
+        let h = MatchingBracketHighlighter::with_style(Style::new().bold());
+        let matched = "\x1b[1m(\x1b[0mcafé\x1b[1m)\x1b[0m";
+        assert_eq!(matched, h.highlight("(café)", 7));
+        assert_eq!(matched, h.highlight("(café)", 6));
+        assert_eq!(matched, h.highlight("(café)", 0));
+        // no bracket under or before the cursor
+        assert_eq!("(café)", h.highlight("(café)", 4));
+        assert_eq!(
+            "\x1b[1m[\x1b[0m\u{1f600} (é)\x1b[1m]\x1b[0m",
+            h.highlight("[\u{1f600} (é)]", 0)
+        );
+        assert_eq!(
+            "[\u{1f600} \x1b[1m(\x1b[0mé\x1b[1m)\x1b[0m]",
+            h.highlight("[\u{1f600} (é)]", 9)
+        );
+        // unmatched
+        assert_eq!("é\x1b[1m\x1b[31m)\x1b[0m", h.highlight("é)", 3));
+
+        let h = RainbowBracketHighlighter::with_palette(vec![Style::new().bold()]);
+        assert_eq!(matched, h.highlight("(café)", 0));
+    }
+
+    #[test]
+    pub fn angle_brackets() {
+        let brackets = BracketSet::new(&[(b'(', b')'), (b'<', b'>')]);
+        assert_eq!(None, brackets.matching_bracket(b'['));
+        let mut h = MatchingBracketHighlighter::with_style(Style::new().bold());
+        assert_eq!("Vec<u8>", h.highlight("Vec<u8>", 7));
+        h.set_brackets(brackets.clone());
+        assert_eq!(
+            "Vec\x1b[1m<\x1b[0mu8\x1b[1m>\x1b[0m",
+            h.highlight("Vec<u8>", 7)
+        );
+        // mixed
+        assert_eq!(
+            "f\x1b[1m(\x1b[0mVec<(u8)>\x1b[1m)\x1b[0m",
+            h.highlight("f(Vec<(u8)>)", 12)
+        );
+        assert_eq!(
+            "f(Vec\x1b[1m<\x1b[0m(u8)\x1b[1m>\x1b[0m)",
+            h.highlight("f(Vec<(u8)>)", 10)
+        );
+
+        let mut h = RainbowBracketHighlighter::with_palette(vec![Style::new().bold()]);
+        h.set_brackets(brackets);
+        assert_eq!(
+            "a\x1b[1m<\x1b[0mb\x1b[1m>\x1b[0m[]",
+            h.highlight("a<b>[]", 0)
+        );
+    }
+
+    #[test]
+    pub fn rainbow_bracket() {
+        let h = RainbowBracketHighlighter::new();
+        assert_eq!("ls", h.highlight("ls", 0));
+        assert_eq!(
+            "\x1b[33m(\x1b[0m\x1b[35m[\x1b[0ma\x1b[35m]\x1b[0m\x1b[33m)\x1b[0m",
+            h.highlight("([a])", 0)
+        );
+        // the palette is cycled
+        let h = RainbowBracketHighlighter::with_palette(vec![
+            Style::new().bold(),
+            Style::new().italic(),
+        ]);
+        assert_eq!(
+            "\x1b[1m{\x1b[0m\x1b[3m{\x1b[0m\x1b[1m{\x1b[0m",
+            h.highlight("{{{", 3)
+        );
+        // stray closers are not colored
+        assert_eq!(")\x1b[1m(\x1b[0m]\x1b[1m)\x1b[0m", h.highlight(")(])", 0));
+        assert!(h.highlight_char_at("f(", 2));
+        assert!(!h.highlight_char_at("f(x", 3));
+    }
+
+    #[test]
+    pub fn downsampling() {
+        let rgb = "a\x1b[1;38;2;255;0;0;48;2;18;18;18mb\x1b[0m";
+        assert_eq!(rgb, downsample(rgb, ColorDepth::TrueColor));
+        assert_eq!(
+            "a\x1b[1;38;5;196;48;5;233mb\x1b[0m",
+            downsample(rgb, ColorDepth::Ansi256)
+        );
+        assert_eq!(
+            "a\x1b[1;91;40mb\x1b[0m",
+            downsample(rgb, ColorDepth::Ansi16)
+        );
+        assert_eq!("\x1b[34m", downsample("\x1b[38;5;4m", ColorDepth::Ansi16));
+        // no underline color with 16 colors
+        assert_eq!("ul", downsample("\x1b[58;2;0;0;255mul", ColorDepth::Ansi16));
+        // reset and other sequences are kept
+        assert_eq!(
+            "\x1b[m\x1b[0K",
+            downsample("\x1b[m\x1b[0K", ColorDepth::Ansi16)
+        );
+    }
+
+    #[test]
+    pub fn keyword() {
+        let bold = Style::new().bold();
+        let italic = Style::new().italic();
+        let h = KeywordHighlighter::new(vec![("IS NOT", bold), ("NOT", italic), ("->", bold)]);
+        assert_eq!("SELECTED", h.highlight("SELECTED", 0));
+        // first match wins
+        assert_eq!(
+            "a \x1b[1mIS NOT\x1b[0m b NOT_c \x1b[3mNOT\x1b[0m",
+            h.highlight("a IS NOT b NOT_c NOT", 0)
+        );
+        assert_eq!("a\x1b[1m->\x1b[0mb", h.highlight("a->b", 0));
+        // typed at the end of the line
+        assert!(h.highlight_char_at("NOT", 3));
+        assert!(h.highlight_char_at("a->", 3));
+        // a non-word char in plain text
+        assert!(!h.highlight_char_at("a +", 3));
+    }
+
+    #[test]
+    pub fn search_match() {
+        assert_eq!(
+            "cargo \x1b[7mbuild\x1b[0m",
+            ().highlight_search_match("cargo build", 0, 6, 11)
+        );
+        // empty match
+        assert_eq!("cargo", ().highlight_search_match("cargo", 0, 2, 2));
+        // over the spans
+        let h = KeywordHighlighter::new(vec![("NOT", Style::new().bold())]);
+        assert_eq!(
+            "\x1b[1mN\x1b[0m\x1b[7mOT\x1b[0m a",
+            h.highlight_search_match("NOT a", 0, 1, 3)
+        );
+    }
+
+    #[test]
+    pub fn mixed_indent() {
+        assert!(!has_mixed_indent("    pass"));
+        assert!(!has_mixed_indent("\t\tpass"));
+        assert!(has_mixed_indent(" \tpass"));
+        assert!(has_mixed_indent("\t pass"));
+        // only the leading whitespace matters
+        assert!(!has_mixed_indent("\tpass \t"));
+
+        struct IndentHighlighter;
+        impl Highlighter for IndentHighlighter {
+            fn highlight_mixed_indent(&self) -> bool {
+                true
+            }
+        }
+        let h = IndentHighlighter;
+        assert_eq!("    pass", h.highlight("    pass", 0));
+        assert_eq!("\tpass", h.highlight("\tpass", 0));
+        assert_eq!("\x1b[43m \t\x1b[0mpass", h.highlight(" \tpass", 0));
+        assert_eq!(
+            "if x:\n\x1b[43m\t \x1b[0mpass",
+            h.highlight("if x:\n\t pass", 0)
+        );
+        assert!(h.highlight_char_at("\t ", 2));
+        assert!(h.highlight_char_at("if x:\n ", 7));
+        assert!(!h.highlight_char_at(" pass", 5));
+        assert!(!().highlight_char_at(" ", 1));
+    }
+
+    #[test]
+    pub fn trailing_whitespace() {
+        let h = TrailingWhitespaceHighlighter::new();
+        assert_eq!("ls", h.highlight("ls", 2));
+        assert_eq!("ls\x1b[41m  \t\x1b[0m", h.highlight("ls  \t", 4));
+        assert_eq!(
+            "a\x1b[41m \x1b[0m\nb\n\x1b[41m  \x1b[0m",
+            h.highlight("a \nb\n  ", 0)
+        );
+        // cursor inside the trailing run
+        assert_eq!("ls\x1b[41m   \x1b[0m", h.highlight("ls   ", 3));
+        assert!(h.highlight_char_at("ls ", 3));
+        assert!(h.highlight_char_at("ls x", 4));
+        assert!(h.highlight_char_at("ls  ", 3));
+        assert!(h.highlight_char_at("a \nb", 2));
+        assert!(!h.highlight_char_at("ls x", 2));
+        // no trailing whitespace touched
+        assert!(!h.highlight_char_at("ls -l", 3));
+        assert!(!h.highlight_char_at("a \nb", 4));
+        assert!(!h.highlight_char_at("ls", 2));
+    }
+
+    struct WordHighlighter(&'static str, Style);
+
+    impl Highlighter for WordHighlighter {
+        fn highlight_spans(&self, line: &str, _: usize) -> Vec<Span> {
+            line.match_indices(self.0)
+                .map(|(i, w)| (i..i + w.len(), self.1))
+                .collect()
+        }
+    }
+
+    #[test]
+    pub fn combined() {
+        let blue = Style::new().fg_color(Some(AnsiColor::Blue.into()));
+        assert_eq!(
+            "\x1b[34mls\x1b[0m -l",
+            WordHighlighter("ls", blue).highlight("ls -l", 0)
+        );
+        let h = CombinedHighlighter::new(vec![
+            Box::new(WordHighlighter("ls", blue)),
+            Box::new(TrailingWhitespaceHighlighter::new()),
+        ]);
+        assert_eq!("cd", h.highlight("cd", 2));
+        assert_eq!("\x1b[34mls\x1b[0m", h.highlight("ls", 2));
+        assert_eq!("\x1b[34mls\x1b[0m\x1b[41m \x1b[0m", h.highlight("ls ", 3));
+        // later highlighters override earlier ones
+        let h = CombinedHighlighter::new(vec![
+            Box::new(TrailingWhitespaceHighlighter::new()),
+            Box::new(WordHighlighter("  ", blue)),
+        ]);
+        assert_eq!(
+            "ls\x1b[34m  \x1b[0m\x1b[41m \x1b[0m",
+            h.highlight("ls   ", 5)
+        );
+        assert!(h.highlight_char_at("ls ", 3));
+        assert!(!h.highlight_char_at("ls", 2));
+    }
+
+    struct UppercaseHighlighter;
+
+    impl Highlighter for UppercaseHighlighter {
+        fn highlight<'l>(&self, line: &'l str, _: usize) -> Cow<'l, str> {
+            Cow::Owned(line.to_uppercase())
+        }
+
+        fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+            Cow::Owned(hint.to_uppercase())
+        }
+    }
+
+    #[test]
+    pub fn composite() {
+        let h = CompositeHighlighter::new(vec![
+            Box::new(UppercaseHighlighter),
+            Box::new(MatchingBracketHighlighter::with_style(Style::new().bold())),
+        ]);
+        assert_eq!("F\x1b[1m(\x1b[0mA\x1b[1m)\x1b[0m", h.highlight("f(a)", 1));
+        assert_eq!("FA", h.highlight("fa", 1));
+        assert_eq!("HINT", h.highlight_hint("hint"));
+        assert_eq!("> ", h.highlight_prompt("> "));
+        assert!(h.highlight_char_at("f(a)", 1));
+        assert!(!h.highlight_char_at("fa", 1));
+        // the search match over the uppercased line
+        assert_eq!("F\x1b[7mA\x1b[0m", h.highlight_search_match("fa", 0, 1, 2));
+        assert_eq!(
+            vec![(1..2, Style::new().bold()), (3..4, Style::new().bold())],
+            h.highlight_spans("f(a)", 1)
+        );
+        assert_eq!("ls", CompositeHighlighter::new(vec![]).highlight("ls", 0));
+    }
+
+    struct ParenHighlighter;
+
+    impl Highlighter for ParenHighlighter {
+        fn highlight_char(&self, grapheme: &str) -> bool {
+            grapheme == "("
+        }
+    }
+
+    #[test]
+    pub fn highlight_char() {
+        let h = ParenHighlighter;
+        // typed
+        assert!(h.highlight_char_at("f(", 2));
+        // under the cursor
+        assert!(h.highlight_char_at("f(a", 1));
+        assert!(!h.highlight_char_at("f(a", 3));
+        assert!(!h.highlight_char_at("", 0));
+    }
+
+    struct DescriptionHighlighter;
+
+    impl Highlighter for DescriptionHighlighter {
+        fn highlight_candidate_item<'c>(
+            &self,
+            candidate: &'c Candidate,
+            _: CompletionType,
+        ) -> Cow<'c, str> {
+            let display = candidate.display();
+            let n = candidate.replacement().len();
+            if display.len() > n && display.starts_with(candidate.replacement()) {
+                Cow::Owned(format!("{}\x1b[2m{}\x1b[0m", &display[..n], &display[n..]))
+            } else {
+                Cow::Borrowed(display)
+            }
+        }
+    }
+
+    #[test]
+    pub fn candidate() {
+        let pair = Pair {
+            display: "ls (list)".to_owned(),
+            replacement: "ls".to_owned(),
+        };
+        let h = DescriptionHighlighter;
+        assert_eq!(
+            "ls\x1b[2m (list)\x1b[0m",
+            h.highlight_candidate_item(&pair, CompletionType::List)
+        );
+        assert_eq!(
+            "ls",
+            h.highlight_candidate_item(&"ls", CompletionType::List)
+        );
+        assert_eq!(
+            "ls",
+            ().highlight_candidate_item(&pair.replacement, CompletionType::List)
+        );
+        assert_eq!(
+            "\x1b[7mls\x1b[27m",
+            ().highlight_selected_candidate(&"ls", CompletionType::Menu)
+        );
+        // only the display is highlighted
+        assert_eq!(
+            "LS (LIST)",
+            StrCandidateHighlighter.highlight_candidate_item(&pair, CompletionType::List)
+        );
+    }
+
+    #[test]
+    pub fn match_indices() {
+        assert_eq!(
+            "\x1b[1mca\x1b[0mf\x1b[1mé\x1b[0m",
+            super::highlight_match_indices("café", &[0, 1, 3])
+        );
+        // inside `é`, out of bounds and out of order
+        assert_eq!(
+            "\x1b[1mc\x1b[0maf\x1b[1mé\x1b[0m",
+            super::highlight_match_indices("café", &[0, 3, 4, 5, 9, 1])
+        );
+        assert_eq!("café", super::highlight_match_indices("café", &[4]));
+    }
+
+    struct StrCandidateHighlighter;
+
+    impl Highlighter for StrCandidateHighlighter {
+        fn highlight_candidate<'c>(&self, candidate: &'c str, _: CompletionType) -> Cow<'c, str> {
+            Cow::Owned(candidate.to_uppercase())
+        }
+    }
+
+    #[test]
+    pub fn styled_blocks() {
+        let bold = Style::new().bold();
+        let consumed = Cell::new(0);
+        let blocks = StyledBlocks(
+            vec![(bold, "foo"), (Style::new(), " bar")]
+                .into_iter()
+                .inspect(|_| consumed.set(consumed.get() + 1)),
+        );
+        assert_eq!(0, consumed.get());
+        assert_eq!("\x1b[1mfoo\x1b[0m bar", blocks.render());
+        assert_eq!(2, consumed.get());
+
+        assert_eq!("", StyledBlocks(vec![(bold, "")]).render());
+        assert_eq!("", StyledBlocks(Vec::<(Style, String)>::new()).render());
+        let hint: Cow<str> = StyledBlocks(vec![(bold, "x".to_owned())]).into();
+        assert_eq!("\x1b[1mx\x1b[0m", hint);
+    }
+
+    #[test]
+    pub fn collect_blocks() {
+        let bold = Style::new().bold();
+        let blocks: StyledBlocks<_> = "a b".split(' ').map(|w| (bold, w)).collect();
+        assert_eq!(2, blocks.0.len());
+        assert_eq!("\x1b[1ma\x1b[0m\x1b[1mb\x1b[0m", blocks.render());
+
+        let blocks: StyledBlocks<Vec<_>> = vec![(bold, "a"), (bold, "")].into_iter().collect();
+        let blocks = blocks.map(|(_, text)| (Style::new(), text.to_uppercase()));
+        assert_eq!("A", blocks.render());
+    }
+
+    /// Accepts `.0` bytes then fails like a pipe closed by its reader.
+    struct ClosedPipe(usize);
+
+    impl io::Write for ClosedPipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.0 == 0 {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"));
+            }
+            let n = cmp::min(self.0, buf.len());
+            self.0 -= n;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    pub fn print_to() {
+        let bold = Style::new().bold();
+        let mut out = Vec::new();
+        StyledBlocks(vec![(bold, "foo"), (Style::new(), " bar")])
+            .print_to(&mut out)
+            .unwrap();
+        assert_eq!(&b"\x1b[1mfoo\x1b[0m bar"[..], &out[..]);
+
+        let err = StyledBlocks(vec![(bold, "foo"), (Style::new(), " bar")])
+            .print_to(&mut ClosedPipe(5))
+            .unwrap_err();
+        assert_eq!(io::ErrorKind::BrokenPipe, err.kind());
+    }
+
+    #[test]
+    pub fn borrowed_blocks() {
+        let link = LinkedBlock {
+            style: Style::new(),
+            text: "a",
+            url: "b",
+        };
+        let links = [link, link];
+        assert_eq!(
+            StyledBlocks(links.iter().cloned()).render(),
+            StyledBlocks(&links).render()
+        );
+    }
+
+    #[test]
+    pub fn linked_block() {
+        let link = LinkedBlock {
+            style: Style::new().underline(),
+            text: "E0425",
+            url: "https://example.com/E0425",
+        };
+        assert_eq!(
+            "\x1b]8;;https://example.com/E0425\x1b\\\x1b[4mE0425\x1b[0m\x1b]8;;\x1b\\",
+            StyledBlocks(Some(link)).render()
+        );
+    }
+
+    #[cfg(feature = "crossterm-style")]
+    #[test]
+    pub fn crossterm_style() {
+        use crossterm::style::{Color, ContentStyle, Stylize};
+        let blocks = vec![
+            (ContentStyle::new().bold(), "foo"),
+            (ContentStyle::new(), " "),
+            (ContentStyle::new().with(Color::Blue), "bar"),
+        ];
+        assert_eq!(
+            "\x1b[1mfoo\x1b[0m \x1b[38;5;12mbar\x1b[0m",
+            StyledBlocks(blocks).render()
+        );
+    }
+}
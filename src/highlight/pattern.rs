@@ -0,0 +1,135 @@
+//! Highlighting with regular expressions
+use anstyle::Style;
+use regex::{self, Regex};
+
+use super::{Highlighter, Span};
+
+/// Highlights the matches of regular expressions with their style.
+///
+/// The matches do not overlap: when they do, the rule added first wins. The
+/// text between the matches is not styled.
+///
+/// ```
+/// extern crate anstyle;
+/// extern crate rustyline;
+///
+/// use anstyle::{AnsiColor, Style};
+/// use rustyline::highlight::{Highlighter, PatternHighlighter};
+///
+/// # fn main() {
+/// let blue = Style::new().fg_color(Some(AnsiColor::Blue.into()));
+/// let mut h = PatternHighlighter::new();
+/// h.add_rule(r"(?i)\b(select|from)\b", blue).unwrap();
+/// assert_eq!(
+///     "\x1b[34mSELECT\x1b[0m * \x1b[34mfrom\x1b[0m t",
+///     h.highlight("SELECT * from t", 0)
+/// );
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct PatternHighlighter {
+    rules: Vec<(Regex, Style)>,
+}
+
+impl PatternHighlighter {
+    pub fn new() -> PatternHighlighter {
+        PatternHighlighter::default()
+    }
+
+    /// Highlights the matches of `pattern` with `style` (with a lower
+    /// priority than the rules already added).
+    pub fn add_rule(&mut self, pattern: &str, style: Style) -> Result<(), regex::Error> {
+        let regex = try!(Regex::new(pattern));
+        self.rules.push((regex, style));
+        Ok(())
+    }
+}
+
+impl Highlighter for PatternHighlighter {
+    fn highlight_spans(&self, line: &str, _pos: usize) -> Vec<Span> {
+        let mut spans: Vec<Span> = Vec::new();
+        for &(ref regex, style) in &self.rules {
+            for m in regex.find_iter(line) {
+                let (start, end) = (m.start(), m.end());
+                if start == end
+                    || spans
+                        .iter()
+                        .any(|&(ref range, _)| range.start < end && start < range.end)
+                {
+                    continue; // empty or overlapping a previous rule
+                }
+                spans.push((start..end, style));
+            }
+        }
+        spans.sort_by_key(|&(ref range, _)| range.start);
+        spans
+    }
+
+    fn highlight_char_at(&self, line: &str, pos: usize) -> bool {
+        // any char typed may start, extend or break a match, even away from
+        // the cursor (like a closing quote)
+        let _ = (line, pos);
+        true
+    }
+
+    fn highlight_is_pos_dependent(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PatternHighlighter;
+    use anstyle::{AnsiColor, Style};
+    use highlight::Highlighter;
+
+    #[test]
+    fn sql_keywords() {
+        let blue = Style::new().fg_color(Some(AnsiColor::Blue.into()));
+        let bold = Style::new().bold();
+        let mut h = PatternHighlighter::new();
+        h.add_rule(r"\b(SELECT|FROM)\b", blue).unwrap();
+        // overlaps `FROM`: lower priority
+        h.add_rule(r"\w+", bold).unwrap();
+        assert!(h.add_rule(r"(", bold).is_err());
+
+        let line = "SELECT café FROM été";
+        let spans = h.highlight_spans(line, 0);
+        let styled: Vec<(&str, Style)> = spans
+            .iter()
+            .map(|&(ref range, style)| (&line[range.clone()], style))
+            .collect();
+        assert_eq!(
+            vec![
+                ("SELECT", blue),
+                ("café", bold),
+                ("FROM", blue),
+                ("été", bold)
+            ],
+            styled
+        );
+        for &(ref range, _) in &spans {
+            assert!(line.is_char_boundary(range.start));
+            assert!(line.is_char_boundary(range.end));
+        }
+        // no empty match
+        let mut h = PatternHighlighter::new();
+        h.add_rule(r"x*", bold).unwrap();
+        assert!(h.highlight_spans("abc", 0).is_empty());
+    }
+
+    #[test]
+    fn typed_match() {
+        let mut h = PatternHighlighter::new();
+        h.add_rule(r"\bSELECT\b", Style::new().bold()).unwrap();
+        // each char typed at the end of the line refreshes it, up to the match
+        let line = "x SELECT";
+        for pos in 1..=line.len() {
+            assert!(h.highlight_char_at(&line[..pos], pos));
+        }
+        assert_eq!("x \x1b[1mSELECT\x1b[0m", h.highlight(line, line.len()));
+        // broken by the next one
+        assert!(h.highlight_char_at("x SELECTS", 9));
+        assert_eq!("x SELECTS", h.highlight("x SELECTS", 9));
+    }
+}
@@ -0,0 +1,311 @@
+//! Incremental syntax highlighting with tree-sitter
+use std::cell::RefCell;
+use std::cmp;
+
+use anstyle::Style;
+use tree_sitter::{
+    InputEdit, Language, Parser, Point, Query, QueryCursor, QueryError, QueryErrorKind, Tree,
+};
+
+use super::{Highlighter, Span};
+
+/// Highlights the line with a user-supplied tree-sitter highlight query.
+///
+/// The parse tree is updated incrementally from each edit of the line and
+/// the query is run on the updated tree.
+pub struct TreeSitterHighlighter {
+    query: Query,
+    // style of each capture of the query
+    capture_styles: Vec<Option<Style>>,
+    state: RefCell<State>,
+}
+
+struct State {
+    parser: Parser,
+    tree: Option<Tree>,
+    // line parsed
+    source: String,
+    // spans of the last highlighted line
+    spans: Vec<Span>,
+    cursor: QueryCursor,
+}
+
+impl TreeSitterHighlighter {
+    /// The captures of `highlights_query` are highlighted with the `styles`
+    /// whose name matches (e.g. `("keyword", Style::new().bold())`): a
+    /// capture like `@keyword.control` falls back to `keyword` when it has no
+    /// style of its own.
+    ///
+    /// When several patterns capture the same node, the first one wins; a
+    /// node nested in a captured node is highlighted with its own style.
+    pub fn new(
+        language: Language,
+        highlights_query: &str,
+        styles: &[(&str, Style)],
+    ) -> Result<TreeSitterHighlighter, QueryError> {
+        let query = try!(Query::new(language, highlights_query));
+        let capture_styles = query
+            .capture_names()
+            .iter()
+            .map(|capture| capture_style(capture, styles))
+            .collect();
+        let mut parser = Parser::new();
+        if parser.set_language(language).is_err() {
+            return Err(QueryError {
+                row: 0,
+                column: 0,
+                offset: 0,
+                message: "incompatible language version".to_owned(),
+                kind: QueryErrorKind::Language,
+            });
+        }
+        Ok(TreeSitterHighlighter {
+            query,
+            capture_styles,
+            state: RefCell::new(State {
+                parser,
+                tree: None,
+                source: String::new(),
+                spans: Vec::new(),
+                cursor: QueryCursor::new(),
+            }),
+        })
+    }
+}
+
+/// Style of the `capture` name: the one of the longest name among `styles`
+/// which is the capture or a prefix of its dot-separated components.
+fn capture_style(capture: &str, styles: &[(&str, Style)]) -> Option<Style> {
+    styles
+        .iter()
+        .filter(|&&(name, _)| {
+            capture == name || (capture.starts_with(name) && capture[name.len()..].starts_with('.'))
+        })
+        .max_by_key(|&&(name, _)| name.len())
+        .map(|&(_, style)| style)
+}
+
+impl State {
+    /// Updates the parse tree from `line`.
+    /// Returns `true` when the syntax tree has changed.
+    fn update(&mut self, line: &str) -> bool {
+        if self.tree.is_some() && self.source == line {
+            return false;
+        }
+        let changed = match self.tree.take() {
+            Some(mut old) => {
+                old.edit(&input_edit(&self.source, line));
+                let new = self.parser.parse(line, Some(&old));
+                let changed = match new {
+                    Some(ref new) => old.changed_ranges(new).len() > 0,
+                    None => true,
+                };
+                self.tree = new;
+                changed
+            }
+            None => {
+                self.tree = self.parser.parse(line, None);
+                true
+            }
+        };
+        self.source.clear();
+        self.source.push_str(line);
+        changed
+    }
+}
+
+impl Highlighter for TreeSitterHighlighter {
+    fn highlight_spans(&self, line: &str, _pos: usize) -> Vec<Span> {
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+        state.update(line);
+        let tree = match state.tree {
+            Some(ref tree) => tree,
+            None => {
+                state.spans.clear();
+                return Vec::new();
+            }
+        };
+        // (range, pattern, style) of the captured nodes
+        let mut captures = Vec::new();
+        for (m, i) in state
+            .cursor
+            .captures(&self.query, tree.root_node(), line.as_bytes())
+        {
+            let capture = m.captures[i];
+            if let Some(style) = self.capture_styles[capture.index as usize] {
+                captures.push((capture.node.byte_range(), m.pattern_index, style));
+            }
+        }
+        // outer nodes first, then the first pattern of a node
+        captures.sort_by(|a, b| (a.0.start, b.0.end, a.1).cmp(&(b.0.start, a.0.end, b.1)));
+        captures.dedup_by(|b, a| a.0 == b.0);
+        // inner nodes override the outer ones
+        let mut styles: Vec<Option<(usize, Style)>> = vec![None; line.len()];
+        for (n, &(ref range, _, style)) in captures.iter().enumerate() {
+            for byte in &mut styles[range.start..cmp::min(range.end, line.len())] {
+                *byte = Some((n, style));
+            }
+        }
+        let mut spans: Vec<Span> = Vec::new();
+        // capture of the last byte
+        let mut last = None;
+        for (i, byte) in styles.into_iter().enumerate() {
+            match byte {
+                Some((n, _)) if last == Some(n) => spans.last_mut().unwrap().0.end = i + 1,
+                Some((n, style)) => {
+                    spans.push((i..i + 1, style));
+                    last = Some(n);
+                }
+                None => last = None,
+            }
+        }
+        state.spans = spans.clone();
+        spans
+    }
+
+    fn highlight_char_at(&self, line: &str, pos: usize) -> bool {
+        let mut state = self.state.borrow_mut();
+        if state.tree.is_some() && state.source == line {
+            // cursor moved
+            return false;
+        }
+        // the char typed may extend (or split) a highlighted token
+        let typed = pos.saturating_sub(1);
+        let touched = state
+            .spans
+            .iter()
+            .any(|&(ref range, _)| range.start <= typed && typed <= range.end);
+        state.update(line) || touched
+    }
+
+    fn highlight_is_pos_dependent(&self) -> bool {
+        false
+    }
+}
+
+/// Computes the edit from `old` to `new` (common prefix and suffix excluded).
+fn input_edit(old: &str, new: &str) -> InputEdit {
+    let mut start = old
+        .bytes()
+        .zip(new.bytes())
+        .take_while(|&(a, b)| a == b)
+        .count();
+    while !old.is_char_boundary(start) || !new.is_char_boundary(start) {
+        start -= 1;
+    }
+    let max_suffix = cmp::min(old.len(), new.len()) - start;
+    let mut suffix = old
+        .bytes()
+        .rev()
+        .zip(new.bytes().rev())
+        .take(max_suffix)
+        .take_while(|&(a, b)| a == b)
+        .count();
+    while !old.is_char_boundary(old.len() - suffix) || !new.is_char_boundary(new.len() - suffix) {
+        suffix -= 1;
+    }
+    let old_end = old.len() - suffix;
+    let new_end = new.len() - suffix;
+    InputEdit {
+        start_byte: start,
+        old_end_byte: old_end,
+        new_end_byte: new_end,
+        start_position: point(old, start),
+        old_end_position: point(old, old_end),
+        new_end_position: point(new, new_end),
+    }
+}
+
+fn point(s: &str, byte: usize) -> Point {
+    let s = &s[..byte];
+    match s.rfind('\n') {
+        Some(i) => Point::new(s.matches('\n').count(), byte - i - 1),
+        None => Point::new(0, byte),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{input_edit, point};
+    use tree_sitter::Point;
+
+    #[test]
+    fn edit() {
+        let edit = input_edit("[1, 2]", "[1, 23]");
+        assert_eq!(5, edit.start_byte);
+        assert_eq!(5, edit.old_end_byte);
+        assert_eq!(6, edit.new_end_byte);
+        let edit = input_edit("a\nbc", "a\nb");
+        assert_eq!(3, edit.start_byte);
+        assert_eq!(Point::new(1, 1), edit.start_position);
+        assert_eq!(Point::new(1, 2), edit.old_end_position);
+        assert_eq!(Point::new(1, 2), point("a\nbc", 4));
+    }
+
+    // highlighting with the JSON grammar
+    #[cfg(feature = "tree-sitter-json")]
+    mod json {
+        extern crate tree_sitter_json;
+
+        use anstyle::{AnsiColor, Style};
+
+        use highlight::tree_sitter::TreeSitterHighlighter;
+        use highlight::Highlighter;
+
+        const QUERY: &str = r#"
+(pair key: (string) @property)
+(string) @string
+(number) @number
+[(true) (false)] @constant.builtin
+"#;
+
+        fn highlighter() -> TreeSitterHighlighter {
+            TreeSitterHighlighter::new(
+                tree_sitter_json::language(),
+                QUERY,
+                &[
+                    ("property", Style::new().bold()),
+                    ("string", AnsiColor::Green.on_default()),
+                    ("number", AnsiColor::Blue.on_default()),
+                    ("constant", AnsiColor::Red.on_default()),
+                ],
+            )
+            .unwrap()
+        }
+
+        #[test]
+        fn spans() {
+            let h = highlighter();
+            let bold = Style::new().bold();
+            let green = AnsiColor::Green.on_default();
+            let blue = AnsiColor::Blue.on_default();
+            let red = AnsiColor::Red.on_default();
+            // the first pattern wins, dotted captures fall back to their prefix
+            assert_eq!(
+                vec![(1..4, bold), (6..9, green), (11..14, bold), (16..20, red)],
+                h.highlight_spans(r#"{"a": "b", "c": true}"#, 0)
+            );
+            assert_eq!(vec![(1..3, blue)], h.highlight_spans("[12]", 0));
+            // edited
+            assert_eq!(
+                vec![(1..3, blue), (5..8, green)],
+                h.highlight_spans(r#"[12, "x"]"#, 0)
+            );
+            assert_eq!(
+                Vec::<(::std::ops::Range<usize>, Style)>::new(),
+                h.highlight_spans("", 0)
+            );
+        }
+
+        #[test]
+        fn highlight_char_at() {
+            let h = highlighter();
+            h.highlight_spans("[1", 0);
+            // cursor moved
+            assert!(!h.highlight_char_at("[1", 1));
+            // the number is extended
+            assert!(h.highlight_char_at("[12", 3));
+        }
+    }
+}
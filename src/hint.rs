@@ -1,11 +1,93 @@
 //! Hints (suggestions at the right of the prompt as you type).
 
+use std::borrow::Cow;
+use std::cmp::{self, Ordering};
+use std::collections::HashMap;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use history::HistoryBackend;
+use keymap::Cmd;
+
 /// Hints provider
 pub trait Hinter {
     /// Takes the currently edited `line` with the cursor `pos`ition and
     /// returns the string that should be displayed or `None`
     /// if no hint is available for the text the user currently typed.
     fn hint(&self, line: &str, pos: usize) -> Option<String>;
+
+    /// Same as `hint` with the editing `ctx` (the history and the last
+    /// command): this one is called by the editor.
+    ///
+    /// By default, `hint`.
+    fn hint_with_context(&self, line: &str, pos: usize, ctx: &Context) -> Option<String> {
+        let _ = ctx;
+        self.hint(line, pos)
+    }
+
+    /// Returns all the hints available for the `line`, best first: the user
+    /// cycles through them with `Cmd::CycleHint` (Alt-/ by default).
+    /// The first one should be the one returned by `hint`.
+    ///
+    /// By default, only the one returned by `hint`.
+    fn hints(&self, line: &str, pos: usize) -> Vec<String> {
+        self.hint(line, pos).into_iter().collect()
+    }
+
+    /// Same as `hints` with the editing `ctx`: this one is called by the
+    /// editor. The first one should be the one returned by
+    /// `hint_with_context`.
+    ///
+    /// By default, `hints`.
+    fn hints_with_context(&self, line: &str, pos: usize, ctx: &Context) -> Vec<String> {
+        let _ = ctx;
+        self.hints(line, pos)
+    }
+
+    /// Called when the first `accepted` bytes of `hint` (the next word) have
+    /// been inserted at the end of the line (see `Cmd::AcceptHintWord`).
+    /// The hint is then recomputed for the new line: a stateful hinter can
+    /// serve the remainder of `hint`.
+    fn hint_accepted(&self, hint: &str, accepted: usize) {
+        let _ = (hint, accepted);
+    }
+
+    /// Maximum width (in columns) of the hints displayed: the wider ones are
+    /// truncated, the end being replaced by `…`. The whole hint is accepted
+    /// (see `Cmd::AcceptHintWord`).
+    ///
+    /// By default, no limit.
+    fn max_hint_width(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Context of the edited line given to the hinter.
+pub struct Context<'h> {
+    history: &'h HistoryBackend,
+    last_cmd: &'h Cmd,
+}
+
+impl<'h> Context<'h> {
+    pub fn new(history: &'h HistoryBackend, last_cmd: &'h Cmd) -> Context<'h> {
+        Context { history, last_cmd }
+    }
+
+    /// History of the editor (without the edited line).
+    pub fn history(&self) -> &HistoryBackend {
+        self.history
+    }
+
+    /// Last command typed (`Cmd::Noop` before the first one): like
+    /// `Cmd::SelfInsert` after a char has been inserted.
+    pub fn last_cmd(&self) -> &Cmd {
+        self.last_cmd
+    }
+
+    /// Return `true` if the line has just been completed (the last command
+    /// is `Cmd::Complete`).
+    pub fn is_after_completion(&self) -> bool {
+        *self.last_cmd == Cmd::Complete
+    }
 }
 
 impl Hinter for () {
@@ -13,3 +95,336 @@ impl Hinter for () {
         None
     }
 }
+
+/// Hints the remaining arguments of the known commands: with the
+/// placeholders `["<src>", "<dst>"]` for `cp`, `cp a` is hinted ` <dst>`.
+///
+/// The arguments are separated by whitespaces or, when the command is called
+/// like a function, by commas: `cp(a` is hinted `, <dst>)`.
+/// The hint starts with the placeholder of the argument after the one under
+/// the cursor (only at the end of the line).
+pub struct GrammarHinter {
+    commands: HashMap<String, Vec<String>>,
+}
+
+impl GrammarHinter {
+    /// Takes the argument placeholders of each command (by name).
+    pub fn new(commands: HashMap<String, Vec<String>>) -> GrammarHinter {
+        GrammarHinter { commands }
+    }
+}
+
+impl Hinter for GrammarHinter {
+    fn hint(&self, line: &str, pos: usize) -> Option<String> {
+        if pos < line.len() {
+            return None;
+        }
+        let line = line.trim_start();
+        let name_end = line
+            .find(|c: char| c.is_whitespace() || c == '(')
+            .unwrap_or_else(|| line.len());
+        let params = match self.commands.get(&line[..name_end]) {
+            Some(params) => params,
+            None => return None,
+        };
+        let args = &line[name_end..];
+        if args.starts_with('(') {
+            let args = &args[1..];
+            if args.contains(')') {
+                return None;
+            }
+            // the last one is the argument under the cursor
+            let typed: Vec<&str> = args.split(',').collect();
+            let last = typed[typed.len() - 1];
+            let in_arg = !last.trim().is_empty();
+            let current = cmp::min(typed.len() - 1 + in_arg as usize, params.len());
+            let mut hint = String::new();
+            if in_arg {
+                for param in &params[current..] {
+                    hint.push_str(", ");
+                    hint.push_str(param);
+                }
+            } else {
+                if typed.len() > 1 && last.is_empty() {
+                    hint.push(' ');
+                }
+                hint.push_str(&params[current..].join(", "));
+            }
+            hint.push(')');
+            return Some(hint);
+        }
+        if args.is_empty() {
+            // right after the command name
+            return if params.is_empty() {
+                None
+            } else {
+                Some(format!(" {}", params.join(" ")))
+            };
+        }
+        let count = args.split_whitespace().count();
+        let in_arg = !args.ends_with(char::is_whitespace);
+        let current = cmp::min(count, params.len());
+        if current == params.len() {
+            None
+        } else if in_arg {
+            Some(params[current..].iter().map(|p| format!(" {}", p)).collect())
+        } else {
+            Some(params[current..].join(" "))
+        }
+    }
+}
+
+/// How `HistoryHinter` chooses among the history entries starting with the
+/// line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HintRanking {
+    /// The most recent entry.
+    Recency,
+    /// The most entered entry (see `History::entry_usage_count`), the most
+    /// recent one first in case of a tie.
+    Frequency,
+    /// The most entered entry, the uses of an entry counting half every
+    /// `RANKING_HALF_LIFE` newer entries.
+    Combined,
+    /// The shortest entry, the most recent one first in case of a tie.
+    Shortest,
+}
+
+/// Number of newer entries after which the uses of an entry count half with
+/// `HintRanking::Combined`.
+const RANKING_HALF_LIFE: f64 = 100.0;
+
+/// Hints the end of a history entry starting with the line (when the cursor
+/// is at the end of the line), like the fish shell.
+///
+/// The history is only available through `Hinter::hint_with_context` and
+/// `Hinter::hints_with_context` (which cycles through the other entries):
+/// `hint` and `hints` return nothing.
+pub struct HistoryHinter {
+    ranking: HintRanking,
+    max_width: Option<usize>,
+}
+
+impl HistoryHinter {
+    pub fn new() -> HistoryHinter {
+        HistoryHinter {
+            ranking: HintRanking::Recency,
+            max_width: None,
+        }
+    }
+
+    /// Chooses how the history entries are ranked (`HintRanking::Recency` by
+    /// default).
+    pub fn set_hint_ranking(&mut self, ranking: HintRanking) {
+        self.ranking = ranking;
+    }
+
+    /// Truncates the hints displayed wider than `max_width` columns (not
+    /// truncated by default): the end is replaced by `…` (see
+    /// `Hinter::max_hint_width`).
+    pub fn set_max_width(&mut self, max_width: Option<usize>) {
+        self.max_width = max_width;
+    }
+
+    /// The distinct entries starting with `line` (and longer), best first.
+    fn ranked<'h>(&self, history: &'h HistoryBackend, line: &str) -> Vec<Cow<'h, str>> {
+        let len = history.len();
+        // score and recency rank of each entry (the most recent first)
+        let mut scores: HashMap<Cow<str>, (f64, usize)> = HashMap::new();
+        for (index, entry) in history.starting_with(line) {
+            if entry.len() == line.len() {
+                continue;
+            }
+            let score = match self.ranking {
+                HintRanking::Recency | HintRanking::Shortest => 0.,
+                HintRanking::Frequency => history.usage_count(index) as f64,
+                HintRanking::Combined => {
+                    let age = (len - 1 - index) as f64;
+                    history.usage_count(index) as f64 * 0.5f64.powf(age / RANKING_HALF_LIFE)
+                }
+            };
+            let rank = scores.len();
+            scores.entry(entry).or_insert((0., rank)).0 += score;
+        }
+        let mut ranked: Vec<_> = scores.into_iter().collect();
+        match self.ranking {
+            HintRanking::Recency => ranked.sort_by_key(|&(_, (_, rank))| rank),
+            HintRanking::Shortest => {
+                ranked.sort_by_key(|&(ref entry, (_, rank))| (entry.len(), rank))
+            }
+            HintRanking::Frequency | HintRanking::Combined => {
+                ranked.sort_by(|&(_, (score, rank)), &(_, (other, other_rank))| {
+                    other
+                        .partial_cmp(&score)
+                        .unwrap_or(Ordering::Equal)
+                        .then(rank.cmp(&other_rank))
+                })
+            }
+        }
+        ranked.into_iter().map(|(entry, _)| entry).collect()
+    }
+}
+
+/// Truncates `hint` to `max_width` columns, the last one being `…`, or
+/// returns `None` if no char fits.
+pub(crate) fn truncate_hint(hint: &str, max_width: usize) -> Option<String> {
+    if hint.width() <= max_width {
+        return Some(hint.to_owned());
+    }
+    let mut truncated = String::new();
+    let mut width = 0;
+    for c in hint.chars() {
+        width += c.width().unwrap_or(0);
+        if width + 1 > max_width {
+            break;
+        }
+        truncated.push(c);
+    }
+    if truncated.is_empty() {
+        return None;
+    }
+    truncated.push('…');
+    Some(truncated)
+}
+
+impl Default for HistoryHinter {
+    fn default() -> HistoryHinter {
+        HistoryHinter::new()
+    }
+}
+
+impl Hinter for HistoryHinter {
+    fn hint(&self, _line: &str, _pos: usize) -> Option<String> {
+        None
+    }
+
+    fn hint_with_context(&self, line: &str, pos: usize, ctx: &Context) -> Option<String> {
+        self.hints_with_context(line, pos, ctx).into_iter().next()
+    }
+
+    fn hints_with_context(&self, line: &str, pos: usize, ctx: &Context) -> Vec<String> {
+        if line.is_empty() || pos < line.len() {
+            return Vec::new();
+        }
+        self.ranked(ctx.history(), line)
+            .into_iter()
+            .map(|entry| entry[pos..].to_owned())
+            .collect()
+    }
+
+    fn max_hint_width(&self) -> Option<usize> {
+        self.max_width
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Context, GrammarHinter, HintRanking, Hinter, HistoryHinter};
+    use config::{Config, HistoryDuplicates};
+    use history::History;
+    use keymap::Cmd;
+    use std::collections::HashMap;
+
+    #[test]
+    pub fn grammar() {
+        let mut commands = HashMap::new();
+        commands.insert("cp".to_owned(), vec!["<src>".to_owned(), "<dst>".to_owned()]);
+        commands.insert("quit".to_owned(), vec![]);
+        let h = GrammarHinter::new(commands);
+        for &(line, hint) in &[
+            ("cp", Some(" <src> <dst>")),
+            ("cp ", Some("<src> <dst>")),
+            ("cp a", Some(" <dst>")),
+            ("  cp a  ", Some("<dst>")),
+            ("cp a b", None),
+            ("cp a b c", None),
+            ("cpx", None),
+            ("quit", None),
+            ("cp(", Some("<src>, <dst>)")),
+            ("cp(a", Some(", <dst>)")),
+            ("cp(a,", Some(" <dst>)")),
+            ("cp(a, ", Some("<dst>)")),
+            ("cp(a, b", Some(")")),
+            ("cp(a, b)", None),
+        ] {
+            assert_eq!(hint.map(str::to_owned), h.hint(line, line.len()), "{}", line);
+        }
+        // not at the end of the line
+        assert_eq!(None, h.hint("cp a", 2));
+    }
+
+    #[test]
+    pub fn history_ranking() {
+        let config = Config::builder()
+            .history_duplicates(HistoryDuplicates::IgnoreAll)
+            .build();
+        let mut history = History::with_config(config);
+        history.add("git commit");
+        for _ in 0..3 {
+            history.add("git status");
+        }
+        for i in 0..80 {
+            history.add(format!("ls {}", i));
+        }
+        history.add("git checkout");
+        history.add("git checkout");
+        let ctx = Context::new(&history, &Cmd::Noop);
+        let mut h = HistoryHinter::new();
+        assert_eq!(None, h.hint("git", 3));
+        assert_eq!(Some(" checkout".to_owned()), h.hint_with_context("git", 3, &ctx));
+        // the line itself is not hinted
+        assert_eq!(None, h.hint_with_context("git checkout", 12, &ctx));
+        assert_eq!(None, h.hint_with_context("git", 2, &ctx));
+        assert_eq!(None, h.hint_with_context("", 0, &ctx));
+
+        h.set_hint_ranking(HintRanking::Frequency);
+        assert_eq!(Some(" status".to_owned()), h.hint_with_context("git", 3, &ctx));
+        assert_eq!(Some("heckout".to_owned()), h.hint_with_context("git c", 5, &ctx));
+        assert_eq!(
+            vec![" status", " checkout", " commit"],
+            h.hints_with_context("git", 3, &ctx)
+        );
+        // the old uses are decayed
+        h.set_hint_ranking(HintRanking::Combined);
+        assert_eq!(Some(" checkout".to_owned()), h.hint_with_context("git", 3, &ctx));
+        h.set_hint_ranking(HintRanking::Recency);
+        assert_eq!(
+            vec![" checkout", " status", " commit"],
+            h.hints_with_context("git", 3, &ctx)
+        );
+    }
+
+    #[test]
+    pub fn history_shortest_truncated() {
+        let mut history = History::new();
+        history.add("cargo test --workspace --all-features");
+        history.add("cargo build");
+        history.add("cargo test");
+        history.add("cargo build --release");
+        let ctx = Context::new(&history, &Cmd::Noop);
+        let mut h = HistoryHinter::new();
+        h.set_max_width(Some(8));
+        assert_eq!(Some(8), h.max_hint_width());
+        // truncated when displayed only
+        assert_eq!(
+            Some(" build --release".to_owned()),
+            h.hint_with_context("cargo", 5, &ctx)
+        );
+        h.set_hint_ranking(HintRanking::Shortest);
+        assert_eq!(Some(" test".to_owned()), h.hint_with_context("cargo", 5, &ctx));
+        h.set_max_width(None);
+        assert_eq!(None, h.max_hint_width());
+        assert_eq!(Some(" test".to_owned()), h.hint_with_context("cargo", 5, &ctx));
+    }
+
+    #[test]
+    pub fn truncate_hint() {
+        assert_eq!(Some(" test".to_owned()), super::truncate_hint(" test", 8));
+        assert_eq!(Some(" build …".to_owned()), super::truncate_hint(" build --release", 8));
+        assert_eq!(Some("build -…".to_owned()), super::truncate_hint("build --release", 8));
+        // wide chars
+        assert_eq!(Some("日本…".to_owned()), super::truncate_hint("日本語", 5));
+        // nothing fits
+        assert_eq!(None, super::truncate_hint(" test", 1));
+    }
+}
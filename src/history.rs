@@ -2,16 +2,27 @@
 
 #[cfg(unix)]
 use libc;
+use std::borrow::Cow::{self, Borrowed, Owned};
 use std::collections::vec_deque;
-use std::collections::VecDeque;
-use std::fs::File;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead};
 use std::iter::DoubleEndedIterator;
-use std::ops::Index;
-use std::path::Path;
+use std::ops::{Bound, Index};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use super::Result;
 use config::{Config, HistoryDuplicates};
 
+#[cfg(feature = "sqlite-history")]
+mod sqlite;
+#[cfg(feature = "sqlite-history")]
+pub use self::sqlite::SQLiteHistory;
+
 /// Search direction
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Direction {
@@ -19,13 +30,123 @@ pub enum Direction {
     Reverse,
 }
 
+/// Source of the entries browsed and searched while editing a line.
+pub trait HistoryBackend {
+    /// Return the history entry at position `index`, starting from 0.
+    ///
+    /// The entry is owned when the backend loads it lazily (like from a
+    /// database).
+    fn get(&self, index: usize) -> Option<Cow<'_, str>>;
+    /// Return the number of entries.
+    fn len(&self) -> usize;
+    /// Add a new entry.
+    fn add(&mut self, entry: String);
+
+    /// Return true if there is no entry.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Return how many times the entry at `index` has been entered (see
+    /// `History::entry_usage_count`).
+    ///
+    /// By default, once.
+    fn usage_count(&self, index: usize) -> usize {
+        let _ = index;
+        1
+    }
+    /// Search history (start position inclusive [0, len-1]).
+    ///
+    /// Return the absolute index of the nearest history entry that matches
+    /// `term`.
+    fn search(&self, term: &str, start: usize, dir: Direction) -> Option<usize> {
+        search_entries(self, term, start, dir, usize::max_value(), |entry| entry.contains(term))
+    }
+    /// Same as `search` but examines at most `limit` entries (from `start`).
+    fn search_limited(
+        &self,
+        term: &str,
+        start: usize,
+        dir: Direction,
+        limit: usize,
+    ) -> Option<usize> {
+        search_entries(self, term, start, dir, limit, |entry| entry.contains(term))
+    }
+    /// Anchored search
+    fn starts_with(&self, term: &str, start: usize, dir: Direction) -> Option<usize> {
+        search_entries(self, term, start, dir, usize::max_value(), |entry| {
+            entry.starts_with(term)
+        })
+    }
+    /// Return all the entries starting with `term` with their index, the
+    /// most recent first.
+    ///
+    /// By default, the history is scanned.
+    fn starting_with(&self, term: &str) -> Vec<(usize, Cow<'_, str>)> {
+        (0..self.len())
+            .rev()
+            .filter_map(|index| self.get(index).map(|entry| (index, entry)))
+            .filter(|&(_, ref entry)| entry.starts_with(term))
+            .collect()
+    }
+}
+
+pub(crate) fn search_entries<B, F>(
+    history: &B,
+    term: &str,
+    start: usize,
+    dir: Direction,
+    limit: usize,
+    test: F,
+) -> Option<usize>
+where
+    B: HistoryBackend + ?Sized,
+    F: Fn(&str) -> bool,
+{
+    if term.is_empty() || start >= history.len() {
+        return None;
+    }
+    let matches = |index: &usize| history.get(*index).map_or(false, |entry| test(&entry));
+    match dir {
+        Direction::Reverse => (0..=start).rev().take(limit).find(matches),
+        Direction::Forward => (start..history.len()).take(limit).find(matches),
+    }
+}
+
+/// First line of the history files with timestamps
+const V2_HEADER: &str = "#V2";
+
+/// Maximum number of names tried for the temporary file of `History::save`
+const TEMP_FILE_ATTEMPTS: usize = 100;
+
+/// Minimum number of entries for which the anchored search uses an index
+/// (smaller histories are scanned).
+const PREFIX_INDEX_MIN_LEN: usize = 1000;
+
+/// Maximum number of distinct entries walked in the index by the anchored
+/// search: a prefix matching more is common, so the history is scanned
+/// instead (the nearest match is likely close).
+const PREFIX_INDEX_MAX_MATCHES: usize = 64;
+
 /// Current state of the history.
 #[derive(Default)]
 pub struct History {
     entries: VecDeque<String>,
+    // Creation time of each entry (`None` when loaded from a file without
+    // timestamps)
+    timestamps: VecDeque<Option<SystemTime>>,
+    // Number of times each entry has been entered (the duplicates not added
+    // are counted by the entry they duplicate)
+    uses: VecDeque<usize>,
     max_len: usize,
     pub(crate) ignore_space: bool,
-    pub(crate) ignore_dups: bool,
+    pub(crate) duplicates: HistoryDuplicates,
+    // Id of each entry (increasing, and stable when other entries are
+    // removed)
+    ids: VecDeque<u64>,
+    next_id: u64,
+    // Ids of the entries by text, built on the first anchored search of a
+    // large history then kept up to date
+    prefix_index: Mutex<Option<BTreeMap<String, BTreeSet<u64>>>>,
 }
 
 impl History {
@@ -36,9 +157,14 @@ impl History {
     pub fn with_config(config: Config) -> History {
         History {
             entries: VecDeque::new(),
+            timestamps: VecDeque::new(),
+            uses: VecDeque::new(),
             max_len: config.max_history_size(),
             ignore_space: config.history_ignore_space(),
-            ignore_dups: config.history_duplicates() == HistoryDuplicates::IgnoreConsecutive,
+            duplicates: config.history_duplicates(),
+            ids: VecDeque::new(),
+            next_id: 0,
+            prefix_index: Mutex::new(None),
         }
     }
 
@@ -52,8 +178,37 @@ impl History {
         self.entries.back()
     }
 
+    /// Return the time when the entry at position `index` was added.
+    ///
+    /// Return `None` for the entries loaded from a file saved without
+    /// timestamps (by an older version).
+    pub fn entry_timestamp(&self, index: usize) -> Option<SystemTime> {
+        self.timestamps.get(index).and_then(|&timestamp| timestamp)
+    }
+
+    /// Return how many times the entry at position `index` has been entered:
+    /// the duplicates ignored (see `HistoryDuplicates`) are counted by the
+    /// entry they duplicate.
+    ///
+    /// The counts are not saved: the entries loaded from a file are counted
+    /// once (plus their ignored duplicates).
+    pub fn entry_usage_count(&self, index: usize) -> usize {
+        self.uses.get(index).map_or(0, |&uses| uses)
+    }
+
     /// Add a new entry in the history.
+    ///
+    /// With `HistoryDuplicates::IgnoreAll`, an entry added again is moved to
+    /// the most recent position.
     pub fn add<S: AsRef<str> + Into<String>>(&mut self, line: S) -> bool {
+        self.add_with_timestamp(line, Some(SystemTime::now()))
+    }
+
+    fn add_with_timestamp<S: AsRef<str> + Into<String>>(
+        &mut self,
+        line: S,
+        timestamp: Option<SystemTime>,
+    ) -> bool {
         if self.max_len == 0 {
             return false;
         }
@@ -66,20 +221,66 @@ impl History {
         {
             return false;
         }
-        if self.ignore_dups {
+        if self.duplicates != HistoryDuplicates::AlwaysAdd {
             if let Some(s) = self.entries.back() {
                 if s == line.as_ref() {
+                    *self.uses.back_mut().unwrap() += 1;
                     return false;
                 }
             }
         }
+        let mut uses = 1;
+        if self.duplicates == HistoryDuplicates::IgnoreAll {
+            uses += self.remove_all(line.as_ref());
+        }
         if self.entries.len() == self.max_len {
-            self.entries.pop_front();
+            self.remove(0);
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        if let Some(ref mut prefix_index) = *self.prefix_index.get_mut().unwrap() {
+            let ids = prefix_index
+                .entry(line.as_ref().to_owned())
+                .or_insert_with(BTreeSet::new);
+            ids.insert(id);
         }
         self.entries.push_back(line.into());
+        self.timestamps.push_back(timestamp);
+        self.uses.push_back(uses);
+        self.ids.push_back(id);
         true
     }
 
+    /// Remove the entry at position `index`.
+    fn remove(&mut self, index: usize) {
+        let entry = match self.entries.remove(index) {
+            Some(entry) => entry,
+            None => return,
+        };
+        self.timestamps.remove(index);
+        self.uses.remove(index);
+        let id = self.ids.remove(index).unwrap();
+        if let Some(ref mut prefix_index) = *self.prefix_index.get_mut().unwrap() {
+            let unused = prefix_index.get_mut(&entry).map_or(false, |ids| {
+                ids.remove(&id);
+                ids.is_empty()
+            });
+            if unused {
+                prefix_index.remove(&entry);
+            }
+        }
+    }
+
+    /// Remove the entries equal to `line` and return their usage counts sum.
+    fn remove_all(&mut self, line: &str) -> usize {
+        let mut uses = 0;
+        while let Some(index) = self.entries.iter().position(|entry| entry == line) {
+            uses += self.uses[index];
+            self.remove(index);
+        }
+        uses
+    }
+
     /// Return the number of entries in the history.
     pub fn len(&self) -> usize {
         self.entries.len()
@@ -100,40 +301,81 @@ impl History {
     pub fn set_max_len(&mut self, len: usize) {
         self.max_len = len;
         if len == 0 {
-            self.entries.clear();
+            self.clear();
             return;
         }
         loop {
             if self.entries.len() <= len {
                 break;
             }
-            self.entries.pop_front();
+            self.remove(0);
         }
     }
 
     /// Save the history in the specified file.
+    ///
+    /// The file starts with a `#V2` line and each entry is preceded by a line
+    /// with its timestamp (`#` followed by the UTC time in RFC 3339 format,
+    /// or only `#` when unknown). The newlines (and backslashes) of the
+    /// entries are escaped (`\n`, `\r` and `\\`): each entry is on one line.
+    ///
+    /// The history is written to a new temporary file in the same directory
+    /// (never through an existing file or link) which then replaces the
+    /// file: if the process is killed meanwhile, the previous file is left
+    /// untouched. The permissions (and, if allowed, the owner) of the
+    /// replaced file are kept, and a symbolic link is followed (the target
+    /// is replaced, not the link).
+    /// On Windows, the replacement fails if the file is opened by another
+    /// process without sharing the deletion.
     // TODO append_history
     // http://cnswww.cns.cwru.edu/php/chet/readline/history.html#IDX30
     // TODO history_truncate_file
     // http://cnswww.cns.cwru.edu/php/chet/readline/history.html#IDX31
     pub fn save<P: AsRef<Path> + ?Sized>(&self, path: &P) -> Result<()> {
-        use std::io::{BufWriter, Write};
-
         if self.is_empty() {
             return Ok(());
         }
+        let path = path.as_ref();
+        // replace the target of the link
+        let path = match fs::symlink_metadata(path) {
+            Ok(ref metadata) if metadata.file_type().is_symlink() => try!(fs::canonicalize(path)),
+            _ => path.to_path_buf(),
+        };
         let old_umask = umask();
-        let f = File::create(path);
+        let temp = create_temp(&path);
         restore_umask(old_umask);
-        let file = try!(f);
-        fix_perm(&file);
+        let (file, tmp_path) = try!(temp);
+        let result = self
+            .write_to(file, &path)
+            .and_then(|_| fs::rename(&tmp_path, &path).map_err(From::from))
+            .and_then(|_| sync_dir(&path));
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        result
+    }
+
+    // writes the history into the temporary `file` which replaces `path`
+    fn write_to(&self, file: File, path: &Path) -> Result<()> {
+        use std::io::{BufWriter, Write};
+
+        fix_perm(&file, path);
         let mut wtr = BufWriter::new(file);
-        for entry in &self.entries {
-            try!(wtr.write_all(entry.as_bytes()));
+        try!(wtr.write_all(V2_HEADER.as_bytes()));
+        try!(wtr.write_all(b"\n"));
+        for (entry, timestamp) in self.entries.iter().zip(&self.timestamps) {
+            try!(wtr.write_all(b"#"));
+            if let Some(timestamp) = *timestamp {
+                try!(wtr.write_all(format_timestamp(timestamp).as_bytes()));
+            }
+            try!(wtr.write_all(b"\n"));
+            try!(wtr.write_all(escape(entry).as_bytes()));
             try!(wtr.write_all(b"\n"));
         }
         // https://github.com/rust-lang/rust/issues/32677#issuecomment-204833485
         try!(wtr.flush());
+        // the content must be on disk before the file is replaced
+        try!(wtr.get_ref().sync_all());
         Ok(())
     }
 
@@ -142,19 +384,56 @@ impl History {
     /// # Errors
     /// Will return `Err` if path does not already exist or could not be read.
     pub fn load<P: AsRef<Path> + ?Sized>(&mut self, path: &P) -> Result<()> {
-        use std::io::{BufRead, BufReader};
+        use std::io::BufReader;
 
         let file = try!(File::open(&path));
-        let rdr = BufReader::new(file);
-        for line in rdr.lines() {
-            self.add(try!(line).as_ref()); // TODO truncate to MAX_LINE
+        self.load_from(BufReader::new(file))
+    }
+
+    /// Load the history from `rdr` (one entry per line), like from a pipe or
+    /// a decompressed stream.
+    ///
+    /// Both the format written by `save` and the older one without
+    /// timestamps are supported (the entries of the latter have no
+    /// timestamp).
+    /// Entries are added with the same length and duplicates policies as
+    /// `add`.
+    pub fn load_from<R: BufRead>(&mut self, rdr: R) -> Result<()> {
+        let mut lines = rdr.lines();
+        let first = match lines.next() {
+            Some(line) => try!(line),
+            None => return Ok(()),
+        };
+        if first != V2_HEADER {
+            self.add_with_timestamp(first, None); // TODO truncate to MAX_LINE
+            for line in lines {
+                self.add_with_timestamp(try!(line), None);
+            }
+            return Ok(());
+        }
+        while let Some(line) = lines.next() {
+            let line = try!(line);
+            // an invalid timestamp is ignored
+            let timestamp = if line.starts_with('#') {
+                parse_timestamp(&line[1..])
+            } else {
+                None
+            };
+            match lines.next() {
+                Some(entry) => self.add_with_timestamp(unescape(&try!(entry)), timestamp),
+                None => break,
+            };
         }
         Ok(())
     }
 
     /// Clear history
     pub fn clear(&mut self) {
-        self.entries.clear()
+        self.entries.clear();
+        self.timestamps.clear();
+        self.uses.clear();
+        self.ids.clear();
+        *self.prefix_index.get_mut().unwrap() = None;
     }
 
     /// Search history (start position inclusive [0, len-1]).
@@ -171,9 +450,82 @@ impl History {
     }
 
     /// Anchored search
+    ///
+    /// Large histories are indexed (on the first search): the entries
+    /// starting with `term` are found without scanning the others, unless
+    /// `term` is a prefix of many distinct entries.
     pub fn starts_with(&self, term: &str, start: usize, dir: Direction) -> Option<usize> {
-        let test = |entry: &String| entry.starts_with(term);
-        self.search_match(term, start, dir, test)
+        if term.is_empty() || start >= self.len() {
+            return None;
+        }
+        if self.len() < PREFIX_INDEX_MIN_LEN {
+            let test = |entry: &String| entry.starts_with(term);
+            return self.search_match(term, start, dir, test);
+        }
+        let mut prefix_index = self.prefix_index.lock().unwrap();
+        let prefix_index = self.build_prefix_index(&mut prefix_index);
+        let start_id = self.ids[start];
+        // the entries starting with `term` are contiguous, and the nearest
+        // id of each one is found without scanning the others
+        let matches = prefix_index
+            .range::<str, _>((Bound::Included(term), Bound::Unbounded))
+            .take_while(|&(entry, _)| entry.starts_with(term));
+        let mut nearest: Option<u64> = None;
+        for (n, (_, ids)) in matches.enumerate() {
+            if n == PREFIX_INDEX_MAX_MATCHES {
+                let test = |entry: &String| entry.starts_with(term);
+                return self.search_match(term, start, dir, test);
+            }
+            nearest = match dir {
+                Direction::Reverse => match ids.range(..=start_id).next_back() {
+                    Some(&id) => Some(nearest.map_or(id, |nearest| nearest.max(id))),
+                    None => nearest,
+                },
+                Direction::Forward => match ids.range(start_id..).next() {
+                    Some(&id) => Some(nearest.map_or(id, |nearest| nearest.min(id))),
+                    None => nearest,
+                },
+            };
+        }
+        nearest.map(|id| self.ids.binary_search(&id).unwrap())
+    }
+
+    /// Return the indexes of all the entries starting with `term`, the most
+    /// recent first (with the index of the anchored search).
+    pub fn starting_with(&self, term: &str) -> Vec<usize> {
+        if self.len() < PREFIX_INDEX_MIN_LEN {
+            return (0..self.len())
+                .rev()
+                .filter(|&index| self.entries[index].starts_with(term))
+                .collect();
+        }
+        let mut prefix_index = self.prefix_index.lock().unwrap();
+        let prefix_index = self.build_prefix_index(&mut prefix_index);
+        let mut indexes: Vec<usize> = prefix_index
+            .range::<str, _>((Bound::Included(term), Bound::Unbounded))
+            .take_while(|&(entry, _)| entry.starts_with(term))
+            .flat_map(|(_, ids)| ids.iter())
+            .map(|id| self.ids.binary_search(id).unwrap())
+            .collect();
+        indexes.sort_unstable_by(|a, b| b.cmp(a));
+        indexes
+    }
+
+    /// Returns the index of the entries by text, built if needed.
+    fn build_prefix_index<'i>(
+        &self,
+        prefix_index: &'i mut Option<BTreeMap<String, BTreeSet<u64>>>,
+    ) -> &'i BTreeMap<String, BTreeSet<u64>> {
+        prefix_index.get_or_insert_with(|| {
+            let mut prefix_index = BTreeMap::new();
+            for (entry, &id) in self.entries.iter().zip(self.ids.iter()) {
+                let ids = prefix_index
+                    .entry(entry.clone())
+                    .or_insert_with(BTreeSet::new);
+                ids.insert(id);
+            }
+            prefix_index
+        })
     }
 
     fn search_match<F>(&self, term: &str, start: usize, dir: Direction, test: F) -> Option<usize>
@@ -206,6 +558,39 @@ impl History {
     }
 }
 
+impl HistoryBackend for History {
+    fn get(&self, index: usize) -> Option<Cow<'_, str>> {
+        History::get(self, index).map(|entry| Borrowed(entry.as_str()))
+    }
+
+    fn len(&self) -> usize {
+        History::len(self)
+    }
+
+    fn usage_count(&self, index: usize) -> usize {
+        self.entry_usage_count(index)
+    }
+
+    fn add(&mut self, entry: String) {
+        History::add(self, entry);
+    }
+
+    fn search(&self, term: &str, start: usize, dir: Direction) -> Option<usize> {
+        History::search(self, term, start, dir)
+    }
+
+    fn starts_with(&self, term: &str, start: usize, dir: Direction) -> Option<usize> {
+        History::starts_with(self, term, start, dir)
+    }
+
+    fn starting_with(&self, term: &str) -> Vec<(usize, Cow<'_, str>)> {
+        History::starting_with(self, term)
+            .into_iter()
+            .map(|index| (index, Borrowed(self.entries[index].as_str())))
+            .collect()
+    }
+}
+
 impl Index<usize> for History {
     type Output = String;
 
@@ -244,6 +629,182 @@ impl<'a> DoubleEndedIterator for Iter<'a> {
     }
 }
 
+/// In-memory history, without size limit nor duplicates filtering.
+#[derive(Clone, Debug, Default)]
+pub struct VecHistory {
+    entries: Vec<String>,
+}
+
+impl VecHistory {
+    pub fn new() -> VecHistory {
+        VecHistory {
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl HistoryBackend for VecHistory {
+    fn get(&self, index: usize) -> Option<Cow<'_, str>> {
+        self.entries.get(index).map(|entry| Borrowed(entry.as_str()))
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn add(&mut self, entry: String) {
+        if !entry.is_empty() {
+            self.entries.push(entry);
+        }
+    }
+}
+
+/// Escapes the line breaks and the backslashes of a multi-line `entry`.
+fn escape(entry: &str) -> Cow<'_, str> {
+    if !entry.contains(|c| c == '\\' || c == '\n' || c == '\r') {
+        return Borrowed(entry);
+    }
+    let mut escaped = String::with_capacity(entry.len() + 2);
+    for c in entry.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            _ => escaped.push(c),
+        }
+    }
+    Owned(escaped)
+}
+
+/// Reverses `escape` (an unknown escape sequence is kept as is).
+fn unescape(line: &str) -> String {
+    let mut entry = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            entry.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => entry.push('\n'),
+            Some('r') => entry.push('\r'),
+            Some('\\') => entry.push('\\'),
+            Some(c) => {
+                entry.push('\\');
+                entry.push(c);
+            }
+            None => entry.push('\\'),
+        }
+    }
+    entry
+}
+
+/// Format `timestamp` like `2018-09-29T17:04:36Z` (RFC 3339, UTC, whole
+/// seconds).
+fn format_timestamp(timestamp: SystemTime) -> String {
+    let secs = match timestamp.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+    let days = secs.div_euclid(86_400);
+    let time = secs.rem_euclid(86_400);
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// Parse an RFC 3339 date-time (like `2018-09-29T19:04:36.5+02:00`),
+/// ignoring the fraction of second.
+fn parse_timestamp(s: &str) -> Option<SystemTime> {
+    // value of the `len` ASCII digits at `start` (`None` if one is not a digit)
+    fn number(b: &[u8], start: usize, len: usize) -> Option<i64> {
+        match b.get(start..start + len) {
+            Some(digits) if digits.iter().all(u8::is_ascii_digit) => {
+                Some(digits.iter().fold(0, |n, &d| n * 10 + i64::from(d - b'0')))
+            }
+            _ => None,
+        }
+    }
+    let b = s.as_bytes();
+    if b.len() < 20
+        || b[4] != b'-'
+        || b[7] != b'-'
+        || (b[10] != b'T' && b[10] != b't' && b[10] != b' ')
+        || b[13] != b':'
+        || b[16] != b':'
+    {
+        return None;
+    }
+    let (year, month, day, hour, min, sec) = match (
+        number(b, 0, 4),
+        number(b, 5, 2),
+        number(b, 8, 2),
+        number(b, 11, 2),
+        number(b, 14, 2),
+        number(b, 17, 2),
+    ) {
+        (Some(year), Some(month), Some(day), Some(hour), Some(min), Some(sec)) => {
+            (year, month, day, hour, min, sec)
+        }
+        _ => return None,
+    };
+    if month < 1 || month > 12 || day < 1 || day > 31 || hour > 23 || min > 59 || sec > 60 {
+        return None;
+    }
+    let mut i = 19;
+    if b[i] == b'.' {
+        i += 1;
+        while i < b.len() && b[i].is_ascii_digit() {
+            i += 1;
+        }
+    }
+    let offset = match b.get(i) {
+        Some(&b'Z') | Some(&b'z') if i + 1 == b.len() => 0,
+        Some(&sign) if (sign == b'+' || sign == b'-') && i + 6 == b.len() && b[i + 3] == b':' => {
+            let offset = match (number(b, i + 1, 2), number(b, i + 4, 2)) {
+                (Some(hours), Some(mins)) => hours * 3600 + mins * 60,
+                _ => return None,
+            };
+            if sign == b'+' {
+                offset
+            } else {
+                -offset
+            }
+        }
+        _ => return None,
+    };
+    // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    let secs = days * 86_400 + hour * 3600 + min * 60 + sec - offset;
+    if secs >= 0 {
+        UNIX_EPOCH.checked_add(Duration::from_secs(secs as u64))
+    } else {
+        UNIX_EPOCH.checked_sub(Duration::from_secs(secs.unsigned_abs()))
+    }
+}
+
 #[cfg(windows)]
 fn umask() -> u16 {
     0
@@ -261,22 +822,86 @@ fn restore_umask(old_umask: libc::mode_t) {
     }
 }
 
+/// Creates the temporary file written before replacing `path` (in the same
+/// directory, so that it can be renamed). The file must not exist (a
+/// symbolic link is not followed): a counter is added to its name until it
+/// is new.
+fn create_temp(path: &Path) -> Result<(File, PathBuf)> {
+    let name = path
+        .file_name()
+        .map_or_else(OsString::new, |name| name.to_os_string());
+    let mut error = None;
+    for i in 0..TEMP_FILE_ATTEMPTS {
+        let mut tmp_name = name.clone();
+        tmp_name.push(format!(".{}.{}.tmp", process::id(), i));
+        let tmp_path = path.with_file_name(tmp_name);
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_path)
+        {
+            Ok(file) => return Ok((file, tmp_path)),
+            Err(err) => {
+                if err.kind() != io::ErrorKind::AlreadyExists {
+                    return Err(From::from(err));
+                }
+                error = Some(err);
+            }
+        }
+    }
+    Err(From::from(error.unwrap()))
+}
+
+/// Flushes the directory of the renamed `path`, so that the new file is
+/// found after a crash.
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    try!(try!(File::open(dir)).sync_all());
+    Ok(())
+}
+// the directories cannot be opened (the rename is flushed by the system)
+#[cfg(windows)]
+fn sync_dir(_: &Path) -> Result<()> {
+    Ok(())
+}
+
+// the ACL of the replaced file are not copied (the temporary file inherits
+// the ones of the directory)
 #[cfg(windows)]
-fn fix_perm(_: &File) {}
+fn fix_perm(_: &File, _: &Path) {}
+// copies the mode and owner of the replaced `path` (if any) to `file`
 #[cfg(unix)]
-fn fix_perm(file: &File) {
+fn fix_perm(file: &File, path: &Path) {
+    use std::os::unix::fs::MetadataExt;
     use std::os::unix::io::AsRawFd;
-    unsafe {
-        libc::fchmod(file.as_raw_fd(), libc::S_IRUSR | libc::S_IWUSR);
+    let fd = file.as_raw_fd();
+    match fs::metadata(path) {
+        Ok(metadata) => unsafe {
+            libc::fchmod(fd, metadata.mode() as libc::mode_t & 0o7777);
+            // only allowed to a privileged user (or to change the group)
+            libc::fchown(fd, metadata.uid(), metadata.gid());
+        },
+        Err(_) => unsafe {
+            libc::fchmod(fd, libc::S_IRUSR | libc::S_IWUSR);
+        },
     }
 }
 
 #[cfg(test)]
 mod tests {
     extern crate tempdir;
-    use super::{Direction, History};
-    use config::Config;
+    use super::{format_timestamp, parse_timestamp};
+    use super::{
+        Direction, History, HistoryBackend, VecHistory, PREFIX_INDEX_MAX_MATCHES,
+        PREFIX_INDEX_MIN_LEN,
+    };
+    use config::{Config, HistoryDuplicates};
     use std::path::Path;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
     fn init() -> History {
         let mut history = History::new();
@@ -304,6 +929,63 @@ mod tests {
         assert!(!history.add(" line3"));
     }
 
+    #[test]
+    fn ignore_all_dups() {
+        let config = Config::builder()
+            .history_duplicates(HistoryDuplicates::IgnoreAll)
+            .build();
+        let mut history = History::with_config(config);
+        for line in &["line1", "line2", "line3", "line1"] {
+            assert!(history.add(*line));
+        }
+        assert!(!history.add("line1"));
+        assert_eq!(vec!["line2", "line3", "line1"], history.iter().collect::<Vec<_>>());
+        assert!(history.add("line3"));
+        assert_eq!(vec!["line2", "line1", "line3"], history.iter().collect::<Vec<_>>());
+        assert_eq!(Some(0), history.search("line2", 2, Direction::Reverse));
+
+        // indexed history
+        let config = Config::builder()
+            .max_history_size(PREFIX_INDEX_MIN_LEN + 1)
+            .history_duplicates(HistoryDuplicates::IgnoreAll)
+            .build();
+        let mut history = History::with_config(config);
+        for i in 0..PREFIX_INDEX_MIN_LEN {
+            history.add(format!("cmd{};", i));
+        }
+        let last = history.len() - 1;
+        assert_eq!(Some(1), history.starts_with("cmd1;", last, Direction::Reverse));
+        assert!(history.add("cmd1;"));
+        assert_eq!(PREFIX_INDEX_MIN_LEN, history.len());
+        assert_eq!(Some(last), history.starts_with("cmd1;", last, Direction::Reverse));
+        assert_eq!(Some(1), history.starts_with("cmd2;", last, Direction::Reverse));
+        assert!(history.add("cmd0;"));
+        assert_eq!(Some(last), history.starts_with("cmd0;", last, Direction::Reverse));
+        assert_eq!(Some(0), history.starts_with("cmd2;", last, Direction::Reverse));
+    }
+
+    #[test]
+    fn usage_count() {
+        let mut history = init();
+        assert!(!history.add("line3"));
+        let uses: Vec<usize> = (0..3).map(|i| history.entry_usage_count(i)).collect();
+        assert_eq!(vec![1, 1, 2], uses);
+        assert_eq!(0, history.entry_usage_count(3));
+
+        let config = Config::builder()
+            .history_duplicates(HistoryDuplicates::IgnoreAll)
+            .build();
+        let mut history = History::with_config(config);
+        for line in &["line1", "line2", "line1", "line1", "line2", "line1"] {
+            history.add(*line);
+        }
+        assert_eq!(vec!["line2", "line1"], history.iter().collect::<Vec<_>>());
+        assert_eq!(2, history.usage_count(0));
+        assert_eq!(4, history.usage_count(1));
+        history.clear();
+        assert_eq!(0, history.entry_usage_count(0));
+    }
+
     #[test]
     fn set_max_len() {
         let mut history = init();
@@ -323,6 +1005,143 @@ mod tests {
         td.close().unwrap();
     }
 
+    #[test]
+    #[cfg(unix)]
+    fn save_replace() {
+        use std::fs;
+        use std::os::unix::fs::{symlink, PermissionsExt};
+        use std::process;
+
+        let history = init();
+        let td = tempdir::TempDir::new_in(&Path::new("."), "histo").unwrap();
+        let history_path = td.path().join(".history");
+        fs::write(&history_path, "old\n").unwrap();
+        fs::set_permissions(&history_path, fs::Permissions::from_mode(0o640)).unwrap();
+        let link_path = td.path().join("link");
+        symlink(&history_path, &link_path).unwrap();
+
+        history.save(&link_path).unwrap();
+        assert!(fs::symlink_metadata(&link_path)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        let metadata = fs::metadata(&history_path).unwrap();
+        assert_eq!(0o640, metadata.permissions().mode() & 0o777);
+        let mut loaded = History::new();
+        loaded.load(&history_path).unwrap();
+        assert_eq!(vec!["line1", "line2", "line3"], loaded.iter().collect::<Vec<_>>());
+        // no temporary file left
+        assert_eq!(2, fs::read_dir(td.path()).unwrap().count());
+
+        // new file
+        let new_path = td.path().join(".new_history");
+        history.save(&new_path).unwrap();
+        let metadata = fs::metadata(&new_path).unwrap();
+        assert_eq!(0o600, metadata.permissions().mode() & 0o777);
+
+        // a link planted at the first temporary name is not followed
+        let victim_path = td.path().join("victim");
+        fs::write(&victim_path, "victim\n").unwrap();
+        let tmp_path = td
+            .path()
+            .join(format!(".new_history.{}.0.tmp", process::id()));
+        symlink(&victim_path, &tmp_path).unwrap();
+        history.save(&new_path).unwrap();
+        assert_eq!("victim\n", fs::read_to_string(&victim_path).unwrap());
+        assert!(fs::symlink_metadata(&tmp_path).is_ok());
+        td.close().unwrap();
+    }
+
+    #[test]
+    fn load_from() {
+        let config = Config::builder().max_history_size(2).build();
+        let mut history = History::with_config(config);
+        history.load_from(&b"line1\nline2\nline2\n\nline3\n"[..]).unwrap();
+        assert_eq!(2, history.len());
+        assert_eq!("line2", history[0]);
+        assert_eq!("line3", history[1]);
+    }
+
+    #[test]
+    fn timestamps() {
+        let before = SystemTime::now();
+        let mut history = init();
+        let timestamp = history.entry_timestamp(0).unwrap();
+        assert!(timestamp >= before);
+        assert_eq!(None, history.entry_timestamp(3));
+
+        // whole seconds are saved
+        let timestamp = UNIX_EPOCH + Duration::from_secs(1_538_240_676);
+        history.timestamps[0] = Some(timestamp);
+        history.timestamps[1] = None;
+        let td = tempdir::TempDir::new_in(&Path::new("."), "histo").unwrap();
+        let history_path = td.path().join(".history");
+        history.save(&history_path).unwrap();
+        let mut loaded = History::new();
+        loaded.load(&history_path).unwrap();
+        td.close().unwrap();
+        assert_eq!(vec!["line1", "line2", "line3"], loaded.iter().collect::<Vec<_>>());
+        assert_eq!(Some(timestamp), loaded.entry_timestamp(0));
+        assert_eq!(None, loaded.entry_timestamp(1));
+        assert!(loaded.entry_timestamp(2).is_some());
+
+        // entries looking like a header or a timestamp
+        let mut history = History::new();
+        history
+            .load_from(&b"#V2\n#2018-09-29T17:04:36Z\n#V2\n#\n#2018-09-29T17:04:36Z\n"[..])
+            .unwrap();
+        assert_eq!(vec!["#V2", "#2018-09-29T17:04:36Z"], history.iter().collect::<Vec<_>>());
+        assert_eq!(Some(timestamp), history.entry_timestamp(0));
+        assert_eq!(None, history.entry_timestamp(1));
+
+        // multi-line entries
+        let mut history = History::new();
+        for entry in &["a\nb", "c", "d\\nc:\\e\r\n\\"] {
+            history.add(*entry);
+        }
+        let td = tempdir::TempDir::new_in(&Path::new("."), "histo").unwrap();
+        let history_path = td.path().join(".history");
+        history.save(&history_path).unwrap();
+        let mut loaded = History::new();
+        loaded.load(&history_path).unwrap();
+        td.close().unwrap();
+        assert_eq!(history.iter().collect::<Vec<_>>(), loaded.iter().collect::<Vec<_>>());
+        assert!(loaded.entry_timestamp(0).is_some());
+
+        // without timestamps
+        let mut history = History::new();
+        history.load_from(&b"line1\n#2018-09-29T17:04:36Z\n"[..]).unwrap();
+        assert_eq!(2, history.len());
+        assert_eq!(None, history.entry_timestamp(0));
+        assert_eq!(None, history.entry_timestamp(1));
+    }
+
+    #[test]
+    fn rfc3339() {
+        let timestamp = UNIX_EPOCH + Duration::from_secs(1_538_240_676);
+        assert_eq!("2018-09-29T17:04:36Z", format_timestamp(timestamp));
+        assert_eq!(Some(timestamp), parse_timestamp("2018-09-29T17:04:36Z"));
+        assert_eq!(Some(timestamp), parse_timestamp("2018-09-29T19:04:36.25+02:00"));
+        assert_eq!(Some(timestamp), parse_timestamp("2018-09-29t12:04:36-05:00"));
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951_782_400);
+        assert_eq!("2000-02-29T00:00:00Z", format_timestamp(leap_day));
+        assert_eq!(Some(leap_day), parse_timestamp("2000-02-29T00:00:00Z"));
+        let before_epoch = UNIX_EPOCH - Duration::from_secs(1);
+        assert_eq!("1969-12-31T23:59:59Z", format_timestamp(before_epoch));
+        assert_eq!(Some(before_epoch), parse_timestamp("1969-12-31T23:59:59Z"));
+        for invalid in &[
+            "",
+            "2018-09-29",
+            "2018-09-29T17:04:36",
+            "2018-13-29T17:04:36Z",
+            "2018-09-29T17:04:36+0200",
+            "2018-09-29T17:04:36Zx",
+            "+018-09-29T17:04:36Z",
+        ] {
+            assert_eq!(None, parse_timestamp(invalid));
+        }
+    }
+
     #[test]
     fn search() {
         let history = init();
@@ -335,6 +1154,22 @@ mod tests {
         assert_eq!(Some(2), history.search("line3", 1, Direction::Forward));
     }
 
+    #[test]
+    fn vec_history() {
+        let mut history = VecHistory::new();
+        history.add("line1".to_owned());
+        history.add(String::new());
+        history.add("line2".to_owned());
+        assert_eq!(2, history.len());
+        assert_eq!(Some("line2"), history.get(1).as_deref());
+        assert_eq!(Some(1), history.search("2", 0, Direction::Forward));
+        assert_eq!(Some(1), history.search("line", 1, Direction::Reverse));
+        assert_eq!(None, history.starts_with("2", 1, Direction::Reverse));
+        let entries = history.starting_with("line");
+        let indexes: Vec<usize> = entries.iter().map(|&(index, _)| index).collect();
+        assert_eq!(vec![1, 0], indexes);
+    }
+
     #[test]
     fn reverse_search() {
         let history = init();
@@ -346,4 +1181,66 @@ mod tests {
         assert_eq!(Some(1), history.search("line", 1, Direction::Reverse));
         assert_eq!(Some(0), history.search("line1", 1, Direction::Reverse));
     }
+
+    #[test]
+    fn prefix_index() {
+        let config = Config::builder()
+            .max_history_size(PREFIX_INDEX_MIN_LEN + 100)
+            .history_ignore_dups(false)
+            .build();
+        let mut history = History::with_config(config);
+        // evicted entries included
+        for i in 0..PREFIX_INDEX_MIN_LEN + 200 {
+            history.add(format!("cmd{} {}", i % 7, i % 13));
+        }
+        let linear = |history: &History, term: &str, start: usize, dir: Direction| {
+            let test = |entry: &String| entry.starts_with(term);
+            history.search_match(term, start, dir, test)
+        };
+        let check = |history: &History| {
+            // `cmd` and `c` match too many distinct entries: scanned
+            for term in &["cmd", "cmd3", "cmd3 1", "cmd3 12", "cmd9", "c"] {
+                let scanned: Vec<usize> = (0..history.len())
+                    .rev()
+                    .filter(|&index| history.entries[index].starts_with(term))
+                    .collect();
+                assert_eq!(scanned, history.starting_with(term), "{}", term);
+                for &start in &[0, 1, 500, history.len() - 1] {
+                    for &dir in &[Direction::Forward, Direction::Reverse] {
+                        assert_eq!(
+                            linear(history, term, start, dir),
+                            history.starts_with(term, start, dir),
+                            "{} {} {:?}",
+                            term,
+                            start,
+                            dir
+                        );
+                    }
+                }
+            }
+        };
+        check(&history);
+        assert!(history.prefix_index.lock().unwrap().is_some());
+        assert!(7 * 13 > PREFIX_INDEX_MAX_MATCHES);
+        // kept up to date
+        for i in 0..50 {
+            history.add(format!("cmd3 {}", i));
+        }
+        check(&history);
+        assert_eq!(
+            Some(history.len() - 1),
+            history.starts_with("cmd3 49", history.len() - 1, Direction::Reverse)
+        );
+        history.set_max_len(PREFIX_INDEX_MIN_LEN);
+        check(&history);
+        // the duplicates moved to the most recent position
+        history.duplicates = HistoryDuplicates::IgnoreAll;
+        for i in 0..20 {
+            history.add(format!("cmd{} {}", i % 7, i % 13));
+        }
+        assert!(history.prefix_index.lock().unwrap().is_some());
+        check(&history);
+        history.clear();
+        assert!(history.prefix_index.lock().unwrap().is_none());
+    }
 }
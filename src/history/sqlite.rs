@@ -0,0 +1,432 @@
+//! History stored in a SQLite database
+use std::borrow::Cow;
+use std::char;
+use std::env;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::types::ToSql;
+use rusqlite::{Connection, OptionalExtension, Result};
+
+use super::{Direction, HistoryBackend};
+use config::{Config, HistoryDuplicates};
+
+/// How long a write waits for another process holding the database lock
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// History stored in a SQLite database, shared by several processes.
+///
+/// Each entry is stored with its timestamp, the id of the session (one per
+/// `open`) and the current directory of the process which added it.
+/// The entries are not loaded in memory: they are queried when browsed or
+/// searched, and the anchored search uses an index.
+///
+/// The database is in WAL mode so that concurrent sessions can read and
+/// append without blocking each other.
+///
+/// The index of an entry is its offset from the oldest one: only the oldest
+/// entries are deleted (when there are more than `max_history_size`), so the
+/// ids stay contiguous. So `HistoryDuplicates::IgnoreAll` only ignores the
+/// lines matching the previous entry.
+///
+/// ```
+/// use rustyline::history::{HistoryBackend, SQLiteHistory};
+/// use rustyline::Config;
+///
+/// let mut history = SQLiteHistory::open_in_memory(Config::default()).unwrap();
+/// history.add("SELECT 1;".to_owned());
+/// assert_eq!(Some("SELECT 1;"), history.get(0).as_deref());
+/// ```
+pub struct SQLiteHistory {
+    conn: Connection,
+    session_id: i64,
+    max_len: usize,
+    ignore_space: bool,
+    ignore_dups: bool,
+}
+
+impl SQLiteHistory {
+    /// Opens (or creates) the database at `path` and starts a new session.
+    pub fn open<P: AsRef<Path>>(path: P, config: Config) -> Result<SQLiteHistory> {
+        let conn = try!(Connection::open(path));
+        SQLiteHistory::with_connection(conn, config)
+    }
+
+    /// Opens a private in-memory database (lost when dropped).
+    pub fn open_in_memory(config: Config) -> Result<SQLiteHistory> {
+        let conn = try!(Connection::open_in_memory());
+        SQLiteHistory::with_connection(conn, config)
+    }
+
+    fn with_connection(conn: Connection, config: Config) -> Result<SQLiteHistory> {
+        // "memory" for an in-memory database
+        let _: String = try!(conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| {
+            row.get(0)
+        }));
+        try!(conn.busy_timeout(BUSY_TIMEOUT));
+        try!(conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS session(
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp REAL NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS history(
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                session_id INTEGER NOT NULL REFERENCES session(id),
+                timestamp REAL NOT NULL,
+                cwd TEXT,
+                entry TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS history_entry ON history(entry);"
+        ));
+        try!(conn.execute("INSERT INTO session(timestamp) VALUES (?1)", [now()]));
+        let session_id = conn.last_insert_rowid();
+        Ok(SQLiteHistory {
+            conn,
+            session_id,
+            max_len: config.max_history_size(),
+            ignore_space: config.history_ignore_space(),
+            ignore_dups: config.history_duplicates() != HistoryDuplicates::AlwaysAdd,
+        })
+    }
+
+    /// Id of the current session (stored with the entries it adds).
+    pub fn session_id(&self) -> i64 {
+        self.session_id
+    }
+
+    /// Adds `entry` with the same length and duplicates policies as
+    /// `History::add`.
+    ///
+    /// Return false if the entry is ignored.
+    pub fn add_entry(&mut self, entry: &str) -> Result<bool> {
+        if self.max_len == 0 || entry.is_empty() {
+            return Ok(false);
+        }
+        if self.ignore_space && entry.chars().next().map_or(true, |c| c.is_whitespace()) {
+            return Ok(false);
+        }
+        if self.ignore_dups {
+            let last: Option<String> = try!(self
+                .conn
+                .query_row(
+                    "SELECT entry FROM history ORDER BY id DESC LIMIT 1",
+                    [],
+                    |row| row.get(0),
+                )
+                .optional());
+            if last.as_ref().map_or(false, |last| last == entry) {
+                return Ok(false);
+            }
+        }
+        let cwd = env::current_dir()
+            .ok()
+            .map(|cwd| cwd.to_string_lossy().into_owned());
+        let tx = try!(self.conn.transaction());
+        try!(tx.execute(
+            "INSERT INTO history(session_id, timestamp, cwd, entry) VALUES (?1, ?2, ?3, ?4)",
+            (self.session_id, now(), cwd, entry),
+        ));
+        try!(tx.execute(
+            "DELETE FROM history WHERE id <= (SELECT max(id) FROM history) - ?1",
+            [self.max_len as i64],
+        ));
+        try!(tx.commit());
+        Ok(true)
+    }
+
+    /// Deletes all the entries (of all the sessions).
+    pub fn clear(&mut self) -> Result<()> {
+        try!(self.conn.execute("DELETE FROM history", []));
+        Ok(())
+    }
+
+    /// Returns the ids of the oldest and of the newest entries.
+    fn bounds(&self) -> Option<(i64, i64)> {
+        let bounds = self.conn.query_row("SELECT min(id), max(id) FROM history", [], |row| {
+            Ok((try!(row.get::<_, Option<i64>>(0)), try!(row.get::<_, Option<i64>>(1))))
+        });
+        match bounds {
+            Ok((Some(first), Some(last))) => Some((first, last)),
+            Ok(_) => None,
+            Err(err) => {
+                warn!(target: "rustyline", "cannot query the history: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Returns the index of the nearest entry matching `filter` (an SQL
+    /// condition whose parameters start at `?3`), examining at most `limit`
+    /// entries from `start`.
+    fn find(
+        &self,
+        start: usize,
+        dir: Direction,
+        limit: usize,
+        filter: &str,
+        params: &[&ToSql],
+    ) -> Option<usize> {
+        let (first, last) = match self.bounds() {
+            Some(bounds) => bounds,
+            None => return None,
+        };
+        let start = first.saturating_add(start as i64);
+        if start > last || limit == 0 {
+            return None;
+        }
+        let limit = (limit - 1).min(i64::max_value() as usize) as i64;
+        let (lo, hi, order) = match dir {
+            Direction::Reverse => (start.saturating_sub(limit), start, "DESC"),
+            Direction::Forward => (start, start.saturating_add(limit), "ASC"),
+        };
+        let sql = format!(
+            "SELECT id FROM history WHERE id BETWEEN ?1 AND ?2 AND {} ORDER BY id {} LIMIT 1",
+            filter, order
+        );
+        let mut all: Vec<&ToSql> = vec![&lo, &hi];
+        all.extend_from_slice(params);
+        let id = self
+            .conn
+            .prepare_cached(&sql)
+            .and_then(|mut stmt| stmt.query_row(&*all, |row| row.get::<_, i64>(0)).optional());
+        match id {
+            Ok(id) => id.map(|id| (id - first) as usize),
+            Err(err) => {
+                warn!(target: "rustyline", "cannot search the history: {}", err);
+                None
+            }
+        }
+    }
+}
+
+impl HistoryBackend for SQLiteHistory {
+    fn get(&self, index: usize) -> Option<Cow<'_, str>> {
+        let entry = self
+            .conn
+            .prepare_cached(
+                "SELECT entry FROM history WHERE id = (SELECT min(id) FROM history) + ?1",
+            )
+            .and_then(|mut stmt| stmt.query_row([index as i64], |row| row.get(0)).optional());
+        match entry {
+            Ok(entry) => entry.map(Cow::Owned),
+            Err(err) => {
+                warn!(target: "rustyline", "cannot read the history: {}", err);
+                None
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.bounds()
+            .map_or(0, |(first, last)| (last - first + 1) as usize)
+    }
+
+    fn add(&mut self, entry: String) {
+        if let Err(err) = self.add_entry(&entry) {
+            warn!(target: "rustyline", "cannot add to the history: {}", err);
+        }
+    }
+
+    fn search(&self, term: &str, start: usize, dir: Direction) -> Option<usize> {
+        self.search_limited(term, start, dir, usize::max_value())
+    }
+
+    fn search_limited(
+        &self,
+        term: &str,
+        start: usize,
+        dir: Direction,
+        limit: usize,
+    ) -> Option<usize> {
+        if term.is_empty() {
+            return None;
+        }
+        self.find(start, dir, limit, "instr(entry, ?3) > 0", &[&term])
+    }
+
+    /// Anchored search, with the index on the entries
+    fn starts_with(&self, term: &str, start: usize, dir: Direction) -> Option<usize> {
+        if term.is_empty() {
+            return None;
+        }
+        // the text comparison is binary, like `str` ordering
+        match prefix_upper_bound(term) {
+            Some(upper) => self.find(
+                start,
+                dir,
+                usize::max_value(),
+                "entry >= ?3 AND entry < ?4",
+                &[&term, &upper],
+            ),
+            None => self.find(
+                start,
+                dir,
+                usize::max_value(),
+                "entry >= ?3 AND substr(entry, 1, length(?3)) = ?3",
+                &[&term],
+            ),
+        }
+    }
+
+    /// All the entries starting with `term`, with the index on the entries
+    fn starting_with(&self, term: &str) -> Vec<(usize, Cow<'_, str>)> {
+        let first = match self.bounds() {
+            Some((first, _)) => first,
+            None => return Vec::new(),
+        };
+        let upper = prefix_upper_bound(term);
+        let (filter, params): (_, Vec<&ToSql>) = match upper {
+            Some(ref upper) => ("entry >= ?1 AND entry < ?2", vec![&term, upper]),
+            None => ("entry >= ?1 AND substr(entry, 1, length(?1)) = ?1", vec![&term]),
+        };
+        let sql = format!("SELECT id, entry FROM history WHERE {} ORDER BY id DESC", filter);
+        let entries = self.conn.prepare_cached(&sql).and_then(|mut stmt| {
+            let rows = try!(stmt.query_map(&*params, |row| {
+                Ok((try!(row.get::<_, i64>(0)), try!(row.get::<_, String>(1))))
+            }));
+            rows.collect::<Result<Vec<_>>>()
+        });
+        match entries {
+            Ok(entries) => entries
+                .into_iter()
+                .map(|(id, entry)| ((id - first) as usize, Cow::Owned(entry)))
+                .collect(),
+            Err(err) => {
+                warn!(target: "rustyline", "cannot search the history: {}", err);
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// Returns the smallest string greater than all the strings starting with
+/// `prefix` (`None` if it only contains `char::MAX`).
+fn prefix_upper_bound(prefix: &str) -> Option<String> {
+    let mut chars: Vec<char> = prefix.chars().collect();
+    while let Some(c) = chars.pop() {
+        let next = match c {
+            char::MAX => continue,
+            '\u{D7FF}' => Some('\u{E000}'), // skip the surrogates
+            _ => char::from_u32(c as u32 + 1),
+        };
+        if let Some(next) = next {
+            chars.push(next);
+            return Some(chars.into_iter().collect());
+        }
+    }
+    None
+}
+
+/// Seconds since the Unix epoch
+fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as f64 + f64::from(d.subsec_nanos()) / 1e9)
+        .unwrap_or(0.)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::{prefix_upper_bound, SQLiteHistory};
+    use config::Config;
+    use history::{Direction, HistoryBackend};
+
+    fn init() -> SQLiteHistory {
+        let mut history = SQLiteHistory::open_in_memory(Config::default()).unwrap();
+        history.add("line1".to_owned());
+        history.add("line2".to_owned());
+        history.add("line3".to_owned());
+        history
+    }
+
+    #[test]
+    fn add() {
+        let mut history = init();
+        assert_eq!(3, history.len());
+        assert_eq!(Some("line1"), history.get(0).as_deref());
+        assert_eq!(None, history.get(3));
+        // empty and consecutive duplicate
+        assert!(!history.add_entry("").unwrap());
+        assert!(!history.add_entry("line3").unwrap());
+        assert_eq!(3, history.len());
+
+        let config = Config::builder()
+            .max_history_size(2)
+            .history_ignore_space(true)
+            .build();
+        let mut history = SQLiteHistory::open_in_memory(config).unwrap();
+        assert!(!history.add_entry(" line").unwrap());
+        for line in &["line1", "line2", "line3"] {
+            assert!(history.add_entry(line).unwrap());
+        }
+        assert_eq!(2, history.len());
+        assert_eq!(Some("line2"), history.get(0).as_deref());
+
+        history.clear().unwrap();
+        assert!(history.is_empty());
+    }
+
+    #[test]
+    fn search() {
+        let history = init();
+        assert_eq!(None, history.search("", 0, Direction::Forward));
+        assert_eq!(None, history.search("none", 0, Direction::Forward));
+        assert_eq!(None, history.search("line", 3, Direction::Forward));
+        assert_eq!(Some(0), history.search("line", 0, Direction::Forward));
+        assert_eq!(Some(1), history.search("line", 1, Direction::Forward));
+        assert_eq!(Some(2), history.search("line3", 1, Direction::Forward));
+        assert_eq!(Some(2), history.search("line", 2, Direction::Reverse));
+        assert_eq!(Some(0), history.search("line1", 2, Direction::Reverse));
+        assert_eq!(None, history.search("line3", 1, Direction::Reverse));
+        assert_eq!(None, history.search_limited("line1", 2, Direction::Reverse, 2));
+        assert_eq!(Some(0), history.search_limited("line1", 2, Direction::Reverse, 3));
+    }
+
+    #[test]
+    fn starts_with() {
+        let mut history = init();
+        history.add("été".to_owned());
+        history.add("\u{10FFFF}x".to_owned());
+        assert_eq!(Some(2), history.starts_with("line", 3, Direction::Reverse));
+        assert_eq!(None, history.starts_with("ine", 3, Direction::Reverse));
+        assert_eq!(Some(0), history.starts_with("line", 0, Direction::Forward));
+        assert_eq!(Some(3), history.starts_with("ét", 0, Direction::Forward));
+        assert_eq!(Some(4), history.starts_with("\u{10FFFF}", 0, Direction::Forward));
+        let entries = history.starting_with("line");
+        let indexes: Vec<usize> = entries.iter().map(|&(index, _)| index).collect();
+        assert_eq!(vec![2, 1, 0], indexes);
+        assert_eq!(Some("line3"), entries.first().map(|&(_, ref entry)| &**entry));
+        assert_eq!(1, history.starting_with("\u{10FFFF}").len());
+
+        assert_eq!(Some("linf".to_owned()), prefix_upper_bound("line"));
+        assert_eq!(Some("b".to_owned()), prefix_upper_bound("a\u{10FFFF}"));
+        assert_eq!(Some("\u{E000}".to_owned()), prefix_upper_bound("\u{D7FF}"));
+        assert_eq!(None, prefix_upper_bound("\u{10FFFF}"));
+    }
+
+    #[test]
+    fn sessions() {
+        let dir = tempdir::TempDir::new("rustyline").unwrap();
+        let path = dir.path().join("history.sqlite3");
+        let mut first = SQLiteHistory::open(&path, Config::default()).unwrap();
+        let mut second = SQLiteHistory::open(&path, Config::default()).unwrap();
+        assert!(first.session_id() < second.session_id());
+        first.add("first".to_owned());
+        second.add("second".to_owned());
+        assert_eq!(Some("second"), first.get(1).as_deref());
+        drop(first);
+        drop(second);
+
+        let history = SQLiteHistory::open(&path, Config::default()).unwrap();
+        assert_eq!(2, history.len());
+        let session: i64 = history
+            .conn
+            .query_row("SELECT session_id FROM history WHERE entry = 'first'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert!(session < history.session_id());
+    }
+}
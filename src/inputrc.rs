@@ -0,0 +1,493 @@
+//! Key bindings and settings read from an inputrc file, like the one of GNU
+//! readline (see `Editor::load_config_file`).
+use std::env;
+use std::result;
+
+use config::EditMode;
+use keymap::{Anchor, At, Cmd, Movement, Word};
+use keys::{self, KeyPress};
+
+/// Reason why a line is skipped
+type ParseResult<T> = result::Result<T, &'static str>;
+
+/// Binding or setting of an inputrc file
+#[derive(Debug, PartialEq)]
+pub enum Item {
+    /// `"\C-x": function-name`, `Control-x: "text"`
+    Bind(KeyPress, Cmd),
+    /// `"\C-x\C-e": function-name`
+    BindPair(KeyPress, KeyPress, Cmd),
+    /// `set editing-mode emacs|vi`
+    EditMode(EditMode),
+    /// `set completion-ignore-case on|off`
+    CompletionIgnoreCase(bool),
+    /// `set completion-query-items n`
+    CompletionQueryItems(usize),
+    /// `set history-size n`
+    HistorySize(usize),
+    /// `set keyseq-timeout ms`
+    KeyseqTimeout(i32),
+}
+
+/// Parses the `text` of an inputrc file read by an editor in the `mode`
+/// (until a `set editing-mode`), which is tested by `$if mode=...`.
+///
+/// The lines which are not supported are skipped with a warning.
+pub fn parse(text: &str, mut mode: EditMode) -> Vec<Item> {
+    let mut items = Vec::new();
+    // for each `$if` enclosing the current line: `true` if its branch is read
+    let mut conditions: Vec<bool> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('$') {
+            if !directive(&line[1..], mode, &mut conditions) {
+                warn!(
+                    target: "rustyline",
+                    "inputrc line {}: unsupported directive: {}",
+                    i + 1,
+                    line
+                );
+            }
+            continue;
+        }
+        if conditions.contains(&false) {
+            continue;
+        }
+        let item = match line.split_whitespace().next() {
+            Some("set") => setting(&line[3..]),
+            _ => binding(line),
+        };
+        match item {
+            Ok(item) => {
+                if let Item::EditMode(edit_mode) = item {
+                    mode = edit_mode;
+                }
+                items.push(item)
+            }
+            Err(reason) => {
+                warn!(target: "rustyline", "inputrc line {}: {}: {}", i + 1, reason, line)
+            }
+        }
+    }
+    items
+}
+
+/// Handles `$if`, `$else` and `$endif`.
+/// Returns `false` if the directive is not supported.
+fn directive(line: &str, mode: EditMode, conditions: &mut Vec<bool>) -> bool {
+    let mut words = line.splitn(2, char::is_whitespace);
+    match (words.next(), words.next().map(str::trim)) {
+        (Some("if"), test) => {
+            conditions.push(test.map_or(false, |test| condition(test, mode)));
+            true
+        }
+        (Some("else"), _) if !conditions.is_empty() => {
+            let last = conditions.last_mut().unwrap();
+            *last = !*last;
+            true
+        }
+        (Some("endif"), _) => conditions.pop().is_some(),
+        _ => false,
+    }
+}
+
+/// Evaluates the test of an `$if`: `mode=emacs|vi` or `term=name` (matched
+/// against `$TERM` or the part of it before the first `-`). The application
+/// name and the version are not known: they never match.
+fn condition(test: &str, mode: EditMode) -> bool {
+    if let Some(name) = strip_prefix_ignore_case(test, "mode=") {
+        match name {
+            "emacs" => mode == EditMode::Emacs,
+            "vi" => mode == EditMode::Vi,
+            _ => false,
+        }
+    } else if let Some(name) = strip_prefix_ignore_case(test, "term=") {
+        match env::var("TERM") {
+            Ok(term) => term == name || term.split('-').next() == Some(name),
+            Err(_) => false,
+        }
+    } else {
+        false
+    }
+}
+
+/// Parses `variable value` after `set`.
+fn setting(line: &str) -> ParseResult<Item> {
+    let mut words = line.split_whitespace();
+    let (name, value) = match (words.next(), words.next()) {
+        (Some(name), Some(value)) => (name.to_lowercase(), value),
+        _ => return Err("missing value"),
+    };
+    match name.as_ref() {
+        "editing-mode" => match value {
+            "emacs" => Ok(Item::EditMode(EditMode::Emacs)),
+            "vi" => Ok(Item::EditMode(EditMode::Vi)),
+            _ => Err("unknown editing mode"),
+        },
+        "completion-ignore-case" => Ok(Item::CompletionIgnoreCase(on(value))),
+        "completion-query-items" => value
+            .parse()
+            .map(Item::CompletionQueryItems)
+            .map_err(|_| "invalid number"),
+        "history-size" => value
+            .parse()
+            .map(Item::HistorySize)
+            .map_err(|_| "invalid number"),
+        "keyseq-timeout" => value
+            .parse()
+            .map(Item::KeyseqTimeout)
+            .map_err(|_| "invalid number"),
+        _ => Err("unsupported variable"),
+    }
+}
+
+/// Value of a boolean variable: `on` or `1` (any other value is `off`)
+fn on(value: &str) -> bool {
+    value.eq_ignore_ascii_case("on") || value == "1"
+}
+
+/// Parses `"keyseq": function-name` or `keyname: "text"`.
+fn binding(line: &str) -> ParseResult<Item> {
+    let ((key, snd_key), rest) = if line.starts_with('"') {
+        let end = try!(closing_quote(line));
+        (try!(key_seq(&line[1..end])), &line[end + 1..])
+    } else {
+        match line.find(':') {
+            Some(colon) => ((try!(key_name(line[..colon].trim())), None), &line[colon..]),
+            None => return Err("missing ':'"),
+        }
+    };
+    let rest = rest.trim();
+    if !rest.starts_with(':') {
+        return Err("missing ':'");
+    }
+    let function = rest[1..].trim();
+    let cmd = if function.starts_with('"') || function.starts_with('\'') {
+        // macro: the text is inserted
+        let end = try!(closing_quote(function));
+        Cmd::Insert(1, try!(unescape(&function[1..end])))
+    } else {
+        try!(command(function, snd_key.unwrap_or(key)))
+    };
+    Ok(match snd_key {
+        Some(snd_key) => Item::BindPair(key, snd_key, cmd),
+        None => Item::Bind(key, cmd),
+    })
+}
+
+/// Byte index of the quote closing the string `s` starts with
+fn closing_quote(s: &str) -> ParseResult<usize> {
+    let quote = s.chars().next().unwrap();
+    let mut escaped = false;
+    for (i, c) in s.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return Ok(i);
+        }
+    }
+    Err("missing closing quote")
+}
+
+/// Replaces the escape sequences of a key sequence or a macro: `\C-`
+/// (control), `\M-` (meta, as an escape prefix), `\e`, `\\`, `\"`, `\'`,
+/// `\a`, `\b`, `\d`, `\f`, `\n`, `\r`, `\t`, `\v`, `\nnn` (octal) and `\xHH`
+/// (hexadecimal).
+fn unescape(s: &str) -> ParseResult<String> {
+    let mut unescaped = String::new();
+    let mut chars = s.chars().peekable();
+    // `true` after `\C-`: the next char is a control char
+    let mut ctrl = false;
+    while let Some(c) = chars.next() {
+        let c = if c != '\\' {
+            c
+        } else {
+            let c = match chars.next() {
+                Some(c) => c,
+                None => return Err("incomplete escape sequence"),
+            };
+            match c {
+                'C' | 'M' if chars.peek() == Some(&'-') => {
+                    chars.next();
+                    if c == 'C' {
+                        ctrl = true;
+                    } else {
+                        unescaped.push('\x1b');
+                    }
+                    continue;
+                }
+                'e' => '\x1b',
+                'a' => '\x07',
+                'b' => '\x08',
+                'd' => '\x7f',
+                'f' => '\x0c',
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                'v' => '\x0b',
+                c if c.is_digit(8) => {
+                    let mut code = c.to_digit(8).unwrap();
+                    for _ in 0..2 {
+                        match chars.peek().and_then(|c| c.to_digit(8)) {
+                            Some(digit) => code = code * 8 + digit,
+                            None => break,
+                        }
+                        chars.next();
+                    }
+                    try!(::std::char::from_u32(code).ok_or("invalid char"))
+                }
+                'x' => {
+                    let mut code = 0;
+                    for _ in 0..2 {
+                        match chars.peek().and_then(|c| c.to_digit(16)) {
+                            Some(digit) => code = code * 16 + digit,
+                            None => break,
+                        }
+                        chars.next();
+                    }
+                    try!(::std::char::from_u32(code).ok_or("invalid char"))
+                }
+                // `\\`, `\"`, `\'` and the others: the char itself
+                c => c,
+            }
+        };
+        if ctrl {
+            ctrl = false;
+            unescaped.push(try!(control(c)));
+        } else {
+            unescaped.push(c);
+        }
+    }
+    if ctrl {
+        return Err("incomplete escape sequence");
+    }
+    Ok(unescaped)
+}
+
+/// Control char typed with `c` (`?` for Rubout)
+fn control(c: char) -> ParseResult<char> {
+    if c == '?' {
+        Ok('\x7f')
+    } else if c.is_ascii() {
+        Ok((c.to_ascii_uppercase() as u8 & 0x1f) as char)
+    } else {
+        Err("invalid control char")
+    }
+}
+
+/// Key(s) of a quoted key sequence: a single key or a pair of keys
+fn key_seq(seq: &str) -> ParseResult<(KeyPress, Option<KeyPress>)> {
+    let seq = try!(unescape(seq));
+    let err = match key_press(&seq) {
+        Ok(key) => return Ok((key, None)),
+        Err(err) => err,
+    };
+    // the first split in two keys
+    for (i, _) in seq.char_indices().skip(1) {
+        if let (Ok(key), Ok(snd_key)) = (key_press(&seq[..i]), key_press(&seq[i..])) {
+            return Ok((key, Some(snd_key)));
+        }
+    }
+    Err(err)
+}
+
+/// Key of an unquoted key name, like `Control-u`, `C-u`, `Meta-Rubout`,
+/// `M-f` or `Tab`.
+fn key_name(name: &str) -> ParseResult<KeyPress> {
+    let mut seq = String::new();
+    let mut ctrl = false;
+    let mut name = name;
+    loop {
+        if let Some(rest) = strip_prefix_ignore_case(name, "control-")
+            .or_else(|| strip_prefix_ignore_case(name, "c-"))
+        {
+            ctrl = true;
+            name = rest;
+        } else if let Some(rest) = strip_prefix_ignore_case(name, "meta-")
+            .or_else(|| strip_prefix_ignore_case(name, "m-"))
+        {
+            seq.push('\x1b');
+            name = rest;
+        } else {
+            break;
+        }
+    }
+    let c = match name.to_lowercase().as_ref() {
+        "del" | "rubout" => '\x7f',
+        "esc" | "escape" => '\x1b',
+        "lfd" | "newline" => '\n',
+        "ret" | "return" => '\r',
+        "spc" | "space" => ' ',
+        "tab" => '\t',
+        _ => {
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => return Err("unknown key name"),
+            }
+        }
+    };
+    seq.push(if ctrl { try!(control(c)) } else { c });
+    key_press(&seq)
+}
+
+/// A key of the custom bindings: a char, a meta char or the escape sequence
+/// of a special key.
+fn key_press(seq: &str) -> ParseResult<KeyPress> {
+    let mut chars = seq.chars();
+    let key = match (chars.next(), chars.next(), chars.next()) {
+        (Some(c), None, _) => keys::char_to_key_press(c),
+        (Some('\x1b'), Some('\x1b'), None) => KeyPress::Esc,
+        (Some('\x1b'), Some(c), None) => KeyPress::Meta(c),
+        _ => match seq {
+            "\x1b[A" | "\x1bOA" => KeyPress::Up,
+            "\x1b[B" | "\x1bOB" => KeyPress::Down,
+            "\x1b[C" | "\x1bOC" => KeyPress::Right,
+            "\x1b[D" | "\x1bOD" => KeyPress::Left,
+            "\x1b[H" | "\x1bOH" | "\x1b[1~" | "\x1b[7~" => KeyPress::Home,
+            "\x1b[F" | "\x1bOF" | "\x1b[4~" | "\x1b[8~" => KeyPress::End,
+            "\x1b[2~" => KeyPress::Insert,
+            "\x1b[3~" => KeyPress::Delete,
+            "\x1b[5~" => KeyPress::PageUp,
+            "\x1b[6~" => KeyPress::PageDown,
+            "" => return Err("empty key sequence"),
+            _ => return Err("unsupported key sequence (only one or two keys can be bound)"),
+        },
+    };
+    if key == KeyPress::Null {
+        return Err("unsupported key");
+    }
+    Ok(key)
+}
+
+/// Command of a readline function
+fn command(function: &str, key: KeyPress) -> ParseResult<Cmd> {
+    Ok(match function.to_lowercase().as_ref() {
+        "abort" => Cmd::Abort,
+        "accept-line" => Cmd::AcceptLine,
+        "backward-char" => Cmd::Move(Movement::BackwardChar(1)),
+        "backward-delete-char" => Cmd::Kill(Movement::BackwardChar(1)),
+        "backward-kill-line" | "unix-line-discard" => Cmd::Kill(Movement::BeginningOfLine),
+        "backward-kill-word" => Cmd::Kill(Movement::BackwardWord(1, Word::Emacs)),
+        "backward-word" => Cmd::Move(Movement::BackwardWord(1, Word::Emacs)),
+        "beginning-of-history" => Cmd::BeginningOfHistory,
+        "beginning-of-line" => Cmd::Move(Movement::BeginningOfLine),
+        "capitalize-word" => Cmd::CapitalizeWord,
+        "clear-screen" => Cmd::ClearScreen,
+        "complete" => Cmd::Complete,
+        "delete-char" => Cmd::Kill(Movement::ForwardChar(1)),
+        "downcase-word" => Cmd::DowncaseWord,
+        "edit-and-execute-command" => Cmd::Edit,
+        "end-of-file" => Cmd::EndOfFile,
+        "end-of-history" => Cmd::EndOfHistory,
+        "end-of-line" => Cmd::Move(Movement::EndOfLine),
+        "exchange-point-and-mark" => Cmd::ExchangePointAndMark,
+        "forward-char" => Cmd::Move(Movement::ForwardChar(1)),
+        "forward-search-history" => Cmd::ForwardSearchHistory,
+        "forward-word" => Cmd::Move(Movement::ForwardWord(1, At::AfterEnd, Word::Emacs)),
+        "history-search-backward" => Cmd::HistorySearchBackward,
+        "history-search-forward" => Cmd::HistorySearchForward,
+        "kill-line" => Cmd::Kill(Movement::EndOfLine),
+        "kill-whole-line" => Cmd::Kill(Movement::WholeLine),
+        "kill-word" => Cmd::Kill(Movement::ForwardWord(1, At::AfterEnd, Word::Emacs)),
+        "next-history" => Cmd::NextHistory,
+        "previous-history" => Cmd::PreviousHistory,
+        "quoted-insert" => Cmd::QuotedInsert,
+        "reverse-search-history" => Cmd::ReverseSearchHistory,
+        "self-insert" => match key {
+            KeyPress::Char(c) => Cmd::SelfInsert(1, c),
+            _ => return Err("self-insert of a special key"),
+        },
+        "set-mark" => Cmd::SetMark,
+        "tab-insert" => Cmd::SelfInsert(1, '\t'),
+        "transpose-chars" => Cmd::TransposeChars,
+        "transpose-words" => Cmd::TransposeWords(1),
+        "undo" => Cmd::Undo(1),
+        "unix-word-rubout" => Cmd::Kill(Movement::BackwardWord(1, Word::Big)),
+        "upcase-word" => Cmd::UpcaseWord,
+        "yank" => Cmd::Yank(1, Anchor::Before),
+        "yank-pop" => Cmd::YankPop,
+        _ => return Err("unsupported function"),
+    })
+}
+
+/// `s` without `prefix` (compared case-insensitively) if it starts with it
+fn strip_prefix_ignore_case<'s>(s: &'s str, prefix: &str) -> Option<&'s str> {
+    match s.get(..prefix.len()) {
+        Some(start) if start.eq_ignore_ascii_case(prefix) => Some(&s[prefix.len()..]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Item};
+    use config::EditMode;
+    use keymap::{Anchor, Cmd, Movement, Word};
+    use keys::KeyPress;
+
+    #[test]
+    fn bindings() {
+        let text = r#"
+# comment
+"\C-x\C-e": edit-and-execute-command
+"\C-k": kill-whole-line
+"\M-u": upcase-word
+"\e[A": history-search-backward
+Control-y: yank
+Meta-Rubout: backward-kill-word
+"\C-t": "\"quoted\"\t"
+C-o: unknown-function
+"#;
+        assert_eq!(
+            vec![
+                Item::BindPair(KeyPress::Ctrl('X'), KeyPress::Ctrl('E'), Cmd::Edit),
+                Item::Bind(KeyPress::Ctrl('K'), Cmd::Kill(Movement::WholeLine)),
+                Item::Bind(KeyPress::Meta('u'), Cmd::UpcaseWord),
+                Item::Bind(KeyPress::Up, Cmd::HistorySearchBackward),
+                Item::Bind(KeyPress::Ctrl('Y'), Cmd::Yank(1, Anchor::Before)),
+                Item::Bind(
+                    KeyPress::Meta('\x7f'),
+                    Cmd::Kill(Movement::BackwardWord(1, Word::Emacs))
+                ),
+                Item::Bind(KeyPress::Ctrl('T'), Cmd::Insert(1, "\"quoted\"\t".to_owned())),
+            ],
+            parse(text, EditMode::Emacs)
+        );
+    }
+
+    #[test]
+    fn settings() {
+        let text = "
+set editing-mode vi
+set completion-ignore-case On
+set history-size 1000
+set keyseq-timeout 250
+set bell-style none
+$if mode=vi
+    \"\\C-l\": clear-screen
+$else
+    \"\\C-l\": abort
+$endif
+$if term=unknown-terminal-name
+    \"\\C-a\": abort
+$endif
+$include /etc/inputrc
+";
+        assert_eq!(
+            vec![
+                Item::EditMode(EditMode::Vi),
+                Item::CompletionIgnoreCase(true),
+                Item::HistorySize(1000),
+                Item::KeyseqTimeout(250),
+                Item::Bind(KeyPress::Ctrl('L'), Cmd::ClearScreen),
+            ],
+            parse(text, EditMode::Emacs)
+        );
+    }
+}
@@ -1,11 +1,16 @@
 //! Bindings from keys to command for Emacs and Vi modes
+use std::cmp;
 use std::collections::HashMap;
+use std::fmt;
+use std::mem;
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
 use super::Result;
 use config::Config;
 use config::EditMode;
 use keys::KeyPress;
+use line_buffer::LineBuffer;
 use tty::RawReader;
 
 /// The number of times one command should be repeated.
@@ -17,22 +22,39 @@ pub type RepeatCount = usize;
 pub enum Cmd {
     /// abort
     Abort, // Miscellaneous Command
+    /// Accept the next word of the hint (when the cursor is at the end of the
+    /// line). `forward-word` does the same at the end of the line, except
+    /// in vi command mode.
+    AcceptHintWord,
     /// accept-line
     AcceptLine,
     /// beginning-of-history
     BeginningOfHistory,
+    /// Insert the typed text at the same column (the one of the mark) on each
+    /// line of the region, until another command is typed.
+    BlockInsert,
     /// capitalize-word
     CapitalizeWord,
     /// clear-screen
     ClearScreen,
     /// complete
     Complete,
+    /// Display the next hint (see `Hinter::hints`)
+    CycleHint,
+    /// edit-and-execute-command: edit the line in an external editor (see
+    /// `Editor::set_external_editor`); the edited line is not accepted
+    Edit,
+    /// Custom command: its handler is called with the line being edited and
+    /// returns the command to execute.
+    Custom(Handler),
     /// downcase-word
     DowncaseWord,
     /// vi-eof-maybe
     EndOfFile,
     /// end-of-history
     EndOfHistory,
+    /// exchange-point-and-mark
+    ExchangePointAndMark,
     /// forward-search-history
     ForwardSearchHistory,
     /// history-search-backward
@@ -40,6 +62,10 @@ pub enum Cmd {
     /// history-search-forward
     HistorySearchForward,
     Insert(RepeatCount, String),
+    /// Accept the Nth (0-indexed) candidate of the active completion session
+    /// (no-op otherwise or if out of bounds).
+    /// Intended for macros and scripted input.
+    InsertCompletion(usize),
     Interrupt,
     /// backward-delete-char, backward-kill-line, backward-kill-word
     /// delete-char, kill-line, kill-word, unix-line-discard, unix-word-rubout,
@@ -66,7 +92,12 @@ pub enum Cmd {
     ReverseSearchHistory,
     /// self-insert
     SelfInsert(RepeatCount, char),
+    /// set-mark
+    SetMark,
     Suspend,
+    /// Switch the incremental history search between substring and regular
+    /// expression matching (requires the `regex` feature).
+    ToggleRegexSearch,
     /// transpose-chars
     TransposeChars,
     /// transpose-words
@@ -113,6 +144,23 @@ impl Cmd {
         }
     }
 
+    /// Whether the command is executed `n` times with a numeric argument `n`
+    /// (the other commands have a count or ignore it).
+    fn is_repeated_by_count(&self) -> bool {
+        match *self {
+            Cmd::CapitalizeWord
+            | Cmd::DowncaseWord
+            | Cmd::HistorySearchBackward
+            | Cmd::HistorySearchForward
+            | Cmd::NextHistory
+            | Cmd::PreviousHistory
+            | Cmd::TransposeChars
+            | Cmd::UpcaseWord
+            | Cmd::YankPop => true,
+            _ => false,
+        }
+    }
+
     fn is_repeatable(&self) -> bool {
         match *self {
             Cmd::Move(_) => true,
@@ -162,6 +210,115 @@ impl Cmd {
     }
 }
 
+/// Read access to the line being edited, given to a `CommandHandler`.
+///
+/// The line is borrowed only while the handler runs: the context cannot be
+/// kept and the line cannot be modified directly, the handler returns the
+/// command to execute instead.
+pub struct EventContext<'r> {
+    line: &'r LineBuffer,
+    repeat_count: usize,
+    since_last_key: Option<Duration>,
+}
+
+impl<'r> EventContext<'r> {
+    pub(crate) fn new(line: &'r LineBuffer, input_state: &InputState) -> EventContext<'r> {
+        EventContext {
+            line,
+            repeat_count: input_state.repeat_count,
+            since_last_key: input_state.since_last_key,
+        }
+    }
+
+    /// Line being edited
+    pub fn line(&self) -> &str {
+        self.line.as_str()
+    }
+
+    /// Cursor position (byte index in `line`)
+    pub fn pos(&self) -> usize {
+        self.line.pos()
+    }
+
+    /// Number of auto-repeats of the key (0 when it has just been pressed),
+    /// like to accelerate a motion while the key is held down.
+    ///
+    /// Terminals do not report when a key is released: the repeat is
+    /// inferred from the timing. The key is considered repeated when it is
+    /// read less than 100 ms after the same key (an auto-repeat rate is
+    /// usually around 30 ms; the delay before the first auto-repeat is
+    /// longer, so it is counted from the second one). So keys typed very
+    /// fast or pasted are counted too.
+    pub fn repeat_count(&self) -> usize {
+        self.repeat_count
+    }
+
+    /// Delay since the previous press of the same key (`None` if another key
+    /// has been pressed in between).
+    pub fn since_last_key(&self) -> Option<Duration> {
+        self.since_last_key
+    }
+}
+
+/// Custom command bound to a key (see `Cmd::Custom`).
+///
+/// ```
+/// extern crate rustyline;
+///
+/// use rustyline::{Cmd, Editor, EventContext, Handler, KeyPress};
+///
+/// # fn main() {
+/// let mut rl = Editor::<()>::new();
+/// // help about the word before the cursor
+/// let help = Handler::new(|ctx: &EventContext| {
+///     let word = ctx.line()[..ctx.pos()].split_whitespace().last();
+///     word.map(|word| Cmd::Insert(1, format!(" (help: {})", word)))
+/// });
+/// rl.bind_sequence(KeyPress::F(1), Cmd::Custom(help));
+/// # }
+/// ```
+pub trait CommandHandler: Send + Sync {
+    /// Takes the context of the line being edited and returns the command to
+    /// execute (`None` to do nothing).
+    fn handle(&self, ctx: &EventContext) -> Option<Cmd>;
+}
+
+impl<F> CommandHandler for F
+where
+    F: Fn(&EventContext) -> Option<Cmd> + Send + Sync,
+{
+    fn handle(&self, ctx: &EventContext) -> Option<Cmd> {
+        self(ctx)
+    }
+}
+
+/// Shared `CommandHandler`: two handlers are equal only if they are the same.
+#[derive(Clone)]
+pub struct Handler(Arc<CommandHandler>);
+
+impl Handler {
+    pub fn new<H: CommandHandler + 'static>(handler: H) -> Handler {
+        Handler(Arc::new(handler))
+    }
+
+    /// Calls the handler.
+    pub fn handle(&self, ctx: &EventContext) -> Option<Cmd> {
+        self.0.handle(ctx)
+    }
+}
+
+impl fmt::Debug for Handler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Handler")
+    }
+}
+
+impl PartialEq for Handler {
+    fn eq(&self, other: &Handler) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
 fn repeat_count(previous: RepeatCount, new: Option<RepeatCount>) -> RepeatCount {
     match new {
         Some(n) => n,
@@ -276,13 +433,29 @@ enum InputMode {
 pub struct InputState {
     mode: EditMode,
     custom_bindings: Arc<RwLock<HashMap<KeyPress, Cmd>>>,
+    // bindings of two keys, like Ctrl-X Ctrl-E
+    custom_pairs: Arc<RwLock<HashMap<(KeyPress, KeyPress), Cmd>>>,
     input_mode: InputMode, // vi only ?
     // numeric arguments: http://web.mit.edu/gnu/doc/html/rlman_1.html#SEC7
     num_args: i16,
     last_cmd: Cmd,                        // vi only
     last_char_search: Option<CharSearch>, // vi only
+    cursor_acceleration: bool,
+    // first key of the last command and when it has been read
+    last_key: Option<(KeyPress, Instant)>,
+    since_last_key: Option<Duration>, // since the previous press of the same key
+    repeat_count: usize,              // auto-repeats of the last key
+    // times the last command is executed (see `Cmd::is_repeated_by_count`)
+    times: RepeatCount,
 }
 
+/// Maximum delay (in milliseconds) between two presses of a key for the
+/// second one to be considered an auto-repeat
+const KEY_REPEAT_DELAY: u64 = 100;
+/// Number of auto-repeats after which a character motion is accelerated
+/// again (see `Config::cursor_acceleration`)
+const ACCELERATION_STEP: usize = 8;
+
 pub trait Refresher {
     /// Rewrite the currently edited line accordingly to the buffer content,
     /// cursor position, and number of columns of the terminal.
@@ -305,17 +478,41 @@ impl InputState {
         InputState {
             mode: config.edit_mode(),
             custom_bindings,
+            custom_pairs: Arc::new(RwLock::new(HashMap::new())),
             input_mode: InputMode::Insert,
             num_args: 0,
             last_cmd: Cmd::Noop,
             last_char_search: None,
+            cursor_acceleration: config.cursor_acceleration(),
+            last_key: None,
+            since_last_key: None,
+            repeat_count: 0,
+            times: 1,
         }
     }
 
+    /// Set the bindings of two keys (see `Editor::bind_key_pair`).
+    pub fn set_custom_pairs(
+        &mut self,
+        custom_pairs: Arc<RwLock<HashMap<(KeyPress, KeyPress), Cmd>>>,
+    ) {
+        self.custom_pairs = custom_pairs;
+    }
+
+    /// Return (once) how many times the last command read is executed: its
+    /// numeric argument when it has no count, 1 otherwise.
+    pub fn take_times(&mut self) -> RepeatCount {
+        mem::replace(&mut self.times, 1)
+    }
+
     pub fn is_emacs_mode(&self) -> bool {
         self.mode == EditMode::Emacs
     }
 
+    pub fn is_vi_command_mode(&self) -> bool {
+        self.mode == EditMode::Vi && self.input_mode == InputMode::Command
+    }
+
     /// Parse user input into one command
     /// `single_esc_abort` is used in emacs mode on unix platform when a single
     /// esc key is expected to abort current action.
@@ -325,14 +522,105 @@ impl InputState {
         wrt: &mut Refresher,
         single_esc_abort: bool,
     ) -> Result<Cmd> {
-        match self.mode {
+        let cmd = try!(match self.mode {
             EditMode::Emacs => self.emacs(rdr, wrt, single_esc_abort),
             EditMode::Vi if self.input_mode != InputMode::Command => self.vi_insert(rdr, wrt),
             EditMode::Vi => self.vi_command(rdr, wrt),
+        });
+        Ok(self.accelerate(cmd))
+    }
+
+    /// Read the first key of a command, inferring whether it is an
+    /// auto-repeat (see `EventContext::repeat_count`).
+    fn next_key<R: RawReader>(&mut self, rdr: &mut R, single_esc_abort: bool) -> Result<KeyPress> {
+        let key = try!(rdr.next_key(single_esc_abort));
+        let now = Instant::now();
+        self.since_last_key = match self.last_key {
+            Some((last, at)) if last == key => Some(now.duration_since(at)),
+            _ => None,
+        };
+        self.repeat_count = match self.since_last_key {
+            Some(delay) if delay <= Duration::from_millis(KEY_REPEAT_DELAY) => {
+                self.repeat_count + 1
+            }
+            _ => 0,
+        };
+        self.last_key = Some((key, now));
+        Ok(key)
+    }
+
+    /// Command bound to the pair of keys `key`, `snd_key` (see
+    /// `Editor::bind_key_pair`)
+    fn custom_pair(
+        &self,
+        wrt: &Refresher,
+        key: KeyPress,
+        snd_key: KeyPress,
+        n: Option<RepeatCount>,
+    ) -> Option<Cmd> {
+        let pairs = self.custom_pairs.read().unwrap();
+        pairs.get(&(key, snd_key)).map(|cmd| {
+            debug!(target: "rustyline", "Custom command: {:?}", cmd);
+            if cmd.is_repeatable() {
+                cmd.redo(n, wrt)
+            } else {
+                cmd.clone()
+            }
+        })
+    }
+
+    /// When `key` is the first key of a bound pair, read the second one and
+    /// return the command of this pair (`Cmd::Unknown` if it is not bound).
+    fn custom_prefix<R: RawReader>(
+        &self,
+        rdr: &mut R,
+        wrt: &Refresher,
+        key: KeyPress,
+        n: Option<RepeatCount>,
+    ) -> Result<Option<Cmd>> {
+        let is_prefix = {
+            let pairs = self.custom_pairs.read().unwrap();
+            pairs.keys().any(|&(prefix, _)| prefix == key)
+        };
+        if !is_prefix {
+            return Ok(None);
+        }
+        let snd_key = try!(rdr.next_key(true));
+        Ok(Some(
+            self.custom_pair(wrt, key, snd_key, n)
+                .unwrap_or(Cmd::Unknown),
+        ))
+    }
+
+    /// Multiply the count of the character motions while their key is held
+    /// down (when `Config::cursor_acceleration` is set).
+    fn accelerate(&self, cmd: Cmd) -> Cmd {
+        if !self.cursor_acceleration || self.repeat_count < ACCELERATION_STEP {
+            return cmd;
+        }
+        // 2, 4 then 8
+        let factor = 1 << cmp::min(self.repeat_count / ACCELERATION_STEP, 3);
+        match cmd {
+            Cmd::Move(Movement::BackwardChar(n)) => {
+                Cmd::Move(Movement::BackwardChar(n.saturating_mul(factor)))
+            }
+            Cmd::Move(Movement::ForwardChar(n)) => {
+                Cmd::Move(Movement::ForwardChar(n.saturating_mul(factor)))
+            }
+            _ => cmd,
         }
     }
 
     // TODO dynamic prompt (arg: ?)
+    /// Insert the pasted text literally (as a single change), without
+    /// interpreting its newlines or any other key.
+    fn paste<R: RawReader>(&mut self, rdr: &mut R) -> Result<Cmd> {
+        // the pasted keys are not repeated keys
+        self.last_key = None;
+        let text = try!(rdr.read_pasted_text());
+        Ok(Cmd::Insert(1, text))
+    }
+
     fn emacs_digit_argument<R: RawReader>(
         &mut self,
         rdr: &mut R,
@@ -378,12 +666,17 @@ impl InputState {
         wrt: &mut Refresher,
         single_esc_abort: bool,
     ) -> Result<Cmd> {
-        let mut key = try!(rdr.next_key(single_esc_abort));
+        let mut key = try!(self.next_key(rdr, single_esc_abort));
+        if key == KeyPress::BracketedPasteStart {
+            return self.paste(rdr);
+        }
         if let KeyPress::Meta(digit @ '-') = key {
             key = try!(self.emacs_digit_argument(rdr, wrt, digit));
         } else if let KeyPress::Meta(digit @ '0'...'9') = key {
             key = try!(self.emacs_digit_argument(rdr, wrt, digit));
         }
+        // Ctrl-D deletes chars instead of ending the input with an argument
+        let explicit_num_args = self.num_args != 0;
         let (n, positive) = self.emacs_num_args(); // consume them in all cases
         {
             let bindings = self.custom_bindings.read().unwrap();
@@ -396,12 +689,19 @@ impl InputState {
                 });
             }
         }
+        // the Ctrl-X pairs are read with the built-in ones
+        if key != KeyPress::Ctrl('X') {
+            if let Some(cmd) = try!(self.custom_prefix(rdr, wrt, key, Some(n))) {
+                return Ok(cmd);
+            }
+        }
         let cmd = match key {
             KeyPress::Char(c) => if positive {
                 Cmd::SelfInsert(n, c)
             } else {
                 Cmd::Unknown
             },
+            KeyPress::Ctrl(' ') => Cmd::SetMark,
             KeyPress::Ctrl('A') => Cmd::Move(Movement::BeginningOfLine),
             KeyPress::Ctrl('B') => if positive {
                 Cmd::Move(Movement::BackwardChar(n))
@@ -414,6 +714,11 @@ impl InputState {
             } else {
                 Cmd::Move(Movement::BackwardChar(n))
             },
+            KeyPress::Ctrl('D') if explicit_num_args => if positive {
+                Cmd::Kill(Movement::ForwardChar(n))
+            } else {
+                Cmd::Kill(Movement::BackwardChar(n))
+            },
             KeyPress::Ctrl('G') | KeyPress::Esc | KeyPress::Meta('\x07') => Cmd::Abort,
             KeyPress::Ctrl('H') | KeyPress::Backspace => if positive {
                 Cmd::Kill(Movement::BackwardChar(n))
@@ -431,9 +736,15 @@ impl InputState {
             KeyPress::Ctrl('P') => Cmd::PreviousHistory,
             KeyPress::Ctrl('X') => {
                 let snd_key = try!(rdr.next_key(true));
+                if let Some(cmd) = self.custom_pair(wrt, key, snd_key, Some(n)) {
+                    return Ok(cmd);
+                }
                 match snd_key {
                     KeyPress::Ctrl('G') | KeyPress::Esc => Cmd::Abort,
+                    KeyPress::Ctrl('E') => Cmd::Edit,
                     KeyPress::Ctrl('U') => Cmd::Undo(n),
+                    KeyPress::Ctrl('X') => Cmd::ExchangePointAndMark,
+                    KeyPress::Char('I') | KeyPress::Char('i') => Cmd::BlockInsert,
                     _ => Cmd::Unknown,
                 }
             }
@@ -442,6 +753,7 @@ impl InputState {
             } else {
                 Cmd::Kill(Movement::ForwardWord(n, At::AfterEnd, Word::Emacs))
             },
+            KeyPress::Meta('/') => Cmd::CycleHint,
             KeyPress::Meta('<') => Cmd::BeginningOfHistory,
             KeyPress::Meta('>') => Cmd::EndOfHistory,
             KeyPress::Meta('B') | KeyPress::Meta('b') => if positive {
@@ -461,11 +773,15 @@ impl InputState {
                 Cmd::Move(Movement::BackwardWord(n, Word::Emacs))
             },
             KeyPress::Meta('L') | KeyPress::Meta('l') => Cmd::DowncaseWord,
+            KeyPress::Meta('R') | KeyPress::Meta('r') => Cmd::ToggleRegexSearch,
             KeyPress::Meta('T') | KeyPress::Meta('t') => Cmd::TransposeWords(n),
             KeyPress::Meta('U') | KeyPress::Meta('u') => Cmd::UpcaseWord,
             KeyPress::Meta('Y') | KeyPress::Meta('y') => Cmd::YankPop,
             _ => self.common(key, n, positive),
         };
+        if explicit_num_args && positive && cmd.is_repeated_by_count() {
+            self.times = n;
+        }
         debug!(target: "rustyline", "Emacs command: {:?}", cmd);
         Ok(cmd)
     }
@@ -496,7 +812,10 @@ impl InputState {
     }
 
     fn vi_command<R: RawReader>(&mut self, rdr: &mut R, wrt: &mut Refresher) -> Result<Cmd> {
-        let mut key = try!(rdr.next_key(false));
+        let mut key = try!(self.next_key(rdr, false));
+        if key == KeyPress::BracketedPasteStart {
+            return self.paste(rdr);
+        }
         if let KeyPress::Char(digit @ '1'...'9') = key {
             key = try!(self.vi_arg_digit(rdr, wrt, digit));
         }
@@ -517,6 +836,10 @@ impl InputState {
                 });
             }
         }
+        let count = if no_num_args { None } else { Some(n) };
+        if let Some(cmd) = try!(self.custom_prefix(rdr, wrt, key, count)) {
+            return Ok(cmd);
+        }
         let cmd = match key {
             KeyPress::Char('$') |
             KeyPress::End => Cmd::Move(Movement::EndOfLine),
@@ -625,6 +948,7 @@ impl InputState {
                 Cmd::Replace(Movement::WholeLine, None)
             }
             KeyPress::Char('u') => Cmd::Undo(n),
+            KeyPress::Char('v') => Cmd::Edit,
             // KeyPress::Char('U') => Cmd::???, // revert-line
             KeyPress::Char('w') => Cmd::Move(Movement::ForwardWord(n, At::Start, Word::Vi)), // vi-next-word
             KeyPress::Char('W') => Cmd::Move(Movement::ForwardWord(n, At::Start, Word::Big)), // vi-next-word
@@ -661,6 +985,9 @@ impl InputState {
             KeyPress::Esc => Cmd::Noop,
             _ => self.common(key, n, true),
         };
+        if !no_num_args && cmd.is_repeated_by_count() {
+            self.times = n;
+        }
         debug!(target: "rustyline", "Vi command: {:?}", cmd);
         if cmd.is_repeatable_change() {
             self.last_cmd = cmd.clone();
@@ -669,7 +996,10 @@ impl InputState {
     }
 
     fn vi_insert<R: RawReader>(&mut self, rdr: &mut R, wrt: &mut Refresher) -> Result<Cmd> {
-        let key = try!(rdr.next_key(false));
+        let key = try!(self.next_key(rdr, false));
+        if key == KeyPress::BracketedPasteStart {
+            return self.paste(rdr);
+        }
         {
             let bindings = self.custom_bindings.read().unwrap();
             if let Some(cmd) = bindings.get(&key) {
@@ -681,6 +1011,9 @@ impl InputState {
                 });
             }
         }
+        if let Some(cmd) = try!(self.custom_prefix(rdr, wrt, key, None)) {
+            return Ok(cmd);
+        }
         let cmd = match key {
             KeyPress::Char(c) => if self.input_mode == InputMode::Replace {
                 Cmd::Overwrite(c)
@@ -806,6 +1139,20 @@ impl InputState {
                     Cmd::Move(Movement::ForwardChar(n))
                 }
             }
+            KeyPress::ControlLeft => {
+                if positive {
+                    Cmd::Move(Movement::BackwardWord(n, Word::Emacs))
+                } else {
+                    Cmd::Move(Movement::ForwardWord(n, At::AfterEnd, Word::Emacs))
+                }
+            }
+            KeyPress::ControlRight => {
+                if positive {
+                    Cmd::Move(Movement::ForwardWord(n, At::AfterEnd, Word::Emacs))
+                } else {
+                    Cmd::Move(Movement::BackwardWord(n, Word::Emacs))
+                }
+            }
             KeyPress::Ctrl('C') => Cmd::Interrupt,
             KeyPress::Ctrl('D') => Cmd::EndOfFile,
             KeyPress::Delete => {
@@ -6,6 +6,11 @@ pub enum KeyPress {
     UnknownEscSeq,
     Backspace, // Ctrl('H')
     BackTab,
+    /// Start of the text pasted in the bracketed paste mode (read with
+    /// `RawReader::read_pasted_text`)
+    BracketedPasteStart,
+    /// End of the text pasted in the bracketed paste mode
+    BracketedPasteEnd,
     Char(char),
     ControlDown,
     ControlLeft,
@@ -1,4 +1,5 @@
 //! Kill Ring management
+use keymap::RepeatCount;
 use line_buffer::{DeleteListener, Direction};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -70,13 +71,14 @@ impl KillRing {
         }
     }
 
-    /// Yank previously killed text.
+    /// Yank previously killed text (inserted `n` times, replaced by the next
+    /// `yank_pop`).
     /// Return `None` when kill-ring is empty.
-    pub fn yank(&mut self) -> Option<&String> {
+    pub fn yank(&mut self, n: RepeatCount) -> Option<&String> {
         if self.slots.is_empty() {
             None
         } else {
-            self.last_action = Action::Yank(self.slots[self.index].len());
+            self.last_action = Action::Yank(self.slots[self.index].len() * n);
             Some(&self.slots[self.index])
         }
     }
@@ -135,7 +137,7 @@ mod tests {
         assert_eq!(0, kill_ring.index);
         assert_eq!(Action::Kill, kill_ring.last_action);
 
-        assert_eq!(None, kill_ring.yank());
+        assert_eq!(None, kill_ring.yank(1));
         assert_eq!(Action::Kill, kill_ring.last_action);
     }
 
@@ -208,10 +210,10 @@ mod tests {
         kill_ring.reset();
         kill_ring.kill("word2", Mode::Append);
 
-        assert_eq!(Some(&"word2".to_owned()), kill_ring.yank());
-        assert_eq!(Action::Yank(5), kill_ring.last_action);
-        assert_eq!(Some(&"word2".to_owned()), kill_ring.yank());
+        assert_eq!(Some(&"word2".to_owned()), kill_ring.yank(1));
         assert_eq!(Action::Yank(5), kill_ring.last_action);
+        assert_eq!(Some(&"word2".to_owned()), kill_ring.yank(3));
+        assert_eq!(Action::Yank(15), kill_ring.last_action);
     }
 
     #[test]
@@ -222,7 +224,7 @@ mod tests {
         kill_ring.kill("longword2", Mode::Append);
 
         assert_eq!(None, kill_ring.yank_pop());
-        kill_ring.yank();
+        kill_ring.yank(1);
         assert_eq!(Some((9, &"word1".to_owned())), kill_ring.yank_pop());
         assert_eq!(Some((5, &"longword2".to_owned())), kill_ring.yank_pop());
         assert_eq!(Some((9, &"word1".to_owned())), kill_ring.yank_pop());
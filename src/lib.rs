@@ -19,13 +19,26 @@
 // #![feature(non_exhaustive)]
 // #![feature(tool_lints)]
 
+extern crate anstyle;
+#[cfg(feature = "crossterm-style")]
+extern crate crossterm;
 extern crate dirs;
+#[cfg(feature = "ignore")]
+extern crate ignore;
 extern crate libc;
 #[macro_use]
 extern crate log;
 extern crate memchr;
 #[cfg(unix)]
 extern crate nix;
+#[cfg(feature = "regex")]
+extern crate regex;
+#[cfg(feature = "sqlite-history")]
+extern crate rusqlite;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(feature = "tree-sitter")]
+extern crate tree_sitter;
 extern crate unicode_segmentation;
 extern crate unicode_width;
 #[cfg(unix)]
@@ -33,60 +46,398 @@ extern crate utf8parse;
 #[cfg(windows)]
 extern crate winapi;
 
+#[cfg(feature = "tokio")]
+mod async_editor;
 pub mod completion;
 pub mod config;
 mod edit;
 pub mod error;
+mod external_editor;
 pub mod highlight;
 pub mod hint;
 pub mod history;
+mod inputrc;
 mod keymap;
 mod keys;
 mod kill_ring;
 pub mod line_buffer;
+mod secret;
 mod undo;
+pub mod validate;
 
 mod tty;
 
+use std::borrow::Cow;
+use std::cmp;
 use std::collections::HashMap;
 use std::fmt;
-use std::io::{self, Write};
+use std::fs::File;
+use std::io::{self, BufRead, Read, Write};
+use std::mem;
+use std::ops::Range;
 use std::path::Path;
 use std::result;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, Mutex, RwLock};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
 use unicode_width::UnicodeWidthStr;
 
-use tty::{RawMode, RawReader, Renderer, Term, Terminal};
-
-use completion::{longest_common_prefix, Candidate, Completer};
-pub use config::{ColorMode, CompletionType, Config, EditMode, HistoryDuplicates};
-use edit::State;
-use highlight::Highlighter;
+use tty::{CancellableReader, RawMode, RawReader, Renderer, Term, Terminal};
+
+#[cfg(feature = "tokio")]
+pub use async_editor::{AsyncEditor, Readline};
+use completion::{
+    longest_common_prefix, longest_common_prefix_ignore_case, starts_with_ignore_case, Candidate,
+    Completer, Completion, CompletionFuture, FuzzyCompleter,
+};
+use config::Configurer;
+pub use config::{
+    ColorDepth, ColorMode, CompletionSort, CompletionType, Config, ControlCharRender, EditMode,
+    HistoryDuplicates, TitleTemplate,
+};
+use edit::{Snapshot, State};
+use highlight::{Downsampler, Highlighter};
 use hint::Hinter;
-use history::{Direction, History};
-pub use keymap::{Anchor, At, CharSearch, Cmd, Movement, RepeatCount, Word};
+use history::{Direction, History, HistoryBackend, VecHistory};
+use inputrc::Item;
+pub use keymap::{
+    Anchor, At, CharSearch, Cmd, CommandHandler, EventContext, Handler, Movement, RepeatCount,
+    Word,
+};
 use keymap::{InputState, Refresher};
 pub use keys::KeyPress;
 use kill_ring::{KillRing, Mode};
-use line_buffer::WordAction;
+use line_buffer::{LineBuffer, WordAction};
+pub use secret::PrivateLine;
+use validate::{ValidationResult, Validator};
 
 /// The error type for I/O and Linux Syscalls (Errno)
 pub type Result<T> = result::Result<T, error::ReadlineError>;
 
 /// Completes the line/word
+///
+/// `on_menu` is called with `true` when the completion menu opens and with
+/// `false` when it closes.
 fn complete_line<R: RawReader, C: Completer>(
     rdr: &mut R,
     s: &mut State,
     input_state: &mut InputState,
     completer: &C,
     highlighter: Option<&Highlighter>,
+    on_menu: Option<&Fn(bool)>,
+    config: &Config,
+) -> Result<Option<Cmd>> {
+    completer.begin_completion();
+    let result = if config.fuzzy_completion() && completer.async_completer().is_none() {
+        let completer = FuzzyCompleter::new(completer);
+        complete_line_session(
+            rdr,
+            s,
+            input_state,
+            &completer,
+            highlighter,
+            on_menu,
+            config,
+        )
+    } else {
+        complete_line_session(
+            rdr,
+            s,
+            input_state,
+            completer,
+            highlighter,
+            on_menu,
+            config,
+        )
+    };
+    // even if the completion has been aborted
+    completer.end_completion();
+    result
+}
+
+/// Delay (in milliseconds) before the spinner is displayed
+const SPINNER_DELAY: u64 = 200;
+/// Delay (in milliseconds) between two frames of the spinner
+const SPINNER_FRAME_DELAY: u64 = 100;
+const SPINNER_FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+
+/// Call the completer, displaying a spinner when it is slow.
+/// Discard the candidates found after `timeout`: the completer runs on the
+/// editing thread until it returns (it is neither `Send` nor `'static`), so
+/// a hanging completer still hangs `readline`. The thread of the spinner is
+/// stopped before returning: the next completion starts another one.
+fn complete_with_timeout<C: Completer>(
+    s: &mut State,
+    completer: &C,
+    timeout: Option<Duration>,
+) -> Result<Completion<C::Candidate>> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return completer.complete_advanced(&s.line, s.line.pos()),
+    };
+    let started = Instant::now();
+    let spinner = s.out.spinner_writer().map(Spinner::start);
+    // stopped when dropped if the completer panics
+    let result = completer.complete_advanced(&s.line, s.line.pos());
+    if spinner.map_or(false, Spinner::stop) {
+        // erase the spinner
+        try!(s.refresh_line());
+    }
+    if started.elapsed() > timeout {
+        debug!(target: "rustyline", "completion timeout");
+        let pos = s.line.pos();
+        return Ok(Completion {
+            start: pos,
+            end: pos,
+            candidates: Vec::new(),
+            replace_whole_line: None,
+        });
+    }
+    result
+}
+
+/// Spinner animated by its own thread while a completer runs, stopped (and
+/// its thread joined) when dropped.
+struct Spinner {
+    // disconnected to stop the spinner
+    done: Option<mpsc::Sender<()>>,
+    thread: Option<thread::JoinHandle<bool>>,
+}
+
+impl Spinner {
+    fn start(out: Box<Write + Send>) -> Spinner {
+        let (done, rx) = mpsc::channel::<()>();
+        Spinner {
+            done: Some(done),
+            thread: Some(thread::spawn(move || spin(out, &rx))),
+        }
+    }
+
+    /// Stop the spinner and return `true` if it has been displayed.
+    fn stop(mut self) -> bool {
+        self.done = None;
+        match self.thread.take() {
+            Some(thread) => thread.join().unwrap_or(true),
+            None => false,
+        }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.done = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Animate a spinner at the cursor position until `done` is disconnected.
+/// Return `true` if the spinner has been displayed.
+fn spin(mut out: Box<Write + Send>, done: &mpsc::Receiver<()>) -> bool {
+    let delay = Duration::from_millis(SPINNER_DELAY);
+    if done.recv_timeout(delay) != Err(RecvTimeoutError::Timeout) {
+        return false;
+    }
+    for frame in SPINNER_FRAMES.iter().cycle() {
+        let _ = write!(out, "{}\x08", frame);
+        let _ = out.flush();
+        let delay = Duration::from_millis(SPINNER_FRAME_DELAY);
+        if done.recv_timeout(delay) != Err(RecvTimeoutError::Timeout) {
+            break;
+        }
+    }
+    true
+}
+
+/// Delay (in milliseconds) between two checks of the keys pressed while the
+/// asynchronous candidates are computed
+const ASYNC_KEY_DELAY: u64 = 20;
+
+/// Notifies the editing thread that the future of the candidates can make
+/// progress.
+struct Woken(Mutex<mpsc::Sender<()>>);
+
+impl Wake for Woken {
+    fn wake(self: Arc<Self>) {
+        let _ = self.0.lock().unwrap().send(());
+    }
+}
+
+/// Poll the `future` of the candidates until they are ready, displaying a
+/// spinner hint ("| computing…") meanwhile.
+/// Return `None` when a key is pressed first: the completion is cancelled
+/// and the key is left to be read.
+///
+/// The future is polled again as soon as it wakes the editing thread up; the
+/// keys are checked every `ASYNC_KEY_DELAY`.
+fn complete_async<R: RawReader, C: Candidate>(
+    rdr: &mut R,
+    s: &mut State,
+    mut future: CompletionFuture<C>,
+) -> Result<Option<(usize, Vec<C>)>> {
+    let (notify, woken) = mpsc::channel();
+    let waker = Waker::from(Arc::new(Woken(Mutex::new(notify))));
+    let mut cx = Context::from_waker(&waker);
+    let started = Instant::now();
+    // index of the spinner frame displayed
+    let mut frame = None;
+    // polled first without a wake up
+    let mut ready = true;
+    loop {
+        if ready {
+            if let Poll::Ready(result) = future.as_mut().poll(&mut cx) {
+                if frame.is_some() {
+                    // erase the hint
+                    try!(s.refresh_line());
+                }
+                return result.map(Some);
+            }
+        }
+        let elapsed = started.elapsed();
+        let frames = (elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis()))
+            / SPINNER_FRAME_DELAY;
+        let i = (frames % SPINNER_FRAMES.len() as u64) as usize;
+        if frame != Some(i) {
+            try!(s.show_hint(&format!("{} computing…", SPINNER_FRAMES[i])));
+            frame = Some(i);
+        }
+        if try!(s.poll_input(rdr, 0)) {
+            debug!(target: "rustyline", "completion cancelled");
+            try!(s.refresh_line());
+            return Ok(None);
+        }
+        ready = match woken.recv_timeout(Duration::from_millis(ASYNC_KEY_DELAY)) {
+            Ok(()) => {
+                // several wake ups are handled by a single poll
+                while woken.try_recv().is_ok() {}
+                true
+            }
+            Err(_) => false,
+        };
+    }
+}
+
+/// Text of the original line replaced with the elected candidate (see
+/// `Completer::complete_advanced`).
+#[derive(Clone, Copy)]
+enum Reshape<'l> {
+    /// The bytes after the cursor
+    Tail(usize),
+    /// The whole line, by this one with the cursor at this position
+    WholeLine(&'l str, usize),
+}
+
+/// Deletes the text after the cursor (or replaces the whole line) as
+/// `reshape` tells then updates the `line` with the `elected` candidate.
+fn update_range<C: Completer>(
+    completer: &C,
+    line: &mut LineBuffer,
+    start: usize,
+    reshape: Reshape,
+    elected: &str,
+) {
+    match reshape {
+        Reshape::Tail(0) => {}
+        Reshape::Tail(tail) => {
+            let pos = line.pos();
+            line.delete_range(pos..pos + tail);
+        }
+        Reshape::WholeLine(new_line, pos) => line.update(new_line, pos),
+    }
+    completer.update(line, start, elected)
+}
+
+/// Sorts the `candidates` as configured, then with `Completer::sort`.
+fn sort_candidates<C: Completer>(
+    completer: &C,
+    sort: CompletionSort,
+    candidates: &mut [C::Candidate],
+) {
+    use std::cmp;
+
+    match sort {
+        CompletionSort::Unsorted => {}
+        CompletionSort::Alphabetical => candidates.sort_by(|a, b| a.display().cmp(b.display())),
+        CompletionSort::Kind => candidates.sort_by(|a, b| match (a.kind(), b.kind()) {
+            (Some(a), Some(b)) => a.cmp(b),
+            (Some(_), None) => cmp::Ordering::Less,
+            (None, Some(_)) => cmp::Ordering::Greater,
+            (None, None) => cmp::Ordering::Equal,
+        }),
+    }
+    completer.sort(candidates);
+}
+
+fn complete_line_session<R: RawReader, C: Completer>(
+    rdr: &mut R,
+    s: &mut State,
+    input_state: &mut InputState,
+    completer: &C,
+    highlighter: Option<&Highlighter>,
+    on_menu: Option<&Fn(bool)>,
     config: &Config,
 ) -> Result<Option<Cmd>> {
     // get a list of completions
-    let (start, candidates) = try!(completer.complete(&s.line, s.line.pos()));
+    let pos = s.line.pos();
+    let completion = match completer.async_completer() {
+        Some(async_completer) => {
+            let future = async_completer.complete(&s.line, pos);
+            match try!(complete_async(rdr, s, future)) {
+                Some((start, candidates)) => Completion {
+                    start,
+                    end: pos,
+                    candidates,
+                    replace_whole_line: None,
+                },
+                None => return Ok(None),
+            }
+        }
+        None => try!(complete_with_timeout(
+            s,
+            completer,
+            config.completion_timeout()
+        )),
+    };
+    let start = completion.start;
+    let mut candidates = completion.candidates;
+    if config.completion_ignore_case()
+        && !config.fuzzy_completion()
+        && completion.replace_whole_line.is_none()
+    {
+        // the candidates not matching the typed word are dropped
+        let typed = &s.line[start.min(pos)..pos];
+        candidates.retain(|c| starts_with_ignore_case(c.replacement(), typed));
+    }
+    sort_candidates(completer, config.completion_sort(), &mut candidates);
+    let mut reshape = match completion.replace_whole_line {
+        Some(ref new_line) => {
+            Reshape::WholeLine(new_line, completion.end.min(new_line.len()))
+        }
+        // text after the cursor replaced by the elected candidate
+        None => Reshape::Tail(completion.end.min(s.line.len()).saturating_sub(pos)),
+    };
     // if no completions, we are done
     if candidates.is_empty() {
-        try!(s.out.beep());
+        if let Reshape::WholeLine(new_line, pos) = reshape {
+            s.changes.borrow_mut().begin();
+            s.line.update(new_line, pos);
+            s.changes.borrow_mut().end();
+            try!(s.refresh_line());
+        } else {
+            try!(s.out.beep());
+        }
+        Ok(None)
+    } else if candidates.len() == 1 && !completer.is_partial(&candidates[0]) {
+        // insert the unique candidate directly (nothing to cycle through or to
+        // list)
+        s.changes.borrow_mut().begin();
+        update_range(completer, &mut s.line, start, reshape, candidates[0].replacement());
+        s.changes.borrow_mut().end();
+        try!(s.refresh_line());
         Ok(None)
     } else if CompletionType::Circular == config.completion_type() {
         let mark = s.changes.borrow_mut().begin();
@@ -95,26 +446,67 @@ fn complete_line<R: RawReader, C: Completer>(
         let backup_pos = s.line.pos();
         let mut cmd;
         let mut i = 0;
+        // `true` while the original line is displayed
+        let mut original = true;
+        // `true` while the documentation of a candidate is displayed
+        let mut docs;
         loop {
             // Show completion or original buffer
             if i < candidates.len() {
                 let candidate = candidates[i].replacement();
                 // TODO we can't highlight the line buffer directly
                 /*let candidate = if let Some(highlighter) = s.highlighter {
-                    highlighter.highlight_candidate(candidate, CompletionType::Circular)
+                    highlighter.highlight_candidate_item(&candidates[i], CompletionType::Circular)
                 } else {
                     Borrowed(candidate)
                 };*/
-                completer.update(&mut s.line, start, candidate);
+                if original {
+                    update_range(completer, &mut s.line, start, reshape, candidate);
+                    original = false;
+                } else {
+                    completer.update(&mut s.line, start, candidate);
+                }
                 try!(s.refresh_line());
+                docs = false;
+                if config.completion_docs_height() > 0 {
+                    if let Some(doc) = candidates[i].documentation() {
+                        try!(s.show_below(doc, config.completion_docs_height()));
+                        docs = true;
+                    }
+                }
             } else {
                 // Restore current edited line
                 s.line.update(&backup, backup_pos);
+                original = true;
                 try!(s.refresh_line());
+                docs = false;
             }
 
             cmd = try!(s.next_cmd(input_state, rdr, true));
+            if cmd == Cmd::AcceptLine && i < candidates.len() {
+                // drill down
+                let mut refined = completer.refine(&candidates[i]);
+                if !refined.is_empty() {
+                    sort_candidates(completer, config.completion_sort(), &mut refined);
+                    candidates = refined;
+                    i = 0;
+                    continue;
+                }
+            }
             match cmd {
+                Cmd::Complete if candidates.len() == 1 && completer.is_partial(&candidates[0]) => {
+                    // descend into the unique candidate
+                    s.changes.borrow_mut().end();
+                    return complete_line_session(
+                        rdr,
+                        s,
+                        input_state,
+                        completer,
+                        highlighter,
+                        on_menu,
+                        config,
+                    );
+                }
                 Cmd::Complete => {
                     i = (i + 1) % (candidates.len() + 1); // Circular
                     if i == candidates.len() {
@@ -123,25 +515,62 @@ fn complete_line<R: RawReader, C: Completer>(
                 }
                 Cmd::Abort => {
                     // Re-show original buffer
-                    if i < candidates.len() {
+                    if !original {
                         s.line.update(&backup, backup_pos);
                         try!(s.refresh_line());
                     }
                     s.changes.borrow_mut().truncate(mark);
                     return Ok(None);
                 }
+                Cmd::InsertCompletion(n) => if n < candidates.len() {
+                    let candidate = candidates[n].replacement();
+                    if original {
+                        update_range(completer, &mut s.line, start, reshape, candidate);
+                    } else {
+                        completer.update(&mut s.line, start, candidate);
+                    }
+                    s.changes.borrow_mut().end();
+                    try!(s.refresh_line());
+                    return Ok(None);
+                },
                 _ => {
                     s.changes.borrow_mut().end();
+                    if docs {
+                        // erase the documentation
+                        try!(s.refresh_line());
+                    }
                     break;
                 }
             }
         }
         Ok(Some(cmd))
-    } else if CompletionType::List == config.completion_type() {
-        if let Some(lcp) = longest_common_prefix(&candidates) {
-            // if we can extend the item, extend it
-            if lcp.len() > s.line.pos() - start {
-                completer.update(&mut s.line, start, lcp);
+    } else if CompletionType::List == config.completion_type()
+        || CompletionType::ListWithDescription == config.completion_type()
+    {
+        let lcp = if config.completion_ignore_case() {
+            longest_common_prefix_ignore_case(&candidates)
+        } else {
+            longest_common_prefix(&candidates)
+        };
+        if let Reshape::WholeLine(new_line, pos) = reshape {
+            // the common prefix is completed in the new line
+            s.line.update(new_line, pos);
+            try!(s.refresh_line());
+            reshape = Reshape::Tail(0);
+        }
+        if let Some(lcp) = lcp {
+            // compared by chars: the case folding may change the byte length
+            let typed_len = s.line[start..s.line.pos()].chars().count();
+            let lcp_len = lcp.chars().count();
+            // if we can extend the item (or fix its case), extend it
+            if lcp_len > typed_len
+                || (config.completion_ignore_case()
+                    && lcp_len == typed_len
+                    && lcp != &s.line[start..s.line.pos()])
+            {
+                // the text after the cursor is replaced too
+                update_range(completer, &mut s.line, start, reshape, lcp);
+                reshape = Reshape::Tail(0);
                 try!(s.refresh_line());
             }
         }
@@ -153,6 +582,14 @@ fn complete_line<R: RawReader, C: Completer>(
         }
         // we can't complete any further, wait for second tab
         let mut cmd = try!(s.next_cmd(input_state, rdr, true));
+        while let Cmd::InsertCompletion(n) = cmd {
+            if n < candidates.len() {
+                update_range(completer, &mut s.line, start, reshape, candidates[n].replacement());
+                try!(s.refresh_line());
+                return Ok(None);
+            }
+            cmd = try!(s.next_cmd(input_state, rdr, true));
+        }
         // if any character other than tab, pass it to the main loop
         if cmd != Cmd::Complete {
             return Ok(Some(cmd));
@@ -182,39 +619,285 @@ fn complete_line<R: RawReader, C: Completer>(
             true
         };
         if show_completions {
-            page_completions(rdr, s, input_state, highlighter, &candidates)
+            let page_size = config.completion_page_size();
+            page_completions(
+                rdr,
+                s,
+                input_state,
+                highlighter,
+                config.completion_type(),
+                page_size,
+                &candidates,
+            )
         } else {
             try!(s.refresh_line());
             Ok(None)
         }
+    } else if CompletionType::Menu == config.completion_type() {
+        if let Some(on_menu) = on_menu {
+            on_menu(true);
+        }
+        let result = menu_completions(
+            rdr,
+            s,
+            input_state,
+            completer,
+            highlighter,
+            start,
+            reshape,
+            candidates,
+            config.completion_sort(),
+            config.completion_docs_height(),
+        );
+        // even if the completion has been aborted
+        if let Some(on_menu) = on_menu {
+            on_menu(false);
+        }
+        result
     } else {
         Ok(None)
     }
 }
 
+/// Displays the `candidates` in a grid below the line, followed by (at most
+/// `docs_height` rows of) the documentation of the selected one, and moves
+/// the selection with the arrow keys (or Tab) until one is inserted (Enter)
+/// or the menu is cancelled (Esc).
+fn menu_completions<R: RawReader, C: Completer>(
+    rdr: &mut R,
+    s: &mut State,
+    input_state: &mut InputState,
+    completer: &C,
+    highlighter: Option<&Highlighter>,
+    start: usize,
+    reshape: Reshape,
+    mut candidates: Vec<C::Candidate>,
+    sort: CompletionSort,
+    docs_height: usize,
+) -> Result<Option<Cmd>> {
+    use std::cmp;
+
+    let min_col_pad = 2;
+    // the last column is left empty to avoid the automatic wrap
+    let cols = cmp::max(s.out.get_columns(), 2) - 1;
+    // the grid is scrolled when it does not fit below the line (with the
+    // documentation)
+    let max_rows = cmp::max(
+        s.out.get_rows().saturating_sub(s.old_rows + 2 + docs_height),
+        1,
+    );
+    let highlighter = highlighter.unwrap_or(&());
+
+    // the candidates are replaced when drilling down
+    'menu: loop {
+        let (display_width, kind_width) = candidate_columns(&candidates);
+        let max_width = cmp::min(cols, display_width + kind_width + min_col_pad);
+        let num_cols = cmp::max(cols / max_width, 1);
+        let num_rows = (candidates.len() + num_cols - 1) / num_cols;
+        let mut selected = 0;
+        let mut first_row = 0;
+        loop {
+            let selected_row = selected % num_rows;
+            if selected_row < first_row {
+                first_row = selected_row;
+            } else if selected_row >= first_row + max_rows {
+                first_row = selected_row + 1 - max_rows;
+            }
+            let mut rows = Vec::new();
+            for row in first_row..cmp::min(num_rows, first_row + max_rows) {
+                let mut ab = String::new();
+                for col in 0..num_cols {
+                    let i = (col * num_rows) + row;
+                    if i < candidates.len() {
+                        let candidate = &candidates[i];
+                        let highlighted = if i == selected {
+                            highlighter
+                                .highlight_selected_candidate(candidate, CompletionType::Menu)
+                        } else {
+                            highlighter.highlight_candidate_item(candidate, CompletionType::Menu)
+                        };
+                        ab.push_str(&highlighted);
+                        let width = push_candidate_kind(
+                            &mut ab,
+                            candidate,
+                            Some(highlighter),
+                            CompletionType::Menu,
+                            display_width,
+                        );
+                        if ((col + 1) * num_rows) + row < candidates.len() {
+                            for _ in width..max_width {
+                                ab.push(' ');
+                            }
+                        }
+                    }
+                }
+                rows.push(ab);
+            }
+            // erase the previous grid
+            try!(s.refresh_line());
+            try!(s.show_rows(&rows));
+            if docs_height > 0 {
+                if let Some(doc) = candidates[selected].documentation() {
+                    try!(s.show_below(doc, docs_height));
+                }
+            }
+
+            let cmd = try!(s.next_cmd(input_state, rdr, true));
+            if cmd == Cmd::AcceptLine {
+                // drill down
+                let mut refined = completer.refine(&candidates[selected]);
+                if !refined.is_empty() {
+                    sort_candidates(completer, sort, &mut refined);
+                    candidates = refined;
+                    continue 'menu;
+                }
+            }
+            match cmd {
+                Cmd::Complete => selected = (selected + 1) % candidates.len(),
+                Cmd::NextHistory => if selected + 1 < candidates.len() {
+                    selected += 1;
+                },
+                Cmd::PreviousHistory => if selected > 0 {
+                    selected -= 1;
+                },
+                Cmd::Move(Movement::ForwardChar(_)) => if selected + num_rows < candidates.len() {
+                    selected += num_rows;
+                },
+                Cmd::Move(Movement::BackwardChar(_)) => if selected >= num_rows {
+                    selected -= num_rows;
+                },
+                Cmd::AcceptLine | Cmd::InsertCompletion(_) => {
+                    let n = match cmd {
+                        Cmd::InsertCompletion(n) => n,
+                        _ => selected,
+                    };
+                    if n < candidates.len() {
+                        s.changes.borrow_mut().begin();
+                        let candidate = candidates[n].replacement();
+                        update_range(completer, &mut s.line, start, reshape, candidate);
+                        s.changes.borrow_mut().end();
+                        try!(s.refresh_line());
+                        return Ok(None);
+                    }
+                }
+                Cmd::Abort => {
+                    try!(s.refresh_line());
+                    return Ok(None);
+                }
+                _ => {
+                    try!(s.refresh_line());
+                    return Ok(Some(cmd));
+                }
+            }
+        }
+    }
+}
+
+/// Returns the width of the `display` column and the width of the `kind`
+/// column (with its separator, 0 when no candidate has a kind) of the listed
+/// `candidates`.
+fn candidate_columns<C: Candidate>(candidates: &[C]) -> (usize, usize) {
+    let display_width = candidates
+        .iter()
+        .map(|c| c.display().width())
+        .max()
+        .unwrap_or(0);
+    let kind_width = candidates
+        .iter()
+        .filter_map(|c| c.kind())
+        .map(|kind| kind.width() + 1)
+        .max()
+        .unwrap_or(0);
+    (display_width, kind_width)
+}
+
+/// Appends the `kind` of the `candidate` (whose `display` has been appended)
+/// aligned in its column and returns the width of the cell.
+fn push_candidate_kind(
+    ab: &mut String,
+    candidate: &Candidate,
+    highlighter: Option<&Highlighter>,
+    completion: CompletionType,
+    display_width: usize,
+) -> usize {
+    let width = candidate.display().width();
+    let kind = match candidate.kind() {
+        Some(kind) => kind,
+        None => return width,
+    };
+    for _ in width..display_width {
+        ab.push(' ');
+    }
+    ab.push(' ');
+    match highlighter {
+        Some(highlighter) => ab.push_str(&highlighter.highlight_candidate_kind(kind, completion)),
+        None => ab.push_str(kind),
+    }
+    width.max(display_width) + 1 + kind.width()
+}
+
+/// Appends the `description` of the `candidate` (whose cell of `width` has
+/// been appended) from the `column` and truncated to `cols`.
+fn push_candidate_description(
+    ab: &mut String,
+    candidate: &Candidate,
+    width: usize,
+    column: usize,
+    cols: usize,
+) {
+    use unicode_width::UnicodeWidthChar;
+
+    let description = match candidate.description().and_then(|d| d.lines().next()) {
+        Some(description) if column + 1 < cols => description,
+        _ => return,
+    };
+    for _ in width..column {
+        ab.push(' ');
+    }
+    // the last column is left empty to not wrap
+    let max_width = cols - column - 1;
+    if description.width() <= max_width {
+        ab.push_str(description);
+        return;
+    }
+    let mut width = 0;
+    for c in description.chars() {
+        width += c.width().unwrap_or(0);
+        if width + 1 > max_width {
+            break;
+        }
+        ab.push(c);
+    }
+    ab.push('…');
+}
+
 fn page_completions<R: RawReader, C: Candidate>(
     rdr: &mut R,
     s: &mut State,
     input_state: &mut InputState,
     highlighter: Option<&Highlighter>,
+    completion: CompletionType,
+    page_size: usize,
     candidates: &[C],
 ) -> Result<Option<Cmd>> {
     use std::cmp;
 
     let min_col_pad = 2;
     let cols = s.out.get_columns();
-    let max_width = cmp::min(
-        cols,
-        candidates
-            .into_iter()
-            .map(|s| s.display().width())
-            .max()
-            .unwrap()
-            + min_col_pad,
-    );
-    let num_cols = cols / max_width;
+    // at most one screen (with `--More--`)
+    let screen_rows = cmp::max(s.out.get_rows(), 2) - 1;
+    let page_rows = if page_size == 0 {
+        screen_rows
+    } else {
+        cmp::min(page_size, screen_rows)
+    };
+    let (display_width, kind_width) = candidate_columns(candidates);
+    let max_width = cmp::min(cols, display_width + kind_width + min_col_pad);
+    // one candidate per row, with its description
+    let descriptions = completion == CompletionType::ListWithDescription;
+    let num_cols = if descriptions { 1 } else { cols / max_width };
 
-    let mut pause_row = s.out.get_rows() - 1;
+    let mut pause_row = page_rows;
     let num_rows = (candidates.len() + num_cols - 1) / num_cols;
     let mut ab = String::new();
     for row in 0..num_rows {
@@ -235,12 +918,17 @@ fn page_completions<R: RawReader, C: Candidate>(
             }
             match cmd {
                 Cmd::SelfInsert(1, 'y') | Cmd::SelfInsert(1, 'Y') | Cmd::SelfInsert(1, ' ') => {
-                    pause_row += s.out.get_rows() - 1;
+                    pause_row += page_rows;
                 }
                 Cmd::AcceptLine => {
                     pause_row += 1;
                 }
-                _ => break,
+                _ => {
+                    // erase `--More--`: the line is displayed in its place
+                    try!(s.out.write_and_flush(b"\r        \r"));
+                    try!(s.refresh_line());
+                    return Ok(None);
+                }
             }
             try!(s.out.write_and_flush(b"\n"));
         } else {
@@ -250,14 +938,18 @@ fn page_completions<R: RawReader, C: Candidate>(
         for col in 0..num_cols {
             let i = (col * num_rows) + row;
             if i < candidates.len() {
-                let candidate = &candidates[i].display();
-                let width = candidate.width();
+                let candidate = &candidates[i];
                 if let Some(highlighter) = highlighter {
-                    ab.push_str(&highlighter.highlight_candidate(candidate, CompletionType::List));
+                    ab.push_str(&highlighter.highlight_candidate_item(candidate, completion));
                 } else {
-                    ab.push_str(candidate);
+                    ab.push_str(candidate.display());
                 }
-                if ((col + 1) * num_rows) + row < candidates.len() {
+                let width =
+                    push_candidate_kind(&mut ab, candidate, highlighter, completion, display_width);
+                if descriptions {
+                    let column = display_width + kind_width + min_col_pad;
+                    push_candidate_description(&mut ab, candidate, width, column, cols);
+                } else if ((col + 1) * num_rows) + row < candidates.len() {
                     for _ in width..max_width {
                         ab.push(' ');
                     }
@@ -271,48 +963,184 @@ fn page_completions<R: RawReader, C: Candidate>(
     Ok(None)
 }
 
+/// Query of the incremental search: matched as a substring or, once
+/// toggled with `Cmd::ToggleRegexSearch`, as a regular expression.
+struct SearchQuery {
+    text: String,
+    regex_mode: bool,
+    // recompiled after each change, `None` if `text` is not a valid regex
+    #[cfg(feature = "regex")]
+    regex: Option<regex::Regex>,
+}
+
+impl SearchQuery {
+    fn new() -> SearchQuery {
+        SearchQuery {
+            text: String::new(),
+            regex_mode: false,
+            #[cfg(feature = "regex")]
+            regex: None,
+        }
+    }
+
+    fn push(&mut self, c: char) {
+        self.text.push(c);
+        self.compile();
+    }
+
+    fn pop(&mut self) {
+        self.text.pop();
+        self.compile();
+    }
+
+    /// Switch between substring and regex matching (only supported with the
+    /// `regex` feature).
+    fn toggle(&mut self) -> bool {
+        if cfg!(feature = "regex") {
+            self.regex_mode = !self.regex_mode;
+            self.compile();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn compile(&mut self) {
+        #[cfg(feature = "regex")]
+        {
+            self.regex = if self.regex_mode {
+                regex::Regex::new(&self.text).ok()
+            } else {
+                None
+            };
+        }
+    }
+
+    /// Byte range of the first match in `entry`.
+    /// An invalid regex is matched literally.
+    fn find(&self, entry: &str) -> Option<Range<usize>> {
+        #[cfg(feature = "regex")]
+        {
+            if let Some(ref regex) = self.regex {
+                return regex.find(entry).map(|m| m.start()..m.end());
+            }
+        }
+        entry.find(&self.text).map(|i| i..i + self.text.len())
+    }
+
+    fn search(
+        &self,
+        history: &HistoryBackend,
+        start: usize,
+        dir: Direction,
+        limit: usize,
+    ) -> Option<usize> {
+        #[cfg(feature = "regex")]
+        {
+            if let Some(ref regex) = self.regex {
+                return history::search_entries(history, &self.text, start, dir, limit, |entry| {
+                    regex.is_match(entry)
+                });
+            }
+        }
+        history.search_limited(&self.text, start, dir, limit)
+    }
+}
+
+/// Inserts the typed text on each line of the region (see `Cmd::BlockInsert`)
+/// until another command is typed: this command is returned.
+fn block_insert<R: RawReader>(
+    rdr: &mut R,
+    s: &mut State,
+    input_state: &mut InputState,
+) -> Result<Option<Cmd>> {
+    let mut block = match try!(s.edit_block_start()) {
+        Some(block) => block,
+        None => return Ok(None),
+    };
+    loop {
+        match try!(s.next_cmd(input_state, rdr, true)) {
+            // a new line would shift the lines of the block
+            Cmd::SelfInsert(n, c) if c != '\n' => try!(s.edit_block_insert(&mut block, c, n)),
+            Cmd::Kill(Movement::BackwardChar(n)) => try!(s.edit_block_backspace(&mut block, n)),
+            Cmd::Abort => return Ok(None),
+            cmd => return Ok(Some(cmd)),
+        }
+    }
+}
+
 /// Incremental search
 fn reverse_incremental_search<R: RawReader>(
     rdr: &mut R,
     s: &mut State,
     input_state: &mut InputState,
-    history: &History,
+    history: &HistoryBackend,
+    scan_limit: usize,
+    search_prompt: &Fn(&str, Direction, bool) -> Option<String>,
 ) -> Result<Option<Cmd>> {
     if history.is_empty() {
         return Ok(None);
     }
+    let scan_limit = if scan_limit == 0 {
+        usize::max_value()
+    } else {
+        scan_limit
+    };
     let mark = s.changes.borrow_mut().begin();
     // Save the current edited line (and cursor position) before overwriting it
     let backup = s.line.as_str().to_owned();
     let backup_pos = s.line.pos();
 
-    let mut search_buf = String::new();
+    let mut query = SearchQuery::new();
     let mut history_idx = history.len() - 1;
     let mut direction = Direction::Reverse;
     let mut success = true;
+    // where to resume the last search when it has been cut off by the scan
+    // limit
+    let mut resume: Option<(Direction, usize)> = None;
 
     let mut cmd;
     // Display the reverse-i-search prompt and process chars
     loop {
-        let prompt = if success {
-            format!("(reverse-i-search)`{}': ", search_buf)
+        let prompt = if let Some(prompt) = search_prompt(&query.text, direction, success) {
+            prompt
         } else {
-            format!("(failed reverse-i-search)`{}': ", search_buf)
+            let state = if success {
+                ""
+            } else if resume.is_some() {
+                "partial "
+            } else {
+                "failed "
+            };
+            let mode = if query.regex_mode { "regex " } else { "" };
+            let dir = match direction {
+                Direction::Reverse => "reverse-",
+                Direction::Forward => "",
+            };
+            format!("({}{}{}i-search)`{}': ", state, mode, dir, query.text)
         };
         try!(s.refresh_prompt_and_line(&prompt));
 
         cmd = try!(s.next_cmd(input_state, rdr, true));
         if let Cmd::SelfInsert(_, c) = cmd {
-            search_buf.push(c);
+            query.push(c);
         } else {
             match cmd {
                 Cmd::Kill(Movement::BackwardChar(_)) => {
-                    search_buf.pop();
+                    query.pop();
+                    resume = None;
                     continue;
                 }
+                Cmd::ToggleRegexSearch => {
+                    if !query.toggle() {
+                        continue;
+                    }
+                }
                 Cmd::ReverseSearchHistory => {
                     direction = Direction::Reverse;
-                    if history_idx > 0 {
+                    if let Some((Direction::Reverse, idx)) = resume {
+                        history_idx = idx; // search more
+                    } else if history_idx > 0 {
                         history_idx -= 1;
                     } else {
                         success = false;
@@ -321,7 +1149,9 @@ fn reverse_incremental_search<R: RawReader>(
                 }
                 Cmd::ForwardSearchHistory => {
                     direction = Direction::Forward;
-                    if history_idx < history.len() - 1 {
+                    if let Some((Direction::Forward, idx)) = resume {
+                        history_idx = idx; // search more
+                    } else if history_idx < history.len() - 1 {
                         history_idx += 1;
                     } else {
                         success = false;
@@ -331,26 +1161,46 @@ fn reverse_incremental_search<R: RawReader>(
                 Cmd::Abort => {
                     // Restore current edited line (before search)
                     s.line.update(&backup, backup_pos);
+                    s.set_search_match(None);
                     try!(s.refresh_line());
                     s.changes.borrow_mut().truncate(mark);
                     return Ok(None);
                 }
                 Cmd::Move(_) => {
+                    s.set_search_match(None);
                     try!(s.refresh_line()); // restore prompt
                     break;
                 }
-                _ => break,
+                _ => {
+                    s.set_search_match(None);
+                    break;
+                }
             }
         }
-        success = match history.search(&search_buf, history_idx, direction) {
+        resume = None;
+        success = match query.search(history, history_idx, direction, scan_limit) {
             Some(idx) => {
                 history_idx = idx;
                 let entry = history.get(idx).unwrap();
-                let pos = entry.find(&search_buf).unwrap();
-                s.line.update(entry, pos);
+                let range = query.find(&entry).unwrap();
+                s.line.update(&entry, range.start);
+                s.set_search_match(Some(range));
                 true
             }
-            _ => false,
+            _ => {
+                // the entries not examined
+                resume = match direction {
+                    _ if query.text.is_empty() => None,
+                    Direction::Reverse if history_idx >= scan_limit => {
+                        Some((direction, history_idx - scan_limit))
+                    }
+                    Direction::Forward if history.len() - history_idx > scan_limit => {
+                        Some((direction, history_idx + scan_limit))
+                    }
+                    _ => None,
+                };
+                false
+            }
         };
     }
     s.changes.borrow_mut().end();
@@ -362,50 +1212,186 @@ fn reverse_incremental_search<R: RawReader>(
 /// (e.g., C-c will exit readline)
 fn readline_edit<H: Helper>(
     prompt: &str,
+    rprompt: Option<&str>,
     initial: Option<(&str, &str)>,
     editor: &mut Editor<H>,
+    history: Option<&HistoryBackend>,
     original_mode: &tty::Mode,
+    private: bool,
+    select_initial: bool,
+    // where the edition is suspended after the delay (in milliseconds)
+    // without input (see `Editor::try_readline`)
+    mut suspended: Option<(&mut Option<Suspended>, i32)>,
 ) -> Result<String> {
+    let history = match history {
+        Some(history) => history,
+        None => &editor.history,
+    };
     let completer = editor.helper.as_ref();
     let hinter = editor.helper.as_ref().map(|h| h as &Hinter);
+    let validator = editor.helper.as_ref().and_then(|h| h.validator());
+    let helper = editor.helper.as_ref();
+    let on_menu = |open: bool| {
+        if let Some(helper) = helper {
+            if open {
+                helper.on_menu_open()
+            } else {
+                helper.on_menu_close()
+            }
+        }
+    };
+    let search_prompt = |query: &str, direction: Direction, matched: bool| {
+        helper
+            .and_then(|helper| helper.search_prompt(query, direction, matched))
+            .map(Cow::into_owned)
+    };
     let highlighter = if editor.term.colors_enabled() {
         editor.helper.as_ref().map(|h| h as &Highlighter)
     } else {
         None
     };
+    let depth = editor.color_depth();
+    let downsampler = match highlighter {
+        Some(highlighter) if depth != ColorDepth::TrueColor => {
+            Some(Downsampler { highlighter, depth })
+        }
+        _ => None,
+    };
+    let highlighter = match downsampler {
+        Some(ref downsampler) => Some(downsampler as &Highlighter),
+        None => highlighter,
+    };
 
     let mut stdout = editor.term.create_writer();
 
-    editor.reset_kill_ring(); // TODO recreate a new kill ring vs Arc<Mutex<KillRing>>
+    editor.eof_line = None;
     let mut s = State::new(
         &mut stdout,
         prompt,
-        editor.history.len(),
+        history.len(),
         hinter,
         highlighter,
+        editor.config.control_char_rendering(),
+        editor.config.line_numbers(),
     );
-    let mut input_state = InputState::new(&editor.config, Arc::clone(&editor.custom_bindings));
+    // the edition interrupted by the previous `try_readline`
+    let (mut input_state, resumed) = match suspended
+        .as_mut()
+        .and_then(|&mut (ref mut slot, _)| slot.take())
+    {
+        Some(Suspended {
+            snapshot,
+            input_state,
+        }) => (input_state, Some(snapshot)),
+        None => {
+            editor.reset_kill_ring(); // TODO recreate a new kill ring vs Arc<Mutex<KillRing>>
+            let mut input_state =
+                InputState::new(&editor.config, Arc::clone(&editor.custom_bindings));
+            input_state.set_custom_pairs(Arc::clone(&editor.custom_pairs));
+            (input_state, None)
+        }
+    };
 
+    s.rprompt = rprompt;
+    s.private = private;
+    s.line.set_private(private);
+    s.history = Some(history);
     s.line.set_delete_listener(editor.kill_ring.clone());
-    s.line.set_change_listener(s.changes.clone());
+    if !private {
+        // the changes keep copies of the text
+        s.line.set_change_listener(s.changes.clone());
+    }
 
     if let Some((left, right)) = initial {
         s.line
             .update((left.to_owned() + right).as_ref(), left.len());
+        if select_initial {
+            s.select_line();
+        }
+    }
+    if let Some(snapshot) = resumed {
+        // the line is still displayed
+        s.resume(snapshot);
     }
 
     try!(s.refresh_line());
 
-    let mut rdr = try!(editor.term.create_reader(&editor.config));
+    let mut reader_config = editor.config;
+    if suspended.is_some() && reader_config.keyseq_timeout() < 0 {
+        // a lone ESC must not block until the next key
+        reader_config.set_keyseq_timeout(SUSPENDED_KEYSEQ_TIMEOUT);
+    }
+    let rdr = try!(editor.term.create_reader(&reader_config));
+    let mut rdr = CancellableReader::new(rdr, editor.cancel.clone());
+    // command read during a completion triggered by a character
+    let mut pending = None;
+    // `true` while the validator waits for a second Enter
+    let mut confirm = false;
+    // command executed again for its numeric argument, and how many times
+    let mut repeated: Option<(Cmd, RepeatCount)> = None;
 
     loop {
-        let rc = s.next_cmd(&mut input_state, &mut rdr, false);
-        let mut cmd = try!(rc);
+        let mut cmd = if let Some((cmd, times)) = repeated.take() {
+            if times > 1 {
+                repeated = Some((cmd.clone(), times - 1));
+            }
+            cmd
+        } else if let Some(cmd) = pending.take() {
+            cmd
+        } else {
+            if let Some((ref mut slot, timeout_ms)) = suspended {
+                if !try!(s.poll_input(&mut rdr, timeout_ms)) {
+                    **slot = Some(Suspended {
+                        snapshot: s.snapshot(),
+                        input_state,
+                    });
+                    return Ok(String::new());
+                }
+            }
+            let cmd = match s.next_cmd(&mut input_state, &mut rdr, false) {
+                // the input is closed: the line can be recovered
+                Err(error::ReadlineError::Eof) if !private && !s.line.is_empty() => {
+                    editor.eof_line = Some(s.line.as_str().to_owned());
+                    return Err(error::ReadlineError::Eof);
+                }
+                cmd => try!(cmd),
+            };
+            let times = input_state.take_times();
+            if times > 1 {
+                repeated = Some((cmd.clone(), times - 1));
+            }
+            cmd
+        };
+
+        if let Cmd::Custom(handler) = cmd {
+            cmd = match handler.handle(&EventContext::new(&s.line, &input_state)) {
+                Some(cmd) => cmd,
+                None => continue,
+            };
+            s.last_cmd = cmd.clone();
+        }
+
+        if confirm && cmd != Cmd::AcceptLine {
+            // cancel the confirmation (and erase its message)
+            confirm = false;
+            try!(s.refresh_line());
+        }
 
         if cmd.should_reset_kill_ring() {
             editor.reset_kill_ring();
         }
 
+        // the initial selection is replaced by the first insertion or deletion
+        if try!(s.replace_selection(&cmd)) {
+            continue;
+        }
+
+        // the region stays active only while the cursor is moved
+        match cmd {
+            Cmd::Move(_) | Cmd::SetMark | Cmd::ExchangePointAndMark | Cmd::BlockInsert => {}
+            _ => try!(s.deactivate_mark()),
+        }
+
         // autocomplete
         if cmd == Cmd::Complete && completer.is_some() {
             let next = try!(complete_line(
@@ -414,6 +1400,7 @@ fn readline_edit<H: Helper>(
                 &mut input_state,
                 completer.unwrap(),
                 highlighter,
+                Some(&on_menu),
                 &editor.config,
             ));
             if next.is_some() {
@@ -423,8 +1410,26 @@ fn readline_edit<H: Helper>(
             }
         }
 
+        if cmd == Cmd::BlockInsert {
+            match try!(block_insert(&mut rdr, &mut s, &mut input_state)) {
+                Some(next) => cmd = next,
+                None => continue,
+            }
+        }
+
         if let Cmd::SelfInsert(n, c) = cmd {
             try!(s.edit_insert(c, n));
+            if editor.config.is_completion_trigger(c) && completer.is_some() {
+                pending = try!(complete_line(
+                    &mut rdr,
+                    &mut s,
+                    &mut input_state,
+                    completer.unwrap(),
+                    highlighter,
+                    Some(&on_menu),
+                    &editor.config,
+                ));
+            }
             continue;
         } else if let Cmd::Insert(n, text) = cmd {
             try!(s.edit_yank(&input_state, &text, Anchor::Before, n));
@@ -437,7 +1442,9 @@ fn readline_edit<H: Helper>(
                 &mut rdr,
                 &mut s,
                 &mut input_state,
-                &editor.history,
+                history,
+                editor.config.search_scan_limit(),
+                &search_prompt,
             ));
             if next.is_some() {
                 cmd = next.unwrap();
@@ -469,6 +1476,9 @@ fn readline_edit<H: Helper>(
             Cmd::Overwrite(c) => {
                 try!(s.edit_overwrite_char(c));
             }
+            Cmd::EndOfFile if editor.config.ignore_eof_nonempty() && !s.line.is_empty() => {
+                try!(s.out.beep())
+            }
             Cmd::EndOfFile => if !input_state.is_emacs_mode() && !s.line.is_empty() {
                 try!(s.edit_move_end());
                 break;
@@ -492,17 +1502,17 @@ fn readline_edit<H: Helper>(
             }
             Cmd::NextHistory => {
                 // Fetch the next command from the history list.
-                try!(s.edit_history_next(&editor.history, false))
+                try!(s.edit_history_next(history, false))
             }
             Cmd::PreviousHistory => {
                 // Fetch the previous command from the history list.
-                try!(s.edit_history_next(&editor.history, true))
+                try!(s.edit_history_next(history, true))
             }
             Cmd::HistorySearchBackward => {
-                try!(s.edit_history_search(&editor.history, Direction::Reverse))
+                try!(s.edit_history_search(history, Direction::Reverse))
             }
             Cmd::HistorySearchForward => {
-                try!(s.edit_history_search(&editor.history, Direction::Forward))
+                try!(s.edit_history_search(history, Direction::Forward))
             }
             Cmd::TransposeChars => {
                 // Exchange the char before cursor with the character at cursor.
@@ -517,8 +1527,13 @@ fn readline_edit<H: Helper>(
             Cmd::Yank(n, anchor) => {
                 // retrieve (yank) last item killed
                 let mut kill_ring = editor.kill_ring.lock().unwrap();
-                if let Some(text) = kill_ring.yank() {
-                    try!(s.edit_yank(&input_state, text, anchor, n))
+                let yanked = match kill_ring.yank(n) {
+                    Some(text) => try!(s.edit_yank(&input_state, text, anchor, n)),
+                    None => false,
+                };
+                if !yanked {
+                    // nothing to replace by yank-pop
+                    kill_ring.reset();
                 }
             }
             Cmd::ViYankTo(ref mvt) => if let Some(text) = s.line.copy(mvt) {
@@ -527,27 +1542,51 @@ fn readline_edit<H: Helper>(
             },
             // TODO CTRL-_ // undo
             Cmd::AcceptLine => {
+                if let Some(validator) = validator.filter(|_| !confirm) {
+                    match validator.validate(&s.line) {
+                        ValidationResult::Valid => {}
+                        ValidationResult::Invalid(msg) => {
+                            try!(s.refresh_line());
+                            if let Some(msg) = msg {
+                                let rows = s.out.get_rows();
+                                try!(s.show_below(&msg, rows / 2));
+                            }
+                            continue;
+                        }
+                        ValidationResult::Confirm(msg) => {
+                            try!(s.refresh_line());
+                            let rows = s.out.get_rows();
+                            try!(s.show_below(&msg, rows / 2));
+                            confirm = true;
+                            continue;
+                        }
+                    }
+                }
                 #[cfg(test)]
                 {
                     editor.term.cursor = s.cursor.col;
                 }
                 // Accept the line regardless of where the cursor is.
                 try!(s.edit_move_end());
-                if s.hinter.is_some() {
-                    // Force a refresh without hints to leave the previous
-                    // line as the user typed it after a newline.
-                    s.hinter = None;
-                    try!(s.refresh_line());
+                // Force a refresh without hints to leave the previous line as
+                // the user typed it after a newline.
+                try!(s.refresh_accepted());
+                if let Some(template) = editor.config.update_title() {
+                    if !private && editor.term.is_output_tty() {
+                        if let Some(title) = title(template, &s.line) {
+                            try!(s.out.set_title(&title));
+                        }
+                    }
                 }
                 break;
             }
             Cmd::BeginningOfHistory => {
                 // move to first entry in history
-                try!(s.edit_history(&editor.history, true))
+                try!(s.edit_history(history, true))
             }
             Cmd::EndOfHistory => {
                 // move to last entry in history
-                try!(s.edit_history(&editor.history, false))
+                try!(s.edit_history(history, false))
             }
             Cmd::Move(Movement::BackwardWord(n, word_def)) => {
                 // move backwards one word
@@ -561,9 +1600,20 @@ fn readline_edit<H: Helper>(
                 try!(s.edit_kill(mvt));
             }
             Cmd::Move(Movement::ForwardWord(n, at, word_def)) => {
-                // move forwards one word
-                try!(s.edit_move_to_next_word(at, word_def, n))
+                // at the end of the line, accept the next word of the hint
+                // (not with the vi command `w`)
+                if input_state.is_vi_command_mode() || !try!(s.edit_accept_hint_word()) {
+                    // move forwards one word
+                    try!(s.edit_move_to_next_word(at, word_def, n))
+                }
+            }
+            Cmd::AcceptHintWord => {
+                if !try!(s.edit_accept_hint_word()) {
+                    // no hint to accept
+                    try!(s.out.beep());
+                }
             }
+            Cmd::CycleHint => try!(s.edit_cycle_hint()),
             Cmd::DowncaseWord => {
                 // lowercase word after point
                 try!(s.edit_word(WordAction::LOWERCASE))
@@ -579,17 +1629,49 @@ fn readline_edit<H: Helper>(
             Cmd::YankPop => {
                 // yank-pop
                 let mut kill_ring = editor.kill_ring.lock().unwrap();
-                if let Some((yank_size, text)) = kill_ring.yank_pop() {
-                    try!(s.edit_yank_pop(yank_size, text))
+                let yanked = match kill_ring.yank_pop() {
+                    Some((yank_size, text)) => try!(s.edit_yank_pop(yank_size, text)),
+                    None => {
+                        // only valid right after a yank (or a yank-pop)
+                        try!(s.out.beep());
+                        true
+                    }
+                };
+                if !yanked {
+                    kill_ring.reset();
                 }
             }
             Cmd::Move(Movement::ViCharSearch(n, cs)) => try!(s.edit_move_to(cs, n)),
+            Cmd::SetMark => try!(s.edit_set_mark()),
+            Cmd::ExchangePointAndMark => try!(s.edit_exchange_point_and_mark()),
+            Cmd::Edit => {
+                // the line of a private prompt is not written to a file
+                let edited = if private {
+                    Ok(None)
+                } else {
+                    try!(original_mode.disable_raw_mode());
+                    let command = editor.external_editor.as_ref().map(String::as_str);
+                    let edited = external_editor::edit(&s.line, command);
+                    try!(editor.term.enable_raw_mode());
+                    edited
+                };
+                match edited {
+                    Ok(Some(text)) => try!(s.edit_replace_line(&text)),
+                    _ => {
+                        // the line is left unchanged
+                        try!(s.refresh_line());
+                        try!(s.out.beep());
+                    }
+                }
+            }
             Cmd::Undo(n) => {
                 s.line.remove_change_listener();
                 if s.changes.borrow_mut().undo(&mut s.line, n) {
                     try!(s.refresh_line());
                 }
-                s.line.set_change_listener(s.changes.clone());
+                if !private {
+                    s.line.set_change_listener(s.changes.clone());
+                }
             }
             Cmd::Interrupt => {
                 return Err(error::ReadlineError::Interrupted);
@@ -611,7 +1693,23 @@ fn readline_edit<H: Helper>(
     if cfg!(windows) {
         let _ = original_mode; // silent warning
     }
-    Ok(s.line.into_string())
+    let mut line = s.line.take();
+    if !editor.config.keep_trailing_newline() && line.ends_with('\n') {
+        line.pop();
+    }
+    Ok(line)
+}
+
+/// Delay (in milliseconds) after an ESC before it is read as a single key in
+/// a suspendable edition, when `Config::keyseq_timeout` is -1
+const SUSPENDED_KEYSEQ_TIMEOUT: i32 = 500;
+
+/// Edition of a line interrupted by `Editor::try_readline` (or
+/// `Editor::readline_timeout`) when no more input is pending, resumed by the
+/// next call.
+struct Suspended {
+    snapshot: Snapshot,
+    input_state: InputState,
 }
 
 struct Guard<'m>(&'m tty::Mode);
@@ -624,19 +1722,51 @@ impl<'m> Drop for Guard<'m> {
     }
 }
 
+/// Title of the terminal for the accepted `line` (without control chars), or
+/// `None` to keep the current one.
+fn title(template: TitleTemplate, line: &str) -> Option<String> {
+    let title = match template {
+        TitleTemplate::Static(title) => title,
+        TitleTemplate::FirstWord => match line.split_whitespace().next() {
+            Some(word) => word,
+            None => return None,
+        },
+    };
+    Some(title.chars().filter(|c| !c.is_control()).collect())
+}
+
 /// Readline method that will enable RAW mode, call the `readline_edit()`
 /// method and disable raw mode
 fn readline_raw<H: Helper>(
     prompt: &str,
+    rprompt: Option<&str>,
     initial: Option<(&str, &str)>,
     editor: &mut Editor<H>,
+    mut history: Option<&mut HistoryBackend>,
+    private: bool,
+    select_initial: bool,
 ) -> Result<String> {
     let original_mode = try!(editor.term.enable_raw_mode());
     let guard = Guard(&original_mode);
-    let user_input = readline_edit(prompt, initial, editor, &original_mode);
-    if editor.config.auto_add_history() {
+    let user_input = readline_edit(
+        prompt,
+        rprompt,
+        initial,
+        editor,
+        history.as_ref().map(|h| &**h),
+        &original_mode,
+        private,
+        select_initial,
+        None,
+    );
+    if editor.config.auto_add_history() && !private {
         if let Ok(ref line) = user_input {
-            editor.add_history_entry(line.as_ref());
+            match history {
+                Some(ref mut history) => history.add(line.clone()),
+                None => {
+                    editor.add_history_entry(line.as_ref());
+                }
+            }
         }
     }
     drop(guard); // try!(disable_raw_mode(original_mode));
@@ -663,6 +1793,45 @@ where
     Self: Hinter,
     Self: Highlighter,
 {
+    /// Called when the completion menu opens (see `CompletionType::Menu`),
+    /// like to change the prompt or a status line.
+    ///
+    /// As `Completer::begin_completion`, it takes `&self` (the helper is
+    /// shared by the editor while the line is edited): use interior
+    /// mutability to keep state.
+    fn on_menu_open(&self) {}
+    /// Called when the completion menu closes: a candidate is inserted, the
+    /// menu is dismissed or another command is entered.
+    fn on_menu_close(&self) {}
+
+    /// Returns the validator of the line accepted with Enter, if any.
+    ///
+    /// By default, `None`: any line is accepted. A helper implementing
+    /// `Validator` returns `Some(self)`.
+    fn validator(&self) -> Option<&Validator> {
+        None
+    }
+
+    /// Returns the prompt displayed during an incremental history search
+    /// (see `Cmd::ReverseSearchHistory`), like to translate it, for the
+    /// `query` typed so far, searched in the `direction` (changed by
+    /// `Cmd::ForwardSearchHistory` and `Cmd::ReverseSearchHistory`).
+    /// `matched` is `false` when no entry matches the query.
+    ///
+    /// By default, `None`: the built-in prompt is displayed, like
+    /// `` (reverse-i-search)`query': ``, `` (i-search)`query': `` (forward)
+    /// or `` (failed reverse-i-search)`query': ``. It also tells when the
+    /// regular expression mode is on and when the search has been cut off
+    /// by `Config::search_scan_limit` (`partial` instead of `failed`).
+    fn search_prompt<'q>(
+        &self,
+        query: &'q str,
+        direction: Direction,
+        matched: bool,
+    ) -> Option<Cow<'q, str>> {
+        let _ = (query, direction, matched);
+        None
+    }
 }
 
 impl Helper for () {}
@@ -675,6 +1844,17 @@ pub struct Editor<H: Helper> {
     kill_ring: Arc<Mutex<KillRing>>,
     config: Config,
     custom_bindings: Arc<RwLock<HashMap<KeyPress, Cmd>>>,
+    custom_pairs: Arc<RwLock<HashMap<(KeyPress, KeyPress), Cmd>>>,
+    // mode to restore once the line read by `try_readline` is complete
+    raw_mode: Option<tty::Mode>,
+    suspended: Option<Suspended>,
+    // line being edited when the input has been closed
+    eof_line: Option<String>,
+    // command line of the editor of `Cmd::Edit`
+    external_editor: Option<String>,
+    // set to stop the line read by `AsyncEditor::readline` (see
+    // `CancellableReader`)
+    cancel: Option<Arc<AtomicBool>>,
 }
 
 //#[allow(clippy::new_without_default)]
@@ -686,7 +1866,7 @@ impl<H: Helper> Editor<H> {
 
     /// Create an editor with a specific configuration.
     pub fn with_config(config: Config) -> Editor<H> {
-        let term = Terminal::new(config.color_mode());
+        let term = Terminal::new(config.color_mode(), config.bracketed_paste());
         Editor {
             term,
             history: History::with_config(config),
@@ -694,6 +1874,12 @@ impl<H: Helper> Editor<H> {
             kill_ring: Arc::new(Mutex::new(KillRing::new(60))),
             config,
             custom_bindings: Arc::new(RwLock::new(HashMap::new())),
+            custom_pairs: Arc::new(RwLock::new(HashMap::new())),
+            raw_mode: None,
+            suspended: None,
+            eof_line: None,
+            external_editor: None,
+            cancel: None,
         }
     }
 
@@ -704,7 +1890,7 @@ impl<H: Helper> Editor<H> {
     /// Otherwise (e.g., if `stdin` is a pipe or the terminal is not supported),
     /// it uses file-style interaction.
     pub fn readline(&mut self, prompt: &str) -> Result<String> {
-        self.readline_with(prompt, None)
+        self.readline_with(prompt, None, None, false, None)
     }
 
     /// This function behaves in the exact same manner as `readline`, except
@@ -715,10 +1901,59 @@ impl<H: Helper> Editor<H> {
     /// the cursor and the string on the right is what will appear to the
     /// right of the cursor.
     pub fn readline_with_initial(&mut self, prompt: &str, initial: (&str, &str)) -> Result<String> {
-        self.readline_with(prompt, Some(initial))
+        self.readline_with(prompt, None, Some(initial), false, None)
+    }
+
+    /// This function behaves in the exact same manner as `readline`, except
+    /// that the input area is pre-populated with `text`, which is selected
+    /// (the mark is set at the start and the cursor at the end): the first
+    /// char typed, pasted or yanked replaces it, like in the address bar of
+    /// a browser, and the first deletion of a char (like Backspace) deletes
+    /// it. Any other command (like a move) deselects it.
+    ///
+    /// The replaced text is not added to the kill ring (the first yank
+    /// inserts the last text killed before): it is restored by an undo.
+    /// The selection is highlighted like the region (see
+    /// `Highlighter::highlight_selection`), so only with a helper.
+    pub fn readline_with_initial_selected(&mut self, prompt: &str, text: &str) -> Result<String> {
+        self.readline_with(prompt, None, Some((text, "")), true, None)
+    }
+
+    /// This function behaves in the exact same manner as `readline`, except
+    /// that `rprompt` is displayed flush right on the first row of the input
+    /// (like the right prompt of zsh or fish, to show a status like the
+    /// time or a branch), one column before the edge.
+    ///
+    /// It is redrawn with the line (so after a resize, with the new width),
+    /// highlighted with `Highlighter::highlight_prompt`. It is hidden while
+    /// the input (with its hint) does not fit beside it on its first row,
+    /// like when the line wraps or has several lines: it must be a single
+    /// line shorter than the terminal width.
+    pub fn readline_with_rprompt(&mut self, prompt: &str, rprompt: &str) -> Result<String> {
+        self.readline_with(prompt, Some(rprompt), None, false, None)
     }
 
-    fn readline_with(&mut self, prompt: &str, initial: Option<(&str, &str)>) -> Result<String> {
+    /// This function behaves in the exact same manner as `readline`, except
+    /// that `history` is browsed, searched and (with `auto_add_history`)
+    /// appended to instead of the editor's own history.
+    ///
+    /// Useful for a sub-prompt with its own history.
+    pub fn readline_with_history<R: HistoryBackend>(
+        &mut self,
+        prompt: &str,
+        history: &mut R,
+    ) -> Result<String> {
+        self.readline_with(prompt, None, None, false, Some(history))
+    }
+
+    fn readline_with(
+        &mut self,
+        prompt: &str,
+        rprompt: Option<&str>,
+        initial: Option<(&str, &str)>,
+        select_initial: bool,
+        history: Option<&mut HistoryBackend>,
+    ) -> Result<String> {
         if self.term.is_unsupported() {
             debug!(target: "rustyline", "unsupported terminal");
             // Write prompt and flush it to stdout
@@ -732,8 +1967,168 @@ impl<H: Helper> Editor<H> {
             // Not a tty: read from file / pipe.
             readline_direct()
         } else {
-            readline_raw(prompt, initial, self)
+            readline_raw(prompt, rprompt, initial, self, history, false, select_initial)
+        }
+    }
+
+    /// Reads a secret or transient line (like a password): nothing
+    /// retains it once the returned `PrivateLine` is dropped.
+    ///
+    /// The line is displayed as it is typed (it is not masked), but:
+    /// - it is not added to the history (even with `auto_add_history`) and
+    ///   the history cannot be browsed or searched,
+    /// - the text killed is not added to the kill ring (and the kill ring
+    ///   cannot be yanked),
+    /// - the helper is not called: no completion, hint, highlighting or
+    ///   validation (so that it cannot keep the line in a cache),
+    /// - the changes are not recorded: undo is disabled,
+    /// - the line buffer is allocated with the maximum line length (and
+    ///   overwritten with zeros before it is reallocated, when a longer text
+    ///   is pasted) and wiped when the line is returned as a `PrivateLine`
+    ///   (which is wiped when dropped) or when the edition fails (like with
+    ///   Ctrl-C).
+    ///
+    /// What is retained:
+    /// - the bytes read from the terminal and the escape sequences written to
+    ///   display the line go through buffers (in this crate, in the standard
+    ///   library and in the terminal) which are not wiped,
+    /// - when `stdin` is not a terminal, the line is read with
+    ///   `Stdin::read_line` whose buffer is not wiped.
+    pub fn readline_private(&mut self, prompt: &str) -> Result<PrivateLine> {
+        if self.term.is_unsupported() {
+            let mut stdout = io::stdout();
+            try!(stdout.write_all(prompt.as_bytes()));
+            try!(stdout.flush());
+            return readline_direct().map(PrivateLine::new);
+        } else if !self.term.is_stdin_tty() {
+            return readline_direct().map(PrivateLine::new);
+        }
+        let helper = self.helper.take();
+        let disabled = Arc::new(Mutex::new(KillRing::new(0)));
+        let kill_ring = mem::replace(&mut self.kill_ring, disabled);
+        // nothing to browse
+        let mut history = VecHistory::new();
+        let line = readline_raw(prompt, None, None, self, Some(&mut history), true, false);
+        self.kill_ring = kill_ring;
+        self.helper = helper;
+        line.map(PrivateLine::new)
+    }
+
+    /// Reads a line without blocking, for an application with its own event
+    /// loop: processes the pending input (the keys already typed) and
+    /// returns `None` when the line is not complete yet. Call it again (with
+    /// the same `prompt`) when `stdin` is readable, or periodically, to
+    /// resume the edition: the line, the cursor and the input state (like the
+    /// Vi mode or a numeric argument being typed) are kept between the calls.
+    ///
+    /// The terminal is put in raw mode by the first call and stays in raw
+    /// mode until the line is complete (the line or an error, like
+    /// `ReadlineError::Interrupted`, is returned) or the editor is dropped:
+    /// don't write to the terminal meanwhile.
+    ///
+    /// A key is read whole: when only the start of an escape sequence is
+    /// pending, the rest is waited for (up to `Config::keyseq_timeout`), so
+    /// no byte is lost between two calls (a single ESC is read after 500 ms
+    /// when `keyseq_timeout` is -1). A command reading several keys (like the
+    /// completion list or the incremental search) blocks until it ends. The
+    /// undo history, the history browsing position and the mark are kept
+    /// between the calls.
+    ///
+    /// When `stdin` is not a terminal, it blocks like `readline`.
+    pub fn try_readline(&mut self, prompt: &str) -> Result<Option<String>> {
+        self.readline_suspendable(prompt, 0, true)
+    }
+
+    /// This function behaves in the exact same manner as `readline`, except
+    /// that it returns `None` after `timeout` without any key typed, like
+    /// to run periodic tasks. The line being edited is kept: the next call
+    /// (with the same `prompt`) resumes its edition where it was, with the
+    /// same restrictions as `try_readline`.
+    ///
+    /// The terminal mode is restored on timeout, but the line stays
+    /// displayed: don't write to the terminal before the next call (or the
+    /// line is redrawn over the text written).
+    ///
+    /// The timeout is only checked between two keys: an escape sequence is
+    /// never split by a timeout, and the console events which are not keys
+    /// (like a focus change on Windows) don't restart it. It is also only
+    /// checked before a command: a command reading several keys (like the
+    /// incremental search, the completion list, a pair of keys or a vi
+    /// operator waiting for its motion) ignores it until the command ends.
+    /// When `stdin` is not a terminal, it blocks like `readline`.
+    pub fn readline_timeout(&mut self, prompt: &str, timeout: Duration) -> Result<Option<String>> {
+        let timeout_ms = timeout.as_secs() * 1000 + u64::from(timeout.subsec_millis());
+        let timeout_ms = cmp::min(timeout_ms, i32::max_value() as u64) as i32;
+        self.readline_suspendable(prompt, timeout_ms, false)
+    }
+
+    fn readline_suspendable(
+        &mut self,
+        prompt: &str,
+        timeout_ms: i32,
+        keep_raw_mode: bool,
+    ) -> Result<Option<String>> {
+        if self.term.is_unsupported() || !self.term.is_stdin_tty() {
+            return self.readline(prompt).map(Some);
+        }
+        let original_mode = match self.raw_mode.take() {
+            Some(mode) => mode,
+            None => try!(self.term.enable_raw_mode()),
+        };
+        if self.suspended.is_some() && timeout_ms == 0 {
+            // nothing to do until a key is typed
+            let pending = self
+                .term
+                .create_reader(&self.config)
+                .and_then(|mut rdr| rdr.poll(0));
+            if let Ok(false) = pending {
+                return self.suspend(original_mode, keep_raw_mode);
+            }
+        }
+        let mut suspended = self.suspended.take();
+        let user_input = readline_edit(
+            prompt,
+            None,
+            None,
+            self,
+            None,
+            &original_mode,
+            false,
+            false,
+            Some((&mut suspended, timeout_ms)),
+        );
+        if user_input.is_ok() && suspended.is_some() {
+            self.suspended = suspended;
+            return self.suspend(original_mode, keep_raw_mode);
+        }
+        if self.config.auto_add_history() {
+            if let Ok(ref line) = user_input {
+                self.add_history_entry(line.as_ref());
+            }
+        }
+        try!(original_mode.disable_raw_mode());
+        println!();
+        user_input.map(Some)
+    }
+
+    /// Keeps the terminal in raw mode until the suspended edition is resumed
+    /// or restores its `original_mode`.
+    fn suspend(&mut self, original_mode: tty::Mode, keep_raw_mode: bool) -> Result<Option<String>> {
+        if keep_raw_mode {
+            self.raw_mode = Some(original_mode);
+        } else {
+            try!(original_mode.disable_raw_mode());
         }
+        Ok(None)
+    }
+
+    /// Return (once) the line which was being edited when the last
+    /// `readline` failed with `ReadlineError::Eof` because the input was
+    /// closed (like when the terminal is hung up), if it was not empty.
+    ///
+    /// The line of `readline_private` is never kept.
+    pub fn take_eof_line(&mut self) -> Option<String> {
+        self.eof_line.take()
     }
 
     /// Load the history from the specified file.
@@ -741,6 +2136,11 @@ impl<H: Helper> Editor<H> {
         self.history.load(path)
     }
 
+    /// Load the history from `rdr` (like `history -r` from stdin).
+    pub fn load_history_from<R: BufRead>(&mut self, rdr: R) -> Result<()> {
+        self.history.load_from(rdr)
+    }
+
     /// Save the history in the specified file.
     pub fn save_history<P: AsRef<Path> + ?Sized>(&self, path: &P) -> Result<()> {
         self.history.save(path)
@@ -777,6 +2177,62 @@ impl<H: Helper> Editor<H> {
         self.helper.as_ref()
     }
 
+    /// Set the command line of the external editor of `Cmd::Edit` (like
+    /// `"code --wait"`): the path of the file to edit is appended to it.
+    ///
+    /// By default (`None`), `$VISUAL`, `$EDITOR` or the platform default
+    /// (`vi` / `notepad`) is used.
+    pub fn set_external_editor(&mut self, command: Option<String>) {
+        self.external_editor = command;
+    }
+
+    /// Number of colors the styles are downsampled to: the one configured
+    /// (see `Configurer::set_color_depth`) or else the one detected.
+    pub fn color_depth(&self) -> ColorDepth {
+        self.config
+            .color_depth()
+            .unwrap_or_else(|| self.term.color_depth())
+    }
+
+    /// Load the key bindings and the settings of an inputrc file (like
+    /// `~/.inputrc`), written in a subset of the syntax of GNU readline:
+    /// - `"\C-x": function-name` or `Control-x: function-name` binds a key
+    ///   to a readline function (like `kill-whole-line` or `upcase-word`),
+    ///   and `"\M-s": "text"` binds a key to the insertion of a text. A key
+    ///   is a char, Control or Meta with a char, or a special key like
+    ///   `"\e[A"`: a sequence can be a single key or a pair of keys like
+    ///   `"\C-x\C-e"` (see `bind_key_pair`), not a longer one.
+    /// - `set editing-mode emacs|vi`, `set completion-ignore-case on|off`,
+    ///   `set completion-query-items n`, `set history-size n` and
+    ///   `set keyseq-timeout ms` change the configuration.
+    /// - `$if mode=emacs|vi` and `$if term=name` (with `$else` and `$endif`)
+    ///   read the lines conditionally. The application name and the version
+    ///   never match.
+    ///
+    /// The unknown or unsupported lines (like `$include`, a binding of more
+    /// than two keys, an unknown function or variable) are skipped with a
+    /// warning logged. An error is only returned if the file cannot be read.
+    pub fn load_config_file<P: AsRef<Path> + ?Sized>(&mut self, path: &P) -> Result<()> {
+        let mut text = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut text));
+        for item in inputrc::parse(&text, self.config.edit_mode()) {
+            match item {
+                Item::Bind(key, cmd) => {
+                    self.bind_sequence(key, cmd);
+                }
+                Item::BindPair(key, snd_key, cmd) => {
+                    self.bind_key_pair(key, snd_key, cmd);
+                }
+                Item::EditMode(mode) => self.set_edit_mode(mode),
+                Item::CompletionIgnoreCase(yes) => self.set_completion_ignore_case(yes),
+                Item::CompletionQueryItems(limit) => self.set_completion_prompt_limit(limit),
+                Item::HistorySize(size) => self.set_max_history_size(size),
+                Item::KeyseqTimeout(timeout_ms) => self.set_keyseq_timeout(timeout_ms),
+            }
+        }
+        Ok(())
+    }
+
     /// Bind a sequence to a command.
     pub fn bind_sequence(&mut self, key_seq: KeyPress, cmd: Cmd) -> Option<Cmd> {
         let mut bindings = self.custom_bindings.write().unwrap();
@@ -789,6 +2245,24 @@ impl<H: Helper> Editor<H> {
         bindings.remove(&key_seq)
     }
 
+    /// Bind a pair of keys to a command: `key` then `snd_key` (like Ctrl-X
+    /// Ctrl-E).
+    ///
+    /// `key` then becomes a prefix: it no longer runs its built-in command
+    /// and the pairs starting with it which are not bound are unknown (except
+    /// the built-in Ctrl-X pairs of the emacs mode). A key bound alone by
+    /// `bind_sequence` is not a prefix.
+    pub fn bind_key_pair(&mut self, key: KeyPress, snd_key: KeyPress, cmd: Cmd) -> Option<Cmd> {
+        let mut pairs = self.custom_pairs.write().unwrap();
+        pairs.insert((key, snd_key), cmd)
+    }
+
+    /// Remove a binding for the given pair of keys.
+    pub fn unbind_key_pair(&mut self, key: KeyPress, snd_key: KeyPress) -> Option<Cmd> {
+        let mut pairs = self.custom_pairs.write().unwrap();
+        pairs.remove(&(key, snd_key))
+    }
+
     /// ```
     /// let mut rl = rustyline::Editor::<()>::new();
     /// for readline in rl.iter("> ") {
@@ -816,6 +2290,15 @@ impl<H: Helper> Editor<H> {
     }
 }
 
+impl<H: Helper> Drop for Editor<H> {
+    fn drop(&mut self) {
+        // the line read by `try_readline` is not complete
+        if let Some(mode) = self.raw_mode.take() {
+            let _ = mode.disable_raw_mode();
+        }
+    }
+}
+
 impl<H: Helper> config::Configurer for Editor<H> {
     fn config_mut(&mut self) -> &mut Config {
         &mut self.config
@@ -828,7 +2311,12 @@ impl<H: Helper> config::Configurer for Editor<H> {
 
     fn set_history_ignore_dups(&mut self, yes: bool) {
         self.config_mut().set_history_ignore_dups(yes);
-        self.history.ignore_dups = yes;
+        self.history.duplicates = self.config.history_duplicates();
+    }
+
+    fn set_history_duplicates(&mut self, duplicates: HistoryDuplicates) {
+        self.config_mut().set_history_duplicates(duplicates);
+        self.history.duplicates = duplicates;
     }
 
     fn set_history_ignore_space(&mut self, yes: bool) {
@@ -840,6 +2328,11 @@ impl<H: Helper> config::Configurer for Editor<H> {
         self.config_mut().set_color_mode(color_mode);
         self.term.color_mode = color_mode;
     }
+
+    fn set_bracketed_paste(&mut self, yes: bool) {
+        self.config_mut().set_bracketed_paste(yes);
+        self.term.bracketed_paste = yes;
+    }
 }
 
 impl<H: Helper> fmt::Debug for Editor<H> {
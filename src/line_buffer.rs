@@ -1,13 +1,17 @@
 //! Line buffer with current cursor position
 use keymap::{At, CharSearch, Movement, RepeatCount, Word};
+use secret;
 use std::cell::RefCell;
+use std::cmp;
 use std::fmt;
 use std::iter;
+use std::mem;
 use std::ops::{Deref, Index, Range};
 use std::rc::Rc;
 use std::string::Drain;
 use std::sync::{Arc, Mutex};
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 /// Maximum buffer size for the line read
 pub(crate) static MAX_LINE: usize = 4096;
@@ -55,6 +59,7 @@ pub struct LineBuffer {
     pos: usize,  // Current cursor position (byte position) (rl_point)
     dl: Option<Arc<Mutex<DeleteListener>>>,
     cl: Option<Rc<RefCell<ChangeListener>>>,
+    private: bool, // `true` if the buffer is wiped when it is reallocated
 }
 
 impl fmt::Debug for LineBuffer {
@@ -74,6 +79,7 @@ impl LineBuffer {
             pos: 0,
             dl: None,
             cl: None,
+            private: false,
         }
     }
 
@@ -102,6 +108,26 @@ impl LineBuffer {
         self.cl = None;
     }
 
+    /// Overwrites the buffer with zeros before it is reallocated (when text
+    /// is inserted past its capacity), so that no copy of the line is freed
+    /// without being wiped.
+    pub(crate) fn set_private(&mut self, private: bool) {
+        self.private = private;
+    }
+
+    /// Makes room for `additional` bytes. In private mode, the buffer is
+    /// moved to a larger allocation here and the previous one is wiped.
+    fn reserve(&mut self, additional: usize) {
+        if !self.private || self.buf.len() + additional <= self.buf.capacity() {
+            return;
+        }
+        let capacity = cmp::max(self.buf.capacity() * 2, self.buf.len() + additional);
+        let mut buf = String::with_capacity(capacity);
+        buf.push_str(&self.buf);
+        secret::wipe(&mut self.buf);
+        self.buf = buf;
+    }
+
     /// Extracts a string slice containing the entire buffer.
     pub fn as_str(&self) -> &str {
         &self.buf
@@ -112,6 +138,19 @@ impl LineBuffer {
         self.buf
     }
 
+    /// Takes the content of the buffer (without copying) and leaves it
+    /// empty, without capacity.
+    pub(crate) fn take(&mut self) -> String {
+        self.pos = 0;
+        mem::replace(&mut self.buf, String::new())
+    }
+
+    /// Overwrites the buffer with zeros and empties it.
+    pub(crate) fn wipe(&mut self) {
+        secret::wipe(&mut self.buf);
+        self.pos = 0;
+    }
+
     /// Current cursor position (byte position)
     pub fn pos(&self) -> usize {
         self.pos
@@ -590,8 +629,13 @@ impl LineBuffer {
                 let result = match a {
                     WordAction::CAPITALIZE => {
                         let ch = (&word).graphemes(true).next().unwrap();
-                        let cap = ch.to_uppercase();
-                        cap + &word[ch.len()..].to_lowercase()
+                        let mut cap = ch.to_uppercase();
+                        // only the first letter of an expansion (like `ß` to
+                        // `SS`) stays uppercase: `Ss`
+                        let first = cap.chars().next().map_or(0, char::len_utf8);
+                        let lower = cap[first..].to_lowercase();
+                        cap.truncate(first);
+                        cap + &lower + &word[ch.len()..].to_lowercase()
                     }
                     WordAction::LOWERCASE => word.to_lowercase(),
                     WordAction::UPPERCASE => word.to_uppercase(),
@@ -605,8 +649,11 @@ impl LineBuffer {
     }
 
     /// Transpose two words
+    ///
+    /// The cursor is not moved when there are not two words to transpose.
     pub fn transpose_words(&mut self, n: RepeatCount) -> bool {
         let word_def = Word::Emacs;
+        let orig_pos = self.pos;
         self.move_to_next_word(At::AfterEnd, word_def, n);
         let w2_end = self.pos;
         self.move_to_prev_word(word_def, 1);
@@ -616,6 +663,7 @@ impl LineBuffer {
         self.move_to_next_word(At::AfterEnd, word_def, 1);
         let w1_end = self.pos;
         if w1_beg == w2_beg || w2_beg < w1_end {
+            self.pos = orig_pos;
             return false;
         }
 
@@ -641,6 +689,8 @@ impl LineBuffer {
             cl.borrow_mut()
                 .replace(start, self.buf.index(range.clone()), text);
         }
+        let removed = range.len();
+        self.reserve(text.len().saturating_sub(removed));
         self.buf.drain(range);
         if start == self.buf.len() {
             self.buf.push_str(text);
@@ -656,6 +706,7 @@ impl LineBuffer {
         for cl in &self.cl {
             cl.borrow_mut().insert_str(idx, s);
         }
+        self.reserve(s.len());
         if idx == self.buf.len() {
             self.buf.push_str(s);
             true
@@ -665,6 +716,95 @@ impl LineBuffer {
         }
     }
 
+    /// Block of the lines between `mark` and the cursor, at the column of
+    /// `mark` (the lines shorter than the column are skipped).
+    pub(crate) fn block(&self, mark: usize) -> Block {
+        let starts = self.line_starts();
+        let anchor = line_index(&starts, mark);
+        let current = line_index(&starts, self.pos);
+        let column = self.buf[starts[anchor]..mark].width();
+        let lines = (cmp::min(anchor, current)..=cmp::max(anchor, current))
+            .filter(|&line| self.column_pos(starts[line], column).is_some())
+            .collect();
+        Block {
+            column,
+            lines,
+            anchor,
+            inserted: String::new(),
+        }
+    }
+
+    /// Insert `c` at the column of each line of the `block`.
+    /// Return `false` if the line is full.
+    pub(crate) fn block_insert(&mut self, block: &mut Block, c: char) -> bool {
+        if self.buf.len() + c.len_utf8() * block.lines.len() > self.buf.capacity() {
+            return false;
+        }
+        let column = block.column + block.inserted.width();
+        let starts = self.line_starts();
+        let mut bytes = [0; 4];
+        let text = c.encode_utf8(&mut bytes);
+        // from the last line: the positions of the previous ones are kept
+        for &line in block.lines.iter().rev() {
+            let pos = self.column_pos(starts[line], column).unwrap();
+            self.insert_str(pos, text);
+        }
+        block.inserted.push(c);
+        self.move_to_block(block);
+        true
+    }
+
+    /// Delete the last char inserted on each line of the `block`.
+    /// Return `false` if nothing has been inserted.
+    pub(crate) fn block_backspace(&mut self, block: &mut Block) -> bool {
+        let c = match block.inserted.pop() {
+            Some(c) => c,
+            None => return false,
+        };
+        let column = block.column + block.inserted.width();
+        let starts = self.line_starts();
+        for &line in block.lines.iter().rev() {
+            let pos = self.column_pos(starts[line], column).unwrap();
+            self.drain(pos..pos + c.len_utf8(), Direction::default());
+        }
+        self.move_to_block(block);
+        true
+    }
+
+    /// Move the cursor after the text inserted on the anchor line of the
+    /// `block`.
+    fn move_to_block(&mut self, block: &Block) {
+        let start = self.line_starts()[block.anchor];
+        let column = block.column + block.inserted.width();
+        self.pos = self.column_pos(start, column).unwrap();
+    }
+
+    /// Positions of the start of each line.
+    fn line_starts(&self) -> Vec<usize> {
+        iter::once(0)
+            .chain(self.buf.match_indices('\n').map(|(i, _)| i + 1))
+            .collect()
+    }
+
+    /// Position of the display `column` on the line starting at `start`, or
+    /// `None` if the line is shorter (or a wide char is across the column).
+    fn column_pos(&self, start: usize, column: usize) -> Option<usize> {
+        let mut width = 0;
+        for (i, c) in self.buf[start..].char_indices() {
+            if width == column {
+                return Some(start + i);
+            } else if width > column || c == '\n' {
+                return None;
+            }
+            width += c.width().unwrap_or(0);
+        }
+        if width == column {
+            Some(self.buf.len())
+        } else {
+            None
+        }
+    }
+
     /// Remove the specified `range` in the line.
     pub fn delete_range(&mut self, range: Range<usize>) {
         self.set_pos(range.start);
@@ -818,6 +958,23 @@ impl Deref for LineBuffer {
     }
 }
 
+/// Index of the line containing `pos` (given the `starts` of the lines).
+fn line_index(starts: &[usize], pos: usize) -> usize {
+    match starts.binary_search(&pos) {
+        Ok(index) => index,
+        Err(index) => index - 1,
+    }
+}
+
+/// Column of consecutive lines where the typed text is inserted on each line
+/// at once (see `Cmd::BlockInsert`).
+pub(crate) struct Block {
+    column: usize,     // Display column where the text is inserted
+    lines: Vec<usize>, // Indexes of the lines (long enough) of the block
+    anchor: usize,     // Index of the line where the cursor is kept
+    inserted: String,  // Text inserted so far on each line
+}
+
 fn is_start_of_word(word_def: Word, previous: &str, grapheme: &str) -> bool {
     (!is_word_char(word_def, previous) && is_word_char(word_def, grapheme))
         || (word_def == Word::Vi && !is_other_char(previous) && is_other_char(grapheme))
@@ -1350,15 +1507,36 @@ mod test {
 
         let mut s = LineBuffer::init("a ßETA  c", 1, None);
         assert!(s.edit_word(WordAction::CAPITALIZE));
-        assert_eq!("a SSeta  c", s.buf);
+        assert_eq!("a Sseta  c", s.buf);
         assert_eq!(7, s.pos);
 
+        let mut s = LineBuffer::init("ﬁLE ΟΔΟΣ", 0, None);
+        assert!(s.edit_word(WordAction::CAPITALIZE));
+        assert!(s.edit_word(WordAction::CAPITALIZE));
+        assert_eq!("File Οδος", s.buf);
+        assert_eq!(13, s.pos);
+
         let mut s = LineBuffer::init("test", 1, None);
         assert!(s.edit_word(WordAction::CAPITALIZE));
         assert_eq!("tEst", s.buf);
         assert_eq!(4, s.pos);
     }
 
+    #[test]
+    fn private_growth() {
+        let mut s = LineBuffer::with_capacity(4);
+        s.set_private(true);
+        assert!(s.insert_str(0, "pass"));
+        let ptr = s.buf.as_ptr();
+        // reallocated
+        assert!(s.insert_str(4, "word"));
+        assert_ne!(ptr, s.buf.as_ptr());
+        assert_eq!("password", s.buf);
+        s.replace(0..4, "secret-");
+        assert_eq!("secret-word", s.buf);
+        assert!(s.buf.capacity() >= s.buf.len());
+    }
+
     #[test]
     fn transpose_words() {
         let mut s = LineBuffer::init("ßeta / δelta__", 15, None);
@@ -1376,5 +1554,35 @@ mod test {
 
         let mut s = LineBuffer::init("ßeta / __", 9, None);
         assert!(!s.transpose_words(1));
+
+        let mut s = LineBuffer::init("ßeta", 2, None);
+        assert!(!s.transpose_words(1));
+        assert_eq!(2, s.pos);
+    }
+
+    #[test]
+    fn block() {
+        let mut s = LineBuffer::init("a = 1\nbb = 2\n\nddd = 4", 21, None);
+        // the empty line is skipped
+        let mut block = s.block(1);
+        assert!(s.block_insert(&mut block, 'x'));
+        assert!(s.block_insert(&mut block, 'é'));
+        assert_eq!("axé = 1\nbxéb = 2\n\ndxédd = 4", s.buf);
+        assert_eq!(4, s.pos);
+
+        assert!(s.block_backspace(&mut block));
+        assert_eq!("ax = 1\nbxb = 2\n\ndxdd = 4", s.buf);
+        assert_eq!(2, s.pos);
+        assert!(s.block_backspace(&mut block));
+        assert!(!s.block_backspace(&mut block));
+        assert_eq!("a = 1\nbb = 2\n\nddd = 4", s.buf);
+        assert_eq!(1, s.pos);
+
+        // the mark after the cursor
+        let mut s = LineBuffer::init("ab\ncd\nef", 1, None);
+        let mut block = s.block(4);
+        assert!(s.block_insert(&mut block, '|'));
+        assert_eq!("a|b\nc|d\nef", s.buf);
+        assert_eq!(6, s.pos);
     }
 }
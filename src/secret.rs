@@ -0,0 +1,87 @@
+//! Lines wiped from memory when dropped (see `Editor::readline_private`)
+use std::fmt;
+use std::ops::Deref;
+use std::ptr;
+use std::sync::atomic::{compiler_fence, Ordering};
+
+/// Line read by `Editor::readline_private`: its buffer is overwritten with
+/// zeros when it is dropped.
+///
+/// It derefs to `str`. Copies made from it (like with `to_owned`) are not
+/// wiped.
+pub struct PrivateLine(String);
+
+impl PrivateLine {
+    pub(crate) fn new(line: String) -> PrivateLine {
+        PrivateLine(line)
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl Deref for PrivateLine {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl AsRef<str> for PrivateLine {
+    fn as_ref(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+// the content is not displayed
+impl fmt::Debug for PrivateLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("PrivateLine(..)")
+    }
+}
+
+impl Drop for PrivateLine {
+    fn drop(&mut self) {
+        wipe(&mut self.0);
+    }
+}
+
+/// Overwrites the whole allocation of `buf` (including its spare capacity,
+/// where deleted text may remain) with zeros and empties it.
+pub(crate) fn wipe(buf: &mut String) {
+    unsafe {
+        let bytes = buf.as_mut_vec();
+        let ptr = bytes.as_mut_ptr();
+        for i in 0..bytes.capacity() {
+            // not optimized away
+            ptr::write_volatile(ptr.add(i), 0);
+        }
+        bytes.set_len(0);
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod test {
+    use super::{wipe, PrivateLine};
+
+    #[test]
+    fn wipe_capacity() {
+        let mut buf = String::with_capacity(16);
+        buf.push_str("secret");
+        buf.truncate(2);
+        wipe(&mut buf);
+        assert!(buf.is_empty());
+        assert_eq!(16, buf.capacity());
+        unsafe {
+            buf.as_mut_vec().set_len(16);
+        }
+        assert!(buf.bytes().all(|b| b == 0));
+
+        let line = PrivateLine::new("secret".to_owned());
+        assert_eq!("secret", &*line);
+        assert_eq!("PrivateLine(..)", format!("{:?}", line));
+    }
+}
@@ -1,6 +1,6 @@
 ///! Basic commands tests.
 use super::{assert_cursor, assert_line, assert_line_with_initial, init_editor};
-use config::EditMode;
+use config::{Configurer, EditMode};
 use error::ReadlineError;
 use keys::KeyPress;
 
@@ -171,6 +171,20 @@ fn newline_key() {
     }
 }
 
+#[test]
+fn keep_trailing_newline() {
+    for keep in &[true, false] {
+        let mut editor = init_editor(EditMode::Emacs, &[KeyPress::Enter]);
+        editor.set_keep_trailing_newline(*keep);
+        let line = editor.readline_with_initial(">>", ("a\n\n", "")).unwrap();
+        assert_eq!(if *keep { "a\n\n" } else { "a\n" }, line);
+        // single line
+        let mut editor = init_editor(EditMode::Emacs, &[KeyPress::Enter]);
+        editor.set_keep_trailing_newline(*keep);
+        assert_eq!("a", editor.readline_with_initial(">>", ("a", "")).unwrap());
+    }
+}
+
 #[test]
 fn eof_key() {
     for mode in &[EditMode::Emacs, EditMode::Vi] {
@@ -208,6 +222,38 @@ fn eof_key() {
     );
 }
 
+#[test]
+fn ignore_eof_nonempty() {
+    for mode in &[EditMode::Emacs, EditMode::Vi] {
+        let keys = [
+            KeyPress::Char('a'),
+            KeyPress::Left,
+            KeyPress::Ctrl('D'),
+            KeyPress::Char('b'),
+            KeyPress::Enter,
+        ];
+        let mut editor = init_editor(*mode, &keys);
+        editor.set_ignore_eof_nonempty(true);
+        assert_eq!("ba", editor.readline(">>").unwrap());
+        // still the end of the input on an empty line
+        let mut editor = init_editor(*mode, &[KeyPress::Ctrl('D')]);
+        editor.set_ignore_eof_nonempty(true);
+        assert_matches!(editor.readline(">>"), Err(ReadlineError::Eof));
+    }
+}
+
+#[test]
+fn eof_line() {
+    // the input is closed
+    let mut editor = init_editor(EditMode::Emacs, &[KeyPress::Char('a')]);
+    assert_matches!(editor.readline(">>"), Err(ReadlineError::Eof));
+    assert_eq!(Some("a".to_owned()), editor.take_eof_line());
+    assert_eq!(None, editor.take_eof_line());
+    let mut editor = init_editor(EditMode::Emacs, &[KeyPress::Ctrl('D')]);
+    assert_matches!(editor.readline(">>"), Err(ReadlineError::Eof));
+    assert_eq!(None, editor.take_eof_line());
+}
+
 #[test]
 fn interrupt_key() {
     for mode in &[EditMode::Emacs, EditMode::Vi] {
@@ -364,7 +410,7 @@ fn ctrl_w() {
 
 #[test]
 fn ctrl_y() {
-    for mode in &[EditMode::Emacs /* FIXME, EditMode::Vi */] {
+    for mode in &[EditMode::Emacs, EditMode::Vi] {
         assert_cursor(
             *mode,
             ("Hello, ", "world"),
@@ -374,6 +420,60 @@ fn ctrl_y() {
     }
 }
 
+#[test]
+fn bracketed_paste() {
+    for mode in &[EditMode::Emacs, EditMode::Vi] {
+        // the newlines are inserted, not accepted
+        assert_line_with_initial(
+            *mode,
+            ("a", "z"),
+            &[
+                KeyPress::BracketedPasteStart,
+                KeyPress::Char('('),
+                KeyPress::Enter,
+                KeyPress::Tab,
+                KeyPress::Char(')'),
+                KeyPress::BracketedPasteEnd,
+                KeyPress::Char('!'), // just to assert cursor pos
+                KeyPress::Enter,
+            ],
+            "a(\n\t)!z",
+        );
+    }
+}
+
+#[test]
+fn initial_selection() {
+    for mode in &[EditMode::Emacs, EditMode::Vi] {
+        for &(ref keys, expected) in &[
+            (vec![KeyPress::Char('n'), KeyPress::Char('e'), KeyPress::Char('w')], "new"),
+            (vec![KeyPress::Backspace], ""),
+            (vec![KeyPress::Backspace, KeyPress::Backspace], ""),
+            // deselected by a move
+            (vec![KeyPress::Left, KeyPress::Char('x')], "olxd"),
+            (
+                vec![
+                    KeyPress::BracketedPasteStart,
+                    KeyPress::Char('p'),
+                    KeyPress::BracketedPasteEnd,
+                ],
+                "p",
+            ),
+            // restored by undo
+            (
+                vec![KeyPress::Char('x'), KeyPress::Ctrl('_'), KeyPress::Ctrl('_')],
+                "old",
+            ),
+        ] {
+            let mut keys = keys.clone();
+            keys.push(KeyPress::Enter);
+            let mut editor = init_editor(*mode, &keys);
+            let line = editor.readline_with_initial_selected("", "old").unwrap();
+            assert_eq!(expected, line, "{:?}", keys);
+        }
+    }
+}
+
 #[test]
 fn ctrl__() {
     for mode in &[EditMode::Emacs, EditMode::Vi] {
@@ -399,3 +499,57 @@ fn ctrl__() {
         }
     }
 }
+
+#[test]
+#[cfg(unix)]
+fn external_editor() {
+    extern crate tempdir;
+    use std::fs;
+    use std::path::Path;
+
+    let td = tempdir::TempDir::new("rustyline").unwrap();
+    let record = td.path().join("record");
+    let script = td.path().join("editor.sh");
+    // records the mode and the path of the edited file
+    let commands = [
+        format!("ls -l \"$1\" | cut -c1-10 > {}", record.display()),
+        format!("echo \"$1\" >> {}", record.display()),
+        "grep -q fail \"$1\" && exit 1".to_owned(),
+        "echo \"$(cat \"$1\") edited\" > \"$1\"".to_owned(),
+    ];
+    fs::write(&script, commands.join("\n")).unwrap();
+    let command = format!("sh {}", script.display());
+    // also bound in vi insert mode
+    let inputrc = td.path().join("inputrc");
+    fs::write(&inputrc, "\"\\C-x\\C-e\": edit-and-execute-command\n").unwrap();
+    let edit = |mode, initial, keys: &[KeyPress]| {
+        let mut editor = init_editor(mode, keys);
+        editor.load_config_file(&inputrc).unwrap();
+        editor.set_external_editor(Some(command.clone()));
+        editor.readline_with_initial(">>", initial).unwrap()
+    };
+
+    for &(mode, ref keys) in &[
+        (EditMode::Emacs, vec![KeyPress::Ctrl('X'), KeyPress::Ctrl('E')]),
+        (EditMode::Vi, vec![KeyPress::Esc, KeyPress::Char('v')]),
+        (EditMode::Vi, vec![KeyPress::Ctrl('X'), KeyPress::Ctrl('E')]),
+    ] {
+        let mut keys = keys.clone();
+        keys.push(KeyPress::Enter);
+        assert_eq!("ls edited", edit(mode, ("ls", ""), &keys));
+        let record = fs::read_to_string(&record).unwrap();
+        let mut lines = record.lines();
+        assert_eq!(Some("-rw-------"), lines.next());
+        // removed
+        assert!(!Path::new(lines.next().unwrap()).exists());
+    }
+    // non-zero exit status
+    assert_eq!(
+        "fail",
+        edit(
+            EditMode::Emacs,
+            ("fail", ""),
+            &[KeyPress::Ctrl('X'), KeyPress::Ctrl('E'), KeyPress::Enter],
+        )
+    );
+}
@@ -0,0 +1,908 @@
+//! Completion tests
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::future::{self, Future};
+use std::ops::Range;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, RwLock};
+use std::task::{Context, Poll};
+use std::thread;
+use std::time::Duration;
+
+use super::TestHelper;
+use completion::{Candidate, Completer, Completion};
+use config::{CompletionSort, CompletionType, Config, Configurer};
+use edit::init_state;
+use keymap::{Cmd, InputState};
+use keys::KeyPress;
+use tty::Sink;
+use {Editor, Result};
+
+struct SimpleCompleter;
+impl Completer for SimpleCompleter {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, _pos: usize) -> Result<(usize, Vec<String>)> {
+        Ok((0, vec![line.to_owned() + "t"]))
+    }
+}
+
+#[test]
+fn complete_line() {
+    for completion_type in &[CompletionType::Circular, CompletionType::List] {
+        let config = Config::builder()
+            .completion_type(*completion_type)
+            .build();
+        let mut out = Sink::new();
+        let mut s = init_state(&mut out, "rus", 3);
+        let mut input_state = InputState::new(&config, Arc::new(RwLock::new(HashMap::new())));
+        // the unique candidate is inserted without waiting for a key
+        let keys: &[KeyPress] = &[];
+        let mut rdr = keys.iter();
+        let completer = SimpleCompleter;
+        let cmd = ::complete_line(
+            &mut rdr,
+            &mut s,
+            &mut input_state,
+            &completer,
+            None,
+            None,
+            &config,
+        ).unwrap();
+        assert_eq!(None, cmd);
+        assert_eq!("rust", s.line.as_str());
+        assert_eq!(4, s.line.pos());
+    }
+}
+
+// returns all the words, whatever the typed one
+struct WordsCompleter;
+impl Completer for WordsCompleter {
+    type Candidate = String;
+
+    fn complete(&self, _line: &str, _pos: usize) -> Result<(usize, Vec<String>)> {
+        let words = ["README", "Cargo.toml", "readme.txt", "kilo", "kiwi"];
+        Ok((0, words.iter().map(|w| w.to_string()).collect()))
+    }
+}
+
+#[test]
+fn complete_ignore_case() {
+    let config = Config::builder()
+        .completion_type(CompletionType::List)
+        .completion_ignore_case(true)
+        .build();
+    for &(line, expected) in &[
+        // `Cargo.toml` is filtered out
+        ("read", "README"),
+        // the Kelvin sign is longer than `k` but still one char
+        ("\u{212A}", "ki"),
+    ] {
+        let mut out = Sink::new();
+        let mut s = init_state(&mut out, line, line.len());
+        let mut input_state = InputState::new(&config, Arc::new(RwLock::new(HashMap::new())));
+        // the second Tab is not pressed
+        let keys = &[KeyPress::Enter];
+        let mut rdr = keys.iter();
+        ::complete_line(
+            &mut rdr,
+            &mut s,
+            &mut input_state,
+            &WordsCompleter,
+            None,
+            None,
+            &config,
+        ).unwrap();
+        assert_eq!(expected, s.line.as_str());
+    }
+}
+
+struct MultiCompleter;
+impl Completer for MultiCompleter {
+    type Candidate = String;
+
+    fn complete(&self, _line: &str, _pos: usize) -> Result<(usize, Vec<String>)> {
+        Ok((0, vec!["rustc".to_owned(), "rustup".to_owned()]))
+    }
+}
+
+#[test]
+fn insert_completion() {
+    let mut bindings = HashMap::new();
+    bindings.insert(KeyPress::F(1), Cmd::InsertCompletion(1));
+    bindings.insert(KeyPress::F(2), Cmd::InsertCompletion(2));
+    let bindings = Arc::new(RwLock::new(bindings));
+    for completion_type in &[CompletionType::Circular, CompletionType::List] {
+        let config = Config::builder()
+            .completion_type(*completion_type)
+            .build();
+        // out of bounds is a no-op
+        let keys = &[KeyPress::F(2), KeyPress::F(1)];
+        let mut out = Sink::new();
+        let mut s = init_state(&mut out, "ru", 2);
+        let mut input_state = InputState::new(&config, bindings.clone());
+        let mut rdr = keys.iter();
+        let cmd = ::complete_line(
+            &mut rdr,
+            &mut s,
+            &mut input_state,
+            &MultiCompleter,
+            None,
+            None,
+            &config,
+        ).unwrap();
+        assert_eq!(None, cmd);
+        assert_eq!("rustup", s.line.as_str());
+        assert_eq!(6, s.line.pos());
+    }
+}
+
+#[test]
+fn complete_menu() {
+    let config = Config::builder()
+        .completion_type(CompletionType::Menu)
+        .build();
+    for &(ref keys, line, ref cmd) in &[
+        (vec![KeyPress::Enter], "rustc", None),
+        (vec![KeyPress::Down, KeyPress::Enter], "rustup", None),
+        (vec![KeyPress::Right, KeyPress::Enter], "rustup", None),
+        (vec![KeyPress::Right, KeyPress::Left, KeyPress::Up, KeyPress::Enter], "rustc", None),
+        (vec![KeyPress::Tab, KeyPress::Tab, KeyPress::Enter], "rustc", None),
+        (vec![KeyPress::Esc], "ru", None),
+        (vec![KeyPress::Char('x')], "ru", Some(Cmd::SelfInsert(1, 'x'))),
+    ] {
+        let mut out = Sink::new();
+        let mut s = init_state(&mut out, "ru", 2);
+        let mut input_state = InputState::new(&config, Arc::new(RwLock::new(HashMap::new())));
+        let mut rdr = keys.iter();
+        let menu = Cell::new((0, 0)); // (opened, closed)
+        let on_menu = |open: bool| {
+            let (opened, closed) = menu.get();
+            menu.set(if open {
+                (opened + 1, closed)
+            } else {
+                (opened, closed + 1)
+            });
+        };
+        let res = ::complete_line(
+            &mut rdr,
+            &mut s,
+            &mut input_state,
+            &MultiCompleter,
+            None,
+            Some(&on_menu),
+            &config,
+        ).unwrap();
+        assert_eq!(*cmd, res);
+        assert_eq!(line, s.line.as_str());
+        // the menu is erased
+        assert_eq!(0, s.old_rows);
+        assert_eq!((1, 1), menu.get());
+    }
+}
+
+struct DocumentedCandidate(&'static str, &'static str);
+impl Candidate for DocumentedCandidate {
+    fn display(&self) -> &str {
+        self.0
+    }
+
+    fn replacement(&self) -> &str {
+        self.0
+    }
+
+    fn documentation(&self) -> Option<&str> {
+        Some(self.1)
+    }
+}
+
+struct DocumentedCompleter;
+impl Completer for DocumentedCompleter {
+    type Candidate = DocumentedCandidate;
+
+    fn complete(&self, _line: &str, _pos: usize) -> Result<(usize, Vec<DocumentedCandidate>)> {
+        Ok((
+            0,
+            vec![
+                DocumentedCandidate("rustc", "The compiler"),
+                DocumentedCandidate("rustup", "The toolchain installer\nand updater"),
+            ],
+        ))
+    }
+}
+
+#[test]
+fn complete_menu_docs() {
+    let config = Config::builder()
+        .completion_type(CompletionType::Menu)
+        .completion_docs_height(1)
+        .build();
+    let keys = &[KeyPress::Down, KeyPress::Esc];
+    let mut out = Sink::new();
+    {
+        let mut s = init_state(&mut out, "ru", 2);
+        let mut input_state = InputState::new(&config, Arc::new(RwLock::new(HashMap::new())));
+        let mut rdr = keys.iter();
+        let cmd = ::complete_line(
+            &mut rdr,
+            &mut s,
+            &mut input_state,
+            &DocumentedCompleter,
+            None,
+            None,
+            &config,
+        ).unwrap();
+        assert_eq!(None, cmd);
+        assert_eq!("ru", s.line.as_str());
+        // the menu and the documentation are erased
+        assert_eq!(0, s.old_rows);
+    }
+    // below the menu, updated with the selection (one row)
+    let docs: Vec<&str> = out
+        .written
+        .iter()
+        .filter(|w| !w.contains("rustc"))
+        .map(String::as_str)
+        .collect();
+    assert_eq!(vec!["\nThe compiler", "\nThe toolchain installer"], docs);
+}
+
+// replaces the whole word around the cursor
+struct WordCompleter;
+impl Completer for WordCompleter {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize) -> Result<(usize, Vec<String>)> {
+        let (range, candidates) = try!(self.complete_range(line, pos));
+        Ok((range.start, candidates))
+    }
+
+    fn complete_range(
+        &self,
+        line: &str,
+        pos: usize,
+    ) -> Result<(Range<usize>, Vec<String>)> {
+        let start = line[..pos].rfind(' ').map_or(0, |i| i + 1);
+        let end = line[pos..].find(' ').map_or(line.len(), |i| pos + i);
+        Ok((start..end, vec!["rustc".to_owned(), "rustup".to_owned()]))
+    }
+}
+
+#[test]
+fn complete_range() {
+    let mut bindings = HashMap::new();
+    bindings.insert(KeyPress::F(1), Cmd::InsertCompletion(1));
+    let bindings = Arc::new(RwLock::new(bindings));
+    for &(completion_type, ref keys, line) in &[
+        (
+            CompletionType::Circular,
+            vec![KeyPress::Tab, KeyPress::Enter],
+            "ls rustup x",
+        ),
+        // back to the original line and again
+        (
+            CompletionType::Circular,
+            vec![KeyPress::Tab, KeyPress::Tab, KeyPress::Tab, KeyPress::Enter],
+            "ls rustc x",
+        ),
+        (
+            CompletionType::Circular,
+            vec![KeyPress::Tab, KeyPress::Tab, KeyPress::F(1)],
+            "ls rustup x",
+        ),
+        // the longest common prefix replaces the whole word
+        (CompletionType::List, vec![KeyPress::Esc], "ls rust x"),
+        // after the longest common prefix
+        (CompletionType::List, vec![KeyPress::F(1)], "ls rustup x"),
+        (
+            CompletionType::Menu,
+            vec![KeyPress::Down, KeyPress::Enter],
+            "ls rustup x",
+        ),
+    ] {
+        let config = Config::builder().completion_type(completion_type).build();
+        let mut out = Sink::new();
+        // cursor in the middle of `rust`
+        let mut s = init_state(&mut out, "ls rust x", 5);
+        let mut input_state = InputState::new(&config, bindings.clone());
+        let mut rdr = keys.iter();
+        ::complete_line(
+            &mut rdr,
+            &mut s,
+            &mut input_state,
+            &WordCompleter,
+            None,
+            None,
+            &config,
+        ).unwrap();
+        assert_eq!(line, s.line.as_str());
+        assert_eq!(line.len() - 2, s.line.pos());
+    }
+}
+
+struct KeywordCompleter;
+impl Completer for KeywordCompleter {
+    type Candidate = String;
+
+    // not filtered
+    fn complete(&self, line: &str, pos: usize) -> Result<(usize, Vec<String>)> {
+        let start = line[..pos].rfind(' ').map_or(0, |i| i + 1);
+        let keywords = ["DELETE", "SELECT_LIST", "SELECT"];
+        Ok((start, keywords.iter().map(|&k| k.to_owned()).collect()))
+    }
+}
+
+#[test]
+fn fuzzy_completion() {
+    let config = Config::builder().fuzzy_completion(true).build();
+    for &(ref keys, line) in &[
+        // best match first: `l` at a word boundary
+        (vec![KeyPress::Enter], "x SELECT_LIST"),
+        (vec![KeyPress::Tab, KeyPress::Enter], "x SELECT"),
+        // back to the original line
+        (vec![KeyPress::Tab, KeyPress::Tab, KeyPress::Enter], "x slt"),
+    ] {
+        let mut out = Sink::new();
+        let mut s = init_state(&mut out, "x slt", 5);
+        let mut input_state = InputState::new(&config, Arc::new(RwLock::new(HashMap::new())));
+        let mut rdr = keys.iter();
+        let cmd = ::complete_line(
+            &mut rdr,
+            &mut s,
+            &mut input_state,
+            &KeywordCompleter,
+            None,
+            None,
+            &config,
+        ).unwrap();
+        assert_eq!(Some(Cmd::AcceptLine), cmd);
+        assert_eq!(line, s.line.as_str());
+    }
+}
+
+struct SqlCompleter;
+impl Completer for SqlCompleter {
+    type Candidate = String;
+
+    fn complete(&self, _line: &str, _pos: usize) -> Result<(usize, Vec<String>)> {
+        unreachable!()
+    }
+
+    // the keywords are uppercased
+    fn complete_advanced(&self, line: &str, pos: usize) -> Result<Completion<String>> {
+        let start = line[..pos].rfind(' ').map_or(0, |i| i + 1);
+        let keywords: &[&str] = match &line[start..pos] {
+            "f" => &["FROM"],
+            "w" => &["WHERE", "WITH"],
+            _ => &[],
+        };
+        Ok(Completion {
+            start,
+            end: pos,
+            candidates: keywords.iter().map(|&k| k.to_owned()).collect(),
+            replace_whole_line: Some(line[..pos].to_uppercase()),
+        })
+    }
+}
+
+#[test]
+fn complete_advanced() {
+    for &(completion_type, line, ref keys, expected) in &[
+        (CompletionType::Circular, "select * f", vec![], "SELECT * FROM"),
+        // no candidate
+        (CompletionType::Circular, "select x", vec![], "SELECT X"),
+        (CompletionType::Circular, "select * from t w", vec![KeyPress::Esc], "select * from t w"),
+        (CompletionType::Menu, "select * from t w", vec![KeyPress::Enter], "SELECT * FROM T WHERE"),
+        // common prefix
+        (CompletionType::List, "select * from t w", vec![KeyPress::Esc], "SELECT * FROM T W"),
+    ] {
+        let config = Config::builder().completion_type(completion_type).build();
+        let mut out = Sink::new();
+        let mut s = init_state(&mut out, line, line.len());
+        let mut input_state = InputState::new(&config, Arc::new(RwLock::new(HashMap::new())));
+        let mut rdr = keys.iter();
+        ::complete_line(
+            &mut rdr,
+            &mut s,
+            &mut input_state,
+            &SqlCompleter,
+            None,
+            None,
+            &config,
+        ).unwrap();
+        assert_eq!(expected, s.line.as_str());
+        assert_eq!(s.line.len(), s.line.pos());
+    }
+}
+
+#[test]
+fn completion_page_size() {
+    // one candidate per row
+    let candidates: Vec<String> = (0..60).map(|i| format!("{:>40}", i)).collect();
+    let config = Config::default();
+    for &(page_size, left) in &[
+        // pauses after rows 10, 20, 30, 40 and 50: cancelled
+        (10, 1),
+        // one screen: pauses after rows 23 and 46
+        (0, 4),
+        (30, 4),
+    ] {
+        let keys = &[
+            KeyPress::Char(' '),
+            KeyPress::Char(' '),
+            KeyPress::Char(' '),
+            KeyPress::Char(' '),
+            KeyPress::Char('q'),
+            KeyPress::Char('x'),
+        ];
+        let mut out = Sink::new();
+        let mut s = init_state(&mut out, "", 0);
+        let mut input_state = InputState::new(&config, Arc::new(RwLock::new(HashMap::new())));
+        let mut rdr = keys.iter();
+        let cmd = ::page_completions(
+            &mut rdr,
+            &mut s,
+            &mut input_state,
+            None,
+            CompletionType::List,
+            page_size,
+            &candidates,
+        ).unwrap();
+        assert_eq!(None, cmd);
+        assert_eq!(left, rdr.len(), "{}", page_size);
+    }
+}
+
+struct DescribedCandidate(&'static str, &'static str);
+impl Candidate for DescribedCandidate {
+    fn display(&self) -> &str {
+        self.0
+    }
+
+    fn replacement(&self) -> &str {
+        self.0
+    }
+
+    fn description(&self) -> Option<&str> {
+        Some(self.1)
+    }
+}
+
+#[test]
+fn list_with_description() {
+    let candidate = DescribedCandidate("len", "Returns the length\nin bytes");
+    let mut ab = "len".to_owned();
+    ::push_candidate_description(&mut ab, &candidate, 3, 8, 80);
+    // first line only
+    assert_eq!("len     Returns the length", ab);
+    ab = "len".to_owned();
+    ::push_candidate_description(&mut ab, &candidate, 3, 8, 20);
+    // truncated (the last column is left empty)
+    assert_eq!("len     Returns th…", ab);
+
+    // short candidates listed one per row: paged like a long list
+    let candidates: Vec<DescribedCandidate> =
+        (0..30).map(|_| DescribedCandidate("x", "")).collect();
+    let config = Config::default();
+    for &(completion, left) in &[
+        (CompletionType::List, 2),
+        // pauses after row 23
+        (CompletionType::ListWithDescription, 1),
+    ] {
+        let keys = &[KeyPress::Char('q'), KeyPress::Char('x')];
+        let mut out = Sink::new();
+        let mut s = init_state(&mut out, "", 0);
+        let mut input_state = InputState::new(&config, Arc::new(RwLock::new(HashMap::new())));
+        let mut rdr = keys.iter();
+        let cmd = ::page_completions(
+            &mut rdr,
+            &mut s,
+            &mut input_state,
+            None,
+            completion,
+            0,
+            &candidates,
+        ).unwrap();
+        assert_eq!(None, cmd);
+        assert_eq!(left, rdr.len());
+    }
+}
+
+struct KindCandidate(&'static str, Option<&'static str>);
+impl Candidate for KindCandidate {
+    fn display(&self) -> &str {
+        self.0
+    }
+
+    fn replacement(&self) -> &str {
+        self.0
+    }
+
+    fn kind(&self) -> Option<&str> {
+        self.1
+    }
+}
+
+#[test]
+fn candidate_kind() {
+    let candidates = [
+        KindCandidate("len", Some("fn")),
+        KindCandidate("length", None),
+        KindCandidate("l", Some("var")),
+    ];
+    // the kinds are aligned after the longest display
+    assert_eq!((6, 4), ::candidate_columns(&candidates));
+    assert_eq!((6, 0), ::candidate_columns(&candidates[1..2]));
+
+    let mut ab = String::new();
+    let width = ::push_candidate_kind(&mut ab, &candidates[0], None, CompletionType::List, 6);
+    assert_eq!("    fn", ab);
+    assert_eq!(9, width);
+    ab.clear();
+    let width = ::push_candidate_kind(&mut ab, &candidates[1], None, CompletionType::List, 6);
+    assert_eq!("", ab);
+    assert_eq!(6, width);
+    // dim by default
+    let width = ::push_candidate_kind(
+        &mut ab,
+        &candidates[2],
+        Some(&()),
+        CompletionType::Menu,
+        6,
+    );
+    assert_eq!("      \x1b[2mvar\x1b[22m", ab);
+    assert_eq!(10, width);
+}
+
+// sorts the candidates by length if `true`
+struct KindCompleter(bool);
+impl Completer for KindCompleter {
+    type Candidate = KindCandidate;
+
+    fn complete(&self, _line: &str, _pos: usize) -> Result<(usize, Vec<KindCandidate>)> {
+        Ok((0, Vec::new()))
+    }
+
+    fn sort(&self, candidates: &mut [KindCandidate]) {
+        if self.0 {
+            candidates.sort_by_key(|c| c.0.len());
+        }
+    }
+}
+
+#[test]
+fn completion_sort() {
+    for &(sort, by_length, expected) in &[
+        (CompletionSort::Unsorted, false, ["len", "abs", "all", "zz", "any"]),
+        (CompletionSort::Alphabetical, false, ["abs", "all", "any", "len", "zz"]),
+        (CompletionSort::Kind, false, ["len", "any", "abs", "all", "zz"]),
+        // ties kept in alphabetical order
+        (CompletionSort::Alphabetical, true, ["zz", "abs", "all", "any", "len"]),
+    ] {
+        let mut candidates = vec![
+            KindCandidate("len", Some("fn")),
+            KindCandidate("abs", Some("var")),
+            KindCandidate("all", None),
+            KindCandidate("zz", None),
+            KindCandidate("any", Some("fn")),
+        ];
+        ::sort_candidates(&KindCompleter(by_length), sort, &mut candidates);
+        let actual: Vec<&str> = candidates.iter().map(|c| c.0).collect();
+        assert_eq!(expected, &actual[..]);
+    }
+}
+
+#[derive(Default)]
+struct SessionCompleter {
+    begin: Cell<usize>,
+    end: Cell<usize>,
+}
+impl Completer for SessionCompleter {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, _pos: usize) -> Result<(usize, Vec<String>)> {
+        assert_eq!(self.begin.get(), self.end.get() + 1);
+        Ok((0, vec![line.to_owned() + "t"]))
+    }
+
+    fn begin_completion(&self) {
+        self.begin.set(self.begin.get() + 1);
+    }
+
+    fn end_completion(&self) {
+        self.end.set(self.end.get() + 1);
+    }
+}
+
+#[test]
+fn complete_line_session() {
+    let config = Config::builder()
+        .completion_type(CompletionType::Circular)
+        .build();
+    for keys in &[
+        [KeyPress::Enter, KeyPress::Enter],
+        [KeyPress::Esc, KeyPress::Enter],
+    ] {
+        let mut out = Sink::new();
+        let mut s = init_state(&mut out, "rus", 3);
+        let mut input_state = InputState::new(&config, Arc::new(RwLock::new(HashMap::new())));
+        let mut rdr = keys.iter();
+        let completer = SessionCompleter::default();
+        ::complete_line(&mut rdr, &mut s, &mut input_state, &completer, None, None, &config)
+            .unwrap();
+        assert_eq!(1, completer.begin.get());
+        assert_eq!(1, completer.end.get());
+    }
+}
+
+struct SlowCompleter;
+impl Completer for SlowCompleter {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, _pos: usize) -> Result<(usize, Vec<String>)> {
+        // always longer than a zero timeout
+        thread::sleep(Duration::from_millis(1));
+        Ok((0, vec![line.to_owned() + "t"]))
+    }
+}
+
+#[test]
+fn complete_line_timeout() {
+    for &(timeout, expected) in &[(0, "rus"), (3600, "rust")] {
+        let config = Config::builder()
+            .completion_timeout(Duration::from_secs(timeout))
+            .build();
+        let mut out = Sink::new();
+        let mut s = init_state(&mut out, "rus", 3);
+        let mut input_state = InputState::new(&config, Arc::new(RwLock::new(HashMap::new())));
+        let keys = &[KeyPress::Enter];
+        let mut rdr = keys.iter();
+        let cmd = ::complete_line(
+            &mut rdr,
+            &mut s,
+            &mut input_state,
+            &SlowCompleter,
+            None,
+            None,
+            &config,
+        ).unwrap();
+        assert_eq!(expected, s.line.as_str());
+        if expected == "rus" {
+            // no candidate: the key is not read
+            assert_eq!(None, cmd);
+        }
+    }
+}
+
+// hangs longer than the spinner delay, with the spinner frames
+struct HungCompleter(Arc<Mutex<Vec<u8>>>);
+impl Completer for HungCompleter {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, _pos: usize) -> Result<(usize, Vec<String>)> {
+        thread::sleep(Duration::from_millis(250));
+        // the test, the renderer, the completer and a single spinner
+        assert_eq!(4, Arc::strong_count(&self.0));
+        Ok((0, vec![line.to_owned() + "t"]))
+    }
+}
+
+#[test]
+fn complete_line_spinner() {
+    let frames = Arc::new(Mutex::new(Vec::new()));
+    let config = Config::builder()
+        .completion_timeout(Duration::from_millis(100))
+        .build();
+    let mut out = Sink::new();
+    out.spinner = Some(Arc::clone(&frames));
+    let completer = HungCompleter(Arc::clone(&frames));
+    let keys: &[KeyPress] = &[];
+    // Tab pressed again after each timeout
+    for _ in 0..3 {
+        let mut s = init_state(&mut out, "rus", 3);
+        let mut input_state = InputState::new(&config, Arc::new(RwLock::new(HashMap::new())));
+        let mut rdr = keys.iter();
+        let cmd = ::complete_line(
+            &mut rdr,
+            &mut s,
+            &mut input_state,
+            &completer,
+            None,
+            None,
+            &config,
+        ).unwrap();
+        assert_eq!(None, cmd);
+        assert_eq!("rus", s.line.as_str());
+        // the spinner has stopped
+        assert_eq!(3, Arc::strong_count(&frames));
+    }
+    assert!(frames.lock().unwrap().starts_with(b"|\x08"));
+}
+
+#[test]
+fn complete_line_descend() {
+    extern crate tempdir;
+    use std::fs;
+    use std::path::MAIN_SEPARATOR;
+
+    use completion::FilenameCompleter;
+
+    let td = tempdir::TempDir::new("rustyline").unwrap();
+    let dir2 = td.path().join("dir1").join("dir2");
+    fs::create_dir_all(&dir2).unwrap();
+    fs::File::create(dir2.join("file")).unwrap();
+    let root = format!("{}{}", td.path().to_str().unwrap(), MAIN_SEPARATOR);
+    let config = Config::builder()
+        .completion_type(CompletionType::Circular)
+        .build();
+    let mut out = Sink::new();
+    let mut s = init_state(&mut out, &root, root.len());
+    let mut input_state = InputState::new(&config, Arc::new(RwLock::new(HashMap::new())));
+    let keys = &[KeyPress::Tab, KeyPress::Tab];
+    let mut rdr = keys.iter();
+    let completer = FilenameCompleter::new();
+    let cmd = ::complete_line(
+        &mut rdr,
+        &mut s,
+        &mut input_state,
+        &completer,
+        None,
+        None,
+        &config,
+    ).unwrap();
+    // the unique file is inserted directly
+    assert_eq!(None, cmd);
+    let expected = format!("{}dir1{}dir2{}file", root, MAIN_SEPARATOR, MAIN_SEPARATOR);
+    assert_eq!(expected, s.line.as_str());
+    td.close().unwrap();
+}
+
+struct ModuleCompleter;
+impl Completer for ModuleCompleter {
+    type Candidate = String;
+
+    fn complete(&self, _line: &str, _pos: usize) -> Result<(usize, Vec<String>)> {
+        Ok((0, vec!["std::".to_owned(), "core::".to_owned()]))
+    }
+
+    fn refine(&self, selected: &String) -> Vec<String> {
+        match selected.as_str() {
+            "std::" => vec!["std::io".to_owned(), "std::fs".to_owned()],
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[test]
+fn complete_refine() {
+    for &(completion_type, ref keys, expected, ref expected_cmd) in &[
+        // Enter on a leaf
+        (
+            CompletionType::Circular,
+            vec![KeyPress::Enter, KeyPress::Tab, KeyPress::Enter],
+            "std::fs",
+            Some(Cmd::AcceptLine),
+        ),
+        (
+            CompletionType::Circular,
+            vec![KeyPress::Enter, KeyPress::Esc],
+            "s",
+            None,
+        ),
+        (
+            CompletionType::Menu,
+            vec![KeyPress::Enter, KeyPress::Down, KeyPress::Enter],
+            "std::fs",
+            None,
+        ),
+    ] {
+        let config = Config::builder().completion_type(completion_type).build();
+        let mut out = Sink::new();
+        let mut s = init_state(&mut out, "s", 1);
+        let mut input_state = InputState::new(&config, Arc::new(RwLock::new(HashMap::new())));
+        let mut rdr = keys.iter();
+        let cmd = ::complete_line(
+            &mut rdr,
+            &mut s,
+            &mut input_state,
+            &ModuleCompleter,
+            None,
+            None,
+            &config,
+        ).unwrap();
+        assert_eq!(expected, s.line.as_str());
+        assert_eq!(*expected_cmd, cmd);
+    }
+}
+
+#[test]
+fn completion_triggers() {
+    let calls = Cell::new(0);
+    let helper = TestHelper::default().with_complete(|line, pos| {
+        calls.set(calls.get() + 1);
+        if line[..pos].ends_with("foo.") {
+            (pos, vec!["bar".to_owned()])
+        } else {
+            (pos, vec![])
+        }
+    });
+    let mut editor = Editor::new();
+    editor.set_helper(Some(helper));
+    editor.set_completion_triggers(".");
+    editor.term.keys.extend(&[
+        KeyPress::Char('f'),
+        KeyPress::Char('o'),
+        KeyPress::Char('o'),
+        KeyPress::Char('.'),
+        KeyPress::Enter,
+    ]);
+    assert_eq!("foo.bar", editor.readline("").unwrap());
+    assert_eq!(1, calls.get());
+
+    // no trigger by default
+    let config = Config::default();
+    assert!(!config.is_completion_trigger('.'));
+}
+
+#[test]
+fn async_completer() {
+    // candidates computed or loading forever
+    for &(ready, ref keys, expected) in &[
+        (
+            true,
+            vec![KeyPress::Char('h'), KeyPress::Tab, KeyPress::Enter],
+            "hello",
+        ),
+        // cancelled by the next key
+        (
+            false,
+            vec![
+                KeyPress::Char('h'),
+                KeyPress::Tab,
+                KeyPress::Char('i'),
+                KeyPress::Enter,
+            ],
+            "hi",
+        ),
+    ] {
+        let helper = TestHelper::default().with_complete_async(|line, pos| {
+            if ready {
+                let candidate = format!("{}ello", &line[..pos]);
+                Box::pin(future::ready(Ok((0, vec![candidate]))))
+            } else {
+                Box::pin(future::pending())
+            }
+        });
+        let mut editor = Editor::new();
+        editor.set_helper(Some(helper));
+        editor.term.keys.extend(keys.iter().cloned());
+        assert_eq!(expected, editor.readline("").unwrap());
+    }
+}
+
+/// Candidates computed by another thread, which wakes the editing thread up.
+struct Spawned(Option<thread::JoinHandle<()>>);
+
+impl Future for Spawned {
+    type Output = Result<(usize, Vec<String>)>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if let Some(thread) = self.0.take() {
+            thread.join().unwrap();
+            return Poll::Ready(Ok((0, vec!["hello".to_owned()])));
+        }
+        let waker = cx.waker().clone();
+        self.0 = Some(thread::spawn(move || waker.wake()));
+        Poll::Pending
+    }
+}
+
+#[test]
+fn complete_async_woken() {
+    let mut out = Sink::new();
+    let mut s = init_state(&mut out, "h", 1);
+    let keys: &[KeyPress] = &[];
+    let mut rdr = keys.iter();
+    let completion = ::complete_async(&mut rdr, &mut s, Box::pin(Spawned(None))).unwrap();
+    assert_eq!(Some((0, vec!["hello".to_owned()])), completion);
+}
@@ -0,0 +1,112 @@
+//! Editor settings and non-blocking reads tests
+use std::time::Duration;
+
+use super::init_editor;
+use config::{ColorDepth, Configurer, EditMode};
+use keys::KeyPress;
+
+#[test]
+fn color_depth() {
+    let mut editor = init_editor(EditMode::Emacs, &[]);
+    // detected
+    assert_eq!(ColorDepth::TrueColor, editor.color_depth());
+    editor.set_color_depth(Some(ColorDepth::Ansi16));
+    assert_eq!(ColorDepth::Ansi16, editor.color_depth());
+    editor.set_color_depth(None);
+    assert_eq!(ColorDepth::TrueColor, editor.color_depth());
+}
+
+#[test]
+fn bracketed_paste() {
+    let mut editor = init_editor(EditMode::Emacs, &[]);
+    assert!(editor.config.bracketed_paste());
+    assert!(editor.term.bracketed_paste);
+    editor.set_bracketed_paste(false);
+    assert!(!editor.config.bracketed_paste());
+    // applied the next time the terminal is put in raw mode
+    assert!(!editor.term.bracketed_paste);
+}
+
+#[test]
+fn update_title() {
+    use title;
+    use config::TitleTemplate;
+
+    assert_eq!(Some("app".to_owned()), title(TitleTemplate::Static("app"), "ls -l"));
+    assert_eq!(Some("ls".to_owned()), title(TitleTemplate::FirstWord, "  ls -l"));
+    assert_eq!(None, title(TitleTemplate::FirstWord, " \n "));
+    // no escape sequence from the line
+    assert_eq!(
+        Some("e]2;vil".to_owned()),
+        title(TitleTemplate::FirstWord, "e\x1b]2;vil\x07 x")
+    );
+}
+
+#[test]
+fn try_readline() {
+    let mut editor = init_editor(EditMode::Emacs, &[]);
+    assert_eq!(None, editor.try_readline(">>").unwrap());
+    editor.term.keys = vec![KeyPress::Char('a'), KeyPress::Char('b'), KeyPress::Left];
+    assert_eq!(None, editor.try_readline(">>").unwrap());
+    // nothing typed
+    editor.term.keys.clear();
+    assert_eq!(None, editor.try_readline(">>").unwrap());
+    editor.term.keys = vec![KeyPress::Char('c'), KeyPress::Enter];
+    assert_eq!(Some("acb".to_owned()), editor.try_readline(">>").unwrap());
+    assert!(editor.raw_mode.is_none());
+    // a new line
+    editor.term.keys = vec![KeyPress::Char('d'), KeyPress::Enter];
+    assert_eq!(Some("d".to_owned()), editor.try_readline(">>").unwrap());
+}
+
+#[test]
+fn try_readline_state() {
+    let mut editor = init_editor(EditMode::Emacs, &[]);
+    editor.add_history_entry("line1");
+    editor.add_history_entry("line2");
+    // history browsing
+    for _ in 0..2 {
+        editor.term.keys = vec![KeyPress::Up];
+        assert_eq!(None, editor.try_readline(">>").unwrap());
+    }
+    editor.term.keys = vec![KeyPress::Down, KeyPress::Down];
+    assert_eq!(None, editor.try_readline(">>").unwrap());
+    // back to the line typed before browsing
+    editor.term.keys = vec![KeyPress::Up, KeyPress::Up];
+    assert_eq!(None, editor.try_readline(">>").unwrap());
+    editor.term.keys = vec![KeyPress::Enter];
+    assert_eq!(Some("line1".to_owned()), editor.try_readline(">>").unwrap());
+    // undo
+    editor.term.keys = vec![KeyPress::Char('a'), KeyPress::Char(' '), KeyPress::Char('b')];
+    assert_eq!(None, editor.try_readline(">>").unwrap());
+    editor.term.keys = vec![KeyPress::Ctrl('W')];
+    assert_eq!(None, editor.try_readline(">>").unwrap());
+    editor.term.keys = vec![KeyPress::Ctrl('_'), KeyPress::Enter];
+    assert_eq!(Some("a b".to_owned()), editor.try_readline(">>").unwrap());
+    // mark
+    editor.term.keys = vec![KeyPress::Char('a'), KeyPress::Ctrl(' '), KeyPress::Home];
+    assert_eq!(None, editor.try_readline(">>").unwrap());
+    editor.term.keys = vec![
+        KeyPress::Ctrl('X'),
+        KeyPress::Ctrl('X'),
+        KeyPress::Char('b'),
+        KeyPress::Enter,
+    ];
+    assert_eq!(Some("ab".to_owned()), editor.try_readline(">>").unwrap());
+}
+
+#[test]
+fn readline_timeout() {
+    let timeout = Duration::from_millis(10);
+    let mut editor = init_editor(EditMode::Emacs, &[KeyPress::Char('a'), KeyPress::Char('b')]);
+    assert_eq!(None, editor.readline_timeout(">>", timeout).unwrap());
+    // the terminal mode is restored
+    assert!(editor.raw_mode.is_none());
+    editor.term.keys = vec![KeyPress::Home, KeyPress::Char('c'), KeyPress::Enter];
+    assert_eq!(
+        Some("cab".to_owned()),
+        editor.readline_timeout(">>", timeout).unwrap()
+    );
+    editor.term.keys = vec![KeyPress::Enter];
+    assert_eq!(Some("".to_owned()), editor.readline_timeout(">>", timeout).unwrap());
+}
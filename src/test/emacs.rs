@@ -1,5 +1,5 @@
 //! Emacs specific key bindings
-use super::{assert_cursor, assert_history};
+use super::{assert_cursor, assert_history, assert_line_with_initial};
 use config::EditMode;
 use keys::KeyPress;
 
@@ -77,6 +77,39 @@ fn ctrl_f() {
     );
 }
 
+#[test]
+fn ctrl_d() {
+    assert_cursor(
+        EditMode::Emacs,
+        ("a", "bcde"),
+        &[KeyPress::Meta('3'), KeyPress::Ctrl('D'), KeyPress::Enter],
+        ("a", "e"),
+    );
+    assert_cursor(
+        EditMode::Emacs,
+        ("abc", "de"),
+        &[
+            KeyPress::Meta('-'),
+            KeyPress::Meta('2'),
+            KeyPress::Ctrl('D'),
+            KeyPress::Enter,
+        ],
+        ("a", "de"),
+    );
+    // not the end of the input with an argument
+    assert_cursor(
+        EditMode::Emacs,
+        ("", ""),
+        &[
+            KeyPress::Meta('2'),
+            KeyPress::Ctrl('D'),
+            KeyPress::Char('a'),
+            KeyPress::Enter,
+        ],
+        ("a", ""),
+    );
+}
+
 #[test]
 fn ctrl_h() {
     assert_cursor(
@@ -175,12 +208,13 @@ fn ctrl_p() {
 
 #[test]
 fn ctrl_t() {
-    /* FIXME
+    // repeated with the argument
     assert_cursor(
+        EditMode::Emacs,
         ("ab", "cd"),
         &[KeyPress::Meta('2'), KeyPress::Ctrl('T'), KeyPress::Enter],
         ("acdb", ""),
-    );*/
+    );
 }
 
 #[test]
@@ -198,6 +232,76 @@ fn ctrl_x_ctrl_u() {
     );
 }
 
+#[test]
+fn ctrl_x_ctrl_x() {
+    assert_cursor(
+        EditMode::Emacs,
+        ("Hello, ", "world"),
+        &[
+            KeyPress::Ctrl(' '),
+            KeyPress::Ctrl('E'),
+            KeyPress::Ctrl('X'),
+            KeyPress::Ctrl('X'),
+            KeyPress::Enter,
+        ],
+        ("Hello, ", "world"),
+    );
+    // no mark
+    assert_cursor(
+        EditMode::Emacs,
+        ("Hello, ", "world"),
+        &[KeyPress::Ctrl('X'), KeyPress::Ctrl('X'), KeyPress::Enter],
+        ("Hello, ", "world"),
+    );
+    // the mark is deactivated by an edit
+    assert_cursor(
+        EditMode::Emacs,
+        ("Hello, ", "world"),
+        &[
+            KeyPress::Ctrl(' '),
+            KeyPress::Char('!'),
+            KeyPress::Ctrl('X'),
+            KeyPress::Ctrl('X'),
+            KeyPress::Enter,
+        ],
+        ("Hello, !", "world"),
+    );
+}
+
+#[test]
+fn ctrl_x_i() {
+    assert_line_with_initial(
+        EditMode::Emacs,
+        ("a", " = 1\nbb = 2\nccc = 3"),
+        &[
+            KeyPress::Ctrl(' '),
+            KeyPress::Ctrl('E'),
+            KeyPress::Ctrl('X'),
+            KeyPress::Char('i'),
+            KeyPress::Char('x'),
+            KeyPress::Char('y'),
+            KeyPress::Backspace,
+            // ends the block insertion
+            KeyPress::Ctrl('E'),
+            KeyPress::Char('!'),
+            KeyPress::Enter,
+        ],
+        "ax = 1\nbxb = 2\ncxcc = 3!",
+    );
+    // no mark
+    assert_line_with_initial(
+        EditMode::Emacs,
+        ("a", "\nb"),
+        &[
+            KeyPress::Ctrl('X'),
+            KeyPress::Char('i'),
+            KeyPress::Char('x'),
+            KeyPress::Enter,
+        ],
+        "ax\nb",
+    );
+}
+
 #[test]
 fn meta_b() {
     assert_cursor(
@@ -300,12 +404,20 @@ fn meta_u() {
         &[KeyPress::Meta('U'), KeyPress::Enter],
         ("HI", ""),
     );
-    /* FIXME
+    // the word gets longer
     assert_cursor(
+        EditMode::Emacs,
+        ("", "straße ok"),
+        &[KeyPress::Meta('U'), KeyPress::Enter],
+        ("STRASSE", " ok"),
+    );
+    // repeated with the argument
+    assert_cursor(
+        EditMode::Emacs,
         ("", "hi test"),
         &[KeyPress::Meta('2'), KeyPress::Meta('U'), KeyPress::Enter],
         ("HI TEST", ""),
-    );*/
+    );
 }
 
 #[test]
@@ -332,12 +444,33 @@ fn meta_t() {
         &[KeyPress::Meta('T'), KeyPress::Enter],
         ("world, Hello", "!"),
     );
-    /* FIXME
     assert_cursor(
+        EditMode::Emacs,
         ("One Two", " Three Four"),
         &[KeyPress::Meta('T'), KeyPress::Enter],
-        ("One Four Three Two", ""),
-    );*/
+        ("One Three Two", " Four"),
+    );
+    // cursor in whitespace
+    assert_cursor(
+        EditMode::Emacs,
+        ("One ", " Two"),
+        &[KeyPress::Meta('T'), KeyPress::Enter],
+        ("Two  One", ""),
+    );
+    // end of line: the last two words
+    assert_cursor(
+        EditMode::Emacs,
+        ("One Two Three", ""),
+        &[KeyPress::Meta('T'), KeyPress::Enter],
+        ("One Three Two", ""),
+    );
+    // a single word: nothing moves
+    assert_cursor(
+        EditMode::Emacs,
+        ("On", "e"),
+        &[KeyPress::Meta('T'), KeyPress::Enter],
+        ("On", "e"),
+    );
 }
 
 #[test]
@@ -355,6 +488,34 @@ fn meta_y() {
         ],
         ("world", " !"),
     );
+    // the yank is repeated
+    assert_cursor(
+        EditMode::Emacs,
+        ("Hello, world", "!"),
+        &[
+            KeyPress::Ctrl('W'),
+            KeyPress::Left,
+            KeyPress::Ctrl('W'),
+            KeyPress::Meta('2'),
+            KeyPress::Ctrl('Y'),
+            KeyPress::Meta('Y'),
+            KeyPress::Enter,
+        ],
+        ("world", " !"),
+    );
+    // not after a yank
+    assert_cursor(
+        EditMode::Emacs,
+        ("Hello, world", "!"),
+        &[
+            KeyPress::Ctrl('W'),
+            KeyPress::Ctrl('Y'),
+            KeyPress::Left,
+            KeyPress::Meta('Y'),
+            KeyPress::Enter,
+        ],
+        ("Hello, worl", "d!"),
+    );
 }
 
 #[test]
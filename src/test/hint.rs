@@ -0,0 +1,205 @@
+//! Hints tests
+use std::cell::RefCell;
+
+use super::TestHelper;
+use config::{Config, EditMode};
+use hint::{Hinter, HistoryHinter};
+use keymap::Cmd;
+use keys::KeyPress;
+use Editor;
+
+// hints the rest of `suggestion` and records the bytes of the hints accepted
+fn suggest<'t>(suggestion: &'static str, accepted: &'t RefCell<Vec<usize>>) -> TestHelper<'t> {
+    TestHelper::default()
+        .with_hints(move |line, pos, _| {
+            if pos == line.len() && suggestion.starts_with(line) {
+                vec![suggestion[pos..].to_owned()]
+            } else {
+                vec![]
+            }
+        })
+        .with_hint_accepted(move |_, bytes| accepted.borrow_mut().push(bytes))
+}
+
+#[test]
+fn accept_hint_word() {
+    let keys = [
+        KeyPress::Char('g'),
+        KeyPress::Meta('f'),
+        KeyPress::ControlRight,
+        KeyPress::Ctrl('B'),
+        // not at the end of the line: moves
+        KeyPress::Meta('f'),
+        KeyPress::Meta('f'),
+        KeyPress::Meta('f'),
+        // no more hint
+        KeyPress::Meta('f'),
+        KeyPress::Enter,
+    ];
+    let accepted = RefCell::new(Vec::new());
+    let mut editor = Editor::new();
+    editor.set_helper(Some(suggest("git commit --amend café", &accepted)));
+    editor.term.keys.extend(keys.iter().cloned());
+    assert_eq!("git commit --amend café", editor.readline("").unwrap());
+    assert_eq!(vec![2, 7, 8, 6], *accepted.borrow());
+
+    // multi-byte chars at the words boundaries and trailing spaces
+    let keys = [
+        KeyPress::Char('c'),
+        KeyPress::F(1),
+        KeyPress::F(1),
+        // no word left: the whole hint
+        KeyPress::F(1),
+        // no more hint: beeps
+        KeyPress::F(1),
+        KeyPress::Enter,
+    ];
+    let accepted = RefCell::new(Vec::new());
+    let mut editor = Editor::new();
+    editor.set_helper(Some(suggest("crème brûlée  ", &accepted)));
+    editor.bind_sequence(KeyPress::F(1), Cmd::AcceptHintWord);
+    editor.term.keys.extend(keys.iter().cloned());
+    assert_eq!("crème brûlée  ", editor.readline("").unwrap());
+    assert_eq!(vec![5, 9, 2], *accepted.borrow());
+
+    // only in vi insert mode
+    let keys = [
+        KeyPress::Esc,
+        KeyPress::Char('w'),
+        KeyPress::Char('a'),
+        KeyPress::ControlRight,
+        KeyPress::Enter,
+    ];
+    let config = Config::builder().edit_mode(EditMode::Vi).build();
+    let accepted = RefCell::new(Vec::new());
+    let mut editor = Editor::with_config(config);
+    editor.set_helper(Some(suggest("git commit", &accepted)));
+    editor.term.keys.extend(keys.iter().cloned());
+    assert_eq!("git", editor.readline("").unwrap());
+    assert_eq!(vec![3], *accepted.borrow());
+}
+
+#[test]
+fn hint_context() {
+    let keys = [KeyPress::Char('a'), KeyPress::Tab, KeyPress::Enter];
+    // contexts (last command, history length, after completion) seen by the hinter
+    let seen = RefCell::new(Vec::new());
+    let helper = TestHelper::default()
+        .with_complete(|line, _| (0, vec![line.to_owned() + "t"]))
+        .with_hints(|_, _, ctx| {
+            let context = (ctx.last_cmd().clone(), ctx.history().len(), ctx.is_after_completion());
+            let mut seen = seen.borrow_mut();
+            if seen.last() != Some(&context) {
+                seen.push(context);
+            }
+            vec![]
+        });
+    let mut editor = Editor::new();
+    editor.set_helper(Some(helper));
+    editor.add_history_entry("ls");
+    editor.term.keys.extend(keys.iter().cloned());
+    assert_eq!("at", editor.readline("").unwrap());
+    assert_eq!(
+        vec![
+            (Cmd::Noop, 1, false),
+            (Cmd::SelfInsert(1, 'a'), 1, false),
+            (Cmd::Complete, 1, true),
+        ],
+        *seen.borrow()
+    );
+}
+
+#[test]
+fn cycle_hints() {
+    for &(ref keys, expected) in &[
+        (vec![KeyPress::Meta('f')], "git"),
+        (vec![KeyPress::Meta('/'), KeyPress::Meta('f')], "gh"),
+        (
+            vec![KeyPress::Meta('/'), KeyPress::Meta('/'), KeyPress::Meta('f')],
+            "go",
+        ),
+        // back to the first one
+        (
+            vec![
+                KeyPress::Meta('/'),
+                KeyPress::Meta('/'),
+                KeyPress::Meta('/'),
+                KeyPress::Meta('f'),
+            ],
+            "git",
+        ),
+        // the line has changed: back to the first one
+        (
+            vec![
+                KeyPress::Meta('/'),
+                KeyPress::Meta('/'),
+                KeyPress::Backspace,
+                KeyPress::Meta('f'),
+            ],
+            "git",
+        ),
+        // a single hint
+        (
+            vec![KeyPress::Char('h'), KeyPress::Meta('/'), KeyPress::Meta('f')],
+            "gh pr",
+        ),
+    ] {
+        let helper = TestHelper::default().with_hints(|line, pos, _| {
+            ["git commit", "gh pr", "go build"]
+                .iter()
+                .filter(|h| pos == line.len() && h.starts_with(line))
+                .map(|h| h[pos..].to_owned())
+                .collect()
+        });
+        let mut editor = Editor::new();
+        editor.set_helper(Some(helper));
+        editor.term.keys.push(KeyPress::Char('g'));
+        editor.term.keys.extend(keys.iter().cloned());
+        editor.term.keys.push(KeyPress::Enter);
+        assert_eq!(expected, editor.readline("").unwrap());
+    }
+}
+
+// hints of the history
+fn history_hints(hinter: HistoryHinter) -> TestHelper<'static> {
+    let max_width = hinter.max_hint_width();
+    TestHelper::default()
+        .with_hints(move |line, pos, ctx| hinter.hints_with_context(line, pos, ctx))
+        .with_max_hint_width(max_width)
+}
+
+#[test]
+fn cycle_history_hints() {
+    for &(ref keys, expected) in &[
+        (vec![KeyPress::Meta('f')], "git status"),
+        (vec![KeyPress::Meta('/'), KeyPress::Meta('f')], "git commit"),
+        // the duplicates are hinted once
+        (
+            vec![KeyPress::Meta('/'), KeyPress::Meta('/'), KeyPress::Meta('f')],
+            "git status",
+        ),
+    ] {
+        let mut editor = Editor::new();
+        editor.set_helper(Some(history_hints(HistoryHinter::new())));
+        for entry in &["git status", "git commit", "ls", "git status"] {
+            editor.history_mut().add(*entry);
+        }
+        editor.term.keys.extend("git".chars().map(KeyPress::Char));
+        editor.term.keys.extend(keys.iter().cloned());
+        editor.term.keys.push(KeyPress::Enter);
+        assert_eq!(expected, editor.readline("").unwrap());
+    }
+}
+
+#[test]
+fn accept_truncated_hint() {
+    let mut hinter = HistoryHinter::new();
+    // displayed as ` build …`
+    hinter.set_max_width(Some(8));
+    let mut editor = Editor::new();
+    editor.set_helper(Some(history_hints(hinter)));
+    editor.history_mut().add("cargo build --release");
+    editor.term.keys.extend("cargo".chars().map(KeyPress::Char));
+    editor.term.keys.extend(&[KeyPress::Meta('f'), KeyPress::Meta('f'), KeyPress::Enter]);
+    assert_eq!("cargo build --release", editor.readline("").unwrap());
+}
@@ -1,7 +1,13 @@
 //! History related commands tests
-use super::assert_history;
-use config::EditMode;
+use std::cell::RefCell;
+
+use super::{assert_history, TestHelper};
+use config::{Config, Configurer, EditMode};
+use history::{Direction, HistoryBackend, VecHistory};
+use keymap::{Cmd, Movement};
 use keys::KeyPress;
+use line_buffer::MAX_LINE;
+use Editor;
 
 #[test]
 fn down_key() {
@@ -162,6 +168,78 @@ fn ctrl_s() {
     }
 }
 
+#[test]
+fn search_scan_limit() {
+    for &(ref keys, expected) in &[
+        // cut off after `c` and `b`
+        (vec![KeyPress::Ctrl('R'), KeyPress::Char('r'), KeyPress::Enter], ""),
+        // search more
+        (
+            vec![
+                KeyPress::Ctrl('R'),
+                KeyPress::Char('r'),
+                KeyPress::Ctrl('R'),
+                KeyPress::Enter,
+            ],
+            "rustc",
+        ),
+    ] {
+        let config = Config::builder().search_scan_limit(2).build();
+        let mut editor = Editor::<()>::with_config(config);
+        for entry in &["rustc", "a", "b", "c"] {
+            editor.add_history_entry(*entry);
+        }
+        editor.term.keys.extend(keys.iter().cloned());
+        assert_eq!(expected, editor.readline("").unwrap());
+    }
+}
+
+#[test]
+#[cfg(feature = "regex")]
+fn regex_search() {
+    assert_history(
+        EditMode::Emacs,
+        &["rustc", "cargo", "cat"],
+        &[
+            KeyPress::Ctrl('R'),
+            KeyPress::Meta('r'),
+            KeyPress::Char('r'),
+            KeyPress::Char('.'),
+            KeyPress::Char('s'),
+            KeyPress::Right, // just to assert cursor pos
+            KeyPress::Enter,
+        ],
+        ("r", "ustc"),
+    );
+    // invalid pattern matched literally
+    assert_history(
+        EditMode::Emacs,
+        &["f(x)", "g"],
+        &[
+            KeyPress::Ctrl('R'),
+            KeyPress::Meta('r'),
+            KeyPress::Char('('),
+            KeyPress::Right,
+            KeyPress::Enter,
+        ],
+        ("f(", "x)"),
+    );
+    // toggled back to substring matching
+    assert_history(
+        EditMode::Emacs,
+        &["a.b", "abc"],
+        &[
+            KeyPress::Ctrl('R'),
+            KeyPress::Meta('r'),
+            KeyPress::Meta('r'),
+            KeyPress::Char('.'),
+            KeyPress::Right,
+            KeyPress::Enter,
+        ],
+        ("a.", "b"),
+    );
+}
+
 #[test]
 fn meta_lt() {
     assert_history(
@@ -204,3 +282,160 @@ fn meta_gt() {
         ("a", ""),
     );
 }
+
+#[test]
+fn custom_history() {
+    let config = Config::builder().auto_add_history(true).build();
+    let mut editor = Editor::<()>::with_config(config);
+    editor.add_history_entry("main");
+    let mut history = VecHistory::new();
+    history.add("sub".to_owned());
+    editor
+        .term
+        .keys
+        .extend(&[KeyPress::Up, KeyPress::Char('2'), KeyPress::Enter]);
+    let line = editor.readline_with_history("", &mut history).unwrap();
+    assert_eq!("sub2", line);
+    assert_eq!(2, history.len());
+    assert_eq!(Some("sub2"), history.get(1).as_deref());
+    assert_eq!(1, editor.history().len());
+}
+
+#[test]
+fn ignore_space() {
+    let config = Config::builder().auto_add_history(true).build();
+    let mut editor = Editor::<()>::with_config(config);
+    editor.set_history_ignore_space(true);
+    editor
+        .term
+        .keys
+        .extend(&[KeyPress::Char(' '), KeyPress::Char('a'), KeyPress::Enter]);
+    assert_eq!(" a", editor.readline("").unwrap());
+    assert!(editor.history().is_empty());
+    assert!(!editor.add_history_entry(" b"));
+
+    editor.set_history_ignore_space(false);
+    editor.term.keys.clear();
+    editor
+        .term
+        .keys
+        .extend(&[KeyPress::Char(' '), KeyPress::Char('a'), KeyPress::Enter]);
+    assert_eq!(" a", editor.readline("").unwrap());
+    assert_eq!(Some(&" a".to_owned()), editor.history().last());
+}
+
+#[test]
+fn readline_private() {
+    let config = Config::builder().auto_add_history(true).build();
+    let mut editor = Editor::<()>::with_config(config);
+    editor.add_history_entry("main");
+    editor.term.keys.extend(&[
+        KeyPress::Up, // no history
+        KeyPress::Char('p'),
+        KeyPress::Char('w'),
+        KeyPress::Ctrl('W'), // not killed
+        KeyPress::Char('x'),
+        KeyPress::Ctrl('Y'),
+        KeyPress::Ctrl('_'), // no undo
+        KeyPress::Enter,
+    ]);
+    let line = editor.readline_private("").unwrap();
+    assert_eq!("x", line.as_str());
+    assert_eq!(1, editor.history().len());
+
+    // nothing to yank
+    editor.term.keys.clear();
+    editor
+        .term
+        .keys
+        .extend(&[KeyPress::Ctrl('Y'), KeyPress::Enter]);
+    assert_eq!("", editor.readline("").unwrap());
+
+    // inserted past the capacity of the line buffer
+    let secret: String = ::std::iter::repeat('s').take(MAX_LINE + 10).collect();
+    editor.bind_sequence(
+        KeyPress::F(1),
+        Cmd::Replace(Movement::BeginningOfLine, Some(secret.clone())),
+    );
+    editor.term.keys.clear();
+    editor.term.keys.extend(&[KeyPress::F(1), KeyPress::Enter]);
+    let line = editor.readline_private("").unwrap();
+    assert_eq!(secret, line.as_str());
+}
+
+#[test]
+fn pending_edits() {
+    for mode in &[EditMode::Emacs, EditMode::Vi] {
+        // navigate away and back
+        assert_history(
+            *mode,
+            &["line1", "line2"],
+            &[
+                KeyPress::Up,
+                KeyPress::Backspace,
+                KeyPress::Up,
+                KeyPress::Down,
+                KeyPress::Enter,
+            ],
+            ("line", ""),
+        );
+        // back from the current line
+        assert_history(
+            *mode,
+            &["line1"],
+            &[
+                KeyPress::Up,
+                KeyPress::Backspace,
+                KeyPress::Down,
+                KeyPress::Up,
+                KeyPress::Enter,
+            ],
+            ("line", ""),
+        );
+    }
+    // the history entries are not modified
+    let config = Config::builder().auto_add_history(true).build();
+    let mut editor = Editor::<()>::with_config(config);
+    editor.add_history_entry("line1");
+    editor
+        .term
+        .keys
+        .extend(&[KeyPress::Up, KeyPress::Backspace, KeyPress::Enter]);
+    assert_eq!("line", editor.readline("").unwrap());
+    assert_eq!(Some(&"line1".to_owned()), editor.history().get(0));
+}
+
+#[test]
+fn search_prompt() {
+    let keys = [
+        KeyPress::Ctrl('R'),
+        KeyPress::Char('l'),
+        KeyPress::Char('x'),
+        KeyPress::Enter,
+    ];
+    // (query, direction, matched) of the search prompts displayed
+    let seen = RefCell::new(Vec::new());
+    let helper = TestHelper::default().with_search_prompt(|query, direction, matched| {
+        seen.borrow_mut().push((query.to_owned(), direction, matched));
+        // the built-in prompt until a query is typed
+        if query.is_empty() {
+            None
+        } else {
+            Some(query.to_owned())
+        }
+    });
+    let mut editor = Editor::new();
+    editor.set_helper(Some(helper));
+    editor.add_history_entry("ls");
+    editor.add_history_entry("cd");
+    editor.term.keys.extend(keys.iter().cloned());
+    assert_eq!("ls", editor.readline("").unwrap());
+    assert_eq!(
+        vec![
+            ("".to_owned(), Direction::Reverse, true),
+            ("l".to_owned(), Direction::Reverse, true),
+            ("lx".to_owned(), Direction::Reverse, false),
+        ],
+        *seen.borrow()
+    );
+}
@@ -0,0 +1,93 @@
+//! Custom key bindings tests
+use super::init_editor;
+use config::{Config, EditMode};
+use keymap::{Cmd, EventContext, Handler};
+use keys::KeyPress;
+use Editor;
+
+#[test]
+fn custom_command() {
+    for mode in &[EditMode::Emacs, EditMode::Vi] {
+        let keys = [
+            KeyPress::Char('a'),
+            KeyPress::Char('b'),
+            KeyPress::Left,
+            KeyPress::F(1),
+            KeyPress::F(2),
+            KeyPress::Enter,
+        ];
+        let mut editor = init_editor(*mode, &keys);
+        let context = Handler::new(|ctx: &EventContext| {
+            Some(Cmd::Insert(1, format!("[{}:{}]", ctx.line(), ctx.pos())))
+        });
+        editor.bind_sequence(KeyPress::F(1), Cmd::Custom(context));
+        // no-op
+        editor.bind_sequence(KeyPress::F(2), Cmd::Custom(Handler::new(|_: &EventContext| None)));
+        assert_eq!("a[ab:1]b", editor.readline("").unwrap());
+    }
+}
+
+#[test]
+fn key_pair() {
+    for mode in &[EditMode::Emacs, EditMode::Vi] {
+        let keys = [
+            KeyPress::Char('a'),
+            KeyPress::Ctrl('T'),
+            KeyPress::Char('b'),
+            // unknown
+            KeyPress::Ctrl('T'),
+            KeyPress::Char('c'),
+            KeyPress::Enter,
+        ];
+        let mut editor = init_editor(*mode, &keys);
+        let cmd = Cmd::Insert(1, "[b]".to_owned());
+        editor.bind_key_pair(KeyPress::Ctrl('T'), KeyPress::Char('b'), cmd);
+        assert_eq!("a[b]", editor.readline("").unwrap());
+    }
+    // the built-in Ctrl-X pairs are kept
+    let keys = [
+        KeyPress::Char('a'),
+        KeyPress::Ctrl('X'),
+        KeyPress::Ctrl('U'),
+        KeyPress::Ctrl('X'),
+        KeyPress::Char('b'),
+        KeyPress::Enter,
+    ];
+    let mut editor = init_editor(EditMode::Emacs, &keys);
+    let cmd = Cmd::Insert(1, "[b]".to_owned());
+    editor.bind_key_pair(KeyPress::Ctrl('X'), KeyPress::Char('b'), cmd);
+    assert_eq!("[b]", editor.readline("").unwrap());
+}
+
+#[test]
+fn key_repeat() {
+    let keys = [
+        KeyPress::F(3),
+        KeyPress::F(3),
+        KeyPress::End, // another key
+        KeyPress::F(3),
+        KeyPress::Enter,
+    ];
+    let mut editor = init_editor(EditMode::Emacs, &keys);
+    let repeat = Handler::new(|ctx: &EventContext| {
+        let repeated = ctx.since_last_key().is_some();
+        Some(Cmd::Insert(1, format!("{}{}", ctx.repeat_count(), repeated)))
+    });
+    editor.bind_sequence(KeyPress::F(3), Cmd::Custom(repeat));
+    assert_eq!("0false1true0false", editor.readline("").unwrap());
+}
+
+#[test]
+fn cursor_acceleration() {
+    let line = "a".repeat(40);
+    let mut keys = vec![KeyPress::Left; 12];
+    keys.push(KeyPress::Enter);
+    // the last 4 repeats move by 2
+    for &(acceleration, expected_pos) in &[(false, 28), (true, 24)] {
+        let config = Config::builder().cursor_acceleration(acceleration).build();
+        let mut editor = Editor::<()>::with_config(config);
+        editor.term.keys.extend(keys.iter().cloned());
+        editor.readline_with_initial("", (&line, "")).unwrap();
+        assert_eq!(expected_pos, editor.term.cursor);
+    }
+}
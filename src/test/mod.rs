@@ -1,17 +1,23 @@
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::borrow::Cow::{self, Owned};
 
-use super::{Editor, Result};
-use completion::Completer;
+use super::{Editor, Helper, Result};
+use completion::{AsyncCompleter, Completer, CompletionFuture};
 use config::{Config, EditMode};
-use edit::init_state;
-use keymap::{Cmd, InputState};
+use highlight::Highlighter;
+use hint::{Context, Hinter};
+use history::Direction;
 use keys::KeyPress;
-use tty::Sink;
+use validate::{ValidationResult, Validator};
 
 mod common;
+mod completion;
+mod editor;
 mod emacs;
+mod hint;
 mod history;
+mod keymap;
+mod prompt;
+mod validate;
 mod vi_cmd;
 mod vi_insert;
 
@@ -22,35 +28,182 @@ fn init_editor(mode: EditMode, keys: &[KeyPress]) -> Editor<()> {
     editor
 }
 
-struct SimpleCompleter;
-impl Completer for SimpleCompleter {
+/// Function overriding `Completer::complete`
+type CompleteFn<'t> = Fn(&str, usize) -> (usize, Vec<String>) + 't;
+/// Function overriding `AsyncCompleter::complete`
+type CompleteAsyncFn<'t> = Fn(&str, usize) -> CompletionFuture<'static, String> + 't;
+/// Function overriding `Hinter::hints_with_context`
+type HintsFn<'t> = Fn(&str, usize, &Context) -> Vec<String> + 't;
+/// Function overriding `Hinter::hint_accepted`
+type HintAcceptedFn<'t> = Fn(&str, usize) + 't;
+/// Function overriding `Validator::validate`
+type ValidateFn<'t> = Fn(&str) -> ValidationResult + 't;
+/// Function overriding `Helper::search_prompt`
+type SearchPromptFn<'t> = Fn(&str, Direction, bool) -> Option<String> + 't;
+
+/// Helper whose behaviour is overridden by functions (the others behave by
+/// default: no candidate, no hint and any line accepted).
+#[derive(Default)]
+struct TestHelper<'t> {
+    complete: Option<Box<CompleteFn<'t>>>,
+    // used instead of `complete` when set
+    complete_async: Option<Box<CompleteAsyncFn<'t>>>,
+    // the first one is the hint
+    hints: Option<Box<HintsFn<'t>>>,
+    hint_accepted: Option<Box<HintAcceptedFn<'t>>>,
+    max_hint_width: Option<usize>,
+    validate: Option<Box<ValidateFn<'t>>>,
+    search_prompt: Option<Box<SearchPromptFn<'t>>>,
+}
+
+impl<'t> TestHelper<'t> {
+    /// Overrides `Completer::complete`.
+    fn with_complete<F>(mut self, f: F) -> TestHelper<'t>
+    where
+        F: Fn(&str, usize) -> (usize, Vec<String>) + 't,
+    {
+        self.complete = Some(Box::new(f));
+        self
+    }
+
+    /// Overrides `AsyncCompleter::complete` (and makes the helper an async
+    /// completer).
+    fn with_complete_async<F>(mut self, f: F) -> TestHelper<'t>
+    where
+        F: Fn(&str, usize) -> CompletionFuture<'static, String> + 't,
+    {
+        self.complete_async = Some(Box::new(f));
+        self
+    }
+
+    /// Overrides `Hinter::hints_with_context` (and `hint_with_context`).
+    fn with_hints<F>(mut self, f: F) -> TestHelper<'t>
+    where
+        F: Fn(&str, usize, &Context) -> Vec<String> + 't,
+    {
+        self.hints = Some(Box::new(f));
+        self
+    }
+
+    /// Overrides `Hinter::hint_accepted`.
+    fn with_hint_accepted<F>(mut self, f: F) -> TestHelper<'t>
+    where
+        F: Fn(&str, usize) + 't,
+    {
+        self.hint_accepted = Some(Box::new(f));
+        self
+    }
+
+    /// Overrides `Hinter::max_hint_width`.
+    fn with_max_hint_width(mut self, width: Option<usize>) -> TestHelper<'t> {
+        self.max_hint_width = width;
+        self
+    }
+
+    /// Overrides `Validator::validate` (and makes the helper a validator).
+    fn with_validate<F>(mut self, f: F) -> TestHelper<'t>
+    where
+        F: Fn(&str) -> ValidationResult + 't,
+    {
+        self.validate = Some(Box::new(f));
+        self
+    }
+
+    /// Overrides `Helper::search_prompt`.
+    fn with_search_prompt<F>(mut self, f: F) -> TestHelper<'t>
+    where
+        F: Fn(&str, Direction, bool) -> Option<String> + 't,
+    {
+        self.search_prompt = Some(Box::new(f));
+        self
+    }
+}
+
+impl<'t> Completer for TestHelper<'t> {
     type Candidate = String;
 
-    fn complete(&self, line: &str, _pos: usize) -> Result<(usize, Vec<String>)> {
-        Ok((0, vec![line.to_owned() + "t"]))
+    fn complete(&self, line: &str, pos: usize) -> Result<(usize, Vec<String>)> {
+        match self.complete {
+            Some(ref complete) => Ok(complete(line, pos)),
+            None => Ok((0, Vec::new())),
+        }
+    }
+
+    fn async_completer(&self) -> Option<&AsyncCompleter<Candidate = String>> {
+        if self.complete_async.is_some() {
+            Some(self)
+        } else {
+            None
+        }
     }
 }
 
-#[test]
-fn complete_line() {
-    let mut out = Sink::new();
-    let mut s = init_state(&mut out, "rus", 3);
-    let config = Config::default();
-    let mut input_state = InputState::new(&config, Arc::new(RwLock::new(HashMap::new())));
-    let keys = &[KeyPress::Enter];
-    let mut rdr = keys.iter();
-    let completer = SimpleCompleter;
-    let cmd = super::complete_line(
-        &mut rdr,
-        &mut s,
-        &mut input_state,
-        &completer,
-        None,
-        &Config::default(),
-    ).unwrap();
-    assert_eq!(Some(Cmd::AcceptLine), cmd);
-    assert_eq!("rust", s.line.as_str());
-    assert_eq!(4, s.line.pos());
+impl<'t> AsyncCompleter for TestHelper<'t> {
+    type Candidate = String;
+
+    fn complete<'a>(&'a self, line: &str, pos: usize) -> CompletionFuture<'a, String> {
+        (self.complete_async.as_ref().unwrap())(line, pos)
+    }
+}
+
+impl<'t> Hinter for TestHelper<'t> {
+    fn hint(&self, _line: &str, _pos: usize) -> Option<String> {
+        None
+    }
+
+    fn hint_with_context(&self, line: &str, pos: usize, ctx: &Context) -> Option<String> {
+        self.hints_with_context(line, pos, ctx).into_iter().next()
+    }
+
+    fn hints_with_context(&self, line: &str, pos: usize, ctx: &Context) -> Vec<String> {
+        match self.hints {
+            Some(ref hints) => hints(line, pos, ctx),
+            None => Vec::new(),
+        }
+    }
+
+    fn hint_accepted(&self, hint: &str, accepted: usize) {
+        if let Some(ref hint_accepted) = self.hint_accepted {
+            hint_accepted(hint, accepted);
+        }
+    }
+
+    fn max_hint_width(&self) -> Option<usize> {
+        self.max_hint_width
+    }
+}
+
+impl<'t> Highlighter for TestHelper<'t> {}
+
+impl<'t> Validator for TestHelper<'t> {
+    fn validate(&self, line: &str) -> ValidationResult {
+        match self.validate {
+            Some(ref validate) => validate(line),
+            None => ValidationResult::Valid,
+        }
+    }
+}
+
+impl<'t> Helper for TestHelper<'t> {
+    fn validator(&self) -> Option<&Validator> {
+        if self.validate.is_some() {
+            Some(self)
+        } else {
+            None
+        }
+    }
+
+    fn search_prompt<'q>(
+        &self,
+        query: &'q str,
+        direction: Direction,
+        matched: bool,
+    ) -> Option<Cow<'q, str>> {
+        match self.search_prompt {
+            Some(ref search_prompt) => search_prompt(query, direction, matched).map(Owned),
+            None => None,
+        }
+    }
 }
 
 // `keys`: keys to press
@@ -104,3 +257,4 @@ fn unknown_esc_key() {
         assert_line(*mode, &[KeyPress::UnknownEscSeq, KeyPress::Enter], "");
     }
 }
+
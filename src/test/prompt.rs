@@ -0,0 +1,72 @@
+//! Right prompt tests
+use std::borrow::Cow;
+
+use highlight::Highlighter;
+use tty::Sink;
+
+struct RpromptHighlighter;
+impl Highlighter for RpromptHighlighter {
+    fn highlight_prompt<'p>(&self, prompt: &'p str) -> Cow<'p, str> {
+        Cow::Owned(format!("\x1b[1m{}\x1b[0m", prompt))
+    }
+}
+
+#[test]
+fn refresh_rprompt() {
+    use line_buffer::LineBuffer;
+    use tty::{Position, Renderer};
+
+    let mut out = Sink::new();
+    let prompt_size = Position { col: 2, row: 0 };
+    let refresh = |out: &mut Sink, line: &str, hint: Option<&str>, hl: Option<&Highlighter>| {
+        let line = LineBuffer::init(line, line.len(), None);
+        let hint = hint.map(str::to_owned);
+        out.refresh_line("> ", prompt_size, Some("12:00"), &line, hint, 0, 0, hl)
+            .unwrap();
+    };
+    // flush right, one column before the edge (80)
+    refresh(&mut out, "ls", None, None);
+    assert_eq!(format!("{}12:00", " ".repeat(70)), out.rprompt);
+    refresh(&mut out, "ls", None, Some(&RpromptHighlighter as &Highlighter));
+    assert_eq!(format!("{}\x1b[1m12:00\x1b[0m", " ".repeat(70)), out.rprompt);
+    // hidden when the line or its hint reaches it
+    refresh(&mut out, &"x".repeat(72), None, None);
+    assert_eq!("", out.rprompt);
+    refresh(&mut out, "ls", Some(&"x".repeat(70)), None);
+    assert_eq!("", out.rprompt);
+    refresh(&mut out, "ls", Some(" -l"), None);
+    assert_eq!(format!("{}12:00", " ".repeat(67)), out.rprompt);
+}
+
+#[test]
+fn rprompt_col() {
+    use tty::{rprompt_col, Position};
+
+    let out = Sink::new();
+    let prompt_size = Position { col: 2, row: 0 };
+    // flush right, one column before the edge (80)
+    assert_eq!(
+        Some(74),
+        rprompt_col(&out, "12:00", prompt_size, Position { col: 10, row: 0 })
+    );
+    // with a blank column before it
+    assert_eq!(
+        Some(74),
+        rprompt_col(&out, "12:00", prompt_size, Position { col: 73, row: 0 })
+    );
+    assert_eq!(
+        None,
+        rprompt_col(&out, "12:00", prompt_size, Position { col: 74, row: 0 })
+    );
+    // the input wraps
+    assert_eq!(
+        None,
+        rprompt_col(&out, "12:00", prompt_size, Position { col: 3, row: 1 })
+    );
+    // on the first input row, after a multi-line prompt
+    let prompt_size = Position { col: 2, row: 1 };
+    assert_eq!(
+        Some(74),
+        rprompt_col(&out, "12:00", prompt_size, Position { col: 3, row: 1 })
+    );
+}
@@ -0,0 +1,61 @@
+//! Validation tests
+use super::TestHelper;
+use keys::KeyPress;
+use validate::ValidationResult;
+use Editor;
+
+#[test]
+fn validate_confirm() {
+    for &(ref keys, expected) in &[
+        // invalid
+        (vec![KeyPress::Enter, KeyPress::Char('a'), KeyPress::Enter], "a"),
+        // confirmed
+        (
+            vec![
+                KeyPress::Char('r'),
+                KeyPress::Char('m'),
+                KeyPress::Enter,
+                KeyPress::Enter,
+            ],
+            "rm",
+        ),
+        // cancelled then confirmed
+        (
+            vec![
+                KeyPress::Char('r'),
+                KeyPress::Char('m'),
+                KeyPress::Enter,
+                KeyPress::Char('x'),
+                KeyPress::Enter,
+                KeyPress::Enter,
+            ],
+            "rmx",
+        ),
+        // cancelled then fixed
+        (
+            vec![
+                KeyPress::Char('r'),
+                KeyPress::Char('m'),
+                KeyPress::Enter,
+                KeyPress::Home,
+                KeyPress::Delete,
+                KeyPress::Enter,
+            ],
+            "m",
+        ),
+    ] {
+        let helper = TestHelper::default().with_validate(|line| {
+            if line.is_empty() {
+                ValidationResult::Invalid(Some("empty line".to_owned()))
+            } else if line.starts_with("rm") {
+                ValidationResult::Confirm("press Enter again to remove".to_owned())
+            } else {
+                ValidationResult::Valid
+            }
+        });
+        let mut editor = Editor::new();
+        editor.set_helper(Some(helper));
+        editor.term.keys.extend(keys.iter().cloned());
+        assert_eq!(expected, editor.readline("").unwrap());
+    }
+}
@@ -459,6 +459,13 @@ fn k() {
             &[KeyPress::Esc, *key, KeyPress::Enter],
             ("line1", ""),
         );
+        // repeated with the count
+        assert_history(
+            EditMode::Vi,
+            &["line1", "line2", "line3"],
+            &[KeyPress::Esc, KeyPress::Char('2'), *key, KeyPress::Enter],
+            ("line2", ""),
+        );
     }
 }
 
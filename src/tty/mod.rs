@@ -1,9 +1,14 @@
 //! This module implements and describes common TTY methods & traits
+use std::cmp;
 use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
 use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthStr;
 
-use config::{ColorMode, Config};
+use config::{ColorDepth, ColorMode, Config};
+use error::ReadlineError;
 use highlight::Highlighter;
 use keys::KeyPress;
 use line_buffer::LineBuffer;
@@ -19,11 +24,65 @@ pub trait RawMode: Sized {
 pub trait RawReader {
     /// Blocking read of key pressed.
     fn next_key(&mut self, single_esc_abort: bool) -> Result<KeyPress>;
+    /// Waits at most `timeout_ms` milliseconds for some input.
+    /// Returns `true` when a key can be read.
+    fn poll(&mut self, timeout_ms: i32) -> Result<bool>;
+    /// Reads the text pasted (after `KeyPress::BracketedPasteStart`) up to
+    /// the end of the bracketed paste, newlines included.
+    fn read_pasted_text(&mut self) -> Result<String>;
     /// For CTRL-V support
     #[cfg(unix)]
     fn next_char(&mut self) -> Result<char>;
 }
 
+/// Delay (in milliseconds) between two checks of the cancellation while a
+/// `CancellableReader` waits for a key
+const CANCEL_CHECK_MS: i32 = 50;
+
+/// Reader whose key reads fail with `ReadlineError::Interrupted` once
+/// `cancel` is set (like when the future of `AsyncEditor::readline` is
+/// dropped), including the reads of the commands reading several keys. The
+/// rest of a key being read (like an escape sequence) is not interrupted.
+pub struct CancellableReader<R: RawReader> {
+    rdr: R,
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+impl<R: RawReader> CancellableReader<R> {
+    pub fn new(rdr: R, cancel: Option<Arc<AtomicBool>>) -> CancellableReader<R> {
+        CancellableReader { rdr, cancel }
+    }
+}
+
+impl<R: RawReader> RawReader for CancellableReader<R> {
+    fn next_key(&mut self, single_esc_abort: bool) -> Result<KeyPress> {
+        if let Some(ref cancel) = self.cancel {
+            loop {
+                if cancel.load(Ordering::SeqCst) {
+                    return Err(ReadlineError::Interrupted);
+                }
+                if try!(self.rdr.poll(CANCEL_CHECK_MS)) {
+                    break;
+                }
+            }
+        }
+        self.rdr.next_key(single_esc_abort)
+    }
+
+    fn poll(&mut self, timeout_ms: i32) -> Result<bool> {
+        self.rdr.poll(timeout_ms)
+    }
+
+    fn read_pasted_text(&mut self) -> Result<String> {
+        self.rdr.read_pasted_text()
+    }
+
+    #[cfg(unix)]
+    fn next_char(&mut self) -> Result<char> {
+        self.rdr.next_char()
+    }
+}
+
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 pub struct Position {
     pub col: usize,
@@ -34,11 +93,13 @@ pub struct Position {
 pub trait Renderer {
     fn move_cursor(&mut self, old: Position, new: Position) -> Result<()>;
 
-    /// Display `prompt`, line and cursor in terminal output
+    /// Display `prompt`, line and cursor in terminal output, and `rprompt`
+    /// flush right if it fits (see `rprompt_col`)
     fn refresh_line(
         &mut self,
         prompt: &str,
         prompt_size: Position,
+        rprompt: Option<&str>,
         line: &LineBuffer,
         hint: Option<String>,
         current_row: usize,
@@ -61,6 +122,17 @@ pub trait Renderer {
         Ok(())
     }
 
+    /// Output used by another thread to animate a spinner while the
+    /// completer is running.
+    fn spinner_writer(&self) -> Option<Box<Write + Send>> {
+        Some(Box::new(io::stdout()))
+    }
+
+    /// Set the title of the terminal (with the `OSC 2` escape sequence).
+    fn set_title(&mut self, title: &str) -> Result<()> {
+        self.write_and_flush(format!("\x1b]2;{}\x07", title).as_bytes())
+    }
+
     /// Clear the screen. Used to handle ctrl+l
     fn clear_screen(&mut self) -> Result<()>;
 
@@ -83,6 +155,7 @@ impl<'a, R: Renderer + ?Sized> Renderer for &'a mut R {
         &mut self,
         prompt: &str,
         prompt_size: Position,
+        rprompt: Option<&str>,
         line: &LineBuffer,
         hint: Option<String>,
         current_row: usize,
@@ -92,6 +165,7 @@ impl<'a, R: Renderer + ?Sized> Renderer for &'a mut R {
         (**self).refresh_line(
             prompt,
             prompt_size,
+            rprompt,
             line,
             hint,
             current_row,
@@ -112,6 +186,14 @@ impl<'a, R: Renderer + ?Sized> Renderer for &'a mut R {
         (**self).beep()
     }
 
+    fn spinner_writer(&self) -> Option<Box<Write + Send>> {
+        (**self).spinner_writer()
+    }
+
+    fn set_title(&mut self, title: &str) -> Result<()> {
+        (**self).set_title(title)
+    }
+
     fn clear_screen(&mut self) -> Result<()> {
         (**self).clear_screen()
     }
@@ -139,14 +221,18 @@ pub trait Term {
     type Writer: Renderer; // rl_outstream
     type Mode: RawMode;
 
-    fn new(color_mode: ColorMode) -> Self;
+    fn new(color_mode: ColorMode, bracketed_paste: bool) -> Self;
     /// Check if current terminal can provide a rich line-editing user
     /// interface.
     fn is_unsupported(&self) -> bool;
     /// check if stdin is connected to a terminal.
     fn is_stdin_tty(&self) -> bool;
+    /// check if stdout is connected to a terminal.
+    fn is_output_tty(&self) -> bool;
     /// Check if output supports colors.
     fn colors_enabled(&self) -> bool;
+    /// Number of colors supported by the output.
+    fn color_depth(&self) -> ColorDepth;
     /// Enable RAW mode for the terminal.
     fn enable_raw_mode(&mut self) -> Result<Self::Mode>;
     /// Create a RAW reader
@@ -155,6 +241,87 @@ pub trait Term {
     fn create_writer(&self) -> Self::Writer;
 }
 
+/// Calculate the cursor and end positions from the `highlighted` output
+/// (when the `highlighter` changes the display width).
+///
+/// The cursor is placed at the end of the highlighted `line[..pos]`.
+fn highlighted_positions<R: Renderer + ?Sized>(
+    out: &R,
+    highlighter: &Highlighter,
+    highlighted: &str,
+    line: &LineBuffer,
+    prompt_size: Position,
+) -> (Position, Position) {
+    let end_pos = out.calculate_position(highlighted, prompt_size);
+    let mut cursor = out.calculate_position(
+        &highlighter.highlight(&line[..line.pos()], line.pos()),
+        prompt_size,
+    );
+    // the cursor may land in the middle of a token rendered narrower than its
+    // prefix
+    if (cursor.row, cursor.col) > (end_pos.row, end_pos.col) {
+        cursor = end_pos;
+    }
+    (cursor, end_pos)
+}
+
+/// Column where the right prompt is displayed, flush right but one column
+/// before the edge (so that it never wraps), or `None` when it would collide
+/// with the input: the input and its hint must end (at `end`) on the first
+/// input row, at least one column before it.
+pub fn rprompt_col<R: Renderer + ?Sized>(
+    out: &R,
+    rprompt: &str,
+    prompt_size: Position,
+    end: Position,
+) -> Option<usize> {
+    if end.row != prompt_size.row {
+        return None;
+    }
+    let size = out.calculate_position(rprompt, Position::default());
+    let cols = out.get_columns();
+    if size.row > 0 || end.col + size.col + 2 > cols {
+        return None;
+    }
+    Some(cols - 1 - size.col)
+}
+
+/// Text appended to the first input row (ending at `end`) to display the
+/// right prompt flush right: the blanks before it and the prompt itself,
+/// highlighted. Empty when it would collide with the input (see
+/// `rprompt_col`).
+pub fn padded_rprompt<R: Renderer + ?Sized>(
+    out: &R,
+    rprompt: &str,
+    prompt_size: Position,
+    end: Position,
+    highlighter: Option<&Highlighter>,
+) -> String {
+    let mut padded = String::new();
+    if let Some(col) = rprompt_col(out, rprompt, prompt_size, end) {
+        for _ in end.col..col {
+            padded.push(' ');
+        }
+        if let Some(highlighter) = highlighter {
+            padded.push_str(&highlighter.highlight_prompt(rprompt));
+        } else {
+            padded.push_str(rprompt);
+        }
+    }
+    padded
+}
+
+/// What is left of a wait of `timeout_ms` milliseconds (-1 for no timeout)
+/// `started` earlier, like after an interrupted `RawReader::poll`.
+pub fn remaining_ms(timeout_ms: i32, started: Instant) -> i32 {
+    if timeout_ms < 0 {
+        return timeout_ms;
+    }
+    let elapsed = started.elapsed();
+    let elapsed_ms = elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis());
+    cmp::max(i64::from(timeout_ms) - elapsed_ms as i64, 0) as i32
+}
+
 fn truncate(text: &str, col: usize, max_col: usize) -> &str {
     let mut col = col;
     let mut esc_seq = 0;
@@ -174,6 +341,9 @@ fn width(s: &str, esc_seq: &mut u8) -> usize {
         if s == "[" {
             // CSI
             *esc_seq = 2;
+        } else if s == "]" {
+            // OSC (like hyperlinks)
+            *esc_seq = 3;
         } else {
             // two-character sequence
             *esc_seq = 0;
@@ -189,6 +359,15 @@ fn width(s: &str, esc_seq: &mut u8) -> usize {
             *esc_seq = 0;
         }
         0
+    } else if *esc_seq == 3 {
+        if s == "\x07" {
+            // BEL terminator
+            *esc_seq = 0;
+        } else if s == "\x1b" {
+            // ST terminator (ESC \)
+            *esc_seq = 1;
+        }
+        0
     } else if s == "\x1b" {
         *esc_seq = 1;
         0
@@ -1,10 +1,16 @@
 //! Tests specific definitions
+use std::io::{self, Write};
 use std::iter::IntoIterator;
 use std::slice::Iter;
+use std::sync::{Arc, Mutex};
 use std::vec::IntoIter;
+use unicode_segmentation::UnicodeSegmentation;
 
-use super::{truncate, Position, RawMode, RawReader, Renderer, Term};
-use config::{ColorMode, Config};
+use super::{
+    highlighted_positions, padded_rprompt, truncate, width, Position, RawMode, RawReader, Renderer,
+    Term,
+};
+use config::{ColorDepth, ColorMode, Config};
 use error::ReadlineError;
 use highlight::Highlighter;
 use keys::KeyPress;
@@ -19,6 +25,26 @@ impl RawMode for Mode {
     }
 }
 
+// the chars up to `KeyPress::BracketedPasteEnd`
+fn pasted_text<I: Iterator<Item = KeyPress>>(keys: I) -> Result<String> {
+    let mut text = String::new();
+    for key in keys {
+        match key {
+            KeyPress::BracketedPasteEnd => return Ok(text),
+            KeyPress::Char(c) => text.push(c),
+            KeyPress::Enter => text.push('\n'),
+            KeyPress::Tab => text.push('\t'),
+            key => {
+                return Err(ReadlineError::Io(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{:?} in a bracketed paste", key),
+                )))
+            }
+        }
+    }
+    Err(ReadlineError::Eof)
+}
+
 impl<'a> RawReader for Iter<'a, KeyPress> {
     fn next_key(&mut self, _: bool) -> Result<KeyPress> {
         match self.next() {
@@ -27,6 +53,14 @@ impl<'a> RawReader for Iter<'a, KeyPress> {
         }
     }
 
+    fn poll(&mut self, _: i32) -> Result<bool> {
+        Ok(self.len() > 0)
+    }
+
+    fn read_pasted_text(&mut self) -> Result<String> {
+        pasted_text(self.cloned())
+    }
+
     #[cfg(unix)]
     fn next_char(&mut self) -> Result<char> {
         unimplemented!();
@@ -41,6 +75,14 @@ impl RawReader for IntoIter<KeyPress> {
         }
     }
 
+    fn poll(&mut self, _: i32) -> Result<bool> {
+        Ok(self.len() > 0)
+    }
+
+    fn read_pasted_text(&mut self) -> Result<String> {
+        pasted_text(self)
+    }
+
     #[cfg(unix)]
     fn next_char(&mut self) -> Result<char> {
         match self.next() {
@@ -51,11 +93,36 @@ impl RawReader for IntoIter<KeyPress> {
     }
 }
 
-pub struct Sink {}
+pub struct Sink {
+    // right prompt displayed by the last `refresh_line`, with the blanks
+    // before it (the prompt and the line are not kept)
+    pub rprompt: String,
+    // text written by `write_and_flush` (like the rows below the line)
+    pub written: Vec<String>,
+    // frames drawn by the spinners (none displayed by default)
+    pub spinner: Option<Arc<Mutex<Vec<u8>>>>,
+}
 
 impl Sink {
     pub fn new() -> Sink {
-        Sink {}
+        Sink {
+            rprompt: String::new(),
+            written: Vec::new(),
+            spinner: None,
+        }
+    }
+}
+
+/// Writer of the spinner frames shared with the test
+struct SpinnerWriter(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SpinnerWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
     }
 }
 
@@ -68,27 +135,47 @@ impl Renderer for Sink {
         &mut self,
         _: &str,
         prompt_size: Position,
+        rprompt: Option<&str>,
         line: &LineBuffer,
         hint: Option<String>,
         _: usize,
         _: usize,
-        _: Option<&Highlighter>,
+        highlighter: Option<&Highlighter>,
     ) -> Result<(Position, Position)> {
-        let cursor = self.calculate_position(&line[..line.pos()], prompt_size);
+        let (cursor, end) = match highlighter.filter(|h| h.highlight_changes_width()) {
+            Some(h) => {
+                let highlighted = h.highlight(line, line.pos());
+                highlighted_positions(self, h, &highlighted, line, prompt_size)
+            }
+            None => (
+                self.calculate_position(&line[..line.pos()], prompt_size),
+                self.calculate_position(&line, prompt_size),
+            ),
+        };
+        let mut hint_end = end;
         if let Some(hint) = hint {
-            truncate(&hint, 0, 80);
+            let truncate = truncate(&hint, end.col, self.get_columns());
+            hint_end = self.calculate_position(truncate, end);
         }
-        let end = self.calculate_position(&line, prompt_size);
+        self.rprompt = match rprompt {
+            Some(rprompt) => padded_rprompt(self, rprompt, prompt_size, hint_end, highlighter),
+            None => String::new(),
+        };
         Ok((cursor, end))
     }
 
     fn calculate_position(&self, s: &str, orig: Position) -> Position {
         let mut pos = orig;
-        pos.col += s.len();
+        let mut esc_seq = 0;
+        pos.col += s
+            .graphemes(true)
+            .map(|g| width(g, &mut esc_seq))
+            .sum::<usize>();
         pos
     }
 
-    fn write_and_flush(&mut self, _: &[u8]) -> Result<()> {
+    fn write_and_flush(&mut self, buf: &[u8]) -> Result<()> {
+        self.written.push(String::from_utf8_lossy(buf).into_owned());
         Ok(())
     }
 
@@ -96,6 +183,13 @@ impl Renderer for Sink {
         Ok(())
     }
 
+    fn spinner_writer(&self) -> Option<Box<Write + Send>> {
+        match self.spinner {
+            Some(ref frames) => Some(Box::new(SpinnerWriter(Arc::clone(frames)))),
+            None => None,
+        }
+    }
+
     fn clear_screen(&mut self) -> Result<()> {
         Ok(())
     }
@@ -122,6 +216,7 @@ pub struct DummyTerminal {
     pub keys: Vec<KeyPress>,
     pub cursor: usize, // cursor position before last command
     pub color_mode: ColorMode,
+    pub bracketed_paste: bool,
 }
 
 impl Term for DummyTerminal {
@@ -129,11 +224,12 @@ impl Term for DummyTerminal {
     type Reader = IntoIter<KeyPress>;
     type Writer = Sink;
 
-    fn new(color_mode: ColorMode) -> DummyTerminal {
+    fn new(color_mode: ColorMode, bracketed_paste: bool) -> DummyTerminal {
         DummyTerminal {
             keys: Vec::new(),
             cursor: 0,
             color_mode: color_mode,
+            bracketed_paste,
         }
     }
 
@@ -147,10 +243,18 @@ impl Term for DummyTerminal {
         true
     }
 
+    fn is_output_tty(&self) -> bool {
+        false
+    }
+
     fn colors_enabled(&self) -> bool {
         false
     }
 
+    fn color_depth(&self) -> ColorDepth {
+        ColorDepth::TrueColor
+    }
+
     // Interactive loop:
 
     fn enable_raw_mode(&mut self) -> Result<Mode> {
@@ -162,7 +266,7 @@ impl Term for DummyTerminal {
     }
 
     fn create_writer(&self) -> Sink {
-        Sink {}
+        Sink::new()
     }
 }
 
@@ -3,9 +3,11 @@ use std;
 use std::io::{self, Read, Stdout, Write};
 use std::sync;
 use std::sync::atomic;
+use std::time::Instant;
 
 use libc;
 use nix;
+use nix::errno::Errno;
 use nix::poll::{self, EventFlags};
 use nix::sys::signal;
 use nix::sys::termios;
@@ -13,8 +15,11 @@ use nix::sys::termios::SetArg;
 use unicode_segmentation::UnicodeSegmentation;
 use utf8parse::{Parser, Receiver};
 
-use super::{truncate, width, Position, RawMode, RawReader, Renderer, Term};
-use config::{ColorMode, Config};
+use super::{
+    highlighted_positions, padded_rprompt, remaining_ms, truncate, width, Position, RawMode,
+    RawReader, Renderer, Term,
+};
+use config::{ColorDepth, ColorMode, Config};
 use error;
 use highlight::Highlighter;
 use keys::{self, KeyPress};
@@ -24,6 +29,12 @@ use Result;
 const STDIN_FILENO: libc::c_int = libc::STDIN_FILENO;
 const STDOUT_FILENO: libc::c_int = libc::STDOUT_FILENO;
 
+/// Enable the bracketed paste mode: the pasted text is delimited by
+/// `ESC [ 200 ~` and `ESC [ 201 ~`
+const BRACKETED_PASTE_ON: &[u8] = b"\x1b[?2004h";
+const BRACKETED_PASTE_OFF: &[u8] = b"\x1b[?2004l";
+const BRACKETED_PASTE_END: &str = "\x1b[201~";
+
 /// Unsupported Terminals that don't support RAW mode
 static UNSUPPORTED_TERM: [&'static str; 3] = ["dumb", "cons25", "emacs"];
 
@@ -58,17 +69,51 @@ fn is_unsupported_term() -> bool {
     }
 }
 
+/// Guess the color depth from `$COLORTERM` and `$TERM`.
+fn color_depth(colorterm: Option<&str>, term: Option<&str>) -> ColorDepth {
+    match colorterm {
+        Some("truecolor") | Some("24bit") => return ColorDepth::TrueColor,
+        _ => {}
+    }
+    match term {
+        Some(term) if term.ends_with("-direct") => ColorDepth::TrueColor,
+        Some(term) if term.contains("256color") => ColorDepth::Ansi256,
+        _ => ColorDepth::Ansi16,
+    }
+}
+
 /// Return whether or not STDIN, STDOUT or STDERR is a TTY
 fn is_a_tty(fd: libc::c_int) -> bool {
     unsafe { libc::isatty(fd) != 0 }
 }
 
-pub type Mode = termios::Termios;
+fn write_and_flush(buf: &[u8]) -> Result<()> {
+    let mut out = io::stdout();
+    try!(out.write_all(buf));
+    try!(out.flush());
+    Ok(())
+}
+
+pub type Mode = PosixMode;
+
+/// Terminal mode to restore
+#[derive(Clone)]
+pub struct PosixMode {
+    termios: termios::Termios,
+    bracketed_paste: bool, // `true` if the bracketed paste mode has been enabled
+}
 
 impl RawMode for Mode {
     /// Disable RAW mode for the terminal.
     fn disable_raw_mode(&self) -> Result<()> {
-        try!(termios::tcsetattr(STDIN_FILENO, SetArg::TCSADRAIN, self));
+        try!(termios::tcsetattr(
+            STDIN_FILENO,
+            SetArg::TCSADRAIN,
+            &self.termios
+        ));
+        if self.bracketed_paste {
+            try!(write_and_flush(BRACKETED_PASTE_OFF));
+        }
         Ok(())
     }
 }
@@ -234,6 +279,18 @@ impl PosixRawReader {
                         KeyPress::UnknownEscSeq
                     }
                 })
+            } else if seq4.is_digit(10) {
+                let seq5 = try!(self.next_char());
+                Ok(match (seq2, seq3, seq4, seq5) {
+                    ('2', '0', '0', '~') => KeyPress::BracketedPasteStart,
+                    ('2', '0', '1', '~') => KeyPress::BracketedPasteEnd,
+                    _ => {
+                        debug!(target: "rustyline",
+                               "unsupported esc sequence: ESC [ {}{}{} {:?}",
+                               seq2, seq3, seq4, seq5);
+                        KeyPress::UnknownEscSeq
+                    }
+                })
             } else if seq4 == ';' {
                 let seq5 = try!(self.next_char());
                 if seq5.is_digit(10) {
@@ -349,6 +406,38 @@ impl RawReader for PosixRawReader {
         Ok(key)
     }
 
+    fn poll(&mut self, timeout_ms: i32) -> Result<bool> {
+        let started = Instant::now();
+        let mut remaining = timeout_ms;
+        loop {
+            let mut fds = [poll::PollFd::new(STDIN_FILENO, EventFlags::POLLIN)];
+            match poll::poll(&mut fds, remaining) {
+                Ok(n) => return Ok(n > 0),
+                // like `StdinRaw::read`: the resize is reported to the caller
+                Err(nix::Error::Sys(Errno::EINTR))
+                    if !SIGWINCH.load(atomic::Ordering::Relaxed) =>
+                {
+                    remaining = remaining_ms(timeout_ms, started);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn read_pasted_text(&mut self) -> Result<String> {
+        let mut buffer = String::new();
+        loop {
+            buffer.push(try!(self.next_char()));
+            if buffer.ends_with(BRACKETED_PASTE_END) {
+                let len = buffer.len() - BRACKETED_PASTE_END.len();
+                buffer.truncate(len);
+                break;
+            }
+        }
+        // the terminals send a carriage return for each newline
+        Ok(buffer.replace("\r\n", "\n").replace('\r', "\n"))
+    }
+
     fn next_char(&mut self) -> Result<char> {
         loop {
             let n = try!(self.stdin.read(&mut self.buf));
@@ -443,6 +532,7 @@ impl Renderer for PosixRenderer {
         &mut self,
         prompt: &str,
         prompt_size: Position,
+        rprompt: Option<&str>,
         line: &LineBuffer,
         hint: Option<String>,
         current_row: usize,
@@ -452,10 +542,19 @@ impl Renderer for PosixRenderer {
         use std::fmt::Write;
         self.buffer.clear();
 
-        // calculate the position of the end of the input line
-        let end_pos = self.calculate_position(line, prompt_size);
-        // calculate the desired position of the cursor
-        let cursor = self.calculate_position(&line[..line.pos()], prompt_size);
+        let highlighted = highlighter.map(|h| h.highlight(line, line.pos()));
+        let (cursor, end_pos) = match (highlighter, highlighted.as_ref()) {
+            (Some(h), Some(highlighted)) if h.highlight_changes_width() => {
+                highlighted_positions(self, h, highlighted, line, prompt_size)
+            }
+            _ => {
+                // calculate the position of the end of the input line
+                let end_pos = self.calculate_position(line, prompt_size);
+                // calculate the desired position of the cursor
+                let cursor = self.calculate_position(&line[..line.pos()], prompt_size);
+                (cursor, end_pos)
+            }
+        };
 
         // self.old_rows < self.cursor.row if the prompt spans multiple lines and if
         // this is the default State.
@@ -471,12 +570,11 @@ impl Renderer for PosixRenderer {
         // clear the line
         self.buffer.push_str("\r\x1b[0K");
 
-        if let Some(highlighter) = highlighter {
+        if let (Some(highlighter), Some(highlighted)) = (highlighter, highlighted) {
             // display the prompt
             self.buffer.push_str(&highlighter.highlight_prompt(prompt));
             // display the input line
-            self.buffer
-                .push_str(&highlighter.highlight(line, line.pos()));
+            self.buffer.push_str(&highlighted);
         } else {
             // display the prompt
             self.buffer.push_str(prompt);
@@ -484,14 +582,21 @@ impl Renderer for PosixRenderer {
             self.buffer.push_str(line);
         }
         // display hint
+        let mut end = end_pos;
         if let Some(hint) = hint {
             let truncate = truncate(&hint, end_pos.col, self.cols);
+            end = self.calculate_position(truncate, end_pos);
             if let Some(highlighter) = highlighter {
                 self.buffer.push_str(&highlighter.highlight_hint(truncate));
             } else {
                 self.buffer.push_str(truncate);
             }
         }
+        // display the right prompt
+        if let Some(rprompt) = rprompt {
+            let padded = padded_rprompt(self, rprompt, prompt_size, end, highlighter);
+            self.buffer.push_str(&padded);
+        }
         // we have to generate our own newline on line wrap
         if end_pos.col == 0 && end_pos.row > 0 {
             self.buffer.push_str("\n");
@@ -600,6 +705,7 @@ pub struct PosixTerminal {
     stdin_isatty: bool,
     stdout_isatty: bool,
     pub(crate) color_mode: ColorMode,
+    pub(crate) bracketed_paste: bool,
 }
 
 impl Term for PosixTerminal {
@@ -607,12 +713,13 @@ impl Term for PosixTerminal {
     type Reader = PosixRawReader;
     type Writer = PosixRenderer;
 
-    fn new(color_mode: ColorMode) -> PosixTerminal {
+    fn new(color_mode: ColorMode, bracketed_paste: bool) -> PosixTerminal {
         let term = PosixTerminal {
             unsupported: is_unsupported_term(),
             stdin_isatty: is_a_tty(STDIN_FILENO),
             stdout_isatty: is_a_tty(STDOUT_FILENO),
             color_mode,
+            bracketed_paste,
         };
         if !term.unsupported && term.stdin_isatty && term.stdout_isatty {
             install_sigwinch_handler();
@@ -633,6 +740,10 @@ impl Term for PosixTerminal {
         self.stdin_isatty
     }
 
+    fn is_output_tty(&self) -> bool {
+        self.stdout_isatty
+    }
+
     /// Check if output supports colors.
     fn colors_enabled(&self) -> bool {
         match self.color_mode {
@@ -642,6 +753,15 @@ impl Term for PosixTerminal {
         }
     }
 
+    fn color_depth(&self) -> ColorDepth {
+        let colorterm = std::env::var("COLORTERM").ok();
+        let term = std::env::var("TERM").ok();
+        color_depth(
+            colorterm.as_ref().map(|s| s.as_str()),
+            term.as_ref().map(|s| s.as_str()),
+        )
+    }
+
     // Interactive loop:
 
     fn enable_raw_mode(&mut self) -> Result<Mode> {
@@ -671,7 +791,13 @@ impl Term for PosixTerminal {
         raw.control_chars[SpecialCharacterIndices::VMIN as usize] = 1; // One character-at-a-time input
         raw.control_chars[SpecialCharacterIndices::VTIME as usize] = 0; // with blocking read
         try!(termios::tcsetattr(STDIN_FILENO, SetArg::TCSADRAIN, &raw));
-        Ok(original_mode)
+        if self.bracketed_paste {
+            try!(write_and_flush(BRACKETED_PASTE_ON));
+        }
+        Ok(PosixMode {
+            termios: original_mode,
+            bracketed_paste: self.bracketed_paste,
+        })
     }
 
     /// Create a RAW reader
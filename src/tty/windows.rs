@@ -2,14 +2,19 @@
 use std::io::{self, Stdout, Write};
 use std::mem;
 use std::sync::atomic;
+use std::time::{Duration, Instant};
 
-use unicode_width::UnicodeWidthChar;
+use unicode_segmentation::UnicodeSegmentation;
 use winapi::shared::minwindef::{DWORD, WORD};
+use winapi::shared::winerror;
 use winapi::um::winnt::{CHAR, HANDLE};
-use winapi::um::{consoleapi, handleapi, processenv, winbase, wincon, winuser};
+use winapi::um::{consoleapi, handleapi, processenv, synchapi, winbase, wincon, winuser};
 
-use super::{truncate, Position, RawMode, RawReader, Renderer, Term};
-use config::{ColorMode, Config};
+use super::{
+    highlighted_positions, padded_rprompt, truncate, width, Position, RawMode, RawReader, Renderer,
+    Term,
+};
+use config::{ColorDepth, ColorMode, Config};
 use error;
 use highlight::Highlighter;
 use keys::{self, KeyPress};
@@ -87,6 +92,79 @@ impl RawMode for Mode {
     }
 }
 
+/// Key of a key event without char (an arrow, a function key...), `None` if
+/// it is not handled (like a modifier key alone).
+fn special_key(virtual_key_code: WORD, ctrl: bool, shift: bool) -> Option<KeyPress> {
+    Some(match virtual_key_code as i32 {
+        winuser::VK_LEFT => if ctrl {
+            KeyPress::ControlLeft
+        } else if shift {
+            KeyPress::ShiftLeft
+        } else {
+            KeyPress::Left
+        },
+        winuser::VK_RIGHT => if ctrl {
+            KeyPress::ControlRight
+        } else if shift {
+            KeyPress::ShiftRight
+        } else {
+            KeyPress::Right
+        },
+        winuser::VK_UP => if ctrl {
+            KeyPress::ControlUp
+        } else if shift {
+            KeyPress::ShiftUp
+        } else {
+            KeyPress::Up
+        },
+        winuser::VK_DOWN => if ctrl {
+            KeyPress::ControlDown
+        } else if shift {
+            KeyPress::ShiftDown
+        } else {
+            KeyPress::Down
+        },
+        winuser::VK_DELETE => KeyPress::Delete,
+        winuser::VK_HOME => KeyPress::Home,
+        winuser::VK_END => KeyPress::End,
+        winuser::VK_PRIOR => KeyPress::PageUp,
+        winuser::VK_NEXT => KeyPress::PageDown,
+        winuser::VK_INSERT => KeyPress::Insert,
+        winuser::VK_F1 => KeyPress::F(1),
+        winuser::VK_F2 => KeyPress::F(2),
+        winuser::VK_F3 => KeyPress::F(3),
+        winuser::VK_F4 => KeyPress::F(4),
+        winuser::VK_F5 => KeyPress::F(5),
+        winuser::VK_F6 => KeyPress::F(6),
+        winuser::VK_F7 => KeyPress::F(7),
+        winuser::VK_F8 => KeyPress::F(8),
+        winuser::VK_F9 => KeyPress::F(9),
+        winuser::VK_F10 => KeyPress::F(10),
+        winuser::VK_F11 => KeyPress::F(11),
+        winuser::VK_F12 => KeyPress::F(12),
+        _ => return None,
+    })
+}
+
+/// Whether `next_key` returns for the input event `rec`: a key typed or a
+/// resize of the window (not a key release, a modifier key alone, a focus or
+/// a mouse event)
+fn is_key_event(rec: &wincon::INPUT_RECORD) -> bool {
+    match rec.EventType {
+        wincon::WINDOW_BUFFER_SIZE_EVENT => true,
+        wincon::KEY_EVENT => {
+            let key_event = unsafe { rec.Event.KeyEvent() };
+            // the release of Alt ends the typing of a char by its code
+            if key_event.bKeyDown == 0 && key_event.wVirtualKeyCode != winuser::VK_MENU as WORD {
+                return false;
+            }
+            let utf16 = unsafe { *key_event.uChar.UnicodeChar() };
+            utf16 != 0 || special_key(key_event.wVirtualKeyCode, false, false).is_some()
+        }
+        _ => false,
+    }
+}
+
 /// Console input reader
 pub struct ConsoleRawReader {
     handle: HANDLE,
@@ -147,65 +225,12 @@ impl RawReader for ConsoleRawReader {
 
             let utf16 = unsafe { *key_event.uChar.UnicodeChar() };
             if utf16 == 0 {
-                match key_event.wVirtualKeyCode as i32 {
-                    winuser::VK_LEFT => {
-                        return Ok(if ctrl {
-                            KeyPress::ControlLeft
-                        } else if shift {
-                            KeyPress::ShiftLeft
-                        } else {
-                            KeyPress::Left
-                        })
-                    }
-                    winuser::VK_RIGHT => {
-                        return Ok(if ctrl {
-                            KeyPress::ControlRight
-                        } else if shift {
-                            KeyPress::ShiftRight
-                        } else {
-                            KeyPress::Right
-                        })
-                    }
-                    winuser::VK_UP => {
-                        return Ok(if ctrl {
-                            KeyPress::ControlUp
-                        } else if shift {
-                            KeyPress::ShiftUp
-                        } else {
-                            KeyPress::Up
-                        })
-                    }
-                    winuser::VK_DOWN => {
-                        return Ok(if ctrl {
-                            KeyPress::ControlDown
-                        } else if shift {
-                            KeyPress::ShiftDown
-                        } else {
-                            KeyPress::Down
-                        })
-                    }
-                    winuser::VK_DELETE => return Ok(KeyPress::Delete),
-                    winuser::VK_HOME => return Ok(KeyPress::Home),
-                    winuser::VK_END => return Ok(KeyPress::End),
-                    winuser::VK_PRIOR => return Ok(KeyPress::PageUp),
-                    winuser::VK_NEXT => return Ok(KeyPress::PageDown),
-                    winuser::VK_INSERT => return Ok(KeyPress::Insert),
-                    winuser::VK_F1 => return Ok(KeyPress::F(1)),
-                    winuser::VK_F2 => return Ok(KeyPress::F(2)),
-                    winuser::VK_F3 => return Ok(KeyPress::F(3)),
-                    winuser::VK_F4 => return Ok(KeyPress::F(4)),
-                    winuser::VK_F5 => return Ok(KeyPress::F(5)),
-                    winuser::VK_F6 => return Ok(KeyPress::F(6)),
-                    winuser::VK_F7 => return Ok(KeyPress::F(7)),
-                    winuser::VK_F8 => return Ok(KeyPress::F(8)),
-                    winuser::VK_F9 => return Ok(KeyPress::F(9)),
-                    winuser::VK_F10 => return Ok(KeyPress::F(10)),
-                    winuser::VK_F11 => return Ok(KeyPress::F(11)),
-                    winuser::VK_F12 => return Ok(KeyPress::F(12)),
+                match special_key(key_event.wVirtualKeyCode, ctrl, shift) {
+                    Some(key) => return Ok(key),
                     // winuser::VK_BACK is correctly handled because the key_event.UnicodeChar is
                     // also set.
-                    _ => continue,
-                };
+                    None => continue,
+                }
             } else if utf16 == 27 {
                 return Ok(KeyPress::Esc);
             } else {
@@ -240,6 +265,60 @@ impl RawReader for ConsoleRawReader {
             }
         }
     }
+
+    fn poll(&mut self, timeout_ms: i32) -> Result<bool> {
+        let deadline = if timeout_ms < 0 {
+            None
+        } else {
+            Some(Instant::now() + Duration::from_millis(timeout_ms as u64))
+        };
+        let mut rec: wincon::INPUT_RECORD = unsafe { mem::zeroed() };
+        let mut count = 0;
+        loop {
+            // any console input event (not only a key) signals the handle:
+            // the ones ignored by `next_key` are dropped
+            check!(wincon::PeekConsoleInputW(
+                self.handle,
+                &mut rec,
+                1 as DWORD,
+                &mut count,
+            ));
+            if count > 0 {
+                if is_key_event(&rec) {
+                    return Ok(true);
+                }
+                check!(consoleapi::ReadConsoleInputW(
+                    self.handle,
+                    &mut rec,
+                    1 as DWORD,
+                    &mut count,
+                ));
+                continue;
+            }
+            // wait for the remaining time
+            let timeout = match deadline {
+                None => winbase::INFINITE,
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Ok(false);
+                    }
+                    let remaining = deadline - now;
+                    (remaining.as_secs() * 1000 + u64::from(remaining.subsec_millis())) as DWORD
+                }
+            };
+            match unsafe { synchapi::WaitForSingleObject(self.handle, timeout) } {
+                winbase::WAIT_OBJECT_0 => {}
+                winerror::WAIT_TIMEOUT => return Ok(false),
+                _ => return Err(io::Error::last_os_error().into()),
+            }
+        }
+    }
+
+    fn read_pasted_text(&mut self) -> Result<String> {
+        // the bracketed paste mode is not enabled on the console
+        Ok(String::new())
+    }
 }
 
 pub struct ConsoleRenderer {
@@ -305,16 +384,26 @@ impl Renderer for ConsoleRenderer {
         &mut self,
         prompt: &str,
         prompt_size: Position,
+        rprompt: Option<&str>,
         line: &LineBuffer,
         hint: Option<String>,
         current_row: usize,
         old_rows: usize,
         highlighter: Option<&Highlighter>,
     ) -> Result<(Position, Position)> {
-        // calculate the position of the end of the input line
-        let end_pos = self.calculate_position(line, prompt_size);
-        // calculate the desired position of the cursor
-        let cursor = self.calculate_position(&line[..line.pos()], prompt_size);
+        let highlighted = highlighter.map(|h| h.highlight(line, line.pos()));
+        let (cursor, end_pos) = match (highlighter, highlighted.as_ref()) {
+            (Some(h), Some(highlighted)) if h.highlight_changes_width() => {
+                highlighted_positions(self, h, highlighted, line, prompt_size)
+            }
+            _ => {
+                // calculate the position of the end of the input line
+                let end_pos = self.calculate_position(line, prompt_size);
+                // calculate the desired position of the cursor
+                let cursor = self.calculate_position(&line[..line.pos()], prompt_size);
+                (cursor, end_pos)
+            }
+        };
 
         // position at the start of the prompt, clear to end of previous input
         let mut info = try!(self.get_console_screen_buffer_info());
@@ -326,13 +415,12 @@ impl Renderer for ConsoleRenderer {
             info.dwCursorPosition,
         ));
         self.buffer.clear();
-        if let Some(highlighter) = highlighter {
+        if let (Some(highlighter), Some(highlighted)) = (highlighter, highlighted) {
             // TODO handle ansi escape code (SetConsoleTextAttribute)
             // display the prompt
             self.buffer.push_str(&highlighter.highlight_prompt(prompt));
             // display the input line
-            self.buffer
-                .push_str(&highlighter.highlight(line, line.pos()));
+            self.buffer.push_str(&highlighted);
         } else {
             // display the prompt
             self.buffer.push_str(prompt);
@@ -340,14 +428,21 @@ impl Renderer for ConsoleRenderer {
             self.buffer.push_str(line);
         }
         // display hint
+        let mut end = end_pos;
         if let Some(hint) = hint {
             let truncate = truncate(&hint, end_pos.col, self.cols);
+            end = self.calculate_position(truncate, end_pos);
             if let Some(highlighter) = highlighter {
                 self.buffer.push_str(&highlighter.highlight_hint(truncate));
             } else {
                 self.buffer.push_str(truncate);
             }
         }
+        // display the right prompt
+        if let Some(rprompt) = rprompt {
+            let padded = padded_rprompt(self, rprompt, prompt_size, end, highlighter);
+            self.buffer.push_str(&padded);
+        }
         try!(self.out.write_all(self.buffer.as_bytes()));
         try!(self.out.flush());
 
@@ -368,20 +463,18 @@ impl Renderer for ConsoleRenderer {
     /// Characters with 2 column width are correctly handled (not splitted).
     fn calculate_position(&self, s: &str, orig: Position) -> Position {
         let mut pos = orig;
-        for c in s.chars() {
-            let cw = if c == '\n' {
+        let mut esc_seq = 0;
+        for c in s.graphemes(true) {
+            if c == "\n" {
                 pos.col = 0;
                 pos.row += 1;
-                None
-            } else {
-                c.width()
-            };
-            if let Some(cw) = cw {
-                pos.col += cw;
-                if pos.col > self.cols {
-                    pos.row += 1;
-                    pos.col = cw;
-                }
+                continue;
+            }
+            let cw = width(c, &mut esc_seq);
+            pos.col += cw;
+            if pos.col > self.cols {
+                pos.row += 1;
+                pos.col = cw;
             }
         }
         if pos.col == self.cols {
@@ -391,6 +484,12 @@ impl Renderer for ConsoleRenderer {
         pos
     }
 
+    fn set_title(&mut self, title: &str) -> Result<()> {
+        let title: Vec<u16> = title.encode_utf16().chain(::std::iter::once(0)).collect();
+        check!(wincon::SetConsoleTitleW(title.as_ptr()));
+        Ok(())
+    }
+
     /// Clear the screen. Used to handle ctrl+l
     fn clear_screen(&mut self) -> Result<()> {
         let info = try!(self.get_console_screen_buffer_info());
@@ -434,6 +533,8 @@ pub struct Console {
     stdout_isatty: bool,
     stdout_handle: HANDLE,
     pub(crate) color_mode: ColorMode,
+    // the bracketed paste mode is not supported by the console: ignored
+    pub(crate) bracketed_paste: bool,
     ansi_colors_supported: bool,
 }
 
@@ -444,7 +545,7 @@ impl Term for Console {
     type Reader = ConsoleRawReader;
     type Writer = ConsoleRenderer;
 
-    fn new(color_mode: ColorMode) -> Console {
+    fn new(color_mode: ColorMode, bracketed_paste: bool) -> Console {
         use std::ptr;
         let stdin_handle = get_std_handle(STDIN_FILENO);
         let stdin_isatty = match stdin_handle {
@@ -469,6 +570,7 @@ impl Term for Console {
             stdout_isatty,
             stdout_handle: stdout_handle.unwrap_or(ptr::null_mut()),
             color_mode,
+            bracketed_paste,
             ansi_colors_supported: false,
         }
     }
@@ -482,6 +584,10 @@ impl Term for Console {
         self.stdin_isatty
     }
 
+    fn is_output_tty(&self) -> bool {
+        self.stdout_isatty
+    }
+
     fn colors_enabled(&self) -> bool {
         // TODO ANSI Colors & Windows <10
         match self.color_mode {
@@ -491,6 +597,11 @@ impl Term for Console {
         }
     }
 
+    fn color_depth(&self) -> ColorDepth {
+        // escape sequences are written as is
+        ColorDepth::TrueColor
+    }
+
     // pub fn install_sigwinch_handler(&mut self) {
     // See ReadConsoleInputW && WINDOW_BUFFER_SIZE_EVENT
     // }
@@ -0,0 +1,33 @@
+//! Input validation (when the line is accepted)
+
+/// Result of the validation of the line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationResult {
+    /// The line is accepted.
+    Valid,
+    /// The line is rejected: editing continues and the message (if any) is
+    /// displayed below the line.
+    Invalid(Option<String>),
+    /// The line is accepted only after a confirmation: the message is
+    /// displayed below the line and the line is submitted if Enter is pressed
+    /// again. Any other key cancels the confirmation (and is processed as
+    /// usual).
+    Confirm(String),
+}
+
+/// Input validator
+///
+/// Opt-in: the editor validates the line only when `Helper::validator`
+/// returns the validator.
+pub trait Validator {
+    /// Takes the currently edited `line` when the user presses Enter and
+    /// tells if it can be submitted.
+    ///
+    /// By default, any line is valid.
+    fn validate(&self, line: &str) -> ValidationResult {
+        let _ = line;
+        ValidationResult::Valid
+    }
+}
+
+impl Validator for () {}